@@ -0,0 +1,43 @@
+use egui::{Slider, Ui, Widget};
+use space_engine_shader::renderer::lighting::ambient_occlusion::{AO_SLICE_STEPS, AoSettings};
+
+pub struct AoSelector {
+	pub ao: AoSettings,
+	enabled: bool,
+}
+
+impl Default for AoSelector {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl AoSelector {
+	pub fn new() -> Self {
+		Self {
+			ao: AoSettings {
+				enabled: 1,
+				radius: 0.5,
+				intensity: 1.,
+				sample_count: 2,
+			},
+			enabled: true,
+		}
+	}
+
+	pub fn ui(&mut self, ui: &mut Ui) {
+		ui.strong("Ambient Occlusion:");
+		ui.checkbox(&mut self.enabled, "screen space ambient occlusion");
+		self.ao.enabled = self.enabled as u32;
+		ui.add_enabled_ui(self.enabled, |ui| {
+			Slider::new(&mut self.ao.radius, 0.05..=5.)
+				.logarithmic(true)
+				.text("radius")
+				.ui(ui);
+			Slider::new(&mut self.ao.intensity, 0.1..=4.).text("intensity").ui(ui);
+			Slider::new(&mut self.ao.sample_count, 1..=8)
+				.text(format!("slices of {} samples", AO_SLICE_STEPS * 2))
+				.ui(ui);
+		});
+	}
+}
@@ -0,0 +1,7 @@
+use clap::Parser;
+use meshlet_renderer::headless::{HeadlessArgs, headless_main};
+
+fn main() -> anyhow::Result<()> {
+	env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+	headless_main(&HeadlessArgs::parse())
+}
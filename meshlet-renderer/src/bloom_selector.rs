@@ -0,0 +1,36 @@
+use egui::{Slider, Ui, Widget};
+use space_engine_shader::renderer::bloom::BloomSettings;
+
+pub struct BloomSelector {
+	pub bloom: BloomSettings,
+	enabled: bool,
+}
+
+impl Default for BloomSelector {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl BloomSelector {
+	pub fn new() -> Self {
+		Self {
+			bloom: BloomSettings {
+				enabled: 1,
+				intensity: 0.04,
+			},
+			enabled: true,
+		}
+	}
+
+	pub fn ui(&mut self, ui: &mut Ui) {
+		ui.strong("Bloom:");
+		ui.checkbox(&mut self.enabled, "bloom");
+		self.bloom.enabled = self.enabled as u32;
+		ui.add_enabled_ui(self.enabled, |ui| {
+			Slider::new(&mut self.bloom.intensity, 0. ..=0.3)
+				.text("intensity")
+				.ui(ui);
+		});
+	}
+}
@@ -1,6 +1,7 @@
 use crate::delta_time::DeltaTime;
 use glam::{Affine3A, DVec2, Quat, Vec3, vec3};
 use num_traits::clamp;
+use space_engine_shader::renderer::camera::Projection;
 use std::f32;
 use std::f32::consts::PI;
 use std::ops::{Deref, DerefMut};
@@ -14,6 +15,30 @@ pub struct FpsCameraController {
 	pub state: State,
 	pub move_speed: Vec3,
 	pub mouse_speed: f32,
+	/// vertical field of view of the perspective projections in radians
+	pub fov_y: f32,
+	/// world units covered vertically by the orthographic projection, zoomed with the scroll wheel
+	pub ortho_height: f32,
+}
+
+/// The projections cycled through with P, see [`FpsCameraController::projection`]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ProjectionMode {
+	#[default]
+	Perspective,
+	/// the perspective frustum shifted right by a quarter of its width, like the view of a VR headset's eye
+	Asymmetric,
+	Orthographic,
+}
+
+impl ProjectionMode {
+	pub fn next(self) -> Self {
+		match self {
+			ProjectionMode::Perspective => ProjectionMode::Asymmetric,
+			ProjectionMode::Asymmetric => ProjectionMode::Orthographic,
+			ProjectionMode::Orthographic => ProjectionMode::Perspective,
+		}
+	}
 }
 
 #[derive(Copy, Clone, Default)]
@@ -25,6 +50,7 @@ pub struct State {
 	movement_keys: [[bool; 2]; 3],
 	mouse_disabled: bool,
 	pub move_speed_exponent: i32,
+	pub projection_mode: ProjectionMode,
 }
 
 impl Deref for FpsCameraController {
@@ -47,6 +73,8 @@ impl Default for FpsCameraController {
 			state: State::default(),
 			move_speed: Vec3::splat(1.),
 			mouse_speed: 0.02,
+			fov_y: 90f32.to_radians(),
+			ortho_height: 20.,
 		}
 	}
 }
@@ -80,6 +108,7 @@ impl FpsCameraController {
 					KeyW => self.movement_keys[2][0] = value,
 					KeyS => self.movement_keys[2][1] = value,
 					KeyM if value => self.mouse_disabled = !self.mouse_disabled,
+					KeyP if value => self.projection_mode = self.projection_mode.next(),
 					Home => self.state = State::default(),
 					_ => {}
 				}
@@ -104,7 +133,11 @@ impl FpsCameraController {
 					MouseScrollDelta::LineDelta(_, y) => y,
 				};
 				let y = -y;
-				self.move_speed_exponent += [-1, 1][(y < 0.) as usize];
+				if self.projection_mode == ProjectionMode::Orthographic {
+					self.ortho_height *= [1.25, 0.8][(y < 0.) as usize];
+				} else {
+					self.move_speed_exponent += [-1, 1][(y < 0.) as usize];
+				}
 			}
 			_ => {}
 		}
@@ -125,4 +158,24 @@ impl FpsCameraController {
 		let quat = quat_yaw * Quat::from_axis_angle(vec3(1., 0., 0.), self.rotation_pitch);
 		Affine3A::from_translation(self.position) * Affine3A::from_quat(quat)
 	}
+
+	/// The projection of the current [`ProjectionMode`] for a viewport of `aspect_ratio`
+	pub fn projection(&self, aspect_ratio: f32) -> Projection {
+		match self.projection_mode {
+			ProjectionMode::Perspective => Projection::Perspective { fov_y: self.fov_y },
+			ProjectionMode::Asymmetric => {
+				let half_y = self.fov_y * 0.5;
+				let half_x = f32::tan(half_y) * aspect_ratio;
+				Projection::AsymmetricPerspective {
+					left: f32::atan(-half_x * 0.5),
+					right: f32::atan(half_x * 1.5),
+					down: -half_y,
+					up: half_y,
+				}
+			}
+			ProjectionMode::Orthographic => Projection::Orthographic {
+				height: self.ortho_height,
+			},
+		}
+	}
 }
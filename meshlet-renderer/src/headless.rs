@@ -0,0 +1,251 @@
+use crate::ao_selector::AoSelector;
+use crate::bloom_selector::BloomSelector;
+use crate::debug_settings_selector::DebugSettingsSelector;
+use crate::delta_time::DeltaTime;
+use crate::image_output::OutputFormat;
+use crate::main_loop::{MESHLET_GROUP_CAPACITY, MESHLET_INSTANCE_CAPACITY};
+use crate::nanite_error_selector::NaniteErrorSelector;
+use crate::sample_scenes::sample_scenes;
+use crate::shadow_selector::ShadowSelector;
+use crate::sun_controller::SunController;
+use crate::taa_selector::TaaSelector;
+use crate::tonemap_selector::TonemapSelector;
+use anyhow::Context;
+use ash::vk::{PhysicalDeviceMeshShaderFeaturesEXT, ShaderStageFlags};
+use clap::Parser;
+use glam::{Affine3A, Quat, UVec2, UVec3, Vec3};
+use rust_gpu_bindless::descriptor::{
+	Bindless, BindlessAllocationScheme, BindlessBufferCreateInfo, BindlessBufferUsage, BindlessImageCreateInfo,
+	BindlessImageUsage, BindlessInstance, DescriptorCounts, Image2d, MutDesc, MutDescBufferExt, MutImage,
+};
+use rust_gpu_bindless::pipeline::{
+	HostAccess, MutBufferAccessExt, MutImageAccessExt, StorageReadWrite, TransferRead, TransferWrite,
+};
+use rust_gpu_bindless::platform::ash::{
+	AshSingleGraphicsQueueCreateInfo, Debuggers, ash_init_single_graphics_queue_with_push_next,
+};
+use space_asset_disk::meshlet::scene::MeshletSceneReader;
+use space_asset_rt::meshlet::scene::{InstancedMeshletSceneCpu, upload_scene};
+use space_asset_rt::uploader::Uploader;
+use space_asset_shader::affine_transform::AffineTransform;
+use space_engine::renderer::renderers::main::RenderPipelineMain;
+use space_engine_shader::renderer::camera::{Camera, Projection};
+use space_engine_shader::renderer::frame_data::{DebugSettings, FrameData};
+use space_engine_shader::renderer::lod_selection::LodSelection;
+use space_engine_shader::renderer::tonemap::SCRGB_WHITE_NITS;
+use std::fs;
+use std::path::PathBuf;
+
+/// the fixed time step between frames, so that repeated renders produce the same image
+const FRAME_TIME: f32 = 1. / 60.;
+
+/// Renders a scene offscreen and writes the image to disk, without a window or swapchain.
+///
+/// The renderer requires `VK_EXT_mesh_shader`. Without a GPU, Mesa's lavapipe supports it since Mesa 24.1 and can be
+/// selected with `VK_DRIVER_FILES=/usr/share/vulkan/icd.d/lvp_icd.x86_64.json`, e.g. for golden image tests on CI.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct HeadlessArgs {
+	/// path to a baked scene file or the name of one of the sample scenes
+	#[arg(short, long)]
+	pub scene: String,
+	/// the image written, `.png` for the tonemapped SDR image or `.exr` for the tonemapped, display-referred image in
+	/// linear scRGB
+	#[arg(short, long)]
+	pub output: PathBuf,
+	#[arg(long, default_value_t = 1920)]
+	pub width: u32,
+	#[arg(long, default_value_t = 1080)]
+	pub height: u32,
+	/// camera position as `x,y,z`
+	#[arg(long, value_parser = parse_vec3, default_value = "0,0,0", allow_hyphen_values = true)]
+	pub position: Vec3,
+	/// camera rotation around the up axis in degrees
+	#[arg(long, default_value_t = 0., allow_hyphen_values = true)]
+	pub yaw: f32,
+	/// camera rotation up or down in degrees
+	#[arg(long, default_value_t = 0., allow_hyphen_values = true)]
+	pub pitch: f32,
+	/// vertical field of view of the perspective projection in degrees
+	#[arg(long, default_value_t = 90.)]
+	pub fov: f32,
+	/// renders with an orthographic projection covering this many world units vertically
+	#[arg(long)]
+	pub ortho_height: Option<f32>,
+	/// debug visualization, e.g. `normals` or `lod-level`
+	#[arg(long, value_parser = parse_debug_settings, default_value = "none")]
+	pub debug: DebugSettings,
+	/// draws every meshlet at this LOD level instead of selecting the LOD by its error
+	#[arg(long)]
+	pub lod_level: Option<u32>,
+	/// position of the sun along its path through the day, from 0 to 1
+	#[arg(long, default_value_t = 0.)]
+	pub sun_position: f32,
+	/// frames rendered before the last one is written, letting TAA and auto exposure converge
+	#[arg(long, default_value_t = 1)]
+	pub frames: u32,
+}
+
+fn parse_vec3(s: &str) -> Result<Vec3, String> {
+	let components = s
+		.split(',')
+		.map(|c| c.trim().parse::<f32>().map_err(|e| format!("{c:?}: {e}")))
+		.collect::<Result<Vec<_>, _>>()?;
+	<[f32; 3]>::try_from(components)
+		.map(Vec3::from_array)
+		.map_err(|_| format!("expected 3 comma separated components, got {s:?}"))
+}
+
+fn parse_debug_settings(s: &str) -> Result<DebugSettings, String> {
+	let name = s.replace(['-', '_'], "");
+	(0..DebugSettings::LEN)
+		.map(|i| DebugSettings::try_from(i).unwrap())
+		.find(|x| format!("{:?}", x).eq_ignore_ascii_case(&name))
+		.ok_or_else(|| {
+			let all = (0..DebugSettings::LEN)
+				.map(|i| format!("{:?}", DebugSettings::try_from(i).unwrap()))
+				.collect::<Vec<_>>();
+			format!("unknown debug setting {s:?}, expected one of {}", all.join(", "))
+		})
+}
+
+pub fn headless_main(args: &HeadlessArgs) -> anyhow::Result<()> {
+	let format = OutputFormat::from_path(&args.output)?;
+	let size = UVec2::new(args.width, args.height);
+	let bindless = init_bindless()?;
+	let scene = pollster::block_on(load_scene(&bindless, &args.scene))?;
+
+	let render_pipeline_main = RenderPipelineMain::new(
+		&bindless,
+		format.image_format(),
+		MESHLET_GROUP_CAPACITY,
+		MESHLET_INSTANCE_CAPACITY,
+	)?;
+	let mut renderer_main = render_pipeline_main.new_renderer()?;
+	let mut output_image: MutDesc<MutImage<Image2d>> = bindless.image().alloc(&BindlessImageCreateInfo {
+		format: format.image_format(),
+		extent: size.into(),
+		usage: BindlessImageUsage::STORAGE | BindlessImageUsage::TRANSFER_SRC,
+		name: "headless_output",
+		..Default::default()
+	})?;
+	let download = bindless.buffer().alloc_slice::<u32>(
+		&BindlessBufferCreateInfo {
+			name: "headless_download",
+			usage: BindlessBufferUsage::MAP_READ | BindlessBufferUsage::TRANSFER_DST,
+			allocation_scheme: BindlessAllocationScheme::AllocatorManaged,
+		},
+		(size.x * size.y) as usize * format.words_per_texel(),
+	)?;
+
+	let projection = match args.ortho_height {
+		Some(height) => Projection::Orthographic { height },
+		None => Projection::Perspective {
+			fov_y: args.fov.to_radians(),
+		},
+	};
+	let rotation =
+		Quat::from_axis_angle(Vec3::Y, args.yaw.to_radians()) * Quat::from_axis_angle(Vec3::X, args.pitch.to_radians());
+	let transform = AffineTransform::new(Affine3A::from_rotation_translation(rotation, args.position));
+	let debug_settings_selector = DebugSettingsSelector {
+		debug_settings: args.debug,
+		..DebugSettingsSelector::new()
+	};
+	let mut sun_controller = SunController::new();
+	sun_controller.is_paused = true;
+	sun_controller.position = args.sun_position;
+	let mut taa_selector = TaaSelector::new();
+	let tonemap_selector = TonemapSelector::new(format.colorspace());
+
+	let mut prev_camera = None;
+	for frame in 0..args.frames.max(1) {
+		let delta_time = DeltaTime {
+			delta_time: FRAME_TIME,
+			since_start: frame as f32 * FRAME_TIME,
+		};
+		let camera = Camera::new_rh_y_flip(size, projection, 0.01, 1000., transform, taa_selector.next_jitter());
+		let prev_camera = prev_camera.replace(camera).unwrap_or(camera);
+		let (sun, ambient_light) = sun_controller.eval_sun(delta_time);
+		let mut tonemap = tonemap_selector.tonemap(delta_time);
+		if format == OutputFormat::Exr {
+			// 1.0 is SDR white
+			tonemap.paper_white_nits = SCRGB_WHITE_NITS;
+		}
+		let frame_data = FrameData {
+			camera,
+			prev_camera,
+			debug_settings: debug_settings_selector.debug_settings.into(),
+			debug_mix: debug_settings_selector.debug_mix_adjusted(),
+			debug_lod_level: args
+				.lod_level
+				.map_or_else(LodSelection::new_nanite, LodSelection::new_static),
+			sun,
+			shadow: ShadowSelector::new().shadow,
+			ambient_light,
+			ibl: sun_controller.ibl,
+			ao: AoSelector::new().ao,
+			nanite: NaniteErrorSelector::new().nanite,
+			taa: taa_selector.taa,
+			bloom: BloomSelector::new().bloom,
+			tonemap,
+		};
+
+		output_image = bindless.execute(|cmd| {
+			let output_image = output_image.access_dont_care::<StorageReadWrite>(cmd)?;
+			if let Err(e) = renderer_main.new_frame(cmd, frame_data, &scene, &output_image) {
+				return Ok(Err(e));
+			}
+			Ok(Ok(output_image.into_desc()))
+		})??;
+	}
+
+	let download = bindless.execute(|cmd| {
+		let output_image = output_image.access::<TransferRead>(cmd)?;
+		let download = download.access::<TransferWrite>(cmd)?;
+		unsafe { cmd.copy_image_to_buffer(&output_image, &download)? };
+		Ok(download.transition::<HostAccess>()?.into_desc())
+	})?;
+	let texels = pollster::block_on(download.mapped())?.read_iter().collect::<Vec<_>>();
+	format.write(&args.output, size, &texels)?;
+	log::info!("written {:?}", args.output);
+	Ok(())
+}
+
+fn init_bindless() -> anyhow::Result<Bindless> {
+	let create_info = ash_init_single_graphics_queue_with_push_next(
+		AshSingleGraphicsQueueCreateInfo {
+			extensions: &[ash::ext::mesh_shader::NAME],
+			shader_stages: ShaderStageFlags::ALL_GRAPHICS | ShaderStageFlags::COMPUTE | ShaderStageFlags::MESH_EXT,
+			debug: Debuggers::None,
+			..AshSingleGraphicsQueueCreateInfo::default()
+		},
+		Some(&mut PhysicalDeviceMeshShaderFeaturesEXT::default().mesh_shader(true)),
+	)
+	.context(
+		"Failed to create a Vulkan device supporting VK_EXT_mesh_shader. Without such a GPU, Mesa's lavapipe \
+		 supports it since Mesa 24.1",
+	)?;
+	Ok(unsafe {
+		BindlessInstance::new(
+			create_info,
+			DescriptorCounts {
+				buffers: 100_000,
+				..DescriptorCounts::REASONABLE_DEFAULTS
+			},
+		)
+	})
+}
+
+async fn load_scene(bindless: &Bindless, scene: &str) -> anyhow::Result<InstancedMeshletSceneCpu> {
+	profiling::function_scope!();
+	let path = match sample_scenes().into_iter().find(|file| file.name() == scene) {
+		Some(file) => file.absolute_path()?,
+		None => PathBuf::from(scene),
+	};
+	log::info!("loading scene {:?}", path);
+	let file = fs::File::open(&path).with_context(|| format!("Failed to open scene {:?}", path))?;
+	let reader = MeshletSceneReader::new(file)?;
+	let uploader = Uploader::new(bindless.clone());
+	let cpu = upload_scene(&reader, &uploader).await?;
+	cpu.instantiate(bindless, UVec3::ONE)
+}
@@ -0,0 +1,311 @@
+use anyhow::anyhow;
+use ash::vk::ColorSpaceKHR;
+use glam::UVec2;
+use image::{ExtendedColorType, ImageFormat};
+use rust_gpu_bindless::descriptor::Format;
+use std::fs;
+use std::path::Path;
+
+/// The file format of an image written by [`crate::headless`], selected by the file extension
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum OutputFormat {
+	/// the tonemapped SDR image, as shown on an SDR swapchain
+	Png,
+	/// The tonemapped image in linear scRGB, as shown on an scRGB swapchain, with 1.0 being SDR white and highlights
+	/// exceeding it up to the peak brightness of the tonemapper.
+	///
+	/// The values are display-referred: exposure and the tonemap operator have already been applied, so they are not
+	/// the scene-referred radiance of the HDR render target and are unsuitable for relighting or re-exposing the image.
+	Exr,
+}
+
+impl OutputFormat {
+	pub fn from_path(path: &Path) -> anyhow::Result<Self> {
+		match path.extension().and_then(|ext| ext.to_str()) {
+			Some(ext) if ext.eq_ignore_ascii_case("png") => Ok(Self::Png),
+			Some(ext) if ext.eq_ignore_ascii_case("exr") => Ok(Self::Exr),
+			_ => Err(anyhow!("Output {:?} is neither a .png nor an .exr file", path)),
+		}
+	}
+
+	/// The format of the image rendered into
+	pub fn image_format(self) -> Format {
+		match self {
+			OutputFormat::Png => Format::R8G8B8A8_UNORM,
+			OutputFormat::Exr => Format::R32G32B32A32_SFLOAT,
+		}
+	}
+
+	/// The color space the image is tonemapped for, as if it were a swapchain
+	pub fn colorspace(self) -> ColorSpaceKHR {
+		match self {
+			OutputFormat::Png => ColorSpaceKHR::SRGB_NONLINEAR,
+			OutputFormat::Exr => ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+		}
+	}
+
+	/// The size of a texel of [`Self::image_format`] in 32-bit words
+	pub fn words_per_texel(self) -> usize {
+		match self {
+			OutputFormat::Png => 1,
+			OutputFormat::Exr => 4,
+		}
+	}
+
+	/// Writes the texels of an image of `size`, as read back from an image of [`Self::image_format`], to `path`
+	pub fn write(self, path: &Path, size: UVec2, texels: &[u32]) -> anyhow::Result<()> {
+		assert_eq!(texels.len(), (size.x * size.y) as usize * self.words_per_texel());
+		match self {
+			OutputFormat::Png => {
+				let bytes = texels.iter().flat_map(|texel| texel.to_ne_bytes()).collect::<Vec<_>>();
+				image::save_buffer_with_format(
+					path,
+					&bytes,
+					size.x,
+					size.y,
+					ExtendedColorType::Rgba8,
+					ImageFormat::Png,
+				)?;
+			}
+			OutputFormat::Exr => fs::write(path, encode_exr(size, texels))?,
+		}
+		Ok(())
+	}
+}
+
+const EXR_MAGIC: u32 = 20000630;
+/// single-part scanline image
+const EXR_VERSION: u32 = 2;
+const EXR_PIXEL_TYPE_FLOAT: i32 = 2;
+const EXR_NO_COMPRESSION: u8 = 0;
+const EXR_INCREASING_Y: u8 = 0;
+
+/// Encodes the RGB channels of RGBA `f32` texels as an uncompressed OpenEXR image. A scanline image without compression
+/// is simple enough to not warrant pulling in an EXR crate.
+fn encode_exr(size: UVec2, texels: &[u32]) -> Vec<u8> {
+	fn attribute(out: &mut Vec<u8>, name: &str, ty: &str, value: &[u8]) {
+		for str in [name, ty] {
+			out.extend_from_slice(str.as_bytes());
+			out.push(0);
+		}
+		out.extend_from_slice(&(value.len() as i32).to_le_bytes());
+		out.extend_from_slice(value);
+	}
+
+	// channels must be sorted by name, the index is the channel within the RGBA texel
+	const CHANNELS: [(&str, usize); 3] = [("B", 2), ("G", 1), ("R", 0)];
+	let mut channels = Vec::new();
+	for (name, _) in CHANNELS {
+		channels.extend_from_slice(name.as_bytes());
+		channels.push(0);
+		channels.extend_from_slice(&EXR_PIXEL_TYPE_FLOAT.to_le_bytes());
+		// pLinear and reserved
+		channels.extend_from_slice(&[0; 4]);
+		// x and y sampling
+		channels.extend_from_slice(&1i32.to_le_bytes());
+		channels.extend_from_slice(&1i32.to_le_bytes());
+	}
+	channels.push(0);
+	let window = [0, 0, size.x as i32 - 1, size.y as i32 - 1]
+		.into_iter()
+		.flat_map(i32::to_le_bytes)
+		.collect::<Vec<_>>();
+
+	let mut out = Vec::new();
+	out.extend_from_slice(&EXR_MAGIC.to_le_bytes());
+	out.extend_from_slice(&EXR_VERSION.to_le_bytes());
+	attribute(&mut out, "channels", "chlist", &channels);
+	attribute(&mut out, "compression", "compression", &[EXR_NO_COMPRESSION]);
+	attribute(&mut out, "dataWindow", "box2i", &window);
+	attribute(&mut out, "displayWindow", "box2i", &window);
+	attribute(&mut out, "lineOrder", "lineOrder", &[EXR_INCREASING_Y]);
+	attribute(&mut out, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+	attribute(&mut out, "screenWindowCenter", "v2f", &[0; 8]);
+	attribute(&mut out, "screenWindowWidth", "float", &1f32.to_le_bytes());
+	out.push(0);
+
+	// without compression, every scanline is its own block, preceded by a table of their offsets
+	let width = size.x as usize;
+	let block_data_size = width * CHANNELS.len() * size_of::<f32>();
+	let block_size = 2 * size_of::<i32>() + block_data_size;
+	let blocks_start = out.len() + size.y as usize * size_of::<u64>();
+	for y in 0..size.y as usize {
+		out.extend_from_slice(&((blocks_start + y * block_size) as u64).to_le_bytes());
+	}
+	for (y, row) in texels.chunks_exact(width * 4).enumerate() {
+		out.extend_from_slice(&(y as i32).to_le_bytes());
+		out.extend_from_slice(&(block_data_size as i32).to_le_bytes());
+		for (_, channel) in CHANNELS {
+			for texel in row.chunks_exact(4) {
+				// the bits of an f32, whose little endian bytes are what EXR expects
+				out.extend_from_slice(&texel[channel].to_le_bytes());
+			}
+		}
+	}
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_output_format_from_path() {
+		assert_eq!(
+			OutputFormat::from_path(Path::new("out.png")).unwrap(),
+			OutputFormat::Png
+		);
+		assert_eq!(
+			OutputFormat::from_path(Path::new("dir/out.PNG")).unwrap(),
+			OutputFormat::Png
+		);
+		assert_eq!(
+			OutputFormat::from_path(Path::new("out.exr")).unwrap(),
+			OutputFormat::Exr
+		);
+		assert_eq!(
+			OutputFormat::from_path(Path::new("out.Exr")).unwrap(),
+			OutputFormat::Exr
+		);
+		assert!(OutputFormat::from_path(Path::new("out.jpg")).is_err());
+		assert!(OutputFormat::from_path(Path::new("out")).is_err());
+		assert!(OutputFormat::from_path(Path::new("png")).is_err());
+	}
+
+	/// A minimal reader for the subset of OpenEXR [`encode_exr`] writes
+	struct Exr<'a> {
+		attributes: Vec<(&'a str, &'a str, &'a [u8])>,
+		offsets: Vec<u64>,
+		bytes: &'a [u8],
+	}
+
+	impl<'a> Exr<'a> {
+		fn parse(bytes: &'a [u8]) -> Self {
+			fn read_str<'a>(bytes: &'a [u8], pos: &mut usize) -> &'a str {
+				let len = bytes[*pos..].iter().position(|b| *b == 0).unwrap();
+				let str = std::str::from_utf8(&bytes[*pos..*pos + len]).unwrap();
+				*pos += len + 1;
+				str
+			}
+			fn read_u32(bytes: &[u8], pos: &mut usize) -> u32 {
+				let value = u32::from_le_bytes(bytes[*pos..*pos + 4].try_into().unwrap());
+				*pos += 4;
+				value
+			}
+
+			let mut pos = 0;
+			assert_eq!(read_u32(bytes, &mut pos), EXR_MAGIC);
+			assert_eq!(read_u32(bytes, &mut pos), EXR_VERSION);
+			let mut attributes = Vec::new();
+			while bytes[pos] != 0 {
+				let name = read_str(bytes, &mut pos);
+				let ty = read_str(bytes, &mut pos);
+				let len = read_u32(bytes, &mut pos) as usize;
+				attributes.push((name, ty, &bytes[pos..pos + len]));
+				pos += len;
+			}
+			pos += 1;
+
+			let mut exr = Exr {
+				attributes,
+				offsets: Vec::new(),
+				bytes,
+			};
+			let height = exr.data_window()[3] - exr.data_window()[1] + 1;
+			exr.offsets = (0..height)
+				.map(|_| {
+					let offset = u64::from_le_bytes(bytes[pos..pos + 8].try_into().unwrap());
+					pos += 8;
+					offset
+				})
+				.collect();
+			exr
+		}
+
+		fn attribute(&self, name: &str) -> (&'a str, &'a [u8]) {
+			self.attributes
+				.iter()
+				.find(|(n, _, _)| *n == name)
+				.map(|(_, ty, value)| (*ty, *value))
+				.unwrap_or_else(|| panic!("missing attribute {name:?}"))
+		}
+
+		fn data_window(&self) -> [i32; 4] {
+			let (ty, value) = self.attribute("dataWindow");
+			assert_eq!(ty, "box2i");
+			[0, 1, 2, 3].map(|i| i32::from_le_bytes(value[i * 4..i * 4 + 4].try_into().unwrap()))
+		}
+
+		/// the names of all channels, which must all be 32-bit floats
+		fn channels(&self) -> Vec<&'a str> {
+			let (ty, mut value) = self.attribute("channels");
+			assert_eq!(ty, "chlist");
+			let mut channels = Vec::new();
+			while value[0] != 0 {
+				let len = value.iter().position(|b| *b == 0).unwrap();
+				channels.push(std::str::from_utf8(&value[..len]).unwrap());
+				let pixel_type = i32::from_le_bytes(value[len + 1..len + 5].try_into().unwrap());
+				assert_eq!(pixel_type, EXR_PIXEL_TYPE_FLOAT);
+				value = &value[len + 1 + 16..];
+			}
+			channels
+		}
+
+		/// the value of `channel` at `x`, `y`
+		fn texel(&self, channel: usize, x: usize, y: usize) -> f32 {
+			let width = (self.data_window()[2] - self.data_window()[0] + 1) as usize;
+			let mut pos = self.offsets[y] as usize;
+			let read_i32 = |pos: usize| i32::from_le_bytes(self.bytes[pos..pos + 4].try_into().unwrap());
+			assert_eq!(read_i32(pos), y as i32);
+			assert_eq!(
+				read_i32(pos + 4) as usize,
+				width * self.channels().len() * size_of::<f32>()
+			);
+			pos += 8 + (channel * width + x) * size_of::<f32>();
+			f32::from_le_bytes(self.bytes[pos..pos + 4].try_into().unwrap())
+		}
+	}
+
+	#[test]
+	fn test_exr_header() {
+		let size = UVec2::new(3, 2);
+		let texels = vec![0; (size.x * size.y * 4) as usize];
+		let bytes = encode_exr(size, &texels);
+		let exr = Exr::parse(&bytes);
+
+		assert_eq!(exr.channels(), ["B", "G", "R"]);
+		assert_eq!(exr.attribute("compression"), ("compression", &[EXR_NO_COMPRESSION][..]));
+		assert_eq!(exr.data_window(), [0, 0, 2, 1]);
+		assert_eq!(exr.attribute("displayWindow"), exr.attribute("dataWindow"));
+		assert_eq!(exr.attribute("lineOrder"), ("lineOrder", &[EXR_INCREASING_Y][..]));
+		for name in ["pixelAspectRatio", "screenWindowCenter", "screenWindowWidth"] {
+			exr.attribute(name);
+		}
+		// header, offset table and 2 scanlines of 3 texels with 3 channels
+		let block_size = 8 + 3 * 3 * 4;
+		assert_eq!(exr.offsets[1] - exr.offsets[0], block_size as u64);
+		assert_eq!(bytes.len(), exr.offsets[0] as usize + 2 * block_size);
+	}
+
+	#[test]
+	fn test_exr_round_trip() {
+		let size = UVec2::new(4, 3);
+		let colors = (0..size.x * size.y)
+			.map(|i| [i as f32, -(i as f32) * 0.5, 100. + i as f32 * 0.25, 1.])
+			.collect::<Vec<_>>();
+		let texels = colors.iter().flatten().map(|c| c.to_bits()).collect::<Vec<_>>();
+		let bytes = encode_exr(size, &texels);
+		let exr = Exr::parse(&bytes);
+
+		let channels = exr.channels();
+		for y in 0..size.y as usize {
+			for x in 0..size.x as usize {
+				let color = colors[y * size.x as usize + x];
+				for (rgb, name) in ["R", "G", "B"].into_iter().enumerate() {
+					let channel = channels.iter().position(|c| *c == name).unwrap();
+					assert_eq!(exr.texel(channel, x, y), color[rgb], "{name} at {x}, {y}");
+				}
+			}
+		}
+	}
+}
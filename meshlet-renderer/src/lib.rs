@@ -1,11 +1,18 @@
+pub mod ao_selector;
 pub mod app_focus;
+pub mod bloom_selector;
 pub mod debug_settings_selector;
 pub mod delta_time;
 pub mod fps_camera_controller;
 pub mod fps_ui;
+pub mod headless;
+pub mod image_output;
 pub mod lod_selector;
 pub mod main_loop;
 pub mod nanite_error_selector;
 pub mod sample_scenes;
 pub mod scene_selector;
+pub mod shadow_selector;
 pub mod sun_controller;
+pub mod taa_selector;
+pub mod tonemap_selector;
@@ -1,7 +1,9 @@
-use meshlet_renderer::main_loop::main_loop;
+use clap::Parser;
+use meshlet_renderer::main_loop::{MainLoopArgs, main_loop};
 use rust_gpu_bindless_winit::event_loop::event_loop_init;
 
 fn main() {
+	let args = MainLoopArgs::parse();
 	#[cfg(feature = "profile-with-puffin")]
 	let _puffin_server = {
 		profiling::puffin::set_scopes_on(true);
@@ -9,5 +11,5 @@ fn main() {
 		puffin_http::Server::new(&server_addr).unwrap()
 	};
 
-	event_loop_init(|event_loop, input| async { main_loop(event_loop, input).await.unwrap() });
+	event_loop_init(|event_loop, input| async { main_loop(event_loop, input, args).await.unwrap() });
 }
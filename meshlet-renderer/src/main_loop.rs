@@ -1,4 +1,6 @@
+use crate::ao_selector::AoSelector;
 use crate::app_focus::AppFocus;
+use crate::bloom_selector::BloomSelector;
 use crate::debug_settings_selector::DebugSettingsSelector;
 use crate::delta_time::DeltaTimer;
 use crate::fps_camera_controller::FpsCameraController;
@@ -6,8 +8,12 @@ use crate::fps_ui::FpsUi;
 use crate::lod_selector::LodSelector;
 use crate::nanite_error_selector::NaniteErrorSelector;
 use crate::scene_selector::SceneSelector;
+use crate::shadow_selector::ShadowSelector;
 use crate::sun_controller::SunController;
+use crate::taa_selector::TaaSelector;
+use crate::tonemap_selector::TonemapSelector;
 use ash::vk::{PhysicalDeviceMeshShaderFeaturesEXT, ShaderStageFlags};
+use clap::Parser;
 use egui::{Context, Pos2, RichText, Ui};
 use glam::{UVec3, Vec3Swizzles};
 use rust_gpu_bindless::descriptor::{BindlessImageUsage, BindlessInstance, DescriptorCounts, ImageDescExt};
@@ -26,7 +32,6 @@ use space_asset_shader::affine_transform::AffineTransform;
 use space_engine::renderer::renderers::main::RenderPipelineMain;
 use space_engine_shader::renderer::camera::Camera;
 use space_engine_shader::renderer::frame_data::FrameData;
-use std::f32::consts::PI;
 use std::sync::Arc;
 use std::sync::mpsc::Receiver;
 use winit::dpi::PhysicalSize;
@@ -36,14 +41,27 @@ use winit::window::WindowAttributes;
 
 const DEBUGGER: Debuggers = Debuggers::None;
 
+#[derive(Copy, Clone, Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct MainLoopArgs {
+	/// present to an HDR10 or scRGB swapchain if the display supports it, requires the `VK_EXT_swapchain_colorspace`
+	/// instance extension. The UI is not adjusted and may look off on HDR swapchains.
+	#[arg(long)]
+	pub hdr: bool,
+}
+
 /// how many `MeshletInstance`s can be dynamically allocated, 1 << 17 = 131072
 /// about double what bistro needs if all meshlets rendered
-const MESHLET_INSTANCE_CAPACITY: usize = 1 << 19;
+pub const MESHLET_INSTANCE_CAPACITY: usize = 1 << 19;
 
 /// how many `MeshletGroupInstance` can be dynamically allocated
-const MESHLET_GROUP_CAPACITY: usize = 1 << 19;
+pub const MESHLET_GROUP_CAPACITY: usize = 1 << 19;
 
-pub async fn main_loop(event_loop: EventLoopExecutor, inputs: Receiver<Event<()>>) -> anyhow::Result<()> {
+pub async fn main_loop(
+	event_loop: EventLoopExecutor,
+	inputs: Receiver<Event<()>>,
+	args: MainLoopArgs,
+) -> anyhow::Result<()> {
 	rayon::ThreadPoolBuilder::new()
 		.thread_name(|i| format!("rayon worker {i}"))
 		.build_global()?;
@@ -67,11 +85,16 @@ pub async fn main_loop(event_loop: EventLoopExecutor, inputs: Receiver<Event<()>
 		})
 		.await?;
 
+	let instance_extensions = window_extensions
+		.iter()
+		.copied()
+		.chain(args.hdr.then_some(ash::ext::swapchain_colorspace::NAME))
+		.collect::<Vec<_>>();
 	let bindless = unsafe {
 		BindlessInstance::new(
 			ash_init_single_graphics_queue_with_push_next(
 				AshSingleGraphicsQueueCreateInfo {
-					instance_extensions: window_extensions,
+					instance_extensions: &instance_extensions,
 					extensions: &[ash::khr::swapchain::NAME, ash::ext::mesh_shader::NAME],
 					shader_stages: ShaderStageFlags::ALL_GRAPHICS
 						| ShaderStageFlags::COMPUTE
@@ -95,7 +118,11 @@ pub async fn main_loop(event_loop: EventLoopExecutor, inputs: Receiver<Event<()>
 				&bindless2,
 				surface,
 				BindlessImageUsage::STORAGE | BindlessImageUsage::COLOR_ATTACHMENT,
-				SwapchainImageFormatPreference::UNORM,
+				if args.hdr {
+					SwapchainImageFormatPreference::HDR
+				} else {
+					SwapchainImageFormatPreference::UNORM
+				},
 			)
 		})
 	}
@@ -132,6 +159,12 @@ pub async fn main_loop(event_loop: EventLoopExecutor, inputs: Receiver<Event<()>
 	let mut app_focus = AppFocus::new(event_loop.clone(), window.clone());
 	let mut last_frame = DeltaTimer::default();
 	let mut sun_controller = SunController::new();
+	let mut shadow_selector = ShadowSelector::new();
+	let mut ao_selector = AoSelector::new();
+	let mut taa_selector = TaaSelector::new();
+	let mut bloom_selector = BloomSelector::new();
+	let mut tonemap_selector = TonemapSelector::new(swapchain.params().colorspace);
+	let mut prev_camera = None;
 	let mut fps_ui = FpsUi::new();
 	'outer: loop {
 		profiling::finish_frame!();
@@ -165,24 +198,33 @@ pub async fn main_loop(event_loop: EventLoopExecutor, inputs: Receiver<Event<()>
 			fps_ui.update(delta_time);
 
 			let out_extent = UVec3::from(output_image.extent()).xy();
-			let fov_y = 90.;
-			let camera = Camera::new_perspective_rh_y_flip(
+			let transform = AffineTransform::new(camera_controls.update(delta_time));
+			let camera = Camera::new_rh_y_flip(
 				out_extent,
-				fov_y / 360. * 2. * PI,
+				camera_controls.projection(out_extent.x as f32 / out_extent.y as f32),
 				0.01,
 				1000.,
-				AffineTransform::new(camera_controls.update(delta_time)),
+				transform,
+				taa_selector.next_jitter(),
 			);
+			let prev_camera = prev_camera.replace(camera).unwrap_or(camera);
 
 			let (sun, ambient_light) = sun_controller.eval_sun(delta_time);
 			FrameData {
 				camera,
+				prev_camera,
 				debug_settings: debug_settings_selector.debug_settings.into(),
 				debug_mix: debug_settings_selector.debug_mix_adjusted(),
 				debug_lod_level: lod_selector.lod_selection(),
 				sun,
+				shadow: shadow_selector.shadow,
 				ambient_light,
+				ibl: sun_controller.ibl,
+				ao: ao_selector.ao,
 				nanite: nanite_error_selector.nanite,
+				taa: taa_selector.taa,
+				bloom: bloom_selector.bloom,
+				tonemap: tonemap_selector.tonemap(delta_time),
 			}
 		};
 
@@ -204,6 +246,16 @@ pub async fn main_loop(event_loop: EventLoopExecutor, inputs: Receiver<Event<()>
 					ui.add_space(space);
 					sun_controller.ui(ui);
 					ui.add_space(space);
+					shadow_selector.ui(ui);
+					ui.add_space(space);
+					ao_selector.ui(ui);
+					ui.add_space(space);
+					taa_selector.ui(ui);
+					ui.add_space(space);
+					bloom_selector.ui(ui);
+					ui.add_space(space);
+					tonemap_selector.ui(ui);
+					ui.add_space(space);
 				});
 			fps_ui.ui(ctx);
 		})?;
@@ -248,7 +300,11 @@ fn controls_ui(ui: &mut Ui) {
 				ui.end_row();
 
 				ui.label("Scroll wheel");
-				ui.label("Adjust camera speed");
+				ui.label("Adjust camera speed, or zoom if orthographic");
+				ui.end_row();
+
+				ui.label("P");
+				ui.label("Switch camera projection");
 				ui.end_row();
 
 				ui.label("Home");
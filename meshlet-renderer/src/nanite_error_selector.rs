@@ -4,12 +4,17 @@ use std::ops::RangeInclusive;
 
 pub struct NaniteErrorSelector {
 	pub nanite: NaniteSettings,
+	frustum_culling: bool,
+	occlusion_culling: bool,
+	backface_culling: bool,
+	visibility_buffer: bool,
+	software_rasterization: bool,
 }
 
 impl Default for NaniteErrorSelector {
-    fn default() -> Self {
-        Self::new()
-    }
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 impl NaniteErrorSelector {
@@ -18,7 +23,18 @@ impl NaniteErrorSelector {
 			nanite: NaniteSettings {
 				error_threshold: 1.0,
 				bounding_sphere_scale: 1.0,
+				frustum_culling: 1,
+				occlusion_culling: 1,
+				backface_culling: 1,
+				visibility_buffer: 0,
+				software_rasterization: 1,
+				software_raster_threshold: 2.0,
 			},
+			frustum_culling: true,
+			occlusion_culling: true,
+			backface_culling: true,
+			visibility_buffer: false,
+			software_rasterization: true,
 		}
 	}
 
@@ -32,6 +48,24 @@ impl NaniteErrorSelector {
 			ui,
 		);
 		ui.label("bounding sphere scale <1.0 can cause holes in models");
+		ui.checkbox(&mut self.frustum_culling, "frustum culling");
+		self.nanite.frustum_culling = self.frustum_culling as u32;
+		ui.checkbox(&mut self.occlusion_culling, "occlusion culling");
+		self.nanite.occlusion_culling = self.occlusion_culling as u32;
+		ui.checkbox(&mut self.backface_culling, "backface culling");
+		self.nanite.backface_culling = self.backface_culling as u32;
+		ui.checkbox(&mut self.visibility_buffer, "visibility buffer");
+		self.nanite.visibility_buffer = self.visibility_buffer as u32;
+		ui.add_enabled_ui(self.visibility_buffer, |ui| {
+			ui.checkbox(&mut self.software_rasterization, "software rasterization");
+			slider_with_buttons(
+				&mut self.nanite.software_raster_threshold,
+				0.1..=10.,
+				"software raster triangle size",
+				ui,
+			);
+		});
+		self.nanite.software_rasterization = self.software_rasterization as u32;
 	}
 }
 
@@ -119,8 +119,8 @@ impl<'a> SceneSelector<'a> {
 
 async fn load_scene(bindless: &Bindless, scene_file: MeshletSceneFile<'_>) -> anyhow::Result<Arc<MeshletSceneCpu>> {
 	profiling::function_scope!();
-	let scene = scene_file.load()?;
+	let scene = scene_file.open()?;
 	let uploader = Uploader::new(bindless.clone());
-	let cpu = upload_scene(scene.root(), &uploader).await?;
+	let cpu = upload_scene(&scene, &uploader).await?;
 	Ok(Arc::new(cpu))
 }
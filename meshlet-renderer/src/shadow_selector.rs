@@ -0,0 +1,66 @@
+use egui::{Slider, SliderClamping, Ui, Widget};
+use space_engine_shader::renderer::lighting::shadow::{MAX_SHADOW_CASCADES, ShadowSettings};
+
+const RESOLUTIONS: [u32; 4] = [512, 1024, 2048, 4096];
+
+pub struct ShadowSelector {
+	pub shadow: ShadowSettings,
+	enabled: bool,
+}
+
+impl Default for ShadowSelector {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl ShadowSelector {
+	pub fn new() -> Self {
+		Self {
+			shadow: ShadowSettings {
+				enabled: 1,
+				cascade_count: 4,
+				resolution: 2048,
+				max_distance: 100.,
+				depth_bias: 0.05,
+				normal_bias: 1.5,
+			},
+			enabled: true,
+		}
+	}
+
+	pub fn ui(&mut self, ui: &mut Ui) {
+		ui.strong("Shadows:");
+		ui.checkbox(&mut self.enabled, "sun shadows");
+		self.shadow.enabled = self.enabled as u32;
+		ui.add_enabled_ui(self.enabled, |ui| {
+			Slider::new(&mut self.shadow.cascade_count, 1..=MAX_SHADOW_CASCADES)
+				.text("cascades")
+				.ui(ui);
+			egui::ComboBox::from_id_salt(concat!(file!(), line!()))
+				.selected_text(format!("{0}x{0}", self.shadow.resolution))
+				.show_ui(ui, |ui| {
+					for resolution in RESOLUTIONS {
+						ui.selectable_value(
+							&mut self.shadow.resolution,
+							resolution,
+							format!("{resolution}x{resolution}"),
+						);
+					}
+				});
+			Slider::new(&mut self.shadow.max_distance, 10. ..=1000.)
+				.logarithmic(true)
+				.clamping(SliderClamping::Never)
+				.text("distance")
+				.ui(ui);
+			Slider::new(&mut self.shadow.depth_bias, 0. ..=1.)
+				.logarithmic(true)
+				.text("depth bias")
+				.ui(ui);
+			Slider::new(&mut self.shadow.normal_bias, 0. ..=5.)
+				.text("normal bias")
+				.suffix(" texels")
+				.ui(ui);
+		});
+	}
+}
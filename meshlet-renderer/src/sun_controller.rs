@@ -3,6 +3,7 @@ use egui::{Slider, SliderClamping, Ui, Widget};
 use glam::{Mat3, Vec3, vec3};
 use space_engine_shader::material::light::DirectionalLight;
 use space_engine_shader::material::radiance::Radiance;
+use space_engine_shader::renderer::lighting::ibl::IblSettings;
 use space_engine_shader::renderer::lighting::sky_shader::preetham_sky;
 use space_engine_shader::utils::animated_segments::{AnimatedSegment, Segment};
 use std::f32::consts::PI;
@@ -32,6 +33,8 @@ pub struct SunController {
 	pub position: f32,
 	pub period: f32,
 	pub inclination_degree: f32,
+	pub ibl: IblSettings,
+	ibl_enabled: bool,
 }
 
 impl Default for SunController {
@@ -48,6 +51,11 @@ impl SunController {
 			position: 0.,
 			period: SUN_PERIOD_DEFAULT,
 			inclination_degree: SUN_INCLINATION_DEGREE_DEFAULT,
+			ibl: IblSettings {
+				enabled: 1,
+				intensity: 1.,
+			},
+			ibl_enabled: true,
 		}
 	}
 
@@ -102,5 +110,12 @@ impl SunController {
 			self.period = SUN_PERIOD_DEFAULT;
 			self.inclination_degree = SUN_INCLINATION_DEGREE_DEFAULT;
 		}
+		ui.checkbox(&mut self.ibl_enabled, "sky ambient light (IBL)");
+		self.ibl.enabled = self.ibl_enabled as u32;
+		ui.add_enabled_ui(self.ibl_enabled, |ui| {
+			Slider::new(&mut self.ibl.intensity, 0. ..=4.)
+				.text("IBL intensity")
+				.ui(ui);
+		});
 	}
 }
@@ -0,0 +1,49 @@
+use egui::{Slider, Ui, Widget};
+use glam::Vec2;
+use space_engine_shader::renderer::taa::{TaaSettings, taa_jitter};
+
+pub struct TaaSelector {
+	pub taa: TaaSettings,
+	enabled: bool,
+	frame: u32,
+}
+
+impl Default for TaaSelector {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl TaaSelector {
+	pub fn new() -> Self {
+		Self {
+			taa: TaaSettings {
+				enabled: 1,
+				history_weight: 0.9,
+			},
+			enabled: true,
+			frame: 0,
+		}
+	}
+
+	/// The jitter of the next frame's camera in pixels, zero if TAA is disabled
+	pub fn next_jitter(&mut self) -> Vec2 {
+		if self.enabled {
+			self.frame = self.frame.wrapping_add(1);
+			taa_jitter(self.frame)
+		} else {
+			Vec2::ZERO
+		}
+	}
+
+	pub fn ui(&mut self, ui: &mut Ui) {
+		ui.strong("Anti-Aliasing:");
+		ui.checkbox(&mut self.enabled, "temporal anti-aliasing");
+		self.taa.enabled = self.enabled as u32;
+		ui.add_enabled_ui(self.enabled, |ui| {
+			Slider::new(&mut self.taa.history_weight, 0. ..=0.98)
+				.text("history weight")
+				.ui(ui);
+		});
+	}
+}
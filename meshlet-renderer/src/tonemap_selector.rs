@@ -0,0 +1,85 @@
+use crate::delta_time::DeltaTime;
+use ash::vk::ColorSpaceKHR;
+use egui::{Slider, Ui, Widget};
+use space_engine_shader::renderer::tonemap::{TonemapOperator, TonemapOutput, TonemapSettings};
+
+pub struct TonemapSelector {
+	operator: TonemapOperator,
+	auto_exposure: bool,
+	exposure_ev: f32,
+	adaptation_speed: f32,
+	output: TonemapOutput,
+	paper_white_nits: f32,
+	peak_nits: f32,
+}
+
+impl TonemapSelector {
+	/// Tonemaps for the color space the swapchain negotiated
+	pub fn new(colorspace: ColorSpaceKHR) -> Self {
+		Self {
+			operator: TonemapOperator::AgX,
+			auto_exposure: true,
+			exposure_ev: 0.,
+			adaptation_speed: 1.5,
+			output: match colorspace {
+				ColorSpaceKHR::HDR10_ST2084_EXT => TonemapOutput::Hdr10,
+				ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT => TonemapOutput::ScRgb,
+				_ => TonemapOutput::Sdr,
+			},
+			paper_white_nits: 200.,
+			peak_nits: 1000.,
+		}
+	}
+
+	pub fn tonemap(&self, delta_time: DeltaTime) -> TonemapSettings {
+		TonemapSettings {
+			operator: self.operator.into(),
+			auto_exposure: self.auto_exposure as u32,
+			exposure_ev: self.exposure_ev,
+			adaptation_speed: self.adaptation_speed,
+			delta_time: *delta_time,
+			output: self.output.into(),
+			paper_white_nits: self.paper_white_nits,
+			peak_nits: self.peak_nits,
+		}
+	}
+
+	pub fn ui(&mut self, ui: &mut Ui) {
+		ui.strong("Tonemapping:");
+		egui::ComboBox::from_id_salt(concat!(file!(), line!()))
+			.selected_text(format!("{:?}", self.operator))
+			.show_ui(ui, |ui| {
+				for x in (0..TonemapOperator::LEN).map(|i| TonemapOperator::try_from(i).unwrap()) {
+					ui.selectable_value(&mut self.operator, x, format!("{:?}", x));
+				}
+			});
+		ui.checkbox(&mut self.auto_exposure, "auto exposure");
+		let exposure_text = if self.auto_exposure {
+			"exposure compensation"
+		} else {
+			"exposure"
+		};
+		Slider::new(&mut self.exposure_ev, -8. ..=8.)
+			.suffix(" EV")
+			.text(exposure_text)
+			.ui(ui);
+		ui.add_enabled_ui(self.auto_exposure, |ui| {
+			Slider::new(&mut self.adaptation_speed, 0.1..=10.)
+				.logarithmic(true)
+				.text("adaptation speed")
+				.ui(ui);
+		});
+		ui.label(format!("output: {:?}", self.output));
+		ui.add_enabled_ui(self.output.is_hdr(), |ui| {
+			Slider::new(&mut self.paper_white_nits, 80. ..=500.)
+				.suffix(" nits")
+				.text("paper white")
+				.ui(ui);
+			Slider::new(&mut self.peak_nits, 400. ..=4000.)
+				.logarithmic(true)
+				.suffix(" nits")
+				.text("peak brightness")
+				.ui(ui);
+		});
+	}
+}
@@ -1,5 +1,8 @@
 use anyhow::Context;
-use space_asset_preprocess::meshlet::build_script::{build_script, out_and_export_dir};
+use space_asset_preprocess::meshlet::build_script::{
+	bake_warnings, build_script, ensure_all_baked, out_and_export_dir,
+};
+use space_asset_preprocess::settings::PreprocessSettings;
 use std::env;
 use std::path::Path;
 
@@ -21,11 +24,15 @@ fn main() -> anyhow::Result<()> {
 
 fn inner_main() -> anyhow::Result<()> {
 	let (out_dir, export_dir) = out_and_export_dir().context("Failed to find export path")?;
-	build_script(
+	let models = build_script(
 		Path::new(MODELS_DIR),
 		&export_dir,
 		Some(&out_dir.join("models.rs")),
 		true,
+		&PreprocessSettings::default(),
 	)?;
-	Ok(())
+	for warning in bake_warnings(&models) {
+		println!("cargo:warning={warning}");
+	}
+	ensure_all_baked(&models)
 }
@@ -224,10 +224,32 @@ pub fn ash_init_single_graphics_queue_with_push_next(
 		};
 
 		let physical_device = {
-			instance
-				.enumerate_physical_devices()
-				.unwrap()
-				.into_iter()
+			let physical_devices = instance.enumerate_physical_devices()?;
+			if physical_devices.is_empty() {
+				return Err(anyhow!("No physical devices available"));
+			}
+			// devices lacking any of the requested extensions would fail creating the device, so skip them
+			let missing_extensions = physical_devices
+				.iter()
+				.map(|phy| {
+					let supported = instance.enumerate_device_extension_properties(*phy)?;
+					Ok(create_info
+						.extensions
+						.iter()
+						.copied()
+						.filter(|ext| {
+							!supported
+								.iter()
+								.any(|prop| prop.extension_name_as_c_str().is_ok_and(|name| name == *ext))
+						})
+						.collect::<SmallVec<[_; 2]>>())
+				})
+				.collect::<anyhow::Result<Vec<_>>>()?;
+			physical_devices
+				.iter()
+				.zip(&missing_extensions)
+				.filter(|(_, missing)| missing.is_empty())
+				.map(|(phy, _)| *phy)
 				.min_by_key(|phy| match instance.get_physical_device_properties(*phy).device_type {
 					PhysicalDeviceType::DISCRETE_GPU => 1,
 					PhysicalDeviceType::VIRTUAL_GPU => 2,
@@ -235,7 +257,24 @@ pub fn ash_init_single_graphics_queue_with_push_next(
 					PhysicalDeviceType::CPU => 4,
 					_ => 5,
 				})
-				.ok_or(anyhow!("No physical devices available"))?
+				.ok_or_else(|| {
+					let devices = physical_devices
+						.iter()
+						.zip(&missing_extensions)
+						.map(|(phy, missing)| {
+							let properties = instance.get_physical_device_properties(*phy);
+							format!(
+								"{:?} lacks {:?}",
+								properties.device_name_as_c_str().unwrap_or(c"?"),
+								missing
+							)
+						})
+						.collect::<Vec<_>>();
+					anyhow!(
+						"No physical device supports all required extensions: {}",
+						devices.join(", ")
+					)
+				})?
 		};
 
 		let queue_family_index = {
@@ -54,12 +54,15 @@ pub struct AshSwapchainParams {
 pub enum SwapchainImageFormatPreference {
 	UNORM,
 	SRGB,
+	/// HDR10 or scRGB if the surface supports them, otherwise the same as [`Self::UNORM`]. Requires the
+	/// `VK_EXT_swapchain_colorspace` instance extension for the surface to report HDR formats.
+	HDR,
 }
 
 impl SwapchainImageFormatPreference {
 	pub fn value_format(&self, format: Format) -> i32 {
 		match self {
-			SwapchainImageFormatPreference::UNORM => match format {
+			SwapchainImageFormatPreference::UNORM | SwapchainImageFormatPreference::HDR => match format {
 				Format::R8G8B8A8_UNORM => 50,
 				Format::B8G8R8A8_UNORM => 40,
 				Format::R8G8B8A8_SRGB => 30,
@@ -75,6 +78,20 @@ impl SwapchainImageFormatPreference {
 			},
 		}
 	}
+
+	/// The value of a surface format, `None` if its color space is not accepted
+	pub fn value_surface_format(&self, format: Format, colorspace: ColorSpaceKHR) -> Option<i32> {
+		match (self, colorspace) {
+			(_, ColorSpaceKHR::SRGB_NONLINEAR) => Some(self.value_format(format)),
+			(SwapchainImageFormatPreference::HDR, ColorSpaceKHR::HDR10_ST2084_EXT) => {
+				(format == Format::A2B10G10R10_UNORM_PACK32).then_some(70)
+			}
+			(SwapchainImageFormatPreference::HDR, ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT) => {
+				(format == Format::R16G16B16A16_SFLOAT).then_some(60)
+			}
+			_ => None,
+		}
+	}
 }
 
 impl AshSwapchainParams {
@@ -108,10 +125,13 @@ impl AshSwapchainParams {
 			let (format, colorspace) = surface_ext
 				.get_physical_device_surface_formats(phy, *surface)?
 				.into_iter()
-				.map(|e| (e.format, e.color_space))
-				.filter(|(_, c)| *c == ColorSpaceKHR::SRGB_NONLINEAR)
-				.max_by_key(|(format, _)| format_preference.value_format(*format))
-				.context("No SRGB_NONLINEAR surface format available")?;
+				.filter_map(|e| {
+					let value = format_preference.value_surface_format(e.format, e.color_space)?;
+					Some(((e.format, e.color_space), value))
+				})
+				.max_by_key(|(_, value)| *value)
+				.map(|(format, _)| format)
+				.with_context(|| format!("No surface format available matching {format_preference:?}"))?;
 
 			let present_mode = surface_ext
 				.get_physical_device_surface_present_modes(phy, *surface)?
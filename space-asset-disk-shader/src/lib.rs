@@ -2,6 +2,7 @@
 // otherwise you won't see any warnings
 #![cfg_attr(target_arch = "spirv", deny(warnings))]
 
+pub mod light;
 pub mod material;
 pub mod meshlet;
 pub mod range;
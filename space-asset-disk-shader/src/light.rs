@@ -0,0 +1,41 @@
+use glam::Vec3;
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use rust_gpu_bindless_macros::{BufferStructPlain, assert_transfer_size};
+
+/// A point or spot light in world space, as imported from `KHR_lights_punctual`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, BufferStructPlain)]
+#[cfg_attr(feature = "disk", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
+pub struct PunctualLight {
+	pub position: Vec3,
+	/// distance at which the light's influence smoothly falls off to zero
+	pub range: f32,
+	/// unit vector the spot light is pointing towards, unused by point lights
+	pub direction: Vec3,
+	/// see [`Self::kind`]
+	pub kind: u32,
+	/// linear color multiplied by the intensity
+	pub color: Vec3,
+	/// cosine of the angle from the direction within which a spot light is at full intensity
+	pub inner_cone_cos: f32,
+	/// cosine of the angle from the direction beyond which a spot light emits no light
+	pub outer_cone_cos: f32,
+}
+assert_transfer_size!(PunctualLight, 13 * 4);
+
+impl PunctualLight {
+	pub fn kind(&self) -> PunctualLightKind {
+		PunctualLightKind::try_from(self.kind).unwrap_or(PunctualLightKind::Point)
+	}
+}
+
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, TryFromPrimitive, IntoPrimitive)]
+#[cfg_attr(feature = "disk", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
+pub enum PunctualLightKind {
+	/// emits light in all directions
+	#[default]
+	Point,
+	/// emits light in a cone around its direction
+	Spot,
+}
@@ -1,4 +1,5 @@
 use glam::{Vec2, Vec3, Vec4};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
 use rust_gpu_bindless_macros::{BufferStructPlain, assert_transfer_size};
 
 #[repr(C)]
@@ -6,7 +7,79 @@ use rust_gpu_bindless_macros::{BufferStructPlain, assert_transfer_size};
 #[cfg_attr(feature = "disk", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
 pub struct PbrVertex {
 	pub tangent: Vec4,
+	/// `COLOR_0` in linear space, white if absent
+	pub color: Vec4,
 	pub normal: Vec3,
+	/// `TEXCOORD_0`
 	pub tex_coord: Vec2,
+	/// `TEXCOORD_1`
+	pub tex_coord_1: Vec2,
+}
+assert_transfer_size!(PbrVertex, 15 * 4);
+
+/// Selects the texture coordinate set a texture is sampled with and transforms it, see `KHR_texture_transform`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, BufferStructPlain)]
+#[cfg_attr(feature = "disk", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
+pub struct TextureCoordinates {
+	/// first column of the rotation and scale matrix
+	pub x_axis: Vec2,
+	/// second column of the rotation and scale matrix
+	pub y_axis: Vec2,
+	pub offset: Vec2,
+	/// texture coordinate set, either 0 or 1
+	pub set: u32,
+}
+assert_transfer_size!(TextureCoordinates, 7 * 4);
+
+impl TextureCoordinates {
+	/// Untransformed texture coordinates of `set`
+	pub const fn new(set: u32) -> Self {
+		Self {
+			x_axis: Vec2::X,
+			y_axis: Vec2::Y,
+			offset: Vec2::ZERO,
+			set,
+		}
+	}
+
+	/// Texture coordinates of `set` transformed by scaling, then rotating counter-clockwise by `rotation` radians and
+	/// finally offsetting them, as specified by `KHR_texture_transform`.
+	pub fn from_offset_rotation_scale(set: u32, offset: Vec2, rotation: f32, scale: Vec2) -> Self {
+		let [cos, sin] = Vec2::from_angle(rotation).to_array();
+		Self {
+			x_axis: Vec2::new(cos, -sin) * scale.x,
+			y_axis: Vec2::new(sin, cos) * scale.y,
+			offset,
+			set,
+		}
+	}
+
+	pub fn transform(&self, tex_coord: Vec2) -> Vec2 {
+		self.x_axis * tex_coord.x + self.y_axis * tex_coord.y + self.offset
+	}
+
+	/// Transforms a difference of texture coordinates, like a gradient, which ignores the offset
+	pub fn transform_vector(&self, tex_coord_delta: Vec2) -> Vec2 {
+		self.x_axis * tex_coord_delta.x + self.y_axis * tex_coord_delta.y
+	}
+}
+
+impl Default for TextureCoordinates {
+	fn default() -> Self {
+		Self::new(0)
+	}
+}
+
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, TryFromPrimitive, IntoPrimitive)]
+#[cfg_attr(feature = "disk", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
+pub enum AlphaMode {
+	/// alpha is ignored and the surface is fully opaque
+	#[default]
+	Opaque,
+	/// the surface is either fully opaque or fully transparent, depending on whether alpha is at least `alpha_cutoff`
+	Mask,
+	/// alpha blending, currently rendered like [`Self::Mask`] with a minimal cutoff
+	Blend,
 }
-assert_transfer_size!(PbrVertex, 9 * 4);
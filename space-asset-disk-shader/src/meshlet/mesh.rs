@@ -1,5 +1,6 @@
 use crate::meshlet::lod_level_bitmask::LodLevelBitmask;
 use crate::meshlet::offset::MeshletOffset;
+use crate::shape::cone::NormalCone;
 use crate::shape::sphere::Sphere;
 use rust_gpu_bindless_macros::{BufferStructPlain, assert_transfer_size};
 
@@ -14,9 +15,10 @@ pub struct MeshletData {
 	pub error: f32,
 	pub parent_error: f32,
 	pub lod_level_bitmask: LodLevelBitmask,
-	pub _pad: [u32; 1],
+	pub normal_cone: NormalCone,
+	pub _pad: [u32; 2],
 }
-assert_transfer_size!(MeshletData, 16 * 4);
+assert_transfer_size!(MeshletData, 24 * 4);
 
 impl AsRef<MeshletData> for MeshletData {
 	fn as_ref(&self) -> &MeshletData {
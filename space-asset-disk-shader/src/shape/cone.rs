@@ -0,0 +1,41 @@
+use glam::Vec3;
+use rust_gpu_bindless_macros::BufferStructPlain;
+
+/// A cone bounding the normals of all triangles of a meshlet, for backface culling. See `meshopt_computeMeshletBounds`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, BufferStructPlain)]
+#[cfg_attr(feature = "disk", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
+pub struct NormalCone {
+	pub apex: Vec3,
+	pub axis: Vec3,
+	/// `cos(angle)` of the cone, `1.` or more if the normals are too spread out to ever be culled
+	pub cutoff: f32,
+}
+
+impl NormalCone {
+	pub fn new(apex: Vec3, axis: Vec3, cutoff: f32) -> Self {
+		Self { apex, axis, cutoff }
+	}
+
+	/// A cone that is never backfacing
+	pub fn never_culled() -> Self {
+		Self::new(Vec3::ZERO, Vec3::Z, 1.)
+	}
+
+	/// Whether all triangles within this cone face away from the `camera_position`, in the same space as the cone.
+	pub fn is_backfacing(&self, camera_position: Vec3) -> bool {
+		self.is_backfacing_direction((self.apex - camera_position).normalize())
+	}
+
+	/// Whether all triangles within this cone face away from a camera looking along the normalized `view_direction`,
+	/// like an orthographic camera whose rays are all parallel.
+	pub fn is_backfacing_direction(&self, view_direction: Vec3) -> bool {
+		self.cutoff < 1. && Vec3::dot(view_direction, self.axis) >= self.cutoff
+	}
+}
+
+impl Default for NormalCone {
+	fn default() -> Self {
+		Self::never_culled()
+	}
+}
@@ -1 +1,2 @@
+pub mod cone;
 pub mod sphere;
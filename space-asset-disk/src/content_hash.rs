@@ -0,0 +1,42 @@
+use std::hash::Hasher;
+
+/// A stable 64-bit FNV-1a hasher. Unlike [`std::hash::DefaultHasher`], its output is guaranteed not to change between
+/// rust versions or executions, which is required for any hash that is written to disk.
+#[derive(Copy, Clone, Debug)]
+pub struct ContentHasher(u64);
+
+impl ContentHasher {
+	const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+	const PRIME: u64 = 0x100000001b3;
+
+	pub const fn new() -> Self {
+		Self(Self::OFFSET_BASIS)
+	}
+
+	pub fn hash_bytes(bytes: &[u8]) -> u64 {
+		let mut hasher = Self::new();
+		hasher.write(bytes);
+		hasher.finish()
+	}
+}
+
+impl Default for ContentHasher {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Hasher for ContentHasher {
+	fn finish(&self) -> u64 {
+		self.0
+	}
+
+	fn write(&mut self, bytes: &[u8]) {
+		let mut hash = self.0;
+		for b in bytes {
+			hash ^= *b as u64;
+			hash = hash.wrapping_mul(Self::PRIME);
+		}
+		self.0 = hash;
+	}
+}
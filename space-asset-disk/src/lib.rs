@@ -1,3 +1,4 @@
+pub mod content_hash;
 pub mod image;
 pub mod material;
 pub mod meshlet;
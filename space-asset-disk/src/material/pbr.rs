@@ -1,16 +1,32 @@
-use crate::image::{ImageDiskRgLinear, ImageDiskRgbaLinear, ImageDiskRgbaSrgb};
+use crate::image::{ImageDiskRLinear, ImageDiskRgLinear, ImageDiskRgbaLinear, ImageDiskRgbaSrgb};
 use rkyv::{Archive, Deserialize, Serialize};
 
 #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
 pub struct PbrMaterialDisk {
 	pub base_color: Option<ImageDiskRgbaSrgb>,
+	pub base_color_tex_coord: TextureCoordinates,
 	pub base_color_factor: [f32; 4],
 	pub normal: Option<ImageDiskRgLinear>,
+	pub normal_tex_coord: TextureCoordinates,
 	pub normal_scale: f32,
-	pub occlusion_roughness_metallic: Option<ImageDiskRgbaLinear>,
-	pub occlusion_strength: f32,
+	/// roughness in the green and metallic in the blue channel
+	pub metallic_roughness: Option<ImageDiskRgbaLinear>,
+	pub metallic_roughness_tex_coord: TextureCoordinates,
 	pub metallic_factor: f32,
 	pub roughness_factor: f32,
+	/// `None` if there is no occlusion texture or if it is [`Self::occlusion_in_metallic_roughness`]
+	pub occlusion: Option<ImageDiskRLinear>,
+	/// the occlusion is stored in the red channel of [`Self::metallic_roughness`], as in an ORM texture
+	pub occlusion_in_metallic_roughness: bool,
+	pub occlusion_tex_coord: TextureCoordinates,
+	pub occlusion_strength: f32,
+	pub emissive: Option<ImageDiskRgbaSrgb>,
+	pub emissive_tex_coord: TextureCoordinates,
+	/// includes `KHR_materials_emissive_strength`
+	pub emissive_factor: [f32; 3],
+	pub alpha_mode: AlphaMode,
+	pub alpha_cutoff: f32,
+	pub double_sided: bool,
 }
 
 pub use space_asset_disk_shader::material::pbr::*;
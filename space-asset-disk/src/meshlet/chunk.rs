@@ -0,0 +1,239 @@
+use crate::content_hash::ContentHasher;
+use crate::meshlet::header::MeshletSceneLoadError;
+use rkyv::api::high::{HighSerializer, HighValidator};
+use rkyv::bytecheck::CheckBytes;
+use rkyv::rancor::Panic;
+use rkyv::ser::allocator::ArenaHandle;
+use rkyv::util::AlignedVec;
+use rkyv::{Archive, Deserialize, Serialize};
+use std::fs::File;
+use std::io;
+use std::marker::PhantomData;
+
+/// zstd level used for chunks, images are typically already compressed and stored as is.
+pub const CHUNK_ZSTD_LEVEL: i32 = 3;
+
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Archive, Serialize, Deserialize)]
+pub enum ChunkCompression {
+	None,
+	Zstd,
+}
+
+/// Reference to an independently addressable and independently compressed chunk. Each chunk contains a single rkyv
+/// archive.
+#[derive(Copy, Clone, Debug, Archive, Serialize, Deserialize)]
+pub struct ChunkRef {
+	/// offset relative to the start of the chunk data section
+	pub offset: u64,
+	/// length of the chunk as stored
+	pub len: u64,
+	/// length of the rkyv archive after decompression
+	pub uncompressed_len: u64,
+	pub compression: ChunkCompression,
+	/// hash of the chunk as stored
+	pub hash: u64,
+}
+
+/// A chunk that was serialized and compressed, but not yet placed within a file.
+pub struct EncodedChunk {
+	pub bytes: Vec<u8>,
+	pub uncompressed_len: u64,
+	pub compression: ChunkCompression,
+}
+
+impl EncodedChunk {
+	pub fn new<T>(value: &T, compression: ChunkCompression) -> Self
+	where
+		T: for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, Panic>>,
+	{
+		profiling::function_scope!();
+		let archive = rkyv::to_bytes::<Panic>(value).unwrap();
+		let bytes = match compression {
+			ChunkCompression::None => archive.to_vec(),
+			ChunkCompression::Zstd => zstd::bulk::compress(&archive, CHUNK_ZSTD_LEVEL).unwrap(),
+		};
+		Self {
+			bytes,
+			uncompressed_len: archive.len() as u64,
+			compression,
+		}
+	}
+}
+
+/// Appends [`EncodedChunk`]s into a single data section.
+#[derive(Default)]
+pub struct ChunkWriter {
+	data: Vec<u8>,
+}
+
+impl ChunkWriter {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn push(&mut self, chunk: EncodedChunk) -> ChunkRef {
+		let chunk_ref = ChunkRef {
+			offset: self.data.len() as u64,
+			len: chunk.bytes.len() as u64,
+			uncompressed_len: chunk.uncompressed_len,
+			compression: chunk.compression,
+			hash: ContentHasher::hash_bytes(&chunk.bytes),
+		};
+		self.data.extend_from_slice(&chunk.bytes);
+		chunk_ref
+	}
+
+	pub fn into_data(self) -> Vec<u8> {
+		self.data
+	}
+}
+
+/// Something chunks can be read from with positioned reads, like a [`File`] or a byte slice.
+pub trait ChunkSource: Send + Sync {
+	fn source_len(&self) -> io::Result<u64>;
+
+	fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()>;
+}
+
+impl ChunkSource for File {
+	fn source_len(&self) -> io::Result<u64> {
+		Ok(self.metadata()?.len())
+	}
+
+	#[cfg(unix)]
+	fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+		std::os::unix::fs::FileExt::read_exact_at(self, buf, offset)
+	}
+
+	#[cfg(windows)]
+	fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+		while !buf.is_empty() {
+			match std::os::windows::fs::FileExt::seek_read(self, buf, offset) {
+				Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+				Ok(n) => {
+					buf = &mut buf[n..];
+					offset += n as u64;
+				}
+				Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+				Err(err) => return Err(err),
+			}
+		}
+		Ok(())
+	}
+}
+
+impl ChunkSource for [u8] {
+	fn source_len(&self) -> io::Result<u64> {
+		Ok(<[u8]>::len(self) as u64)
+	}
+
+	fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+		let src = usize::try_from(offset)
+			.ok()
+			.and_then(|offset| self.get(offset..offset.checked_add(buf.len())?))
+			.ok_or(io::Error::from(io::ErrorKind::UnexpectedEof))?;
+		buf.copy_from_slice(src);
+		Ok(())
+	}
+}
+
+impl ChunkSource for Vec<u8> {
+	fn source_len(&self) -> io::Result<u64> {
+		self.as_slice().source_len()
+	}
+
+	fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+		self.as_slice().read_exact_at(buf, offset)
+	}
+}
+
+impl<T: ChunkSource + ?Sized> ChunkSource for &T {
+	fn source_len(&self) -> io::Result<u64> {
+		T::source_len(self)
+	}
+
+	fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+		T::read_exact_at(self, buf, offset)
+	}
+}
+
+/// Reads `len` bytes at `offset`, mapping an unexpected EOF to [`MeshletSceneLoadError::Truncated`]. The range is
+/// checked against the length of the `source` before allocating, so corrupt lengths can't exhaust memory.
+pub fn read_bytes_at(
+	source: &(impl ChunkSource + ?Sized),
+	offset: u64,
+	len: u64,
+) -> Result<AlignedVec, MeshletSceneLoadError> {
+	profiling::function_scope!();
+	let end = offset.checked_add(len).ok_or(MeshletSceneLoadError::Truncated)?;
+	if end > source.source_len()? {
+		return Err(MeshletSceneLoadError::Truncated);
+	}
+	let mut vec = AlignedVec::with_capacity(len as usize);
+	vec.resize(len as usize, 0);
+	source.read_exact_at(&mut vec, offset).map_err(|err| match err.kind() {
+		io::ErrorKind::UnexpectedEof => MeshletSceneLoadError::Truncated,
+		_ => MeshletSceneLoadError::IoError(err),
+	})?;
+	Ok(vec)
+}
+
+/// A validated rkyv archive of `T` read from a chunk.
+pub struct ArchivedChunk<T: Archive> {
+	archive: AlignedVec,
+	_phantom: PhantomData<fn() -> T>,
+}
+
+impl<T: Archive> ArchivedChunk<T>
+where
+	T::Archived: for<'a> CheckBytes<HighValidator<'a, rkyv::rancor::Error>>,
+{
+	/// Validates `archive` to contain a `T`.
+	pub fn new(archive: AlignedVec) -> Result<Self, MeshletSceneLoadError> {
+		profiling::function_scope!();
+		rkyv::access::<T::Archived, rkyv::rancor::Error>(&archive).map_err(MeshletSceneLoadError::CorruptArchive)?;
+		Ok(Self {
+			archive,
+			_phantom: PhantomData,
+		})
+	}
+
+	/// Reads, verifies, decompresses and validates the chunk `chunk` from `source`, where `data_offset` is the start of
+	/// the chunk data section.
+	pub fn read(
+		source: &(impl ChunkSource + ?Sized),
+		data_offset: u64,
+		chunk: &ArchivedChunkRef,
+	) -> Result<Self, MeshletSceneLoadError> {
+		profiling::function_scope!();
+		let offset = data_offset
+			.checked_add(chunk.offset.to_native())
+			.ok_or(MeshletSceneLoadError::Truncated)?;
+		let bytes = read_bytes_at(source, offset, chunk.len.to_native())?;
+		if ContentHasher::hash_bytes(&bytes) != chunk.hash.to_native() {
+			return Err(MeshletSceneLoadError::ContentHashMismatch);
+		}
+		let archive = match chunk.compression {
+			ArchivedChunkCompression::None => bytes,
+			ArchivedChunkCompression::Zstd => {
+				profiling::scope!("zstd decompress");
+				let mut archive = AlignedVec::with_capacity(chunk.uncompressed_len.to_native() as usize);
+				archive.resize(chunk.uncompressed_len.to_native() as usize, 0);
+				let len = zstd::bulk::decompress_to_buffer(&bytes, &mut archive)?;
+				if len != archive.len() {
+					return Err(MeshletSceneLoadError::Truncated);
+				}
+				archive
+			}
+		};
+		Self::new(archive)
+	}
+}
+
+impl<T: Archive> ArchivedChunk<T> {
+	pub fn root(&self) -> &T::Archived {
+		// Safety: archive was validated on construction
+		unsafe { rkyv::access_unchecked::<T::Archived>(&self.archive) }
+	}
+}
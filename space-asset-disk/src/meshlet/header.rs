@@ -0,0 +1,139 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+
+/// Magic value every [`MeshletSceneDisk`](crate::meshlet::scene::MeshletSceneDisk) file starts with.
+pub const MESHLET_SCENE_MAGIC: [u8; 8] = *b"SPMSHLT\0";
+
+/// Version of the on-disk format. Must be incremented whenever any archived type changes its layout, so that stale
+/// bakes are rejected instead of being misinterpreted.
+pub const MESHLET_SCENE_FORMAT_VERSION: u32 = 8;
+
+/// Fixed size header at the start of the file, followed by the table of contents and then the chunk data section.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct MeshletSceneHeader {
+	pub magic: [u8; 8],
+	pub version: u32,
+	/// length of the table of contents following the header
+	pub toc_len: u64,
+	/// hash of the table of contents, which in turn contains the hashes of all chunks
+	pub content_hash: u64,
+	/// key identifying what this file was baked from: the source gltf, all files it references, the preprocessor
+	/// settings and the format and pipeline versions. The preprocessor skips rebaking if it's unchanged.
+	pub bake_key: u64,
+	/// length of the entire file
+	pub file_len: u64,
+}
+
+impl MeshletSceneHeader {
+	pub const SIZE: usize = 48;
+
+	pub fn new(toc_len: u64, content_hash: u64, bake_key: u64, file_len: u64) -> Self {
+		Self {
+			magic: MESHLET_SCENE_MAGIC,
+			version: MESHLET_SCENE_FORMAT_VERSION,
+			toc_len,
+			content_hash,
+			bake_key,
+			file_len,
+		}
+	}
+
+	/// offset of the chunk data section
+	pub fn data_offset(&self) -> u64 {
+		Self::SIZE as u64 + self.toc_len
+	}
+
+	pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+		let mut out = [0; Self::SIZE];
+		out[0..8].copy_from_slice(&self.magic);
+		out[8..12].copy_from_slice(&self.version.to_le_bytes());
+		// 12..16 padding
+		out[16..24].copy_from_slice(&self.toc_len.to_le_bytes());
+		out[24..32].copy_from_slice(&self.content_hash.to_le_bytes());
+		out[32..40].copy_from_slice(&self.bake_key.to_le_bytes());
+		out[40..48].copy_from_slice(&self.file_len.to_le_bytes());
+		out
+	}
+
+	/// Parses and validates the header, but not the contents of the file.
+	pub fn from_bytes(bytes: &[u8]) -> Result<Self, MeshletSceneLoadError> {
+		let bytes: &[u8; Self::SIZE] = bytes
+			.get(0..Self::SIZE)
+			.and_then(|b| b.try_into().ok())
+			.ok_or(MeshletSceneLoadError::Truncated)?;
+		let u64_at = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+		let header = Self {
+			magic: bytes[0..8].try_into().unwrap(),
+			version: u32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+			toc_len: u64_at(16),
+			content_hash: u64_at(24),
+			bake_key: u64_at(32),
+			file_len: u64_at(40),
+		};
+		if header.magic != MESHLET_SCENE_MAGIC {
+			return Err(MeshletSceneLoadError::InvalidMagic);
+		}
+		if header.version != MESHLET_SCENE_FORMAT_VERSION {
+			return Err(MeshletSceneLoadError::VersionMismatch {
+				expected: MESHLET_SCENE_FORMAT_VERSION,
+				found: header.version,
+			});
+		}
+		Ok(header)
+	}
+}
+
+#[derive(Debug)]
+pub enum MeshletSceneLoadError {
+	IoError(io::Error),
+	Truncated,
+	InvalidMagic,
+	VersionMismatch {
+		expected: u32,
+		found: u32,
+	},
+	ContentHashMismatch,
+	CorruptArchive(rkyv::rancor::Error),
+	/// a chunk was requested by an index beyond the `len` chunks of its `kind` in the table of contents
+	OutOfBounds {
+		kind: &'static str,
+		index: usize,
+		len: usize,
+	},
+	/// a node of the scene graph has a `parent` that doesn't exist or is one of its own descendants
+	InvalidParent {
+		node: u32,
+		parent: u32,
+	},
+}
+
+impl Display for MeshletSceneLoadError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			MeshletSceneLoadError::IoError(err) => Display::fmt(err, f),
+			MeshletSceneLoadError::Truncated => f.write_str("File is truncated"),
+			MeshletSceneLoadError::InvalidMagic => f.write_str("File is not a meshlet scene"),
+			MeshletSceneLoadError::VersionMismatch { expected, found } => write!(
+				f,
+				"Version mismatch, expected version {expected} but found {found}, the scene needs to be rebaked"
+			),
+			MeshletSceneLoadError::ContentHashMismatch => f.write_str("Content hash mismatch, the file is corrupt"),
+			MeshletSceneLoadError::CorruptArchive(err) => write!(f, "Corrupt archive: {err}"),
+			MeshletSceneLoadError::OutOfBounds { kind, index, len } => {
+				write!(f, "{kind} {index} out of bounds, the scene only contains {len}")
+			}
+			MeshletSceneLoadError::InvalidParent { node, parent } => write!(
+				f,
+				"Scene graph node {node} has parent {parent}, which is out of bounds or its own descendant"
+			),
+		}
+	}
+}
+
+impl std::error::Error for MeshletSceneLoadError {}
+
+impl From<io::Error> for MeshletSceneLoadError {
+	fn from(value: io::Error) -> Self {
+		Self::IoError(value)
+	}
+}
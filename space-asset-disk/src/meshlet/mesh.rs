@@ -1,7 +1,8 @@
 use crate::meshlet::stats::SourceMeshStats;
 use rkyv::{Archive, Deserialize, Serialize};
 use space_asset_disk_shader::material::pbr::PbrVertex;
-use space_asset_disk_shader::meshlet::indices::CompressedIndices;
+use space_asset_disk_shader::meshlet::indices::{CompressedIndices, INDICES_PER_WORD};
+use space_asset_disk_shader::meshlet::offset::MeshletOffset;
 use space_asset_disk_shader::meshlet::vertex::DrawVertex;
 
 #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
@@ -15,3 +16,153 @@ pub struct MeshletMeshDisk {
 }
 
 pub use space_asset_disk_shader::meshlet::mesh::*;
+
+/// All data of a [`MeshletMeshDisk`] shared between its lod ranges.
+#[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+pub struct MeshletMeshHeaderDisk {
+	pub pbr_material_vertices: Vec<PbrVertex>,
+	pub pbr_material_id: Option<u32>,
+	pub stats: SourceMeshStats,
+}
+
+/// A contiguous range of meshlets of a [`MeshletMeshDisk`] generated for the same lod level, with their draw vertices
+/// and triangles. Offsets of the meshlets are relative to this range.
+#[derive(Clone, Debug, Default, Archive, Serialize, Deserialize)]
+pub struct MeshletLodRangeDisk {
+	pub meshlets: Vec<MeshletData>,
+	pub draw_vertices: Vec<DrawVertex>,
+	pub triangles: Vec<CompressedIndices>,
+}
+
+/// Start of a lod range within the buffers of a [`MeshletMeshDisk`]
+#[derive(Copy, Clone, Debug, Default)]
+struct LodRangeStart {
+	meshlet: usize,
+	draw_vertex: usize,
+	triangle_word: usize,
+}
+
+impl MeshletMeshDisk {
+	/// Splits this mesh into its header and ranges of meshlets generated for the same lod level, which can be stored
+	/// and loaded independently. Joining them again with [`Self::from_lod_ranges`] yields an identical mesh.
+	pub fn split_lod_ranges(&self) -> (MeshletMeshHeaderDisk, Vec<MeshletLodRangeDisk>) {
+		profiling::function_scope!();
+		let starts = self.lod_range_starts();
+		let ends = starts
+			.iter()
+			.skip(1)
+			.copied()
+			.chain([LodRangeStart {
+				meshlet: self.meshlets.len(),
+				draw_vertex: self.draw_vertices.len(),
+				triangle_word: self.triangles.len(),
+			}])
+			.collect::<Vec<_>>();
+		let ranges = starts
+			.iter()
+			.zip(ends.iter())
+			.map(|(start, end)| {
+				let triangle_start = start.triangle_word * INDICES_PER_WORD / 3;
+				MeshletLodRangeDisk {
+					meshlets: self.meshlets[start.meshlet..end.meshlet]
+						.iter()
+						.map(|m| MeshletData {
+							draw_vertex_offset: MeshletOffset::new(
+								m.draw_vertex_offset.start() - start.draw_vertex,
+								m.draw_vertex_offset.len(),
+							),
+							triangle_offset: MeshletOffset::new(
+								m.triangle_offset.start() - triangle_start,
+								m.triangle_offset.len(),
+							),
+							..*m
+						})
+						.collect(),
+					draw_vertices: self.draw_vertices[start.draw_vertex..end.draw_vertex].to_vec(),
+					triangles: self.triangles[start.triangle_word..end.triangle_word].to_vec(),
+				}
+			})
+			.collect();
+		let header = MeshletMeshHeaderDisk {
+			pbr_material_vertices: self.pbr_material_vertices.clone(),
+			pbr_material_id: self.pbr_material_id,
+			stats: self.stats,
+		};
+		(header, ranges)
+	}
+
+	/// Joins the header and lod ranges previously split by [`Self::split_lod_ranges`].
+	pub fn from_lod_ranges(
+		header: MeshletMeshHeaderDisk,
+		ranges: impl IntoIterator<Item = MeshletLodRangeDisk>,
+	) -> Self {
+		profiling::function_scope!();
+		let mut out = Self {
+			meshlets: Vec::new(),
+			draw_vertices: Vec::new(),
+			triangles: Vec::new(),
+			pbr_material_vertices: header.pbr_material_vertices,
+			pbr_material_id: header.pbr_material_id,
+			stats: header.stats,
+		};
+		for range in ranges {
+			let draw_vertex_start = out.draw_vertices.len();
+			let triangle_start = out.triangles.len() * INDICES_PER_WORD / 3;
+			out.meshlets.extend(range.meshlets.iter().map(|m| MeshletData {
+				draw_vertex_offset: MeshletOffset::new(
+					draw_vertex_start + m.draw_vertex_offset.start(),
+					m.draw_vertex_offset.len(),
+				),
+				triangle_offset: MeshletOffset::new(
+					triangle_start + m.triangle_offset.start(),
+					m.triangle_offset.len(),
+				),
+				..*m
+			}));
+			out.draw_vertices.extend_from_slice(&range.draw_vertices);
+			out.triangles.extend_from_slice(&range.triangles);
+		}
+		out
+	}
+
+	/// Meshlets of each lod level are appended in order with their own vertices and triangles. Find the start of each
+	/// of these ranges and ensure they don't share any vertices or triangle words with other ranges, otherwise merge
+	/// them with the previous range.
+	fn lod_range_starts(&self) -> Vec<LodRangeStart> {
+		let lod_level = |m: &MeshletData| m.lod_level_bitmask.0.trailing_zeros();
+		let mut starts = vec![LodRangeStart::default()];
+		let mut prev_end = LodRangeStart::default();
+		for (i, m) in self.meshlets.iter().enumerate() {
+			let draw_vertex_start = m.draw_vertex_offset.start();
+			let draw_vertex_end = draw_vertex_start + m.draw_vertex_offset.len();
+			let index_start = m.triangle_offset.start() * 3;
+			let index_end = index_start + m.triangle_offset.len() * 3;
+
+			// a range must start on a word boundary that is also a triangle boundary
+			let is_new_range = i != 0 && lod_level(m) != lod_level(&self.meshlets[i - 1]);
+			if is_new_range
+				&& draw_vertex_start >= prev_end.draw_vertex
+				&& index_start % (3 * INDICES_PER_WORD) == 0
+				&& index_start / INDICES_PER_WORD >= prev_end.triangle_word
+			{
+				starts.push(LodRangeStart {
+					meshlet: i,
+					draw_vertex: draw_vertex_start,
+					triangle_word: index_start / INDICES_PER_WORD,
+				});
+			}
+			// meshlet references data of a previous range, merge with it
+			while starts.len() > 1 {
+				let last = starts.last().unwrap();
+				if draw_vertex_start >= last.draw_vertex && index_start / INDICES_PER_WORD >= last.triangle_word {
+					break;
+				}
+				starts.pop();
+			}
+
+			prev_end.draw_vertex = prev_end.draw_vertex.max(draw_vertex_end);
+			prev_end.triangle_word = prev_end.triangle_word.max(index_end.div_ceil(INDICES_PER_WORD));
+		}
+		starts
+	}
+}
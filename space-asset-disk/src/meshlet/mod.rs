@@ -1,6 +1,9 @@
+pub mod chunk;
+pub mod header;
 pub mod instance;
 pub mod mesh;
 pub mod scene;
+pub mod scene_graph;
 pub mod stats;
 
 pub use space_asset_disk_shader::meshlet::*;
@@ -1,60 +1,154 @@
-use crate::image::ImageStorage;
+use crate::content_hash::ContentHasher;
+use crate::image::{DynImage, DynImageMetadata, ImageStorage};
+use crate::light::PunctualLight;
 use crate::material::pbr::PbrMaterialDisk;
+use crate::meshlet::chunk::{
+	ArchivedChunk, ChunkCompression, ChunkRef, ChunkSource, ChunkWriter, EncodedChunk, read_bytes_at,
+};
+use crate::meshlet::header::{MeshletSceneHeader, MeshletSceneLoadError};
 use crate::meshlet::instance::MeshletInstanceDisk;
-use crate::meshlet::mesh::MeshletMeshDisk;
-use crate::meshlet::stats::MeshletSceneStats;
-use rkyv::api::serialize_using;
-use rkyv::ser::Serializer;
-use rkyv::ser::sharing::Share;
-use rkyv::ser::writer::IoWriter;
-use rkyv::util::{AlignedVec, with_arena};
+use crate::meshlet::mesh::{MeshletLodRangeDisk, MeshletMeshDisk, MeshletMeshHeaderDisk};
+use crate::meshlet::scene_graph::SceneGraphDisk;
+use crate::meshlet::stats::{MeshletSceneStats, SourceMeshStats};
+use rayon::prelude::*;
 use rkyv::{Archive, Deserialize, Serialize};
-use std::io::{BufWriter, Read, Write};
+use std::io::{BufWriter, Write};
 use std::path::PathBuf;
 use std::{fs, io};
 
-#[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+/// A fully loaded scene, as produced by the preprocessor. On disk, it is split into a [`MeshletSceneTocDisk`] and
+/// independent chunks for each mesh, lod range and image.
+#[derive(Clone, Debug)]
 pub struct MeshletSceneDisk {
 	pub image_storage: ImageStorage,
 	pub pbr_materials: Vec<PbrMaterialDisk>,
 	pub meshes: Vec<MeshletMeshDisk>,
 	pub instances: Vec<MeshletInstanceDisk>,
+	pub lights: Vec<PunctualLight>,
+	/// may be omitted if disabled in the preprocessor settings
+	pub scene_graph: Option<SceneGraphDisk>,
 	pub stats: MeshletSceneStats,
 }
 
+/// The table of contents, containing all small data of a scene and references to the chunks of all large data.
+#[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+pub struct MeshletSceneTocDisk {
+	pub images: Vec<ImageChunkDisk>,
+	pub pbr_materials: Vec<PbrMaterialDisk>,
+	pub meshes: Vec<MeshletMeshChunksDisk>,
+	pub instances: Vec<MeshletInstanceDisk>,
+	pub lights: Vec<PunctualLight>,
+	/// may be omitted if disabled in the preprocessor settings
+	pub scene_graph: Option<SceneGraphDisk>,
+	pub stats: MeshletSceneStats,
+}
+
+/// Chunk containing a [`DynImage`]
+#[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+pub struct ImageChunkDisk {
+	pub name: String,
+	pub chunk: ChunkRef,
+}
+
+#[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+pub struct MeshletMeshChunksDisk {
+	/// chunk containing a [`MeshletMeshHeaderDisk`]
+	pub header: ChunkRef,
+	/// chunks containing a [`MeshletLodRangeDisk`] each, from the highest to the lowest detail
+	pub lod_ranges: Vec<ChunkRef>,
+	pub stats: SourceMeshStats,
+}
+
 impl MeshletSceneDisk {
-	pub fn serialize_to(&self, write: impl Write) -> io::Result<()> {
+	/// Serializes this scene prefixed with a [`MeshletSceneHeader`]. The `bake_key` should identify everything this
+	/// scene was baked from, see [`MeshletSceneHeader::bake_key`], so that stale bakes can be detected.
+	pub fn serialize_to(&self, write: impl Write, bake_key: u64) -> io::Result<()> {
 		profiling::function_scope!();
-		with_arena(|arena| {
-			let mut serializer = Serializer::new(
-				IoWriter::new(BufWriter::with_capacity(128 * 1024, write)),
-				arena.acquire(),
-				Share::default(),
-			);
-			serialize_using::<_, rkyv::rancor::Panic>(self, &mut serializer).unwrap();
-		});
+		let images = {
+			profiling::scope!("encode images");
+			self.image_storage
+				.images
+				.par_iter()
+				.map(|(image, _)| EncodedChunk::new(image, image_chunk_compression(image)))
+				.collect::<Vec<_>>()
+		};
+		let meshes = {
+			profiling::scope!("encode meshes");
+			self.meshes
+				.par_iter()
+				.map(|mesh| {
+					let (header, lod_ranges) = mesh.split_lod_ranges();
+					let header = EncodedChunk::new(&header, ChunkCompression::Zstd);
+					let lod_ranges = lod_ranges
+						.iter()
+						.map(|range| EncodedChunk::new(range, ChunkCompression::Zstd))
+						.collect::<Vec<_>>();
+					(header, lod_ranges, mesh.stats)
+				})
+				.collect::<Vec<_>>()
+		};
+
+		let mut writer = ChunkWriter::new();
+		let toc = MeshletSceneTocDisk {
+			images: images
+				.into_iter()
+				.zip(self.image_storage.images.iter())
+				.map(|(chunk, (_, name))| ImageChunkDisk {
+					name: name.clone(),
+					chunk: writer.push(chunk),
+				})
+				.collect(),
+			pbr_materials: self.pbr_materials.clone(),
+			meshes: meshes
+				.into_iter()
+				.map(|(header, lod_ranges, stats)| MeshletMeshChunksDisk {
+					header: writer.push(header),
+					lod_ranges: lod_ranges.into_iter().map(|range| writer.push(range)).collect(),
+					stats,
+				})
+				.collect(),
+			instances: self.instances.clone(),
+			lights: self.lights.clone(),
+			scene_graph: self.scene_graph.clone(),
+			stats: self.stats.clone(),
+		};
+		let data = writer.into_data();
+
+		let toc = rkyv::to_bytes::<rkyv::rancor::Panic>(&toc).unwrap();
+		let content_hash = ContentHasher::hash_bytes(&toc);
+		let file_len = (MeshletSceneHeader::SIZE + toc.len() + data.len()) as u64;
+		let header = MeshletSceneHeader::new(toc.len() as u64, content_hash, bake_key, file_len);
+
+		let mut write = BufWriter::with_capacity(128 * 1024, write);
+		write.write_all(&header.to_bytes())?;
+		write.write_all(&toc)?;
+		write.write_all(&data)?;
+		write.flush()?;
 		Ok(())
 	}
 }
 
+/// Images already compressed by their codec would not benefit from compressing their chunk again.
+fn image_chunk_compression(image: &DynImage) -> ChunkCompression {
+	match image.meta {
+		DynImageMetadata::ZstdBCn(_) | DynImageMetadata::Embedded(_) => ChunkCompression::None,
+		DynImageMetadata::Uncompressed(_) | DynImageMetadata::BCn(_) | DynImageMetadata::SinglePixel(_) => {
+			ChunkCompression::Zstd
+		}
+	}
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct MeshletSceneFile<'a> {
 	name: &'a str,
 	path: &'a str,
 }
 
-pub struct LoadedMeshletScene {
-	archive: AlignedVec,
-}
-
 pub const EXPORT_FOLDER_NAME: &str = "assets";
 
 impl<'a> MeshletSceneFile<'a> {
 	/// Create a new MeshletSceneFile with a path relative to the export directory.
-	///
-	/// # Safety
-	/// File must contain a valid datastream retrieved from [`MeshletSceneDisk::serialize_to`]
-	pub const unsafe fn new(name: &'a str, path: &'a str) -> Self {
+	pub const fn new(name: &'a str, path: &'a str) -> Self {
 		Self { name, path }
 	}
 
@@ -77,40 +171,98 @@ impl<'a> MeshletSceneFile<'a> {
 		Ok(file)
 	}
 
-	pub fn load(&self) -> io::Result<LoadedMeshletScene> {
+	/// Opens the file and reads its table of contents. Chunks are only read on demand.
+	pub fn open(&self) -> Result<MeshletSceneReader<fs::File>, MeshletSceneLoadError> {
 		profiling::function_scope!();
-		let path = self.absolute_path()?;
-		let mut file = fs::File::open(&path)?;
+		MeshletSceneReader::new(fs::File::open(self.absolute_path()?)?)
+	}
+}
 
-		let capacity = path.metadata()?.len() as usize + 1;
-		let mut vec = AlignedVec::with_capacity(capacity);
-		unsafe {
-			vec.set_len(capacity);
-		}
+/// Reads a scene written by [`MeshletSceneDisk::serialize_to`] from a [`ChunkSource`]. Only the header and table of
+/// contents are read upfront, all chunks are read with positioned reads on demand, allowing them to be read
+/// concurrently.
+pub struct MeshletSceneReader<S: ChunkSource> {
+	source: S,
+	header: MeshletSceneHeader,
+	toc: ArchivedChunk<MeshletSceneTocDisk>,
+}
 
-		let mut bytes_read = 0;
-		loop {
-			profiling::scope!("File::read");
-			match file.read(&mut vec[bytes_read..]) {
-				Ok(e) => {
-					bytes_read += e;
-					if e == 0 {
-						break;
-					}
-				}
-				Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
-				Err(err) => Err(err)?,
-			}
+impl<S: ChunkSource> MeshletSceneReader<S> {
+	pub fn new(source: S) -> Result<Self, MeshletSceneLoadError> {
+		profiling::function_scope!();
+		let header = read_bytes_at(&source, 0, MeshletSceneHeader::SIZE as u64)?;
+		let header = MeshletSceneHeader::from_bytes(&header)?;
+		if source.source_len()? != header.file_len {
+			return Err(MeshletSceneLoadError::Truncated);
+		}
+		// the header isn't covered by the content hash
+		if header.toc_len > header.file_len.saturating_sub(MeshletSceneHeader::SIZE as u64) {
+			return Err(MeshletSceneLoadError::Truncated);
 		}
-		unsafe {
-			vec.set_len(bytes_read);
+		let toc = read_bytes_at(&source, MeshletSceneHeader::SIZE as u64, header.toc_len)?;
+		if ContentHasher::hash_bytes(&toc) != header.content_hash {
+			return Err(MeshletSceneLoadError::ContentHashMismatch);
 		}
-		Ok(LoadedMeshletScene { archive: vec })
+		let toc = ArchivedChunk::new(toc)?;
+		Ok(Self { source, header, toc })
+	}
+
+	pub fn header(&self) -> &MeshletSceneHeader {
+		&self.header
+	}
+
+	pub fn toc(&self) -> &ArchivedMeshletSceneTocDisk {
+		self.toc.root()
 	}
-}
 
-impl LoadedMeshletScene {
-	pub fn root(&self) -> &ArchivedMeshletSceneDisk {
-		unsafe { rkyv::access_unchecked::<ArchivedMeshletSceneDisk>(&self.archive) }
+	pub fn read_image(&self, image_id: usize) -> Result<ArchivedChunk<DynImage<'static>>, MeshletSceneLoadError> {
+		let image = get_chunk(self.toc().images.as_slice(), "image", image_id)?;
+		ArchivedChunk::read(&self.source, self.header.data_offset(), &image.chunk)
 	}
+
+	pub fn read_mesh_header(
+		&self,
+		mesh_id: usize,
+	) -> Result<ArchivedChunk<MeshletMeshHeaderDisk>, MeshletSceneLoadError> {
+		let mesh = get_chunk(self.toc().meshes.as_slice(), "mesh", mesh_id)?;
+		ArchivedChunk::read(&self.source, self.header.data_offset(), &mesh.header)
+	}
+
+	pub fn read_mesh_lod_range(
+		&self,
+		mesh_id: usize,
+		lod_range: usize,
+	) -> Result<ArchivedChunk<MeshletLodRangeDisk>, MeshletSceneLoadError> {
+		let mesh = get_chunk(self.toc().meshes.as_slice(), "mesh", mesh_id)?;
+		let lod_range = get_chunk(mesh.lod_ranges.as_slice(), "lod range", lod_range)?;
+		ArchivedChunk::read(&self.source, self.header.data_offset(), lod_range)
+	}
+
+	/// Reads all chunks of a mesh and joins them into a [`MeshletMeshDisk`].
+	pub fn read_mesh(&self, mesh_id: usize) -> Result<MeshletMeshDisk, MeshletSceneLoadError> {
+		profiling::function_scope!();
+		let header = deserialize::<MeshletMeshHeaderDisk>(self.read_mesh_header(mesh_id)?.root())?;
+		let lod_range_cnt = get_chunk(self.toc().meshes.as_slice(), "mesh", mesh_id)?
+			.lod_ranges
+			.len();
+		let lod_ranges = (0..lod_range_cnt)
+			.map(|i| deserialize::<MeshletLodRangeDisk>(self.read_mesh_lod_range(mesh_id, i)?.root()))
+			.collect::<Result<Vec<_>, _>>()?;
+		Ok(MeshletMeshDisk::from_lod_ranges(header, lod_ranges))
+	}
+}
+
+fn get_chunk<'a, T>(chunks: &'a [T], kind: &'static str, index: usize) -> Result<&'a T, MeshletSceneLoadError> {
+	chunks.get(index).ok_or(MeshletSceneLoadError::OutOfBounds {
+		kind,
+		index,
+		len: chunks.len(),
+	})
+}
+
+fn deserialize<T: Archive>(archived: &T::Archived) -> Result<T, MeshletSceneLoadError>
+where
+	T::Archived: Deserialize<T, rkyv::api::high::HighDeserializer<rkyv::rancor::Error>>,
+{
+	rkyv::deserialize::<T, rkyv::rancor::Error>(archived).map_err(MeshletSceneLoadError::CorruptArchive)
 }
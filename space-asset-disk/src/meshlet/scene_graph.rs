@@ -0,0 +1,38 @@
+use glam::Affine3A;
+use rkyv::{Archive, Deserialize, Serialize};
+use space_asset_disk_shader::range::RangeU32;
+
+/// The node hierarchy of the source scene, with names and extras. Allows looking up instances by the name of the node
+/// that created them.
+#[derive(Clone, Debug, Default, Archive, Serialize, Deserialize)]
+pub struct SceneGraphDisk {
+	/// all nodes, parents are not guaranteed to precede their children
+	pub nodes: Vec<SceneNodeDisk>,
+	/// named source meshes, indexed by [`SceneNodeDisk::mesh`]
+	pub meshes: Vec<SceneMeshDisk>,
+}
+
+#[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+pub struct SceneNodeDisk {
+	pub name: Option<String>,
+	/// index of the parent node, `None` for root nodes
+	pub parent: Option<u32>,
+	/// transform relative to the parent node
+	pub parent_from_local: Affine3A,
+	/// index into [`SceneGraphDisk::meshes`]
+	pub mesh: Option<u32>,
+	/// [`MeshletInstanceDisk`](crate::meshlet::instance::MeshletInstanceDisk)s created by this node, not including its
+	/// children
+	pub instances: RangeU32,
+	/// application specific data as a json string
+	pub extras: Option<String>,
+}
+
+#[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+pub struct SceneMeshDisk {
+	pub name: Option<String>,
+	/// [`MeshletMeshDisk`](crate::meshlet::mesh::MeshletMeshDisk)s of this mesh, one for each primitive
+	pub mesh_ids: RangeU32,
+	/// application specific data as a json string
+	pub extras: Option<String>,
+}
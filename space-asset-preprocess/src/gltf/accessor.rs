@@ -0,0 +1,167 @@
+use crate::gltf::Gltf;
+use gltf::accessor::sparse::IndexType;
+use gltf::accessor::{DataType, Dimensions};
+use gltf::buffer::View;
+use gltf::mesh::Semantic;
+use gltf::{Accessor, Primitive};
+use std::fmt::{Display, Formatter};
+
+/// Reads the `semantic` attribute of `primitive` with `N` components, see [`read_accessor_f32`].
+pub fn read_attribute_f32<const N: usize>(
+	gltf: &Gltf,
+	primitive: &Primitive,
+	semantic: &Semantic,
+) -> Result<Option<Vec<[f32; N]>>, GltfAccessorError> {
+	primitive
+		.get(semantic)
+		.map(|accessor| read_accessor_f32(gltf, &accessor))
+		.transpose()
+}
+
+/// Reads an accessor of `N` components as floats. Unlike the `gltf` crate's readers, integer components are
+/// dequantized as allowed by `KHR_mesh_quantization`: normalized components are mapped to `[0, 1]` or `[-1, 1]`, others
+/// are converted as is. Sparse accessors are supported.
+pub fn read_accessor_f32<const N: usize>(gltf: &Gltf, accessor: &Accessor) -> Result<Vec<[f32; N]>, GltfAccessorError> {
+	profiling::function_scope!();
+	if accessor.dimensions().multiplicity() != N {
+		return Err(GltfAccessorError::DimensionMismatch {
+			expected: N,
+			found: accessor.dimensions(),
+		});
+	}
+	let data_type = accessor.data_type();
+	let normalized = accessor.normalized();
+	let element_size = data_type.size() * N;
+
+	let mut out = match accessor.view() {
+		Some(view) => {
+			let stride = view.stride().unwrap_or(element_size);
+			read_elements(
+				gltf,
+				&view,
+				accessor.offset(),
+				stride,
+				element_size,
+				accessor.count(),
+				|element| read_vector(element, data_type, normalized),
+			)?
+		}
+		None => vec![[0.; N]; accessor.count()],
+	};
+
+	if let Some(sparse) = accessor.sparse() {
+		let indices = sparse.indices();
+		let index_type = indices.index_type();
+		let index_size = match index_type {
+			IndexType::U8 => 1,
+			IndexType::U16 => 2,
+			IndexType::U32 => 4,
+		};
+		let indices = read_elements(
+			gltf,
+			&indices.view(),
+			indices.offset(),
+			index_size,
+			index_size,
+			sparse.count(),
+			|element| match index_type {
+				IndexType::U8 => element[0] as usize,
+				IndexType::U16 => u16::from_le_bytes([element[0], element[1]]) as usize,
+				IndexType::U32 => u32::from_le_bytes(element.try_into().unwrap()) as usize,
+			},
+		)?;
+		let values = sparse.values();
+		let values = read_elements(
+			gltf,
+			&values.view(),
+			values.offset(),
+			element_size,
+			element_size,
+			sparse.count(),
+			|element| read_vector(element, data_type, normalized),
+		)?;
+		for (index, value) in indices.into_iter().zip(values) {
+			*out.get_mut(index).ok_or(GltfAccessorError::OutOfBounds)? = value;
+		}
+	}
+	Ok(out)
+}
+
+fn read_elements<T>(
+	gltf: &Gltf,
+	view: &View,
+	offset: usize,
+	stride: usize,
+	element_size: usize,
+	count: usize,
+	f: impl Fn(&[u8]) -> T,
+) -> Result<Vec<T>, GltfAccessorError> {
+	let data = gltf
+		.buffer(view.buffer())
+		.and_then(|buffer| buffer.get(view.offset()..view.offset() + view.length()))
+		.ok_or(GltfAccessorError::OutOfBounds)?;
+	(0..count)
+		.map(|i| {
+			let start = offset + i * stride;
+			let element = data
+				.get(start..start + element_size)
+				.ok_or(GltfAccessorError::OutOfBounds)?;
+			Ok(f(element))
+		})
+		.collect()
+}
+
+fn read_vector<const N: usize>(element: &[u8], data_type: DataType, normalized: bool) -> [f32; N] {
+	let size = data_type.size();
+	core::array::from_fn(|i| read_component(&element[i * size..(i + 1) * size], data_type, normalized))
+}
+
+fn read_component(bytes: &[u8], data_type: DataType, normalized: bool) -> f32 {
+	match data_type {
+		DataType::F32 => f32::from_le_bytes(bytes.try_into().unwrap()),
+		DataType::U8 => dequantize_unsigned(bytes[0] as f32, u8::MAX as f32, normalized),
+		DataType::U16 => dequantize_unsigned(
+			u16::from_le_bytes(bytes.try_into().unwrap()) as f32,
+			u16::MAX as f32,
+			normalized,
+		),
+		DataType::U32 => dequantize_unsigned(
+			u32::from_le_bytes(bytes.try_into().unwrap()) as f32,
+			u32::MAX as f32,
+			normalized,
+		),
+		DataType::I8 => dequantize_signed(bytes[0] as i8 as f32, i8::MAX as f32, normalized),
+		DataType::I16 => dequantize_signed(
+			i16::from_le_bytes(bytes.try_into().unwrap()) as f32,
+			i16::MAX as f32,
+			normalized,
+		),
+	}
+}
+
+fn dequantize_unsigned(value: f32, max: f32, normalized: bool) -> f32 {
+	if normalized { value / max } else { value }
+}
+
+fn dequantize_signed(value: f32, max: f32, normalized: bool) -> f32 {
+	if normalized { (value / max).max(-1.) } else { value }
+}
+
+#[derive(Debug)]
+pub enum GltfAccessorError {
+	DimensionMismatch { expected: usize, found: Dimensions },
+	OutOfBounds,
+}
+
+impl Display for GltfAccessorError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			GltfAccessorError::DimensionMismatch { expected, found } => {
+				write!(f, "Expected accessor with {expected} components, but found {found:?}")
+			}
+			GltfAccessorError::OutOfBounds => f.write_str("Accessor is out of bounds"),
+		}
+	}
+}
+
+impl std::error::Error for GltfAccessorError {}
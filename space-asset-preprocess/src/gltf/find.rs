@@ -1,3 +1,4 @@
+use crate::meshlet::bake_cache::BakeStatus;
 use proc_macro_crate::{FoundCrate, crate_name};
 use proc_macro2::TokenStream;
 use quote::{TokenStreamExt, format_ident, quote};
@@ -12,6 +13,7 @@ pub struct GltfFile {
 	pub relative: PathBuf,
 	pub out_relative: PathBuf,
 	pub out_path: PathBuf,
+	pub status: BakeStatus,
 }
 
 pub fn find_gltf_files(models_dir: &Path, out_dir: &Path, print_rerun_if_changed: bool) -> io::Result<Vec<GltfFile>> {
@@ -40,6 +42,7 @@ pub fn find_gltf_files(models_dir: &Path, out_dir: &Path, print_rerun_if_changed
 				relative,
 				out_relative,
 				out_path,
+				status: BakeStatus::Pending,
 			}
 		})
 		.collect::<Vec<_>>())
@@ -86,7 +89,7 @@ pub fn to_mod_hierarchy<'a>(model_paths: impl Iterator<Item = &'a GltfFile>) ->
 		let relative = &model.relative.to_string_lossy();
 		let out_relative = &model.out_relative.to_string_lossy();
 		quote! {
-			pub const #name: #crate_name::meshlet::scene::MeshletSceneFile<'static> = unsafe { #crate_name::meshlet::scene::MeshletSceneFile::new(#relative, #out_relative) };
+			pub const #name: #crate_name::meshlet::scene::MeshletSceneFile<'static> = #crate_name::meshlet::scene::MeshletSceneFile::new(#relative, #out_relative);
 		}
 	});
 	Ok(quote! {
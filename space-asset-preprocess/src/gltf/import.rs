@@ -1,12 +1,22 @@
 use crate::gltf::Scheme;
+use crate::gltf::meshopt::{EXT_MESHOPT_COMPRESSION, decode_meshopt_views, is_fallback_buffer};
+use crate::gltf::texture_transform::KHR_TEXTURE_TRANSFORM;
 use glam::{Affine3A, Quat, Vec3};
 use gltf::buffer::Source;
 use gltf::{Buffer, Document, Node, Scene};
 use smallvec::SmallVec;
 use std::fmt::{Display, Formatter};
-use std::io;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+/// Extensions handled by the importer, which the `gltf` crate would otherwise reject if they are required.
+pub const SUPPORTED_EXTENSIONS: &[&str] = &[
+	"KHR_mesh_quantization",
+	"KHR_materials_emissive_strength",
+	KHR_TEXTURE_TRANSFORM,
+	EXT_MESHOPT_COMPRESSION,
+];
 
 pub struct Gltf {
 	pub document: Document,
@@ -21,11 +31,18 @@ impl Gltf {
 			.parent()
 			.map(Path::to_path_buf)
 			.unwrap_or_else(|| PathBuf::from("./"));
-		let gltf::Gltf { document, mut blob } = gltf::Gltf::open(path)?;
-		let buffers = document
+		let gltf::Gltf { document, mut blob } = gltf::Gltf::from_slice_without_validation(&fs::read(path)?)?;
+		let document = {
+			let mut json = document.into_json();
+			json.extensions_required
+				.retain(|ext| !SUPPORTED_EXTENSIONS.contains(&ext.as_str()));
+			Document::from_json(json)?
+		};
+		let mut buffers: SmallVec<[Vec<u8>; 1]> = document
 			.buffers()
 			.map(|buffer| Self::load_buffer(buffer, base.as_path(), &mut blob))
 			.collect::<Result<_, _>>()?;
+		decode_meshopt_views(&document, &mut buffers)?;
 		Ok(Self {
 			document,
 			base,
@@ -35,6 +52,8 @@ impl Gltf {
 
 	fn load_buffer(buffer: Buffer, base_path: &Path, blob: &mut Option<Vec<u8>>) -> Result<Vec<u8>, GltfImageError> {
 		Ok(match buffer.source() {
+			// fallback buffers without data are entirely filled by decoding compressed buffer views
+			Source::Bin if is_fallback_buffer(&buffer) => vec![0; buffer.length()],
 			Source::Bin => blob.take().ok_or(GltfImageError::MissingBuffer)?,
 			Source::Uri(uri) => Scheme::parse(uri)
 				.ok_or(GltfImageError::UnsupportedUri)?
@@ -96,16 +115,20 @@ impl From<io::Error> for GltfImageError {
 }
 
 impl Gltf {
+	/// The transformation of `node` relative to its parent.
+	pub fn node_transformation(node: &Node) -> Affine3A {
+		let (translation, rotation, scale) = node.transform().decomposed();
+		Affine3A::from_scale_rotation_translation(
+			Vec3::from(scale),
+			Quat::from_array(rotation),
+			Vec3::from(translation),
+		)
+	}
+
 	pub fn absolute_node_transformations(&self, scene: &Scene, base: Affine3A) -> Vec<Affine3A> {
 		profiling::function_scope!();
 		fn walk(out: &mut Vec<Affine3A>, node: Node, parent: Affine3A) {
-			let (translation, rotation, scale) = node.transform().decomposed();
-			let node_absolute = parent
-				* Affine3A::from_scale_rotation_translation(
-					Vec3::from(scale),
-					Quat::from_array(rotation),
-					Vec3::from(translation),
-				);
+			let node_absolute = parent * Gltf::node_transformation(&node);
 			out[node.index()] = node_absolute;
 			for node in node.children() {
 				walk(out, node, node_absolute);
@@ -0,0 +1,177 @@
+//! Decoding of buffer views compressed with [`EXT_meshopt_compression`].
+//!
+//! [`EXT_meshopt_compression`]: https://github.com/KhronosGroup/glTF/tree/main/extensions/2.0/Vendor/EXT_meshopt_compression
+
+use gltf::buffer::View;
+use gltf::{Buffer, Document};
+use serde::Deserialize;
+use std::fmt::{Display, Formatter};
+
+pub const EXT_MESHOPT_COMPRESSION: &str = "EXT_meshopt_compression";
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MeshoptMode {
+	Attributes,
+	Triangles,
+	Indices,
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MeshoptFilter {
+	#[default]
+	None,
+	Octahedral,
+	Quaternion,
+	Exponential,
+}
+
+/// The `EXT_meshopt_compression` extension object of a buffer view
+#[derive(Copy, Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MeshoptCompression {
+	pub buffer: usize,
+	#[serde(default)]
+	pub byte_offset: usize,
+	pub byte_length: usize,
+	pub byte_stride: usize,
+	pub count: usize,
+	pub mode: MeshoptMode,
+	#[serde(default)]
+	pub filter: MeshoptFilter,
+}
+
+impl MeshoptCompression {
+	pub fn from_view(view: &View) -> Result<Option<Self>, GltfMeshoptError> {
+		view.extension_value(EXT_MESHOPT_COMPRESSION)
+			.map(|value| serde_json::from_value(value.clone()).map_err(GltfMeshoptError::InvalidExtension))
+			.transpose()
+	}
+
+	fn validate(&self, decoded_len: usize) -> Result<(), GltfMeshoptError> {
+		let stride_valid = match (self.mode, self.filter) {
+			(MeshoptMode::Attributes, MeshoptFilter::None) => self.byte_stride % 4 == 0 && self.byte_stride <= 256,
+			(MeshoptMode::Attributes, MeshoptFilter::Octahedral) => self.byte_stride == 4 || self.byte_stride == 8,
+			(MeshoptMode::Attributes, MeshoptFilter::Quaternion) => self.byte_stride == 8,
+			(MeshoptMode::Attributes, MeshoptFilter::Exponential) => self.byte_stride % 4 == 0,
+			(MeshoptMode::Triangles | MeshoptMode::Indices, MeshoptFilter::None) => {
+				self.byte_stride == 2 || self.byte_stride == 4
+			}
+			(MeshoptMode::Triangles | MeshoptMode::Indices, _) => false,
+		};
+		if !stride_valid || (self.mode == MeshoptMode::Triangles && self.count % 3 != 0) {
+			return Err(GltfMeshoptError::InvalidStride {
+				mode: self.mode,
+				filter: self.filter,
+				byte_stride: self.byte_stride,
+			});
+		}
+		if self.count * self.byte_stride != decoded_len {
+			return Err(GltfMeshoptError::LengthMismatch);
+		}
+		Ok(())
+	}
+
+	/// Decodes `src` into `dst`, which must be exactly `count * byte_stride` long.
+	pub fn decode(&self, src: &[u8], dst: &mut [u8]) -> Result<(), GltfMeshoptError> {
+		profiling::function_scope!();
+		self.validate(dst.len())?;
+		// Safety: lengths of src and dst are passed along and were validated
+		let result = unsafe {
+			let decode = match self.mode {
+				MeshoptMode::Attributes => meshopt::ffi::meshopt_decodeVertexBuffer,
+				MeshoptMode::Triangles => meshopt::ffi::meshopt_decodeIndexBuffer,
+				MeshoptMode::Indices => meshopt::ffi::meshopt_decodeIndexSequence,
+			};
+			decode(
+				dst.as_mut_ptr().cast(),
+				self.count,
+				self.byte_stride,
+				src.as_ptr(),
+				src.len(),
+			)
+		};
+		if result != 0 {
+			return Err(GltfMeshoptError::DecodeFailed(result));
+		}
+
+		// Safety: stride was validated to be compatible with the filter
+		unsafe {
+			let filter = match self.filter {
+				MeshoptFilter::None => return Ok(()),
+				MeshoptFilter::Octahedral => meshopt::ffi::meshopt_decodeFilterOct,
+				MeshoptFilter::Quaternion => meshopt::ffi::meshopt_decodeFilterQuat,
+				MeshoptFilter::Exponential => meshopt::ffi::meshopt_decodeFilterExp,
+			};
+			filter(dst.as_mut_ptr().cast(), self.count, self.byte_stride);
+		}
+		Ok(())
+	}
+}
+
+/// Fallback buffers may not contain any data, as they are only referenced by compressed buffer views.
+pub fn is_fallback_buffer(buffer: &Buffer) -> bool {
+	buffer
+		.extension_value(EXT_MESHOPT_COMPRESSION)
+		.and_then(|ext| ext.get("fallback"))
+		.and_then(|fallback| fallback.as_bool())
+		.unwrap_or(false)
+}
+
+/// Decodes all compressed buffer views of `document` in place, overwriting the contents of their (fallback) buffers.
+pub fn decode_meshopt_views(document: &Document, buffers: &mut [Vec<u8>]) -> Result<(), GltfMeshoptError> {
+	profiling::function_scope!();
+	for view in document.views() {
+		let Some(compression) = MeshoptCompression::from_view(&view)? else {
+			continue;
+		};
+		let src = buffers
+			.get(compression.buffer)
+			.and_then(|b| b.get(compression.byte_offset..compression.byte_offset + compression.byte_length))
+			.ok_or(GltfMeshoptError::OutOfBounds)?
+			.to_vec();
+		let dst = buffers
+			.get_mut(view.buffer().index())
+			.and_then(|b| b.get_mut(view.offset()..view.offset() + view.length()))
+			.ok_or(GltfMeshoptError::OutOfBounds)?;
+		compression.decode(&src, dst)?;
+	}
+	Ok(())
+}
+
+#[derive(Debug)]
+pub enum GltfMeshoptError {
+	InvalidExtension(serde_json::Error),
+	InvalidStride {
+		mode: MeshoptMode,
+		filter: MeshoptFilter,
+		byte_stride: usize,
+	},
+	OutOfBounds,
+	LengthMismatch,
+	DecodeFailed(i32),
+}
+
+impl Display for GltfMeshoptError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			GltfMeshoptError::InvalidExtension(err) => write!(f, "Invalid {EXT_MESHOPT_COMPRESSION} extension: {err}"),
+			GltfMeshoptError::InvalidStride {
+				mode,
+				filter,
+				byte_stride,
+			} => write!(
+				f,
+				"Invalid byteStride {byte_stride} for mode {mode:?} and filter {filter:?}"
+			),
+			GltfMeshoptError::OutOfBounds => f.write_str("Compressed buffer view is out of bounds"),
+			GltfMeshoptError::LengthMismatch => {
+				f.write_str("Buffer view length does not match the decoded length of the compressed data")
+			}
+			GltfMeshoptError::DecodeFailed(code) => write!(f, "Decoding compressed buffer view failed with {code}"),
+		}
+	}
+}
+
+impl std::error::Error for GltfMeshoptError {}
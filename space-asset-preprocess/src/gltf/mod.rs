@@ -1,5 +1,8 @@
+pub mod accessor;
 mod find;
 mod import;
+pub mod meshopt;
+pub mod texture_transform;
 mod uri;
 
 pub use find::*;
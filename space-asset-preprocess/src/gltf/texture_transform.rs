@@ -0,0 +1,64 @@
+//! Parsing of [`KHR_texture_transform`].
+//!
+//! [`KHR_texture_transform`]: https://github.com/KhronosGroup/glTF/tree/main/extensions/2.0/Khronos/KHR_texture_transform
+
+use glam::Vec2;
+use serde::Deserialize;
+use space_asset_disk::material::pbr::TextureCoordinates;
+
+pub const KHR_TEXTURE_TRANSFORM: &str = "KHR_texture_transform";
+
+/// The `KHR_texture_transform` extension object of a texture info
+#[derive(Copy, Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextureTransform {
+	#[serde(default)]
+	pub offset: [f32; 2],
+	#[serde(default)]
+	pub rotation: f32,
+	#[serde(default = "default_scale")]
+	pub scale: [f32; 2],
+	/// overrides the `texCoord` of the texture info
+	pub tex_coord: Option<u32>,
+}
+
+fn default_scale() -> [f32; 2] {
+	[1., 1.]
+}
+
+impl TextureTransform {
+	pub fn from_extension(value: Option<&serde_json::Value>) -> serde_json::Result<Option<Self>> {
+		value.map(|value| serde_json::from_value(value.clone())).transpose()
+	}
+
+	/// Applies this transform to the texture coordinate set `tex_coord` of the texture info.
+	pub fn texture_coordinates(&self, tex_coord: u32) -> TextureCoordinates {
+		TextureCoordinates::from_offset_rotation_scale(
+			self.tex_coord.unwrap_or(tex_coord),
+			Vec2::from(self.offset),
+			self.rotation,
+			Vec2::from(self.scale),
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::f32::consts::FRAC_PI_2;
+
+	#[test]
+	fn test_texture_transform() -> anyhow::Result<()> {
+		let json = serde_json::json!({ "offset": [0.5, 0.], "rotation": FRAC_PI_2, "scale": [2., 2.], "texCoord": 1 });
+		let transform = TextureTransform::from_extension(Some(&json))?.unwrap();
+		let coords = transform.texture_coordinates(0);
+		assert_eq!(coords.set, 1);
+		let uv = coords.transform(Vec2::new(1., 0.));
+		assert!(uv.abs_diff_eq(Vec2::new(0.5, -2.), 1e-5), "{uv:?}");
+
+		let identity = TextureTransform::from_extension(Some(&serde_json::json!({})))?.unwrap();
+		let uv = identity.texture_coordinates(0).transform(Vec2::new(0.25, 0.75));
+		assert!(uv.abs_diff_eq(Vec2::new(0.25, 0.75), 1e-5), "{uv:?}");
+		Ok(())
+	}
+}
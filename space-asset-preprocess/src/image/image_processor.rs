@@ -1,5 +1,6 @@
 use crate::gltf::{Gltf, GltfImageError, Scheme};
 use crate::image::generate_mips::generate_mips;
+use crate::settings::PreprocessSettings;
 use anyhow::Context;
 use gltf::image::Source;
 use parking_lot::Mutex;
@@ -55,8 +56,9 @@ impl<'a> ImageProcessor<'a> {
 		I::new(image_id)
 	}
 
-	pub fn process(self, settings: EncodeSettings) -> anyhow::Result<ImageStorage> {
+	pub fn process(self, settings: &PreprocessSettings) -> anyhow::Result<ImageStorage> {
 		profiling::scope!("ImageProcessor::process");
+		let encode_settings = settings.image.quality.encode_settings();
 		let ImageProcessor { gltf, inner } = self;
 		let Inner {
 			gltf_image_to_disk_id,
@@ -79,7 +81,7 @@ impl<'a> ImageProcessor<'a> {
 					}
 					Source::Uri { uri, .. } => Scheme::parse(uri).ok_or(GltfImageError::UnsupportedUri)?,
 				};
-				let image = Self::process_individual_image(gltf, &scheme, image_type, settings)
+				let image = Self::process_individual_image(gltf, &scheme, image_type, encode_settings)
 					.with_context(|| format!("gltf image {image_index} scheme {scheme:?}"))?;
 				Ok((disk_id, image))
 			})
@@ -2,3 +2,4 @@ pub mod gltf;
 pub mod image;
 pub mod material;
 pub mod meshlet;
+pub mod settings;
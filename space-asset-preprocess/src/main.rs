@@ -1,5 +1,7 @@
 use clap::Parser;
-use space_asset_preprocess::meshlet::build_script::build_script;
+use space_asset_preprocess::meshlet::build_script::{bake_warnings, build_script, ensure_all_baked};
+use space_asset_preprocess::settings::{ImageEncodeQuality, PreprocessSettings};
+use std::fs;
 use std::ops::Deref;
 use std::path::PathBuf;
 
@@ -12,6 +14,32 @@ pub struct PreprocessArgs {
 	out_dir: PathBuf,
 	#[arg(long)]
 	models_rs: Option<PathBuf>,
+	/// TOML file with the base settings, per-directory and per-model override files are applied on top
+	#[arg(short, long)]
+	settings: Option<PathBuf>,
+	/// overrides the image encode quality of the base settings
+	#[arg(long, value_enum)]
+	image_quality: Option<ImageEncodeQuality>,
+	/// overrides the maximum lod level of the base settings
+	#[arg(long)]
+	max_lod_level: Option<u32>,
+}
+
+impl PreprocessArgs {
+	fn settings(&self) -> anyhow::Result<PreprocessSettings> {
+		let mut settings = match &self.settings {
+			None => PreprocessSettings::default(),
+			Some(path) => PreprocessSettings::from_toml(&fs::read_to_string(path)?)?,
+		};
+		if let Some(image_quality) = self.image_quality {
+			settings.image.quality = image_quality;
+		}
+		if let Some(max_lod_level) = self.max_lod_level {
+			settings.lod.max_lod_level = max_lod_level;
+		}
+		settings.validate()?;
+		Ok(settings)
+	}
 }
 
 pub fn main() -> anyhow::Result<()> {
@@ -21,7 +49,11 @@ pub fn main() -> anyhow::Result<()> {
 		args.out_dir.deref(),
 		args.models_rs.as_deref(),
 		false,
+		&args.settings()?,
 	)?;
 	println!("{result:#?}");
-	Ok(())
+	for warning in bake_warnings(&result) {
+		eprintln!("warning: {warning}");
+	}
+	ensure_all_baked(&result)
 }
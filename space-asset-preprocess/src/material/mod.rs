@@ -1 +1,2 @@
 pub mod pbr;
+pub mod vertex_generation;
@@ -1,30 +1,99 @@
 use crate::gltf::Gltf;
+use crate::gltf::accessor::{GltfAccessorError, read_accessor_f32, read_attribute_f32};
+use crate::gltf::texture_transform::{KHR_TEXTURE_TRANSFORM, TextureTransform};
 use crate::image::image_processor::ImageProcessor;
+use crate::meshlet::error::MeshletError;
 use glam::{Vec2, Vec3, Vec4};
+use gltf::accessor::Dimensions;
+use gltf::mesh::Semantic;
+use gltf::texture::Info;
 use gltf::{Material, Primitive};
-use space_asset_disk::material::pbr::PbrMaterialDisk;
-use space_asset_disk::material::pbr::PbrVertex;
+use space_asset_disk::material::pbr::{AlphaMode, PbrMaterialDisk, PbrVertex, TextureCoordinates};
 
 pub fn process_pbr_vertices(gltf: &Gltf, primitive: Primitive) -> anyhow::Result<Vec<PbrVertex>> {
 	profiling::function_scope!();
-	let reader = primitive.reader(|b| gltf.buffer(b));
-	let vertex_cnt = reader.read_positions().unwrap().len();
-	let mut tex_coords = reader.read_tex_coords(0).map(|tex| tex.into_f32());
-	let mut normals = reader.read_normals();
-	let mut tangents = reader.read_tangents();
+	let vertex_cnt = primitive
+		.get(&Semantic::Positions)
+		.ok_or(MeshletError::NoVertexPositions)?
+		.count();
+	let tex_coords = read_attribute_f32::<2>(gltf, &primitive, &Semantic::TexCoords(0))?;
+	let tex_coords_1 = read_attribute_f32::<2>(gltf, &primitive, &Semantic::TexCoords(1))?;
+	let colors = read_colors(gltf, &primitive)?;
+	let normals = read_attribute_f32::<3>(gltf, &primitive, &Semantic::Normals)?;
+	let tangents = read_attribute_f32::<4>(gltf, &primitive, &Semantic::Tangents)?;
 	let vertices = (0..vertex_cnt)
-		.map(|_| PbrVertex {
-			normal: normals.as_mut().and_then(|n| n.next()).map_or(Vec3::ZERO, Vec3::from),
-			tangent: tangents.as_mut().and_then(|n| n.next()).map_or(Vec4::ZERO, Vec4::from),
+		.map(|i| PbrVertex {
+			normal: normals
+				.as_ref()
+				.and_then(|n| n.get(i))
+				.map_or(Vec3::ZERO, |n| Vec3::from(*n)),
+			tangent: tangents
+				.as_ref()
+				.and_then(|t| t.get(i))
+				.map_or(Vec4::ZERO, |t| Vec4::from(*t)),
+			color: colors.as_ref().and_then(|c| c.get(i)).copied().unwrap_or(Vec4::ONE),
 			tex_coord: tex_coords
-				.as_mut()
-				.and_then(|tex| tex.next())
-				.map_or(Vec2::ZERO, Vec2::from),
+				.as_ref()
+				.and_then(|tex| tex.get(i))
+				.map_or(Vec2::ZERO, |tex| Vec2::from(*tex)),
+			tex_coord_1: tex_coords_1
+				.as_ref()
+				.and_then(|tex| tex.get(i))
+				.map_or(Vec2::ZERO, |tex| Vec2::from(*tex)),
 		})
 		.collect();
 	Ok(vertices)
 }
 
+/// `COLOR_0` may either be RGB or RGBA, RGB colors are opaque.
+fn read_colors(gltf: &Gltf, primitive: &Primitive) -> Result<Option<Vec<Vec4>>, GltfAccessorError> {
+	let Some(accessor) = primitive.get(&Semantic::Colors(0)) else {
+		return Ok(None);
+	};
+	Ok(Some(match accessor.dimensions() {
+		Dimensions::Vec3 => read_accessor_f32::<3>(gltf, &accessor)?
+			.into_iter()
+			.map(|c| Vec4::from((Vec3::from(c), 1.)))
+			.collect(),
+		_ => read_accessor_f32::<4>(gltf, &accessor)?
+			.into_iter()
+			.map(Vec4::from)
+			.collect(),
+	}))
+}
+
+/// The texture coordinate set of the normal texture of `material`, if it has one.
+pub fn normal_texture_tex_coord(material: &Material) -> anyhow::Result<Option<u32>> {
+	material
+		.normal_texture()
+		.map(|normal| {
+			let coords = texture_coordinates(normal.tex_coord(), normal.extension_value(KHR_TEXTURE_TRANSFORM))?;
+			Ok(coords.set)
+		})
+		.transpose()
+}
+
+/// The texture coordinates of a texture info with `tex_coord` and an optional `KHR_texture_transform` extension.
+fn texture_coordinates(
+	tex_coord: u32,
+	texture_transform: Option<&serde_json::Value>,
+) -> anyhow::Result<TextureCoordinates> {
+	let coords = match TextureTransform::from_extension(texture_transform)? {
+		Some(transform) => transform.texture_coordinates(tex_coord),
+		None => TextureCoordinates::new(tex_coord),
+	};
+	match coords.set {
+		0 | 1 => Ok(coords),
+		set => Err(MeshletError::UnsupportedTextureCoordSet(set).into()),
+	}
+}
+
+fn info_texture_coordinates(info: Option<&Info>) -> anyhow::Result<TextureCoordinates> {
+	info.map_or(Ok(TextureCoordinates::default()), |info| {
+		texture_coordinates(info.tex_coord(), info.extension_value(KHR_TEXTURE_TRANSFORM))
+	})
+}
+
 pub fn process_pbr_material<'a>(
 	_gltf: &Gltf,
 	image_processor: &ImageProcessor<'_>,
@@ -32,33 +101,82 @@ pub fn process_pbr_material<'a>(
 ) -> anyhow::Result<PbrMaterialDisk> {
 	profiling::function_scope!();
 	let material_id_format = material.index().unwrap_or(!0);
+	let pbr = material.pbr_metallic_roughness();
+	let base_color = pbr.base_color_texture();
+	let normal = material.normal_texture();
+	let metallic_roughness = pbr
+		.metallic_roughness_texture()
+		// if metallic_roughness_texture is missing, try to use specular_texture. This fixes Bistro.
+		.or_else(|| {
+			material
+				.specular()
+				.and_then(|s| s.specular_texture().or(s.specular_color_texture()))
+		});
+	let occlusion = material.occlusion_texture();
+	// ORM textures store occlusion in the red channel of the metallic_roughness image, encode them only once
+	let occlusion_in_metallic_roughness = occlusion
+		.as_ref()
+		.zip(metallic_roughness.as_ref())
+		.is_some_and(|(o, mr)| o.texture().source().index() == mr.texture().source().index());
+	let emissive = material.emissive_texture();
+	let emissive_strength = material.emissive_strength().unwrap_or(1.);
+
 	Ok(PbrMaterialDisk {
-		base_color: material.pbr_metallic_roughness().base_color_texture().map(|tex| {
+		base_color: base_color.as_ref().map(|tex| {
 			image_processor.image(
 				tex.texture().source(),
 				format!("base_color of material {material_id_format}"),
 			)
 		}),
-		base_color_factor: material.pbr_metallic_roughness().base_color_factor(),
-		normal: material.normal_texture().map(|tex| {
+		base_color_tex_coord: info_texture_coordinates(base_color.as_ref())?,
+		base_color_factor: pbr.base_color_factor(),
+		normal: normal.as_ref().map(|tex| {
 			image_processor.image(
 				tex.texture().source(),
 				format!("normal of material {material_id_format}"),
 			)
 		}),
-		normal_scale: material.normal_texture().map_or(1., |n| n.scale()),
-		occlusion_roughness_metallic: material
-			.pbr_metallic_roughness()
-			.metallic_roughness_texture()
-			// if metallic_roughness_texture is missing, try to use specular_texture. This fixes Bistro.
-			.or_else(|| {
-				material
-					.specular()
-					.and_then(|s| s.specular_texture().or(s.specular_color_texture()))
-			})
-			.map(|tex| image_processor.image(tex.texture().source(), format!("orm of material {material_id_format}"))),
-		occlusion_strength: 0.,
-		roughness_factor: material.pbr_metallic_roughness().roughness_factor(),
-		metallic_factor: material.pbr_metallic_roughness().metallic_factor(),
+		normal_tex_coord: normal.as_ref().map_or(Ok(TextureCoordinates::default()), |tex| {
+			texture_coordinates(tex.tex_coord(), tex.extension_value(KHR_TEXTURE_TRANSFORM))
+		})?,
+		normal_scale: normal.as_ref().map_or(1., |n| n.scale()),
+		metallic_roughness: metallic_roughness.as_ref().map(|tex| {
+			image_processor.image(
+				tex.texture().source(),
+				format!("metallic_roughness of material {material_id_format}"),
+			)
+		}),
+		metallic_roughness_tex_coord: info_texture_coordinates(metallic_roughness.as_ref())?,
+		metallic_factor: pbr.metallic_factor(),
+		roughness_factor: pbr.roughness_factor(),
+		occlusion: occlusion
+			.as_ref()
+			.filter(|_| !occlusion_in_metallic_roughness)
+			.map(|tex| {
+				image_processor.image(
+					tex.texture().source(),
+					format!("occlusion of material {material_id_format}"),
+				)
+			}),
+		occlusion_in_metallic_roughness,
+		occlusion_tex_coord: occlusion.as_ref().map_or(Ok(TextureCoordinates::default()), |tex| {
+			texture_coordinates(tex.tex_coord(), tex.extension_value(KHR_TEXTURE_TRANSFORM))
+		})?,
+		occlusion_strength: occlusion.as_ref().map_or(1., |o| o.strength()),
+		emissive: emissive.as_ref().map(|tex| {
+			image_processor.image(
+				tex.texture().source(),
+				format!("emissive of material {material_id_format}"),
+			)
+		}),
+		emissive_tex_coord: info_texture_coordinates(emissive.as_ref())?,
+		emissive_factor: material.emissive_factor().map(|f| f * emissive_strength),
+		alpha_mode: match material.alpha_mode() {
+			gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+			gltf::material::AlphaMode::Mask => AlphaMode::Mask,
+			gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+		},
+		alpha_cutoff: material.alpha_cutoff().unwrap_or(0.5),
+		double_sided: material.double_sided(),
 	})
 }
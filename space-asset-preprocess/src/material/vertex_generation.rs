@@ -0,0 +1,212 @@
+//! Generation of vertex attributes missing from the source model, as required by the glTF spec: flat normals if a
+//! primitive has no normals and MikkTSpace tangents if it has a normal texture but no tangents.
+
+use crate::settings::NormalGeneration;
+use glam::{Vec3, Vec4};
+use rustc_hash::FxHashMap;
+use space_asset_disk::material::pbr::PbrVertex;
+
+/// Vertices and triangle list indices of a primitive
+#[derive(Clone, Debug, Default)]
+pub struct GeneratedVertices {
+	pub positions: Vec<Vec3>,
+	pub pbr_vertices: Vec<PbrVertex>,
+	pub indices: Vec<u32>,
+}
+
+/// Generates normals and / or tangents for the triangle list `indices`. Generated attributes may differ between the
+/// triangles sharing a vertex, so vertices are split per triangle corner and deduplicated again afterward.
+///
+/// * `normals`: generate normals, overwriting existing ones
+/// * `tangent_tex_coord`: generate MikkTSpace tangents from this texture coordinate set, overwriting existing ones
+pub fn generate_vertex_attributes(
+	positions: &[Vec3],
+	pbr_vertices: &[PbrVertex],
+	indices: &[u32],
+	normals: Option<NormalGeneration>,
+	tangent_tex_coord: Option<u32>,
+) -> GeneratedVertices {
+	profiling::function_scope!();
+	let mut corners = Corners {
+		positions: indices.iter().map(|i| positions[*i as usize]).collect(),
+		pbr_vertices: indices.iter().map(|i| pbr_vertices[*i as usize]).collect(),
+		tex_coord: tangent_tex_coord.unwrap_or(0),
+	};
+
+	match normals {
+		None => (),
+		Some(NormalGeneration::Flat) => corners.flat_normals(),
+		Some(NormalGeneration::Smooth) => corners.smooth_normals(),
+	}
+	if tangent_tex_coord.is_some() {
+		profiling::scope!("bevy_mikktspace::generate_tangents");
+		// fails only for meshes without any triangles
+		bevy_mikktspace::generate_tangents(&mut corners);
+	}
+	corners.weld()
+}
+
+/// Every triangle corner as its own vertex
+struct Corners {
+	positions: Vec<Vec3>,
+	pbr_vertices: Vec<PbrVertex>,
+	tex_coord: u32,
+}
+
+impl Corners {
+	fn face_normal(&self, face: usize) -> Vec3 {
+		let [a, b, c] = [0, 1, 2].map(|i| self.positions[face * 3 + i]);
+		Vec3::cross(b - a, c - a)
+	}
+
+	fn flat_normals(&mut self) {
+		profiling::function_scope!();
+		for face in 0..self.positions.len() / 3 {
+			let normal = self.face_normal(face).normalize_or_zero();
+			for vertex in &mut self.pbr_vertices[face * 3..face * 3 + 3] {
+				vertex.normal = normal;
+			}
+		}
+	}
+
+	/// Averages the normals of all faces sharing a position, weighted by their area.
+	fn smooth_normals(&mut self) {
+		profiling::function_scope!();
+		let mut normals = FxHashMap::<[u32; 3], Vec3>::default();
+		for face in 0..self.positions.len() / 3 {
+			let normal = self.face_normal(face);
+			for position in &self.positions[face * 3..face * 3 + 3] {
+				*normals.entry(position.to_array().map(f32::to_bits)).or_default() += normal;
+			}
+		}
+		for (position, vertex) in self.positions.iter().zip(self.pbr_vertices.iter_mut()) {
+			vertex.normal = normals[&position.to_array().map(f32::to_bits)].normalize_or_zero();
+		}
+	}
+
+	/// Deduplicates bitwise identical corners back into indexed vertices.
+	fn weld(self) -> GeneratedVertices {
+		profiling::function_scope!();
+		let mut out = GeneratedVertices::default();
+		let mut dedup = FxHashMap::default();
+		for (position, vertex) in self.positions.into_iter().zip(self.pbr_vertices) {
+			let index = *dedup.entry(vertex_key(position, vertex)).or_insert_with(|| {
+				out.positions.push(position);
+				out.pbr_vertices.push(vertex);
+				out.positions.len() as u32 - 1
+			});
+			out.indices.push(index);
+		}
+		out
+	}
+}
+
+fn vertex_key(position: Vec3, vertex: PbrVertex) -> [u32; 18] {
+	let floats = [
+		position.to_array().as_slice(),
+		&vertex.tangent.to_array(),
+		&vertex.color.to_array(),
+		&vertex.normal.to_array(),
+		&vertex.tex_coord.to_array(),
+		&vertex.tex_coord_1.to_array(),
+	]
+	.concat();
+	core::array::from_fn(|i| floats[i].to_bits())
+}
+
+impl bevy_mikktspace::Geometry for Corners {
+	fn num_faces(&self) -> usize {
+		self.positions.len() / 3
+	}
+
+	fn num_vertices_of_face(&self, _face: usize) -> usize {
+		3
+	}
+
+	fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+		self.positions[face * 3 + vert].to_array()
+	}
+
+	fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+		self.pbr_vertices[face * 3 + vert].normal.to_array()
+	}
+
+	fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+		let vertex = &self.pbr_vertices[face * 3 + vert];
+		let tex_coord = if self.tex_coord == 1 {
+			vertex.tex_coord_1
+		} else {
+			vertex.tex_coord
+		};
+		tex_coord.to_array()
+	}
+
+	fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+		self.pbr_vertices[face * 3 + vert].tangent = Vec4::from_array(tangent);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use glam::Vec2;
+
+	fn quad() -> (Vec<Vec3>, Vec<PbrVertex>, Vec<u32>) {
+		let positions = vec![
+			Vec3::new(0., 0., 0.),
+			Vec3::new(1., 0., 0.),
+			Vec3::new(1., 1., 0.),
+			Vec3::new(0., 1., 0.),
+		];
+		let pbr_vertices = positions
+			.iter()
+			.map(|p| PbrVertex {
+				tangent: Vec4::ZERO,
+				color: Vec4::ONE,
+				normal: Vec3::ZERO,
+				tex_coord: p.truncate(),
+				tex_coord_1: Vec2::ZERO,
+			})
+			.collect();
+		(positions, pbr_vertices, vec![0, 1, 2, 0, 2, 3])
+	}
+
+	#[test]
+	fn test_generate_normals_tangents() {
+		let (positions, pbr_vertices, indices) = quad();
+		let out = generate_vertex_attributes(
+			&positions,
+			&pbr_vertices,
+			&indices,
+			Some(NormalGeneration::Flat),
+			Some(0),
+		);
+		assert_eq!(out.indices.len(), 6);
+		for vertex in &out.pbr_vertices {
+			assert!(vertex.normal.abs_diff_eq(Vec3::Z, 1e-5), "{:?}", vertex.normal);
+			assert!(
+				vertex.tangent.abs_diff_eq(Vec4::new(1., 0., 0., 1.), 1e-5),
+				"{:?}",
+				vertex.tangent
+			);
+		}
+	}
+
+	#[test]
+	fn test_flat_normals_split_vertices() {
+		let (mut positions, pbr_vertices, indices) = quad();
+		positions[2].z = 1.;
+		let out = generate_vertex_attributes(&positions, &pbr_vertices, &indices, Some(NormalGeneration::Flat), None);
+		// shared edge 0-2 is split, as both triangles have differing normals
+		assert_eq!(out.positions.len(), 6);
+
+		let out = generate_vertex_attributes(
+			&positions,
+			&pbr_vertices,
+			&indices,
+			Some(NormalGeneration::Smooth),
+			None,
+		);
+		assert_eq!(out.positions.len(), 4);
+	}
+}
@@ -0,0 +1,111 @@
+use crate::gltf::{Gltf, GltfFile, Scheme};
+use crate::meshlet::warning::BakeWarning;
+use crate::settings::PreprocessSettings;
+use gltf::image::Source;
+use space_asset_disk::content_hash::ContentHasher;
+use space_asset_disk::meshlet::header::{MESHLET_SCENE_FORMAT_VERSION, MESHLET_SCENE_MAGIC, MeshletSceneHeader};
+use std::collections::HashSet;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+/// Version of the bake pipeline. Increment this whenever the preprocessor changes its output without changing the disk
+/// format, to invalidate all cached bakes.
+pub const BAKE_PIPELINE_VERSION: u32 = 1;
+
+#[derive(Debug, Default)]
+pub enum BakeStatus {
+	/// not yet processed
+	#[default]
+	Pending,
+	/// the model was (re)baked, warnings are only reported when rebaking
+	Rebuilt { warnings: Vec<BakeWarning> },
+	/// an up-to-date bake already existed and was kept
+	Cached,
+	/// baking the model failed
+	Failed(anyhow::Error),
+}
+
+impl BakeStatus {
+	pub fn is_failed(&self) -> bool {
+		matches!(self, BakeStatus::Failed(_))
+	}
+
+	pub fn warnings(&self) -> &[BakeWarning] {
+		match self {
+			BakeStatus::Rebuilt { warnings } => warnings,
+			_ => &[],
+		}
+	}
+}
+
+/// Computes the key identifying a bake of this gltf. It covers the gltf file itself, all of its buffers, all images it
+/// references, the settings used and the format and pipeline versions.
+pub fn bake_key(gltf_path: &Path, gltf: &Gltf, settings: &PreprocessSettings) -> io::Result<u64> {
+	profiling::function_scope!();
+	let mut hasher = ContentHasher::new();
+	MESHLET_SCENE_FORMAT_VERSION.hash(&mut hasher);
+	BAKE_PIPELINE_VERSION.hash(&mut hasher);
+	settings.hash(&mut hasher);
+	hasher.write(&fs::read(gltf_path)?);
+	for buffer in &gltf.buffers {
+		buffer.hash(&mut hasher);
+	}
+	for image in gltf.images() {
+		// images embedded in buffers or as base64 are already covered
+		if let Source::Uri { uri, .. } = image.source() {
+			match Scheme::parse(uri) {
+				Some(scheme @ (Scheme::AbsoluteFile(_) | Scheme::RelativeFile(_))) => {
+					hasher.write(&scheme.read(gltf.base())?);
+				}
+				Some(_) => (),
+				// let the actual processing report the error
+				None => uri.hash(&mut hasher),
+			}
+		}
+	}
+	Ok(hasher.finish())
+}
+
+/// Returns the bake key of an existing output, if it exists and is complete.
+pub fn read_cached_bake_key(out_path: &Path) -> Option<u64> {
+	let mut file = File::open(out_path).ok()?;
+	let file_len = file.metadata().ok()?.len();
+	let mut header = [0; MeshletSceneHeader::SIZE];
+	file.read_exact(&mut header).ok()?;
+	let header = MeshletSceneHeader::from_bytes(&header).ok()?;
+	(file_len == header.file_len).then_some(header.bake_key)
+}
+
+/// Removes all baked scenes within `out_dir` whose source gltf no longer exists. Only files with a valid
+/// [`MeshletSceneHeader`] magic are removed.
+pub fn remove_stale_outputs(out_dir: &Path, models: &[GltfFile]) -> io::Result<Vec<PathBuf>> {
+	profiling::function_scope!();
+	if !out_dir.exists() {
+		return Ok(Vec::new());
+	}
+	let expected = models.iter().map(|m| m.out_path.as_path()).collect::<HashSet<_>>();
+	let mut removed = Vec::new();
+	for entry in walkdir::WalkDir::new(out_dir).into_iter().filter_map(|e| e.ok()) {
+		let path = entry.path();
+		if !entry.file_type().is_file()
+			|| path.extension().is_none_or(|ext| ext != "bin")
+			|| expected.contains(path)
+			|| !is_meshlet_scene(path)
+		{
+			continue;
+		}
+		fs::remove_file(path)?;
+		removed.push(path.to_path_buf());
+	}
+	Ok(removed)
+}
+
+fn is_meshlet_scene(path: &Path) -> bool {
+	let mut header = [0; MeshletSceneHeader::SIZE];
+	File::open(path)
+		.and_then(|mut file| file.read_exact(&mut header))
+		.is_ok_and(|_| header.starts_with(&MESHLET_SCENE_MAGIC))
+}
@@ -1,12 +1,14 @@
 use crate::gltf::{Gltf, find_gltf_files};
 use crate::gltf::{GltfFile, to_mod_hierarchy};
+use crate::meshlet::bake_cache::{BakeStatus, bake_key, read_cached_bake_key, remove_stale_outputs};
 use crate::meshlet::process::process_meshlets;
+use crate::settings::PreprocessSettings;
 use anyhow::Context;
 use rayon::prelude::*;
 use space_asset_disk::meshlet::scene::EXPORT_FOLDER_NAME;
 use std::fs::File;
 use std::path::{Path, PathBuf};
-use std::{env, fs};
+use std::{env, fs, io};
 
 pub fn out_and_export_dir() -> Option<(PathBuf, PathBuf)> {
 	let out_dir = PathBuf::from(&env::var("OUT_DIR").unwrap());
@@ -20,29 +22,26 @@ pub fn build_script(
 	out_dir: &Path,
 	models_rs: Option<&Path>,
 	rerun_if_changed: bool,
+	settings: &PreprocessSettings,
 ) -> anyhow::Result<Vec<GltfFile>> {
 	profiling::function_scope!();
-	let model_paths = find_gltf_files(models_dir, out_dir, rerun_if_changed)?;
+	let mut model_paths = find_gltf_files(models_dir, out_dir, rerun_if_changed)?;
+	let models_dir = fs::canonicalize(models_dir)?;
 
 	{
 		profiling::scope!("processing all models");
-		model_paths
-			.par_iter()
-			.map(|model| {
-				profiling::scope!("processing model", model.src_path.to_str().unwrap());
-				let gltf = Gltf::open(&model.src_path)
-					.with_context(|| format!("opening gltf file failed {:?}", model.src_path))?;
-				let disk =
-					process_meshlets(&gltf).with_context(|| format!("processing gltf failed {:?}", model.src_path))?;
-				fs::create_dir_all(model.out_path.parent().unwrap())
-					.with_context(|| format!("failed creating output directories for file {:?}", model.out_path))?;
-				let out_file = File::create(&model.out_path)
-					.with_context(|| format!("failed creating output file {:?}", model.out_path))?;
-				disk.serialize_to(out_file)
-					.with_context(|| format!("zstd stream failed writing {:?}", model.out_path))?;
-				Ok::<(), anyhow::Error>(())
-			})
-			.collect::<Result<(), _>>()?;
+		model_paths.par_iter_mut().for_each(|model| {
+			model.status = match bake_model(model, &models_dir, settings) {
+				Ok(status) => status,
+				Err(err) => BakeStatus::Failed(remove_failed_output(model, err)),
+			};
+		});
+	}
+
+	{
+		profiling::scope!("removing stale outputs");
+		remove_stale_outputs(out_dir, &model_paths)
+			.with_context(|| format!("failed removing stale outputs in {out_dir:?}"))?;
 	}
 
 	if let Some(models_rs) = models_rs {
@@ -52,3 +51,74 @@ pub fn build_script(
 
 	Ok(model_paths)
 }
+
+fn bake_model(model: &GltfFile, models_dir: &Path, settings: &PreprocessSettings) -> anyhow::Result<BakeStatus> {
+	profiling::scope!("processing model", model.src_path.to_str().unwrap());
+	let settings = settings.for_model(models_dir, &model.src_path)?;
+	let gltf = Gltf::open(&model.src_path).with_context(|| format!("opening gltf file failed {:?}", model.src_path))?;
+	let bake_key = bake_key(&model.src_path, &gltf, &settings)
+		.with_context(|| format!("hashing gltf failed {:?}", model.src_path))?;
+	if read_cached_bake_key(&model.out_path) == Some(bake_key) {
+		return Ok(BakeStatus::Cached);
+	}
+
+	let (disk, warnings) =
+		process_meshlets(&gltf, &settings).with_context(|| format!("processing gltf failed {:?}", model.src_path))?;
+	fs::create_dir_all(model.out_path.parent().unwrap())
+		.with_context(|| format!("failed creating output directories for file {:?}", model.out_path))?;
+	// written to a temporary file first, so an interrupted bake never leaves a truncated output behind
+	let tmp_path = model.out_path.with_extension("tmp");
+	let written = File::create(&tmp_path)
+		.with_context(|| format!("failed creating output file {tmp_path:?}"))
+		.and_then(|out_file| {
+			disk.serialize_to(out_file, bake_key)
+				.with_context(|| format!("zstd stream failed writing {tmp_path:?}"))
+		})
+		.and_then(|()| {
+			fs::rename(&tmp_path, &model.out_path)
+				.with_context(|| format!("failed moving {tmp_path:?} to {:?}", model.out_path))
+		});
+	if written.is_err() {
+		let _ = fs::remove_file(&tmp_path);
+	}
+	written?;
+	Ok(BakeStatus::Rebuilt { warnings })
+}
+
+/// Removes the output of a model that failed to bake, so the last good bake isn't loaded in its place.
+fn remove_failed_output(model: &GltfFile, err: anyhow::Error) -> anyhow::Error {
+	match fs::remove_file(&model.out_path) {
+		Err(remove) if remove.kind() != io::ErrorKind::NotFound => err.context(format!(
+			"failed removing outdated output {:?}: {remove}",
+			model.out_path
+		)),
+		_ => err,
+	}
+}
+
+/// All warnings of models that were rebaked, prefixed with the model's path.
+pub fn bake_warnings(models: &[GltfFile]) -> impl Iterator<Item = String> + '_ {
+	models.iter().flat_map(|model| {
+		model
+			.status
+			.warnings()
+			.iter()
+			.map(|warning| format!("{:?}: {warning}", model.relative))
+	})
+}
+
+/// Returns an error listing all models that failed to bake, if any.
+pub fn ensure_all_baked(models: &[GltfFile]) -> anyhow::Result<()> {
+	let failed = models
+		.iter()
+		.filter_map(|model| match &model.status {
+			BakeStatus::Failed(err) => Some(format!("{:?}: {err:?}", model.relative)),
+			_ => None,
+		})
+		.collect::<Vec<_>>();
+	if failed.is_empty() {
+		Ok(())
+	} else {
+		anyhow::bail!("{} model(s) failed to bake:\n{}", failed.len(), failed.join("\n"))
+	}
+}
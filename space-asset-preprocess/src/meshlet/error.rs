@@ -1,12 +1,13 @@
+use gltf::mesh::Mode;
 use std::fmt::{Debug, Display, Formatter};
 
 #[derive(Debug)]
 pub enum MeshletError {
-	PrimitiveMustBeTriangleList,
+	UnsupportedPrimitiveMode(Mode),
 	NoVertexPositions,
 	NoTextureCoords,
 	NoNormals,
-	MultipleTextureCoords,
+	UnsupportedTextureCoordSet(u32),
 	MissingTextures,
 	NoDefaultScene,
 }
@@ -14,12 +15,17 @@ pub enum MeshletError {
 impl Display for MeshletError {
 	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
 		match self {
-			MeshletError::PrimitiveMustBeTriangleList => f.write_str("All primitives must be triangle lists"),
+			MeshletError::UnsupportedPrimitiveMode(mode) => {
+				write!(f, "Primitive mode {mode:?} is not a triangle list, strip or fan")
+			}
 			MeshletError::NoVertexPositions => f.write_str("A mesh primitive exists with no vertex positions"),
 			MeshletError::NoTextureCoords => f.write_str("A mesh primitive exists with no texture coordinates"),
 			MeshletError::NoNormals => f.write_str("A mesh primitive exists with no normals"),
-			MeshletError::MultipleTextureCoords => {
-				f.write_str("Mesh uses multiple texture coordinates for their materials")
+			MeshletError::UnsupportedTextureCoordSet(set) => {
+				write!(
+					f,
+					"Texture coordinate set {set} is not supported, only sets 0 and 1 are"
+				)
 			}
 			MeshletError::MissingTextures => f.write_str("Some textures were missing"),
 			MeshletError::NoDefaultScene => f.write_str("No default scene exists"),
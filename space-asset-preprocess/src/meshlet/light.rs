@@ -0,0 +1,73 @@
+use crate::gltf::Gltf;
+use crate::meshlet::error::MeshletError;
+use crate::meshlet::warning::BakeWarning;
+use glam::{Affine3A, Vec3};
+use gltf::khr_lights_punctual::Kind;
+use gltf::{Node, Scene};
+use space_asset_disk::light::{PunctualLight, PunctualLightKind};
+
+/// Lights without a range are cut off where their radiance drops below this, as it would no longer be visible after
+/// tone mapping into 8 bit colors.
+pub const UNBOUNDED_LIGHT_CUTOFF: f32 = 1. / 256.;
+
+/// Imports the point and spot lights of `KHR_lights_punctual` attached to nodes of the default scene, placed in world
+/// space. Directional lights are skipped with a warning, as the sun is the only directional light.
+pub fn process_lights(gltf: &Gltf) -> anyhow::Result<(Vec<PunctualLight>, Vec<BakeWarning>)> {
+	profiling::function_scope!();
+	let scene = gltf.default_scene().ok_or(MeshletError::NoDefaultScene)?;
+	let node_transforms = gltf.absolute_node_transformations(&scene, Affine3A::default());
+	let mut lights = Vec::new();
+	let mut warnings = Vec::new();
+	for node in scene_nodes(&scene) {
+		let Some(light) = node.light() else {
+			continue;
+		};
+		let (kind, inner_cone_angle, outer_cone_angle) = match light.kind() {
+			Kind::Point => (PunctualLightKind::Point, 0., 0.),
+			Kind::Spot {
+				inner_cone_angle,
+				outer_cone_angle,
+			} => (PunctualLightKind::Spot, inner_cone_angle, outer_cone_angle),
+			Kind::Directional => {
+				warnings.push(BakeWarning::SkippedDirectionalLight {
+					light: light.index(),
+					light_name: light.name().map(str::to_string),
+				});
+				continue;
+			}
+		};
+		let transform = node_transforms[node.index()];
+		let color = Vec3::from(light.color()) * light.intensity();
+		lights.push(PunctualLight {
+			position: transform.translation.into(),
+			range: light.range().unwrap_or_else(|| unbounded_light_range(color)),
+			direction: transform.transform_vector3(-Vec3::Z).normalize(),
+			kind: kind.into(),
+			color,
+			inner_cone_cos: f32::cos(inner_cone_angle),
+			outer_cone_cos: f32::cos(outer_cone_angle),
+		});
+	}
+	Ok((lights, warnings))
+}
+
+/// All nodes of the `scene` in depth first order. Nodes outside of it are never placed in the world.
+fn scene_nodes<'a>(scene: &Scene<'a>) -> Vec<Node<'a>> {
+	fn walk<'a>(out: &mut Vec<Node<'a>>, node: Node<'a>) {
+		out.push(node.clone());
+		for child in node.children() {
+			walk(out, child);
+		}
+	}
+
+	let mut out = Vec::new();
+	for node in scene.nodes() {
+		walk(&mut out, node);
+	}
+	out
+}
+
+/// The distance at which the inverse square falloff of a light of `color` drops below [`UNBOUNDED_LIGHT_CUTOFF`]
+pub fn unbounded_light_range(color: Vec3) -> f32 {
+	f32::sqrt(color.max_element() / UNBOUNDED_LIGHT_CUTOFF)
+}
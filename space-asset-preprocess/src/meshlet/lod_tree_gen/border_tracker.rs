@@ -3,6 +3,7 @@ use crate::meshlet::lod_tree_gen::indices::{IndexPair, MeshletId};
 use crate::meshlet::lod_tree_gen::sorted_smallvec::SortedSmallVec;
 use crate::meshlet::mesh::MeshletMesh;
 use crate::meshlet::process::lod_mesh_build_meshlets;
+use crate::settings::LodSettings;
 use MeshletGroupSimplifyResult::*;
 use glam::FloatExt;
 use meshopt::{SimplifyOptions, VertexDataAdapter};
@@ -19,11 +20,7 @@ use std::fmt::Debug;
 use std::mem::{offset_of, size_of, size_of_val};
 use std::ops::Deref;
 
-const MAX_LOD_LEVEL: u32 = 50;
-const TARGET_SIMPLIFICATION_FACTOR: f32 = 0.5;
-const MINIMUM_REQUIRED_SIMPLIFICATION_FACTOR: f32 = 0.65;
-
-pub fn process_lod_tree(mut mesh: MeshletMesh) -> anyhow::Result<MeshletMesh> {
+pub fn process_lod_tree(mut mesh: MeshletMesh, settings: &LodSettings) -> anyhow::Result<MeshletMesh> {
 	for m in &mut mesh.lod_mesh.meshlets {
 		m.lod_level_bitmask = LodLevelBitmask(1);
 	}
@@ -32,27 +29,29 @@ pub fn process_lod_tree(mut mesh: MeshletMesh) -> anyhow::Result<MeshletMesh> {
 		.collect::<Vec<_>>();
 	let mut prev_groups = None;
 
-	let mut lod_levels = 1..MAX_LOD_LEVEL;
+	let mut lod_levels = 1..settings.max_lod_level;
 	for lod_level in &mut lod_levels {
-		let lod_faction = lod_level as f32 / MAX_LOD_LEVEL as f32;
+		let lod_faction = lod_level as f32 / settings.max_lod_level as f32;
 
 		// If prev_groups exists, tracker also doesn't get recreated thanks to the optimizer, according to profiling.
 		// Manually reusing it is hard due to its lifetime, so this has to be sufficient for now.
 		let tracker = BorderTracker::from_meshlet_mesh(&mesh.lod_mesh, &queue);
-		let groups = prev_groups.take().unwrap_or_else(|| tracker.metis_partition());
+		let groups = prev_groups
+			.take()
+			.unwrap_or_else(|| tracker.metis_partition(settings.meshlet_merge_cnt));
 		assert!(!groups.is_empty());
 
 		let (mut lod, parent_data) = groups
 			.par_iter()
-			.map(
-				|group| match tracker.simplify_meshlet_group(group, &mesh.pbr_material_vertices, lod_faction) {
+			.map(|group| {
+				match tracker.simplify_meshlet_group(group, &mesh.pbr_material_vertices, lod_faction, settings) {
 					SimplifiedMeshlets(mesh, sphere, error) => {
 						(mesh, SimplifiedMeshlets(LodMesh::default(), sphere, error))
 					}
 					TooLittleSimplification => (LodMesh::default(), TooLittleSimplification),
 					SimplifiedToNothing => (LodMesh::default(), SimplifiedToNothing),
-				},
-			)
+				}
+			})
 			.unzip::<_, _, LodMesh, Vec<_>>();
 
 		if parent_data.iter().all(|a| matches!(a, SimplifiedToNothing)) {
@@ -308,9 +307,8 @@ impl<'a> BorderTracker<'a> {
 	}
 
 	#[allow(clippy::needless_range_loop)]
-	pub fn metis_partition(&self) -> Vec<SmallVec<[QueueId; 6]>> {
+	pub fn metis_partition(&self, meshlet_merge_cnt: usize) -> Vec<SmallVec<[QueueId; 6]>> {
 		profiling::function_scope!();
-		let meshlet_merge_cnt = 4;
 		let n_partitions = self.queued_meshlets().div_ceil(meshlet_merge_cnt);
 		if n_partitions <= 1 {
 			return Vec::from([(0..self.queued_meshlets.len()).map(|i| QueueId(i as u32)).collect()]);
@@ -363,6 +361,7 @@ impl<'a> BorderTracker<'a> {
 		queue_ids: &[QueueId],
 		pbr_material_vertices: &[PbrVertex],
 		lod_faction: f32,
+		settings: &LodSettings,
 	) -> MeshletGroupSimplifyResult {
 		profiling::function_scope!();
 		let meshlets = queue_ids
@@ -424,12 +423,19 @@ impl<'a> BorderTracker<'a> {
 				.collect::<Vec<_>>();
 		}
 
-		let vertex_attrib_scale = 1.;
 		let vertex_attrib_weights = [
-			1., 1., 1., // normal
-			1., 1., // tex coord
-		]
-		.map(|a| a * vertex_attrib_scale);
+			settings.normal_weight,
+			settings.normal_weight,
+			settings.normal_weight,
+			settings.tex_coord_weight,
+			settings.tex_coord_weight,
+			settings.tex_coord_weight,
+			settings.tex_coord_weight,
+			settings.color_weight,
+			settings.color_weight,
+			settings.color_weight,
+			settings.color_weight,
+		];
 
 		let s_vertex_attrib;
 		{
@@ -444,13 +450,19 @@ impl<'a> BorderTracker<'a> {
 						pbr.normal.z,
 						pbr.tex_coord.x,
 						pbr.tex_coord.y,
+						pbr.tex_coord_1.x,
+						pbr.tex_coord_1.y,
+						pbr.color.x,
+						pbr.color.y,
+						pbr.color.z,
+						pbr.color.w,
 					]
 				})
 				.collect::<Vec<f32>>();
 		}
 
 		let original_indices_cnt = s_indices.len();
-		let target_count = (original_indices_cnt as f32 * TARGET_SIMPLIFICATION_FACTOR) as usize;
+		let target_count = (original_indices_cnt as f32 * settings.target_simplification_factor) as usize;
 		let target_error = f32::lerp(0.01, 0.9, lod_faction);
 
 		let adapter = VertexDataAdapter::new(
@@ -478,7 +490,7 @@ impl<'a> BorderTracker<'a> {
 		}
 
 		let simplification_factor = s_indices.len() as f32 / original_indices_cnt as f32;
-		if simplification_factor > MINIMUM_REQUIRED_SIMPLIFICATION_FACTOR {
+		if simplification_factor > settings.minimum_required_simplification_factor {
 			return TooLittleSimplification;
 		}
 
@@ -495,7 +507,13 @@ impl<'a> BorderTracker<'a> {
 
 		if !s_indices.is_empty() {
 			SimplifiedMeshlets(
-				lod_mesh_build_meshlets(&mut s_indices, &mut s_vertices, Some(group_sphere), mesh_space_error),
+				lod_mesh_build_meshlets(
+					&mut s_indices,
+					&mut s_vertices,
+					Some(group_sphere),
+					mesh_space_error,
+					settings.cone_weight,
+				),
 				group_sphere,
 				mesh_space_error,
 			)
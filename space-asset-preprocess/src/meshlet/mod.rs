@@ -1,9 +1,13 @@
+pub mod bake_cache;
 pub mod build_script;
 pub mod error;
+pub mod light;
 pub mod lod_mesh;
 pub mod lod_tree_gen;
 pub mod mesh;
 pub mod process;
+pub mod scene_graph;
+pub mod warning;
 
 #[cfg(test)]
 mod tests;
@@ -1,17 +1,23 @@
 use crate::gltf::Gltf;
+use crate::gltf::accessor::read_attribute_f32;
 use crate::image::image_processor::ImageProcessor;
-use crate::material::pbr::{process_pbr_material, process_pbr_vertices};
+use crate::material::pbr::{normal_texture_tex_coord, process_pbr_material, process_pbr_vertices};
+use crate::material::vertex_generation::generate_vertex_attributes;
 use crate::meshlet::error::MeshletError;
+use crate::meshlet::light::process_lights;
 use crate::meshlet::lod_mesh::LodMesh;
 use crate::meshlet::lod_tree_gen::border_tracker::process_lod_tree;
 use crate::meshlet::mesh::MeshletMesh;
+use crate::meshlet::scene_graph::process_scene_graph;
+use crate::meshlet::warning::BakeWarning;
+use crate::settings::PreprocessSettings;
 use glam::{Affine3A, Vec3};
 use gltf::Primitive;
-use gltf::mesh::Mode;
+use gltf::mesh::{Mode, Semantic};
 use meshopt::VertexDataAdapter;
 use rayon::prelude::*;
 use smallvec::SmallVec;
-use space_asset_disk::image::{EncodeSettings, ImageStorage};
+use space_asset_disk::image::ImageStorage;
 use space_asset_disk::material::pbr::PbrMaterialDisk;
 use space_asset_disk::meshlet::indices::triangle_indices_write_vec;
 use space_asset_disk::meshlet::instance::MeshletInstanceDisk;
@@ -19,35 +25,51 @@ use space_asset_disk::meshlet::lod_level_bitmask::LodLevelBitmask;
 use space_asset_disk::meshlet::mesh::{MeshletData, MeshletMeshDisk};
 use space_asset_disk::meshlet::offset::MeshletOffset;
 use space_asset_disk::meshlet::scene::MeshletSceneDisk;
+use space_asset_disk::meshlet::scene_graph::SceneGraphDisk;
 use space_asset_disk::meshlet::stats::{MeshletSceneStats, SourceMeshStats};
 use space_asset_disk::meshlet::vertex::{DrawVertex, MaterialVertexId};
 use space_asset_disk::meshlet::{MESHLET_MAX_TRIANGLES, MESHLET_MAX_VERTICES};
 use space_asset_disk::range::RangeU32;
+use space_asset_disk::shape::cone::NormalCone;
 use space_asset_disk::shape::sphere::Sphere;
 use std::mem::{offset_of, size_of};
 use std::ops::Range;
 
-pub fn process_meshlets(gltf: &Gltf) -> anyhow::Result<MeshletSceneDisk> {
+pub fn process_meshlets(
+	gltf: &Gltf,
+	settings: &PreprocessSettings,
+) -> anyhow::Result<(MeshletSceneDisk, Vec<BakeWarning>)> {
 	profiling::function_scope!();
 	let mut pbr_materials = None;
 	let mut meshes_instances = None;
 	rayon::in_place_scope(|scope| {
-		scope.spawn(|_| pbr_materials = Some(process_materials(gltf)));
-		scope.spawn(|_| meshes_instances = Some(process_meshes(gltf)));
+		scope.spawn(|_| pbr_materials = Some(process_materials(gltf, settings)));
+		scope.spawn(|_| meshes_instances = Some(process_meshes(gltf, settings)));
 	});
 	let (image_storage, pbr_materials) = pbr_materials.unwrap()?;
-	let (meshes, instances, src_stats) = meshes_instances.unwrap()?;
+	let (meshes, instances, scene_graph, src_stats) = meshes_instances.unwrap()?;
+	let (lights, light_warnings) = process_lights(gltf)?;
 
-	Ok(MeshletSceneDisk {
-		image_storage,
-		pbr_materials,
-		meshes,
-		instances,
-		stats: MeshletSceneStats { source: src_stats },
-	})
+	let mut warnings = skipped_primitives(gltf);
+	warnings.extend(light_warnings);
+	Ok((
+		MeshletSceneDisk {
+			image_storage,
+			pbr_materials,
+			meshes,
+			instances,
+			lights,
+			scene_graph,
+			stats: MeshletSceneStats { source: src_stats },
+		},
+		warnings,
+	))
 }
 
-fn process_materials(gltf: &Gltf) -> anyhow::Result<(ImageStorage, Vec<PbrMaterialDisk>)> {
+fn process_materials(
+	gltf: &Gltf,
+	settings: &PreprocessSettings,
+) -> anyhow::Result<(ImageStorage, Vec<PbrMaterialDisk>)> {
 	profiling::function_scope!();
 	let image_processor = ImageProcessor::new(gltf);
 	let pbr_materials = {
@@ -59,25 +81,35 @@ fn process_materials(gltf: &Gltf) -> anyhow::Result<(ImageStorage, Vec<PbrMateri
 
 	let image_storage = {
 		profiling::scope!("images");
-		let encode_settings = EncodeSettings::ultra_fast();
-		image_processor.process(encode_settings)?
+		image_processor.process(settings)?
 	};
 
 	Ok((image_storage, pbr_materials))
 }
 
-fn process_meshes(gltf: &Gltf) -> anyhow::Result<(Vec<MeshletMeshDisk>, Vec<MeshletInstanceDisk>, SourceMeshStats)> {
+fn process_meshes(
+	gltf: &Gltf,
+	settings: &PreprocessSettings,
+) -> anyhow::Result<(
+	Vec<MeshletMeshDisk>,
+	Vec<MeshletInstanceDisk>,
+	Option<SceneGraphDisk>,
+	SourceMeshStats,
+)> {
 	profiling::function_scope!();
 	let mesh_primitives = {
 		gltf.meshes()
 			.collect::<Vec<_>>()
 			.into_par_iter()
 			.map(|mesh| {
-				let vec = mesh.primitives().collect::<SmallVec<[_; 4]>>();
+				let vec = mesh
+					.primitives()
+					.filter(|primitive| is_triangle_mode(primitive.mode()))
+					.collect::<SmallVec<[_; 4]>>();
 				vec.into_par_iter()
 					.map(|primitive| {
-						let mesh = process_mesh_primitive(gltf, primitive.clone())?;
-						let mesh = process_lod_tree(mesh)?.to_meshlet_mesh_disk()?;
+						let mesh = process_mesh_primitive(gltf, primitive.clone(), settings)?;
+						let mesh = process_lod_tree(mesh, &settings.lod)?.to_meshlet_mesh_disk()?;
 						Ok::<_, anyhow::Error>(mesh)
 					})
 					.collect::<Result<Vec<_>, _>>()
@@ -101,20 +133,32 @@ fn process_meshes(gltf: &Gltf) -> anyhow::Result<(Vec<MeshletMeshDisk>, Vec<Mesh
 		(meshes, mesh2ids)
 	};
 
-	let instances = {
+	let (instances, node2instances) = {
 		profiling::scope!("instance transformations");
 		let scene = gltf.default_scene().ok_or(MeshletError::NoDefaultScene)?;
 		let node_transforms = gltf.absolute_node_transformations(&scene, Affine3A::default());
-		gltf.nodes()
-			.flat_map(|node| {
-				node.mesh().map(|mesh| MeshletInstanceDisk {
-					world_from_local: node_transforms[node.index()],
-					mesh_ids: mesh2ids[mesh.index()],
-				})
+		let mut instances = Vec::new();
+		let node2instances = gltf
+			.nodes()
+			.map(|node| {
+				let start = instances.len() as u32;
+				if let Some(mesh) = node.mesh() {
+					instances.push(MeshletInstanceDisk {
+						world_from_local: node_transforms[node.index()],
+						mesh_ids: mesh2ids[mesh.index()],
+					});
+				}
+				RangeU32::new(start, instances.len() as u32)
 			})
-			.collect::<Vec<_>>()
+			.collect::<Vec<_>>();
+		(instances, node2instances)
 	};
 
+	let scene_graph = settings
+		.scene_graph
+		.enabled
+		.then(|| process_scene_graph(gltf, &mesh2ids, &node2instances));
+
 	let stats = {
 		profiling::scope!("scene stats");
 		instances
@@ -126,34 +170,64 @@ fn process_meshes(gltf: &Gltf) -> anyhow::Result<(Vec<MeshletMeshDisk>, Vec<Mesh
 			.sum()
 	};
 
-	Ok((meshes, instances, stats))
+	Ok((meshes, instances, scene_graph, stats))
 }
 
-fn process_mesh_primitive(gltf: &Gltf, primitive: Primitive) -> anyhow::Result<MeshletMesh> {
+fn process_mesh_primitive(
+	gltf: &Gltf,
+	primitive: Primitive,
+	settings: &PreprocessSettings,
+) -> anyhow::Result<MeshletMesh> {
 	profiling::function_scope!();
-	if primitive.mode() != Mode::Triangles {
-		Err(MeshletError::PrimitiveMustBeTriangleList)?;
-	}
-
 	let reader = primitive.reader(|b| gltf.buffer(b));
-	let mut src_vertices: Vec<_> = reader
-		.read_positions()
+	let mut positions: Vec<_> = read_attribute_f32::<3>(gltf, &primitive, &Semantic::Positions)?
 		.ok_or(MeshletError::NoVertexPositions)?
-		.enumerate()
-		.map(|(i, pos)| DrawVertex {
-			position: Vec3::from_array(pos),
-			material_vertex_id: MaterialVertexId(i as u32),
-		})
+		.into_iter()
+		.map(Vec3::from_array)
 		.collect();
-	let src_vertices_len = src_vertices.len();
+	let mut pbr_material_vertices = process_pbr_vertices(gltf, primitive.clone())?;
+	assert_eq!(pbr_material_vertices.len(), positions.len());
 
-	let mut indices: Vec<_> = if let Some(indices) = reader.read_indices() {
+	let indices: Vec<_> = if let Some(indices) = reader.read_indices() {
 		indices.into_u32().collect()
 	} else {
-		(0..src_vertices_len as u32).collect()
+		(0..positions.len() as u32).collect()
+	};
+	let mut indices = triangle_list_indices(primitive.mode(), indices)?;
+
+	let generate_normals = primitive
+		.get(&Semantic::Normals)
+		.is_none()
+		.then_some(settings.vertex.missing_normals);
+	let generate_tangents = if settings.vertex.generate_tangents && primitive.get(&Semantic::Tangents).is_none() {
+		normal_texture_tex_coord(&primitive.material())?
+	} else {
+		None
 	};
+	if generate_normals.is_some() || generate_tangents.is_some() {
+		let generated = generate_vertex_attributes(
+			&positions,
+			&pbr_material_vertices,
+			&indices,
+			generate_normals,
+			generate_tangents,
+		);
+		positions = generated.positions;
+		pbr_material_vertices = generated.pbr_vertices;
+		indices = generated.indices;
+	}
+
+	let mut src_vertices: Vec<_> = positions
+		.into_iter()
+		.enumerate()
+		.map(|(i, position)| DrawVertex {
+			position,
+			material_vertex_id: MaterialVertexId(i as u32),
+		})
+		.collect();
+	let src_vertices_len = src_vertices.len();
 
-	let lod_mesh = lod_mesh_build_meshlets(&mut indices, &mut src_vertices, None, 0.);
+	let lod_mesh = lod_mesh_build_meshlets(&mut indices, &mut src_vertices, None, 0., settings.lod.cone_weight);
 
 	let stats = SourceMeshStats {
 		unique_vertices: src_vertices_len as u32,
@@ -172,9 +246,6 @@ fn process_mesh_primitive(gltf: &Gltf, primitive: Primitive) -> anyhow::Result<M
 			.unwrap_or(Vec3::ZERO),
 	};
 
-	let pbr_material_vertices = process_pbr_vertices(gltf, primitive.clone())?;
-	assert_eq!(pbr_material_vertices.len(), src_vertices_len);
-
 	Ok(MeshletMesh {
 		lod_mesh,
 		pbr_material_vertices,
@@ -183,11 +254,55 @@ fn process_mesh_primitive(gltf: &Gltf, primitive: Primitive) -> anyhow::Result<M
 	})
 }
 
+fn is_triangle_mode(mode: Mode) -> bool {
+	matches!(mode, Mode::Triangles | Mode::TriangleStrip | Mode::TriangleFan)
+}
+
+/// Warnings for all primitives skipped due to not drawing triangles.
+fn skipped_primitives(gltf: &Gltf) -> Vec<BakeWarning> {
+	gltf.meshes()
+		.flat_map(|mesh| {
+			mesh.primitives()
+				.filter(|primitive| !is_triangle_mode(primitive.mode()))
+				.map(move |primitive| BakeWarning::SkippedPrimitive {
+					mesh: mesh.index(),
+					mesh_name: mesh.name().map(str::to_string),
+					primitive: primitive.index(),
+					mode: primitive.mode(),
+				})
+		})
+		.collect()
+}
+
+/// Converts the indices of triangle strips and fans to triangle lists, removing any degenerate triangles used to
+/// restart strips.
+pub fn triangle_list_indices(mode: Mode, indices: Vec<u32>) -> Result<Vec<u32>, MeshletError> {
+	profiling::function_scope!();
+	let triangles = match mode {
+		Mode::Triangles => return Ok(indices),
+		Mode::TriangleStrip => (0..indices.len().saturating_sub(2))
+			.map(|i| [indices[i], indices[i + 1 + i % 2], indices[i + 2 - i % 2]])
+			.collect::<Vec<_>>(),
+		Mode::TriangleFan => (0..indices.len().saturating_sub(2))
+			.map(|i| [indices[i + 1], indices[i + 2], indices[0]])
+			.collect::<Vec<_>>(),
+		mode => return Err(MeshletError::UnsupportedPrimitiveMode(mode)),
+	};
+	Ok(triangles
+		.into_iter()
+		.filter(|[a, b, c]| a != b && b != c && c != a)
+		.flatten()
+		.collect())
+}
+
+/// Builds meshlets from the triangle list `indices`. Meshlets are bounded by `bounds` if given and by their own
+/// vertices otherwise, their normal cones are always computed.
 pub fn lod_mesh_build_meshlets(
 	indices: &mut [u32],
 	draw_vertices: &mut Vec<DrawVertex>,
 	bounds: Option<Sphere>,
 	error: f32,
+	cone_weight: f32,
 ) -> LodMesh {
 	profiling::function_scope!();
 	{
@@ -215,7 +330,7 @@ pub fn lod_mesh_build_meshlets(
 			&adapter,
 			MESHLET_MAX_VERTICES as usize,
 			MESHLET_MAX_TRIANGLES as usize,
-			0.,
+			cone_weight,
 		)
 	};
 
@@ -243,18 +358,22 @@ pub fn lod_mesh_build_meshlets(
 			.iter()
 			.zip(out.iter())
 			.map(|(m, meshlet)| {
+				let meshlet_bounds = meshopt::compute_meshlet_bounds(meshlet, &adapter);
 				let data = MeshletData {
 					draw_vertex_offset: MeshletOffset::new(m.vertex_offset as usize, m.vertex_count as usize),
 					triangle_offset: MeshletOffset::new(triangle_start, m.triangle_count as usize),
-					bounds: bounds.unwrap_or_else(|| {
-						let bounds = meshopt::compute_meshlet_bounds(meshlet, &adapter);
-						Sphere::new(Vec3::from_array(bounds.center), bounds.radius)
-					}),
+					bounds: bounds
+						.unwrap_or_else(|| Sphere::new(Vec3::from_array(meshlet_bounds.center), meshlet_bounds.radius)),
 					parent_bounds: Sphere::default(),
 					error,
 					parent_error: f32::INFINITY,
 					lod_level_bitmask: LodLevelBitmask::default(),
-					_pad: [0; 1],
+					normal_cone: NormalCone::new(
+						Vec3::from_array(meshlet_bounds.cone_apex),
+						Vec3::from_array(meshlet_bounds.cone_axis),
+						meshlet_bounds.cone_cutoff,
+					),
+					_pad: [0; 2],
 				};
 				triangle_start += m.triangle_count as usize;
 				data
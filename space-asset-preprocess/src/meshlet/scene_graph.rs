@@ -0,0 +1,42 @@
+use crate::gltf::Gltf;
+use space_asset_disk::meshlet::scene_graph::{SceneGraphDisk, SceneMeshDisk, SceneNodeDisk};
+use space_asset_disk::range::RangeU32;
+
+/// Exports the node hierarchy of the gltf. `mesh2ids` maps gltf meshes to [`MeshletMeshDisk`] ids and `node2instances`
+/// maps gltf nodes to the instances they created.
+///
+/// [`MeshletMeshDisk`]: space_asset_disk::meshlet::mesh::MeshletMeshDisk
+pub fn process_scene_graph(gltf: &Gltf, mesh2ids: &[RangeU32], node2instances: &[RangeU32]) -> SceneGraphDisk {
+	profiling::function_scope!();
+	let mut parents = vec![None; gltf.nodes().len()];
+	for node in gltf.nodes() {
+		for child in node.children() {
+			parents[child.index()] = Some(node.index() as u32);
+		}
+	}
+
+	let nodes = gltf
+		.nodes()
+		.map(|node| SceneNodeDisk {
+			name: node.name().map(str::to_string),
+			parent: parents[node.index()],
+			parent_from_local: Gltf::node_transformation(&node),
+			mesh: node.mesh().map(|mesh| mesh.index() as u32),
+			instances: node2instances[node.index()],
+			extras: extras_to_string(node.extras()),
+		})
+		.collect();
+	let meshes = gltf
+		.meshes()
+		.map(|mesh| SceneMeshDisk {
+			name: mesh.name().map(str::to_string),
+			mesh_ids: mesh2ids[mesh.index()],
+			extras: extras_to_string(mesh.extras()),
+		})
+		.collect();
+	SceneGraphDisk { nodes, meshes }
+}
+
+fn extras_to_string(extras: &gltf::json::Extras) -> Option<String> {
+	extras.as_ref().map(|raw| raw.get().to_string())
+}
@@ -1,5 +1,19 @@
 use crate::gltf::Gltf;
-use crate::meshlet::process::process_meshlets;
+use crate::meshlet::bake_cache::BakeStatus;
+use crate::meshlet::build_script::{build_script, ensure_all_baked};
+use crate::meshlet::light::{UNBOUNDED_LIGHT_CUTOFF, process_lights, unbounded_light_range};
+use crate::meshlet::process::{lod_mesh_build_meshlets, process_meshlets, triangle_list_indices};
+use crate::meshlet::warning::BakeWarning;
+use crate::settings::{PreprocessSettings, SceneGraphSettings};
+use base64::Engine;
+use glam::Vec3;
+use gltf::mesh::Mode;
+use space_asset_disk::light::PunctualLightKind;
+use space_asset_disk::meshlet::chunk::read_bytes_at;
+use space_asset_disk::meshlet::header::{MESHLET_SCENE_FORMAT_VERSION, MeshletSceneLoadError};
+use space_asset_disk::meshlet::scene::MeshletSceneReader;
+use space_asset_disk::meshlet::vertex::{DrawVertex, MaterialVertexId};
+use std::fs;
 use std::path::Path;
 
 const LANTERN_GLTF_PATH: &str = concat!(
@@ -10,7 +24,42 @@ const LANTERN_GLTF_PATH: &str = concat!(
 #[test]
 fn test_lantern_gltf() -> anyhow::Result<()> {
 	let gltf = Gltf::open(Path::new(LANTERN_GLTF_PATH))?;
-	let _scene = process_meshlets(&gltf)?;
+	let (_scene, _) = process_meshlets(&gltf, &PreprocessSettings::default())?;
+	Ok(())
+}
+
+#[test]
+fn test_lantern_scene_graph() -> anyhow::Result<()> {
+	let gltf = Gltf::open(Path::new(LANTERN_GLTF_PATH))?;
+	let (scene, _) = process_meshlets(&gltf, &PreprocessSettings::default())?;
+	let scene_graph = scene.scene_graph.as_ref().unwrap();
+	assert_eq!(scene_graph.nodes.len(), gltf.nodes().len());
+	assert_eq!(scene_graph.meshes.len(), gltf.meshes().len());
+	for (node, src) in scene_graph.nodes.iter().zip(gltf.nodes()) {
+		assert_eq!(node.name.as_deref(), src.name());
+		assert_eq!(node.instances.end - node.instances.start, src.mesh().is_some() as u32);
+		if let Some(parent) = node.parent {
+			assert!(
+				gltf.nodes()
+					.nth(parent as usize)
+					.unwrap()
+					.children()
+					.any(|c| c.index() == src.index())
+			);
+		}
+	}
+	let instances = scene_graph
+		.nodes
+		.iter()
+		.map(|n| n.instances.end - n.instances.start)
+		.sum::<u32>();
+	assert_eq!(instances as usize, scene.instances.len());
+
+	let settings = PreprocessSettings {
+		scene_graph: SceneGraphSettings { enabled: false },
+		..PreprocessSettings::default()
+	};
+	assert!(process_meshlets(&gltf, &settings)?.0.scene_graph.is_none());
 	Ok(())
 }
 
@@ -19,6 +68,300 @@ const PLANE_GLTF_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../models/mo
 #[test]
 fn test_plane_gltf() -> anyhow::Result<()> {
 	let gltf = Gltf::open(Path::new(PLANE_GLTF_PATH))?;
-	let _scene = process_meshlets(&gltf)?;
+	let (_scene, _) = process_meshlets(&gltf, &PreprocessSettings::default())?;
 	Ok(())
 }
+
+#[test]
+fn test_scene_header_roundtrip() -> anyhow::Result<()> {
+	let gltf = Gltf::open(Path::new(PLANE_GLTF_PATH))?;
+	let (scene, _) = process_meshlets(&gltf, &PreprocessSettings::default())?;
+	let mut bytes = Vec::new();
+	scene.serialize_to(&mut bytes, 42)?;
+
+	let reader = MeshletSceneReader::new(bytes.as_slice())?;
+	assert_eq!(reader.header().bake_key, 42);
+	assert_eq!(reader.toc().meshes.len(), scene.meshes.len());
+	assert_eq!(reader.toc().images.len(), scene.image_storage.images.len());
+	for (i, mesh) in scene.meshes.iter().enumerate() {
+		let read = reader.read_mesh(i)?;
+		assert_eq!(read.meshlets.len(), mesh.meshlets.len());
+		assert_eq!(read.draw_vertices, mesh.draw_vertices);
+		assert_eq!(read.triangles.len(), mesh.triangles.len());
+	}
+
+	let mesh_cnt = scene.meshes.len();
+	assert!(matches!(
+		reader.read_mesh(mesh_cnt),
+		Err(MeshletSceneLoadError::OutOfBounds { index, len, .. }) if index == mesh_cnt && len == mesh_cnt
+	));
+	assert!(matches!(
+		reader.read_mesh_lod_range(0, usize::MAX),
+		Err(MeshletSceneLoadError::OutOfBounds { .. })
+	));
+	assert!(matches!(
+		reader.read_image(scene.image_storage.images.len()),
+		Err(MeshletSceneLoadError::OutOfBounds { .. })
+	));
+
+	assert!(matches!(
+		MeshletSceneReader::new(&bytes[..bytes.len() - 1]),
+		Err(MeshletSceneLoadError::Truncated)
+	));
+
+	// only the chunk containing the corrupt byte fails to load
+	let mut corrupt = bytes.clone();
+	*corrupt.last_mut().unwrap() ^= 0xFF;
+	let corrupt = MeshletSceneReader::new(corrupt.as_slice())?;
+	let last_mesh = scene.meshes.len() - 1;
+	assert!(matches!(
+		corrupt.read_mesh(last_mesh),
+		Err(MeshletSceneLoadError::ContentHashMismatch)
+	));
+	assert!(corrupt.read_mesh_header(last_mesh).is_ok());
+
+	// lengths in the header are checked before allocating
+	let mut huge_toc = bytes.clone();
+	huge_toc[16..24].copy_from_slice(&u64::MAX.to_le_bytes());
+	assert!(matches!(
+		MeshletSceneReader::new(huge_toc.as_slice()),
+		Err(MeshletSceneLoadError::Truncated)
+	));
+	assert!(matches!(
+		read_bytes_at(bytes.as_slice(), u64::MAX, 2),
+		Err(MeshletSceneLoadError::Truncated)
+	));
+	assert!(matches!(
+		read_bytes_at(bytes.as_slice(), 0, u64::MAX),
+		Err(MeshletSceneLoadError::Truncated)
+	));
+
+	let mut wrong_version = bytes.clone();
+	wrong_version[8..12].copy_from_slice(&(MESHLET_SCENE_FORMAT_VERSION + 1).to_le_bytes());
+	assert!(matches!(
+		MeshletSceneReader::new(wrong_version.as_slice()),
+		Err(MeshletSceneLoadError::VersionMismatch { .. })
+	));
+	Ok(())
+}
+
+#[test]
+fn test_bake_cache() -> anyhow::Result<()> {
+	let models_dir = Path::new(PLANE_GLTF_PATH).parent().unwrap();
+	let out_dir = std::env::temp_dir().join(format!("space-asset-preprocess-bake-cache-{}", std::process::id()));
+	let _ = fs::remove_dir_all(&out_dir);
+
+	let first = build_script(models_dir, &out_dir, None, false, &PreprocessSettings::default())?;
+	assert!(matches!(first[0].status, BakeStatus::Rebuilt { .. }));
+
+	// a bake whose source gltf no longer exists
+	let stale = out_dir.join("removed.gltf.bin");
+	fs::copy(&first[0].out_path, &stale)?;
+
+	let second = build_script(models_dir, &out_dir, None, false, &PreprocessSettings::default())?;
+	assert!(matches!(second[0].status, BakeStatus::Cached));
+	assert!(!stale.exists());
+	assert!(!first[0].out_path.with_extension("tmp").exists());
+
+	fs::remove_dir_all(&out_dir)?;
+	Ok(())
+}
+
+#[test]
+fn test_failed_bake_removes_output() -> anyhow::Result<()> {
+	let dir = std::env::temp_dir().join(format!("space-asset-preprocess-failed-bake-{}", std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	let models_dir = dir.join("models");
+	let out_dir = dir.join("out");
+	fs::create_dir_all(&models_dir)?;
+	fs::create_dir_all(&out_dir)?;
+	fs::write(models_dir.join("broken.gltf"), "not a gltf")?;
+	// the last good bake of a model that now fails to bake
+	fs::write(out_dir.join("broken.gltf.bin"), "last good bake")?;
+
+	let models = build_script(&models_dir, &out_dir, None, false, &PreprocessSettings::default())?;
+	assert!(models[0].status.is_failed());
+	assert!(!models[0].out_path.exists());
+	assert!(ensure_all_baked(&models).is_err());
+
+	fs::remove_dir_all(&dir)?;
+	Ok(())
+}
+
+#[test]
+fn test_quantized_meshopt_gltf() -> anyhow::Result<()> {
+	// quantized positions, compressed with EXT_meshopt_compression into a fallback buffer without data
+	let positions: [[i16; 4]; 4] = [[0, 0, 0, 0], [100, 0, 0, 0], [0, 0, 100, 0], [100, 0, 100, 0]];
+	let mut data = meshopt::encode_vertex_buffer(&positions).unwrap();
+	let compressed_len = data.len();
+	data.resize(compressed_len.next_multiple_of(4), 0);
+	// normalized byte normals
+	let normals_offset = data.len();
+	data.extend_from_slice(&[0, 127, 0, 0].repeat(4));
+	let indices_offset = data.len();
+	data.extend([0u16, 1, 2, 2, 1, 3].iter().flat_map(|i| i.to_le_bytes()));
+
+	let uri = base64::prelude::BASE64_STANDARD.encode(&data);
+	let data_len = data.len();
+	let gltf = format!(
+		r#"{{
+		"asset": {{ "version": "2.0" }},
+		"extensionsUsed": ["KHR_mesh_quantization", "EXT_meshopt_compression"],
+		"extensionsRequired": ["KHR_mesh_quantization", "EXT_meshopt_compression"],
+		"scene": 0,
+		"scenes": [{{ "nodes": [0] }}],
+		"nodes": [{{ "mesh": 0, "scale": [0.01, 0.01, 0.01] }}],
+		"meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0, "NORMAL": 1 }}, "indices": 2 }}] }}],
+		"accessors": [
+			{{ "bufferView": 0, "componentType": 5122, "count": 4, "type": "VEC3", "min": [0, 0, 0], "max": [100, 0, 100] }},
+			{{ "bufferView": 1, "componentType": 5120, "normalized": true, "count": 4, "type": "VEC3" }},
+			{{ "bufferView": 2, "componentType": 5123, "count": 6, "type": "SCALAR" }}
+		],
+		"bufferViews": [
+			{{ "buffer": 1, "byteLength": 32, "byteStride": 8, "extensions": {{ "EXT_meshopt_compression": {{
+				"buffer": 0, "byteLength": {compressed_len}, "byteStride": 8, "count": 4, "mode": "ATTRIBUTES"
+			}} }} }},
+			{{ "buffer": 0, "byteOffset": {normals_offset}, "byteLength": 16, "byteStride": 4 }},
+			{{ "buffer": 0, "byteOffset": {indices_offset}, "byteLength": 12 }}
+		],
+		"buffers": [
+			{{ "byteLength": {data_len}, "uri": "data:application/octet-stream;base64,{uri}" }},
+			{{ "byteLength": 32, "extensions": {{ "EXT_meshopt_compression": {{ "fallback": true }} }} }}
+		]
+	}}"#
+	);
+	let dir = std::env::temp_dir().join(format!("space-asset-preprocess-quantized-{}", std::process::id()));
+	fs::create_dir_all(&dir)?;
+	let path = dir.join("quantized.gltf");
+	fs::write(&path, gltf)?;
+
+	let gltf = Gltf::open(&path)?;
+	let (scene, _) = process_meshlets(&gltf, &PreprocessSettings::default())?;
+	let stats = scene.stats.source;
+	assert!(stats.bounds_min.abs_diff_eq(Vec3::ZERO, 1e-5));
+	assert!(stats.bounds_max.abs_diff_eq(Vec3::new(1., 0., 1.), 1e-5));
+	assert_eq!(stats.triangles, 2);
+	assert!(
+		scene.meshes[0]
+			.pbr_material_vertices
+			.iter()
+			.all(|v| v.normal.abs_diff_eq(Vec3::Y, 1e-5))
+	);
+
+	fs::remove_dir_all(&dir)?;
+	Ok(())
+}
+
+#[test]
+fn test_punctual_lights_gltf() -> anyhow::Result<()> {
+	let gltf = r#"{
+		"asset": { "version": "2.0" },
+		"extensionsUsed": ["KHR_lights_punctual"],
+		"extensions": { "KHR_lights_punctual": { "lights": [
+			{ "type": "point", "color": [1, 0.5, 0.25], "intensity": 4, "range": 10 },
+			{ "type": "spot", "intensity": 16, "spot": { "innerConeAngle": 0.25, "outerConeAngle": 0.5 } },
+			{ "type": "directional", "name": "sun" },
+			{ "type": "point", "name": "outside of the scene" }
+		] } },
+		"scene": 0,
+		"scenes": [{ "nodes": [0, 2] }],
+		"nodes": [
+			{ "translation": [0, 2, 0], "children": [1], "extensions": { "KHR_lights_punctual": { "light": 0 } } },
+			{
+				"translation": [1, 0, 0],
+				"rotation": [-0.70710677, 0, 0, 0.70710677],
+				"extensions": { "KHR_lights_punctual": { "light": 1 } }
+			},
+			{ "extensions": { "KHR_lights_punctual": { "light": 2 } } },
+			{ "extensions": { "KHR_lights_punctual": { "light": 3 } } }
+		]
+	}"#;
+	let dir = std::env::temp_dir().join(format!("space-asset-preprocess-lights-{}", std::process::id()));
+	fs::create_dir_all(&dir)?;
+	let path = dir.join("lights.gltf");
+	fs::write(&path, gltf)?;
+
+	let gltf = Gltf::open(&path)?;
+	let (lights, warnings) = process_lights(&gltf)?;
+	// the node outside of the default scene is skipped
+	assert_eq!(lights.len(), 2);
+	let point = lights[0];
+	assert_eq!(point.kind(), PunctualLightKind::Point);
+	assert!(point.position.abs_diff_eq(Vec3::new(0., 2., 0.), 1e-5));
+	assert!(point.color.abs_diff_eq(Vec3::new(4., 2., 1.), 1e-5));
+	assert_eq!(point.range, 10.);
+
+	// child of the point light's node, pointing down
+	let spot = lights[1];
+	assert_eq!(spot.kind(), PunctualLightKind::Spot);
+	assert!(spot.position.abs_diff_eq(Vec3::new(1., 2., 0.), 1e-5));
+	assert!(spot.direction.abs_diff_eq(-Vec3::Y, 1e-5), "{:?}", spot.direction);
+	assert!((spot.inner_cone_cos - f32::cos(0.25)).abs() < 1e-6);
+	assert!((spot.outer_cone_cos - f32::cos(0.5)).abs() < 1e-6);
+	// without a range, the light ends where it's no longer visible
+	assert!((spot.range - unbounded_light_range(Vec3::splat(16.))).abs() < 1e-3);
+	assert!((16. / (spot.range * spot.range) - UNBOUNDED_LIGHT_CUTOFF).abs() < 1e-6);
+
+	assert!(matches!(
+		warnings.as_slice(),
+		[BakeWarning::SkippedDirectionalLight { light: 2, light_name: Some(name) }] if name == "sun"
+	));
+
+	fs::remove_dir_all(&dir)?;
+	Ok(())
+}
+
+#[test]
+fn test_triangle_list_indices() {
+	assert_eq!(
+		triangle_list_indices(Mode::TriangleStrip, vec![0, 1, 2, 3]).unwrap(),
+		[0, 1, 2, 1, 3, 2]
+	);
+	assert_eq!(
+		triangle_list_indices(Mode::TriangleFan, vec![0, 1, 2, 3]).unwrap(),
+		[1, 2, 0, 2, 3, 0]
+	);
+	// degenerate triangles are removed
+	assert_eq!(
+		triangle_list_indices(Mode::TriangleStrip, vec![0, 1, 2, 2, 3, 4]).unwrap(),
+		[0, 1, 2, 2, 4, 3]
+	);
+	assert!(triangle_list_indices(Mode::Lines, vec![0, 1]).is_err());
+}
+
+#[test]
+fn test_normal_cone_culling() {
+	// grid of 8x8 quads in the xy plane, facing +Z
+	let mut draw_vertices = (0..81)
+		.map(|i| DrawVertex {
+			position: Vec3::new((i % 9) as f32, (i / 9) as f32, 0.),
+			material_vertex_id: MaterialVertexId(i),
+		})
+		.collect::<Vec<_>>();
+	let mut indices = (0..64)
+		.flat_map(|quad| {
+			let a = quad % 8 + quad / 8 * 9;
+			[a, a + 1, a + 10, a, a + 10, a + 9]
+		})
+		.collect::<Vec<_>>();
+	let lod_mesh = lod_mesh_build_meshlets(&mut indices, &mut draw_vertices, None, 0., 0.25);
+
+	for meshlet in &lod_mesh.meshlets {
+		let cone = meshlet.normal_cone;
+		assert!(cone.axis.abs_diff_eq(Vec3::Z, 1e-2), "{cone:?}");
+		for x in (-20..=20).step_by(5) {
+			for y in (-20..=20).step_by(5) {
+				for z in (-20..=20).step_by(5) {
+					let camera = Vec3::new(x as f32, y as f32, z as f32);
+					// all triangles face away from cameras behind the plane, or are seen edge-on
+					if cone.is_backfacing(camera) {
+						assert!(camera.z <= 0., "{cone:?} culled for camera {camera}");
+					}
+					if z <= -10 && x.abs() <= 10 && y.abs() <= 10 {
+						assert!(cone.is_backfacing(camera), "{cone:?} not culled for camera {camera}");
+					}
+				}
+			}
+		}
+	}
+}
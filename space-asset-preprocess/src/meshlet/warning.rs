@@ -0,0 +1,38 @@
+use gltf::mesh::Mode;
+use std::fmt::{Display, Formatter};
+
+/// Issues encountered while baking that did not prevent the model from being baked.
+#[derive(Clone, Debug)]
+pub enum BakeWarning {
+	/// primitives drawing points or lines can't be converted to meshlets
+	SkippedPrimitive {
+		mesh: usize,
+		mesh_name: Option<String>,
+		primitive: usize,
+		mode: Mode,
+	},
+	/// only point and spot lights are imported
+	SkippedDirectionalLight { light: usize, light_name: Option<String> },
+}
+
+impl Display for BakeWarning {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			BakeWarning::SkippedPrimitive {
+				mesh,
+				mesh_name,
+				primitive,
+				mode,
+			} => write!(
+				f,
+				"Skipped primitive {primitive} of mesh {mesh} {:?} with unsupported mode {mode:?}",
+				mesh_name.as_deref().unwrap_or("")
+			),
+			BakeWarning::SkippedDirectionalLight { light, light_name } => write!(
+				f,
+				"Skipped directional light {light} {:?}, only point and spot lights are supported",
+				light_name.as_deref().unwrap_or("")
+			),
+		}
+	}
+}
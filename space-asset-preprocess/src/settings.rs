@@ -0,0 +1,304 @@
+use anyhow::ensure;
+use serde::{Deserialize, Serialize};
+use space_asset_disk::image::EncodeSettings;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::{fs, io};
+
+/// File name of per-directory settings overrides, applies to all models within the directory and its subdirectories.
+pub const DIRECTORY_SETTINGS_FILE_NAME: &str = "preprocess.toml";
+
+/// File extension appended to a model's file name for per-model settings overrides, e.g.
+/// `Lantern.gltf.preprocess.toml`.
+pub const MODEL_SETTINGS_EXTENSION: &str = "preprocess.toml";
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PreprocessSettings {
+	pub image: ImageSettings,
+	pub lod: LodSettings,
+	pub scene_graph: SceneGraphSettings,
+	pub vertex: VertexSettings,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImageSettings {
+	pub quality: ImageEncodeQuality,
+}
+
+/// Presets of [`EncodeSettings`], from fastest to highest quality.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageEncodeQuality {
+	#[default]
+	UltraFast,
+	VeryFast,
+	Fast,
+	Basic,
+	Slow,
+}
+
+impl ImageEncodeQuality {
+	pub fn encode_settings(&self) -> EncodeSettings {
+		match self {
+			ImageEncodeQuality::UltraFast => EncodeSettings::ultra_fast(),
+			ImageEncodeQuality::VeryFast => EncodeSettings::very_fast(),
+			ImageEncodeQuality::Fast => EncodeSettings::fast(),
+			ImageEncodeQuality::Basic => EncodeSettings::basic(),
+			ImageEncodeQuality::Slow => EncodeSettings::slow(),
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LodSettings {
+	/// maximum amount of lod levels generated
+	pub max_lod_level: u32,
+	/// the amount of triangles a meshlet group should be simplified to, relative to its original triangle count
+	pub target_simplification_factor: f32,
+	/// if a meshlet group could not be simplified below this factor, it is retried in the next lod level
+	pub minimum_required_simplification_factor: f32,
+	/// the amount of meshlets METIS should merge into a single group
+	pub meshlet_merge_cnt: usize,
+	/// simplification weight of the normal attribute
+	pub normal_weight: f32,
+	/// simplification weight of the texture coordinate attributes
+	pub tex_coord_weight: f32,
+	/// simplification weight of the vertex color attribute
+	pub color_weight: f32,
+	/// how much meshlet building favors meshlets with similar triangle normals, `0.` to `1.`, improving backface
+	/// culling at the cost of a bit more meshlets
+	pub cone_weight: f32,
+}
+
+impl LodSettings {
+	/// Rejects settings the LOD generation can't work with, e.g. a `meshlet_merge_cnt` of 0
+	pub fn validate(&self) -> anyhow::Result<()> {
+		ensure!(self.max_lod_level >= 1, "lod.max_lod_level must be at least 1");
+		for (name, factor) in [
+			("target_simplification_factor", self.target_simplification_factor),
+			(
+				"minimum_required_simplification_factor",
+				self.minimum_required_simplification_factor,
+			),
+		] {
+			ensure!(
+				factor > 0. && factor <= 1.,
+				"lod.{name} must be within (0, 1], got {factor}"
+			);
+		}
+		ensure!(self.meshlet_merge_cnt >= 1, "lod.meshlet_merge_cnt must be at least 1");
+		for (name, weight) in [
+			("normal_weight", self.normal_weight),
+			("tex_coord_weight", self.tex_coord_weight),
+			("color_weight", self.color_weight),
+		] {
+			ensure!(
+				weight.is_finite() && weight >= 0.,
+				"lod.{name} must be finite and non-negative, got {weight}"
+			);
+		}
+		ensure!(
+			(0. ..=1.).contains(&self.cone_weight),
+			"lod.cone_weight must be within [0, 1], got {}",
+			self.cone_weight
+		);
+		Ok(())
+	}
+}
+
+impl Default for LodSettings {
+	fn default() -> Self {
+		Self {
+			max_lod_level: 50,
+			target_simplification_factor: 0.5,
+			minimum_required_simplification_factor: 0.65,
+			meshlet_merge_cnt: 4,
+			normal_weight: 1.,
+			tex_coord_weight: 1.,
+			color_weight: 1.,
+			cone_weight: 0.25,
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SceneGraphSettings {
+	/// export node names, hierarchy and extras, required to look up instances by name at runtime
+	pub enabled: bool,
+}
+
+impl Default for SceneGraphSettings {
+	fn default() -> Self {
+		Self { enabled: true }
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VertexSettings {
+	/// how to generate normals of primitives without any
+	pub missing_normals: NormalGeneration,
+	/// generate MikkTSpace tangents for primitives with a normal texture but without tangents
+	pub generate_tangents: bool,
+}
+
+impl Default for VertexSettings {
+	fn default() -> Self {
+		Self {
+			missing_normals: NormalGeneration::Flat,
+			generate_tangents: true,
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NormalGeneration {
+	/// normal of each triangle, as required by the glTF spec
+	#[default]
+	Flat,
+	/// area weighted average of the normals of all triangles sharing a vertex position
+	Smooth,
+}
+
+impl PreprocessSettings {
+	pub fn from_toml(toml: &str) -> anyhow::Result<Self> {
+		let settings: Self = toml::from_str(toml)?;
+		settings.validate()?;
+		Ok(settings)
+	}
+
+	pub fn to_toml(&self) -> String {
+		toml::to_string(self).expect("PreprocessSettings are always serializable")
+	}
+
+	/// Returns a copy of these settings with the TOML `overrides` applied on top. Fields absent in `overrides` are
+	/// left unchanged.
+	pub fn with_overrides(&self, overrides: &str) -> anyhow::Result<Self> {
+		let mut value = toml::Value::try_from(self)?;
+		if let toml::Value::Table(table) = &mut value {
+			merge_tables(table, toml::from_str(overrides)?);
+		}
+		let settings: Self = value.try_into()?;
+		settings.validate()?;
+		Ok(settings)
+	}
+
+	/// Rejects values out of range, see [`LodSettings::validate`]
+	pub fn validate(&self) -> anyhow::Result<()> {
+		self.lod.validate()
+	}
+
+	/// Applies all override files applicable to the model at `model_path`: First all [`DIRECTORY_SETTINGS_FILE_NAME`]
+	/// files from `models_dir` down to the model's directory, then the per-model `<model>.preprocess.toml`.
+	pub fn for_model(&self, models_dir: &Path, model_path: &Path) -> anyhow::Result<Self> {
+		let mut settings = *self;
+		let mut dirs = model_path
+			.ancestors()
+			.skip(1)
+			.take_while(|dir| dir.starts_with(models_dir))
+			.collect::<Vec<_>>();
+		dirs.reverse();
+		let model_file = model_path.with_file_name(format!(
+			"{}.{MODEL_SETTINGS_EXTENSION}",
+			model_path.file_name().map(|c| c.to_string_lossy()).unwrap_or_default()
+		));
+		for file in dirs
+			.into_iter()
+			.map(|dir| dir.join(DIRECTORY_SETTINGS_FILE_NAME))
+			.chain([model_file])
+		{
+			if let Some(overrides) = read_optional(&file)? {
+				settings = settings
+					.with_overrides(&overrides)
+					.map_err(|err| err.context(format!("invalid settings file {file:?}")))?;
+			}
+		}
+		Ok(settings)
+	}
+}
+
+impl Hash for PreprocessSettings {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		// floats are not Hash, but their TOML representation is stable
+		self.to_toml().hash(state);
+	}
+}
+
+fn read_optional(path: &Path) -> io::Result<Option<String>> {
+	match fs::read_to_string(path) {
+		Ok(s) => Ok(Some(s)),
+		Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+		Err(err) => Err(err),
+	}
+}
+
+fn merge_tables(base: &mut toml::Table, overrides: toml::Table) {
+	for (key, value) in overrides {
+		match (base.get_mut(&key), value) {
+			(Some(toml::Value::Table(base)), toml::Value::Table(value)) => merge_tables(base, value),
+			(_, value) => {
+				base.insert(key, value);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_settings_overrides() -> anyhow::Result<()> {
+		let base = PreprocessSettings::default();
+		let settings = base.with_overrides(
+			r#"
+			[image]
+			quality = "slow"
+			[lod]
+			max_lod_level = 10
+			"#,
+		)?;
+		assert_eq!(settings.image.quality, ImageEncodeQuality::Slow);
+		assert_eq!(settings.lod.max_lod_level, 10);
+		assert_eq!(settings.lod.meshlet_merge_cnt, base.lod.meshlet_merge_cnt);
+
+		assert_eq!(PreprocessSettings::from_toml(&settings.to_toml())?, settings);
+		assert!(base.with_overrides("[lod]\nunknown = 1").is_err());
+		Ok(())
+	}
+
+	#[test]
+	fn test_settings_validation() -> anyhow::Result<()> {
+		let base = PreprocessSettings::default();
+		base.validate()?;
+		assert!(base.with_overrides("[lod]\nmeshlet_merge_cnt = 0").is_err());
+		assert!(base.with_overrides("[lod]\nmax_lod_level = 0").is_err());
+		assert!(
+			base.with_overrides("[lod]\ntarget_simplification_factor = 0.0")
+				.is_err()
+		);
+		assert!(
+			base.with_overrides("[lod]\nminimum_required_simplification_factor = 1.5")
+				.is_err()
+		);
+		assert!(base.with_overrides("[lod]\nnormal_weight = -1.0").is_err());
+		assert!(base.with_overrides("[lod]\ncone_weight = 2.0").is_err());
+		assert!(PreprocessSettings::from_toml("[lod]\nmeshlet_merge_cnt = 0").is_err());
+		assert_eq!(
+			base.with_overrides("[lod]\nmeshlet_merge_cnt = 1")?
+				.lod
+				.meshlet_merge_cnt,
+			1
+		);
+
+		let mut settings = base;
+		settings.lod.max_lod_level = 0;
+		assert!(settings.validate().is_err());
+		Ok(())
+	}
+}
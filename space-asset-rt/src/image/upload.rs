@@ -15,9 +15,9 @@ use rust_gpu_bindless::pipeline::{
 use rust_gpu_bindless_shaders::descriptor::{Image, Image2d};
 use smallvec::SmallVec;
 use space_asset_disk::image::{
-	ArchivedImageStorage, DynImage, ImageDiskTrait, ImageType, RuntimeImageCompression, RuntimeImageMetadata,
-	SinglePixelMetadata,
+	DynImage, ImageDiskTrait, ImageType, RuntimeImageCompression, RuntimeImageMetadata, SinglePixelMetadata,
 };
+use space_asset_disk::meshlet::chunk::ArchivedChunk;
 use std::future::Future;
 
 pub struct UploadedImages {
@@ -27,10 +27,16 @@ pub struct UploadedImages {
 }
 
 impl UploadedImages {
-	pub fn new<'a>(
+	/// Uploads `image_count` images, each read by `read_image` and dropped once it has been decoded into its staging
+	/// buffer, so that only a few images are held in memory at once.
+	pub fn new<'a, 'b, F>(
 		bindless: &'a Bindless,
-		storage: &'a ArchivedImageStorage,
-	) -> impl Future<Output = anyhow::Result<Self>> + 'a {
+		image_count: usize,
+		read_image: F,
+	) -> impl Future<Output = anyhow::Result<Self>> + use<'a, F>
+	where
+		F: Fn(usize) -> anyhow::Result<(ArchivedChunk<DynImage<'static>>, &'b str)> + Sync,
+	{
 		let defaults = join_all(
 			[
 				(Vec4::splat(1.), "default_white_texture"),
@@ -46,17 +52,18 @@ impl UploadedImages {
 				)
 			}),
 		);
-		let images = join_all(
-			storage
-				.images
-				.par_iter()
-				.map(|i| upload_image(bindless, &i.0.to_image(), &i.1))
-				.collect::<Vec<_>>(),
-		);
+		let images = (0..image_count)
+			.into_par_iter()
+			.map(|i| {
+				let (image, name) = read_image(i)?;
+				Ok(upload_image(bindless, &image.root().to_image(), name))
+			})
+			.collect::<anyhow::Result<Vec<_>>>()
+			.map(join_all);
 		async {
 			let defaults = defaults.await;
 			Ok(Self {
-				images: images.await.into_iter().collect::<Result<Vec<_>, _>>()?,
+				images: images?.await.into_iter().collect::<Result<Vec<_>, _>>()?,
 				default_white_texture: defaults[0].as_ref().unwrap().clone(),
 				default_normal_texture: defaults[1].as_ref().unwrap().clone(),
 			})
@@ -1,9 +1,10 @@
 use crate::image::upload::UploadedImages;
 use crate::upload_traits::ToStrong;
+use crate::uploader::deserialize_infallible;
 use rust_gpu_bindless::descriptor::{RC, RCDescExt};
 use rust_gpu_bindless_shaders::descriptor::Strong;
 use space_asset_disk::material::pbr::ArchivedPbrMaterialDisk;
-use space_asset_shader::material::pbr::PbrMaterial;
+use space_asset_shader::material::pbr::{AlphaMode, PbrMaterial, TextureCoordinates};
 
 pub struct PbrMaterials<'a> {
 	pub pbr_materials: &'a [PbrMaterial<RC>],
@@ -16,13 +17,24 @@ impl ToStrong for PbrMaterial<RC> {
 	fn to_strong(&self) -> Self::StrongType {
 		PbrMaterial {
 			base_color: self.base_color.to_strong(),
+			base_color_tex_coord: self.base_color_tex_coord,
 			base_color_factor: self.base_color_factor,
 			normal: self.normal.to_strong(),
+			normal_tex_coord: self.normal_tex_coord,
 			normal_scale: self.normal_scale,
-			occlusion_roughness_metallic: self.occlusion_roughness_metallic.to_strong(),
-			occlusion_strength: self.occlusion_strength,
+			metallic_roughness: self.metallic_roughness.to_strong(),
+			metallic_roughness_tex_coord: self.metallic_roughness_tex_coord,
 			metallic_factor: self.metallic_factor,
 			roughness_factor: self.roughness_factor,
+			occlusion: self.occlusion.to_strong(),
+			occlusion_tex_coord: self.occlusion_tex_coord,
+			occlusion_strength: self.occlusion_strength,
+			emissive: self.emissive.to_strong(),
+			emissive_tex_coord: self.emissive_tex_coord,
+			emissive_factor: self.emissive_factor,
+			alpha_mode: self.alpha_mode,
+			alpha_cutoff: self.alpha_cutoff,
+			double_sided: self.double_sided,
 		}
 	}
 }
@@ -39,6 +51,7 @@ pub fn upload_pbr_material(
 			.map(|tex| uploader.archived_image(tex))
 			.unwrap_or(&uploader.default_white_texture)
 			.clone(),
+		base_color_tex_coord: deserialize_infallible(&this.base_color_tex_coord),
 		base_color_factor: this.base_color_factor.map(|i| i.to_native()),
 		normal: this
 			.normal
@@ -46,28 +59,61 @@ pub fn upload_pbr_material(
 			.map(|tex| uploader.archived_image(tex))
 			.unwrap_or(&uploader.default_normal_texture)
 			.clone(),
+		normal_tex_coord: deserialize_infallible(&this.normal_tex_coord),
 		normal_scale: this.normal_scale.to_native(),
-		occlusion_roughness_metallic: this
-			.occlusion_roughness_metallic
+		metallic_roughness: this
+			.metallic_roughness
 			.as_ref()
 			.map(|tex| uploader.archived_image(tex))
 			.unwrap_or(&uploader.default_white_texture)
 			.clone(),
-		occlusion_strength: this.occlusion_strength.to_native(),
+		metallic_roughness_tex_coord: deserialize_infallible(&this.metallic_roughness_tex_coord),
 		metallic_factor: this.metallic_factor.to_native(),
 		roughness_factor: this.roughness_factor.to_native(),
+		occlusion: if this.occlusion_in_metallic_roughness {
+			// sampling the red channel of the shared image
+			this.metallic_roughness.as_ref().map(|tex| uploader.archived_image(tex))
+		} else {
+			this.occlusion.as_ref().map(|tex| uploader.archived_image(tex))
+		}
+		.unwrap_or(&uploader.default_white_texture)
+		.clone(),
+		occlusion_tex_coord: deserialize_infallible(&this.occlusion_tex_coord),
+		occlusion_strength: this.occlusion_strength.to_native(),
+		emissive: this
+			.emissive
+			.as_ref()
+			.map(|tex| uploader.archived_image(tex))
+			.unwrap_or(&uploader.default_white_texture)
+			.clone(),
+		emissive_tex_coord: deserialize_infallible(&this.emissive_tex_coord),
+		emissive_factor: this.emissive_factor.map(|i| i.to_native()),
+		alpha_mode: deserialize_infallible::<_, AlphaMode>(&this.alpha_mode).into(),
+		alpha_cutoff: this.alpha_cutoff.to_native(),
+		double_sided: this.double_sided as u32,
 	})
 }
 
 pub fn default_pbr_material(uploader: &UploadedImages) -> PbrMaterial<RC> {
 	PbrMaterial {
 		base_color: uploader.default_white_texture.clone(),
+		base_color_tex_coord: TextureCoordinates::default(),
 		base_color_factor: [1.; 4],
 		normal: uploader.default_normal_texture.clone(),
+		normal_tex_coord: TextureCoordinates::default(),
 		normal_scale: 1.,
-		occlusion_roughness_metallic: uploader.default_white_texture.clone(),
-		occlusion_strength: 1.,
+		metallic_roughness: uploader.default_white_texture.clone(),
+		metallic_roughness_tex_coord: TextureCoordinates::default(),
 		metallic_factor: 1.,
 		roughness_factor: 1.,
+		occlusion: uploader.default_white_texture.clone(),
+		occlusion_tex_coord: TextureCoordinates::default(),
+		occlusion_strength: 1.,
+		emissive: uploader.default_white_texture.clone(),
+		emissive_tex_coord: TextureCoordinates::default(),
+		emissive_factor: [0.; 3],
+		alpha_mode: AlphaMode::Opaque.into(),
+		alpha_cutoff: 0.5,
+		double_sided: 0,
 	}
 }
@@ -1,10 +1,11 @@
 use crate::material::pbr::PbrMaterials;
 use crate::upload_traits::ToStrong;
-use crate::uploader::{Uploader, deserialize_infallible};
+use crate::uploader::Uploader;
 use rust_gpu_bindless::descriptor::{RC, RCDescExt};
 use rust_gpu_bindless_shaders::descriptor::Strong;
-use space_asset_disk::meshlet::mesh::ArchivedMeshletMeshDisk;
+use space_asset_disk::meshlet::mesh::MeshletMeshDisk;
 use space_asset_shader::meshlet::mesh::MeshletMesh;
+use space_asset_shader::shape::sphere::Sphere;
 use std::future::Future;
 
 impl ToStrong for MeshletMesh<RC> {
@@ -18,37 +19,42 @@ impl ToStrong for MeshletMesh<RC> {
 			num_meshlets: self.num_meshlets,
 			pbr_material: self.pbr_material.to_strong(),
 			pbr_material_vertices: self.pbr_material_vertices.to_strong(),
+			bounds: self.bounds,
 		}
 	}
 }
 
+/// Copies `this` into buffers before returning, so it may be dropped before the returned future completes.
 pub fn upload_mesh<'a>(
-	this: &'a ArchivedMeshletMeshDisk,
+	this: &MeshletMeshDisk,
 	uploader: &'a Uploader,
 	pbr_materials: &'a PbrMaterials<'a>,
-) -> impl Future<Output = anyhow::Result<MeshletMesh<RC>>> + 'a {
+) -> impl Future<Output = anyhow::Result<MeshletMesh<RC>>> + use<'a> {
 	profiling::scope!("upload_mesh");
-	let meshlets = uploader.upload_buffer_iter("meshlets", this.meshlets.iter().map(deserialize_infallible));
-	let draw_vertices =
-		uploader.upload_buffer_iter("draw_vertices", this.draw_vertices.iter().map(deserialize_infallible));
-	let triangles = uploader.upload_buffer_iter("triangles", this.triangles.iter().map(deserialize_infallible));
-	let pbr_material_vertices = uploader.upload_buffer_iter(
-		"pbr_material_vertices",
-		this.pbr_material_vertices.iter().map(deserialize_infallible),
+	let meshlets = uploader.upload_buffer_iter("meshlets", this.meshlets.iter().copied());
+	let draw_vertices = uploader.upload_buffer_iter("draw_vertices", this.draw_vertices.iter().copied());
+	let triangles = uploader.upload_buffer_iter("triangles", this.triangles.iter().copied());
+	let pbr_material_vertices =
+		uploader.upload_buffer_iter("pbr_material_vertices", this.pbr_material_vertices.iter().copied());
+	let num_meshlets = this.meshlets.len() as u32;
+	let pbr_material_id = this.pbr_material_id;
+	let bounds = Sphere::new(
+		(this.stats.bounds_min + this.stats.bounds_max) / 2.,
+		this.stats.bounds_min.distance(this.stats.bounds_max) / 2.,
 	);
-	let pbr_material_id: Option<u32> = deserialize_infallible(&this.pbr_material_id);
 	async move {
 		Ok(MeshletMesh {
 			meshlets: meshlets.await?,
 			draw_vertices: draw_vertices.await?,
 			triangles: triangles.await?,
-			num_meshlets: this.meshlets.len() as u32,
+			num_meshlets,
 			pbr_material: pbr_material_id
 				.map_or(pbr_materials.default_pbr_material, |i| {
 					pbr_materials.pbr_materials.get(i as usize).unwrap()
 				})
 				.clone(),
 			pbr_material_vertices: pbr_material_vertices.await?,
+			bounds,
 		})
 	}
 }
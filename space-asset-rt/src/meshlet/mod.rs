@@ -1,2 +1,3 @@
 pub mod mesh;
 pub mod scene;
+pub mod scene_graph;
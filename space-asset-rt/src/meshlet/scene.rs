@@ -1,6 +1,7 @@
 use crate::image::upload::UploadedImages;
 use crate::material::pbr::{PbrMaterials, default_pbr_material, upload_pbr_material};
 use crate::meshlet::mesh::upload_mesh;
+use crate::meshlet::scene_graph::SceneGraphCpu;
 use crate::upload_traits::ToStrong;
 use crate::uploader::{Uploader, deserialize_infallible};
 use futures::future::join_all;
@@ -10,7 +11,10 @@ use rust_gpu_bindless::descriptor::{
 	Bindless, BindlessAllocationScheme, BindlessBufferCreateInfo, BindlessBufferUsage, RC, RCDesc, RCDescExt,
 };
 use rust_gpu_bindless_shaders::descriptor::{Buffer, Strong};
-use space_asset_disk::meshlet::scene::ArchivedMeshletSceneDisk;
+use space_asset_disk::light::PunctualLight;
+use space_asset_disk::meshlet::chunk::ChunkSource;
+use space_asset_disk::meshlet::header::MeshletSceneLoadError;
+use space_asset_disk::meshlet::scene::MeshletSceneReader;
 use space_asset_disk::meshlet::stats::MeshletSceneStats;
 use space_asset_disk::range::{ArchivedRangeU32, RangeU32};
 use space_asset_shader::affine_transform::AffineTransform;
@@ -23,6 +27,9 @@ use space_asset_shader::meshlet::scene::MeshletScene;
 pub struct MeshletSceneCpu {
 	pub meshes: RCDesc<Buffer<[MeshletMesh<Strong>]>>,
 	pub instances: Vec<MeshInstance>,
+	pub lights: Vec<PunctualLight>,
+	/// `None` if the scene was baked without a scene graph
+	pub scene_graph: Option<SceneGraphCpu>,
 	pub stats: MeshletSceneStats,
 }
 
@@ -31,14 +38,24 @@ pub struct InstancedMeshletSceneCpu {
 	pub instance_count: UVec3,
 	pub scene: RCDesc<Buffer<MeshletScene<Strong>>>,
 	pub num_instances: u32,
+	/// contains a single unused light if the scene has no lights, as buffers must not be empty
+	pub lights: RCDesc<Buffer<[PunctualLight]>>,
+	pub num_lights: u32,
 }
 
-pub async fn upload_scene(this: &ArchivedMeshletSceneDisk, uploader: &Uploader) -> anyhow::Result<MeshletSceneCpu> {
+pub async fn upload_scene(
+	reader: &MeshletSceneReader<impl ChunkSource>,
+	uploader: &Uploader,
+) -> anyhow::Result<MeshletSceneCpu> {
 	profiling::function_scope!();
+	let this = reader.toc();
 
 	let uploaded_images = {
 		profiling::scope!("image upload");
-		UploadedImages::new(&uploader.bindless, &this.image_storage).await?
+		UploadedImages::new(&uploader.bindless, this.images.len(), |i| {
+			Ok((reader.read_image(i)?, this.images[i].name.as_str()))
+		})
+		.await?
 	};
 
 	let pbr_materials: Vec<PbrMaterial<RC>> = {
@@ -56,15 +73,12 @@ pub async fn upload_scene(this: &ArchivedMeshletSceneDisk, uploader: &Uploader)
 
 	let meshes: Vec<MeshletMesh<RC>> = {
 		profiling::scope!("mesh upload");
-		join_all(
-			this.meshes
-				.par_iter()
-				.map(|mesh| upload_mesh(mesh, uploader, &pbr_materials))
-				.collect::<Vec<_>>(),
-		)
-		.await
-		.into_iter()
-		.collect::<Result<_, _>>()?
+		// each mesh is dropped once it's copied into its buffers, instead of holding all of them in memory
+		let meshes = (0..this.meshes.len())
+			.into_par_iter()
+			.map(|i| Ok(upload_mesh(&reader.read_mesh(i)?, uploader, &pbr_materials)))
+			.collect::<Result<Vec<_>, MeshletSceneLoadError>>()?;
+		join_all(meshes).await.into_iter().collect::<Result<_, _>>()?
 	};
 
 	let meshes_buffer = {
@@ -77,16 +91,24 @@ pub async fn upload_scene(this: &ArchivedMeshletSceneDisk, uploader: &Uploader)
 	let instances = this
 		.instances
 		.iter()
-		.map(|instance| MeshInstance {
-			world_from_local: AffineTransform::new(instance.world_from_local),
-			mesh_ids: deserialize_infallible::<ArchivedRangeU32, RangeU32>(&instance.mesh_ids),
+		.map(|instance| {
+			let world_from_local = AffineTransform::new(instance.world_from_local);
+			MeshInstance {
+				world_from_local,
+				prev_world_from_local: world_from_local,
+				mesh_ids: deserialize_infallible::<ArchivedRangeU32, RangeU32>(&instance.mesh_ids),
+			}
 		})
 		.collect::<Vec<_>>();
 
+	let lights = deserialize_infallible::<_, Vec<PunctualLight>>(&this.lights);
+	let scene_graph = this.scene_graph.as_ref().map(SceneGraphCpu::new).transpose()?;
 	let stats = deserialize_infallible::<_, MeshletSceneStats>(&this.stats);
 
 	Ok(MeshletSceneCpu {
 		instances,
+		lights,
+		scene_graph,
 		meshes: meshes_buffer,
 		stats,
 	})
@@ -98,20 +120,31 @@ impl MeshletSceneCpu {
 
 		let physical_offset = self.stats.source.bounds_max - self.stats.source.bounds_min;
 		let total_instances = (instance_count.x * instance_count.y * instance_count.z) as usize * self.instances.len();
+		let total_lights = (instance_count.x * instance_count.y * instance_count.z) as usize * self.lights.len();
 		let mut instances = Vec::with_capacity(total_instances);
+		let mut lights = Vec::with_capacity(total_lights.max(1));
 		for x in 0..instance_count.x {
 			for y in 0..instance_count.y {
 				for z in 0..instance_count.z {
 					let instance_offset = UVec3::new(x, y, z);
 					for mut i in self.instances.iter().copied() {
-						i.world_from_local.affine.translation +=
-							Vec3A::from(physical_offset * instance_offset.as_vec3());
+						let offset = Vec3A::from(physical_offset * instance_offset.as_vec3());
+						i.world_from_local.affine.translation += offset;
+						i.prev_world_from_local.affine.translation += offset;
 						instances.push(i);
 					}
+					for mut light in self.lights.iter().copied() {
+						light.position += physical_offset * instance_offset.as_vec3();
+						lights.push(light);
+					}
 				}
 			}
 		}
 		assert_eq!(instances.len(), total_instances);
+		assert_eq!(lights.len(), total_lights);
+		if lights.is_empty() {
+			lights.push(PunctualLight::default());
+		}
 
 		let instances_buffer = bindless.buffer().alloc_shared_from_iter(
 			&BindlessBufferCreateInfo {
@@ -122,6 +155,15 @@ impl MeshletSceneCpu {
 			instances.into_iter(),
 		)?;
 
+		let lights_buffer = bindless.buffer().alloc_shared_from_iter(
+			&BindlessBufferCreateInfo {
+				usage: BindlessBufferUsage::STORAGE_BUFFER | BindlessBufferUsage::MAP_WRITE,
+				name: "lights",
+				allocation_scheme: BindlessAllocationScheme::AllocatorManaged,
+			},
+			lights.into_iter(),
+		)?;
+
 		let scene = bindless.buffer().alloc_shared_from_data(
 			&BindlessBufferCreateInfo {
 				usage: BindlessBufferUsage::STORAGE_BUFFER | BindlessBufferUsage::MAP_WRITE,
@@ -138,6 +180,8 @@ impl MeshletSceneCpu {
 			scene,
 			num_instances: total_instances as u32,
 			instance_count,
+			lights: lights_buffer,
+			num_lights: total_lights as u32,
 		})
 	}
 }
@@ -0,0 +1,351 @@
+use crate::uploader::deserialize_infallible;
+use glam::Affine3A;
+use smallvec::SmallVec;
+use space_asset_disk::meshlet::header::MeshletSceneLoadError;
+use space_asset_disk::meshlet::scene_graph::ArchivedSceneGraphDisk;
+use space_asset_disk::range::{ArchivedRangeU32, RangeU32};
+use space_asset_shader::affine_transform::AffineTransform;
+use space_asset_shader::meshlet::instance::MeshInstance;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Index of a node within a [`SceneGraphCpu`]
+pub type NodeId = u32;
+
+/// Separator of node names within a path
+pub const PATH_SEPARATOR: char = '/';
+
+#[derive(Clone, Debug)]
+pub struct SceneNode {
+	pub name: Option<String>,
+	pub parent: Option<NodeId>,
+	pub children: Vec<NodeId>,
+	pub parent_from_local: Affine3A,
+	/// index of the source mesh
+	pub mesh: Option<u32>,
+	/// instances created by this node, not including its children
+	pub instances: Range<u32>,
+	/// application specific data as a json string
+	pub extras: Option<String>,
+}
+
+/// The node hierarchy of a scene, allowing instances to be found by node name or path and nodes to be re-parented.
+#[derive(Clone, Debug, Default)]
+pub struct SceneGraphCpu {
+	nodes: Vec<SceneNode>,
+	roots: Vec<NodeId>,
+	by_name: HashMap<String, SmallVec<[NodeId; 1]>>,
+}
+
+impl SceneGraphCpu {
+	/// Rejects parents that are out of bounds or form cycles, as bytecheck only validates the archive's layout.
+	pub fn new(disk: &ArchivedSceneGraphDisk) -> Result<Self, MeshletSceneLoadError> {
+		profiling::function_scope!();
+		let mut nodes = disk
+			.nodes
+			.iter()
+			.map(|node| SceneNode {
+				name: node.name.as_ref().map(|name| name.to_string()),
+				parent: deserialize_infallible(&node.parent),
+				children: Vec::new(),
+				parent_from_local: deserialize_infallible(&node.parent_from_local),
+				mesh: deserialize_infallible(&node.mesh),
+				instances: deserialize_infallible::<ArchivedRangeU32, RangeU32>(&node.instances).into(),
+				extras: node.extras.as_ref().map(|extras| extras.to_string()),
+			})
+			.collect::<Vec<_>>();
+
+		let mut roots = Vec::new();
+		let mut by_name = HashMap::<_, SmallVec<_>>::new();
+		for id in 0..nodes.len() as NodeId {
+			match nodes[id as usize].parent {
+				None => roots.push(id),
+				Some(parent) => nodes
+					.get_mut(parent as usize)
+					.ok_or(MeshletSceneLoadError::InvalidParent { node: id, parent })?
+					.children
+					.push(id),
+			}
+			if let Some(name) = &nodes[id as usize].name {
+				by_name.entry(name.clone()).or_default().push(id);
+			}
+		}
+		let graph = Self { nodes, roots, by_name };
+
+		// nodes within a cycle can't be reached from any root
+		let mut reachable = vec![false; graph.nodes.len()];
+		for root in &graph.roots {
+			for id in graph.descendants(*root) {
+				reachable[id as usize] = true;
+			}
+		}
+		if let Some(node) = reachable.iter().position(|reachable| !reachable) {
+			return Err(MeshletSceneLoadError::InvalidParent {
+				node: node as NodeId,
+				parent: graph.nodes[node].parent.unwrap_or_default(),
+			});
+		}
+		Ok(graph)
+	}
+
+	pub fn nodes(&self) -> &[SceneNode] {
+		&self.nodes
+	}
+
+	pub fn node(&self, id: NodeId) -> &SceneNode {
+		&self.nodes[id as usize]
+	}
+
+	pub fn roots(&self) -> &[NodeId] {
+		&self.roots
+	}
+
+	/// All nodes named `name`, as names are not required to be unique.
+	pub fn find_by_name(&self, name: &str) -> &[NodeId] {
+		self.by_name.get(name).map_or(&[], |ids| ids.as_slice())
+	}
+
+	/// Finds a node by the names of itself and all its ancestors, separated by [`PATH_SEPARATOR`], e.g.
+	/// `house/door`. If multiple nodes match, the first one is returned.
+	pub fn find_by_path(&self, path: &str) -> Option<NodeId> {
+		let mut candidates = self.roots.as_slice();
+		let mut found = None;
+		for name in path.split(PATH_SEPARATOR).filter(|name| !name.is_empty()) {
+			let id = *candidates
+				.iter()
+				.find(|id| self.node(**id).name.as_deref() == Some(name))?;
+			candidates = &self.node(id).children;
+			found = Some(id);
+		}
+		found
+	}
+
+	/// The path of a node, as accepted by [`Self::find_by_path`]. Unnamed nodes are represented by an empty string.
+	pub fn path(&self, id: NodeId) -> String {
+		let mut names = self
+			.ancestors(id)
+			.map(|id| self.node(id).name.as_deref().unwrap_or(""))
+			.collect::<Vec<_>>();
+		names.reverse();
+		names.join(&PATH_SEPARATOR.to_string())
+	}
+
+	/// Iterates `id` and all of its ancestors, up to the root.
+	pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+		std::iter::successors(Some(id), |id| self.node(*id).parent)
+	}
+
+	/// `id` and all of its descendants, in depth-first order.
+	pub fn descendants(&self, id: NodeId) -> Vec<NodeId> {
+		let mut out = Vec::new();
+		let mut stack = vec![id];
+		while let Some(id) = stack.pop() {
+			out.push(id);
+			stack.extend(self.node(id).children.iter().rev());
+		}
+		out
+	}
+
+	/// All instances created by nodes named `name` and their descendants.
+	pub fn instances_by_name(&self, name: &str) -> Vec<u32> {
+		self.find_by_name(name)
+			.iter()
+			.flat_map(|id| self.descendants(*id))
+			.flat_map(|id| self.node(id).instances.clone())
+			.collect()
+	}
+
+	pub fn world_from_local(&self, id: NodeId) -> Affine3A {
+		self.ancestors(id)
+			.fold(Affine3A::IDENTITY, |local, id| self.node(id).parent_from_local * local)
+	}
+
+	pub fn set_parent_from_local(&mut self, id: NodeId, parent_from_local: Affine3A) {
+		self.nodes[id as usize].parent_from_local = parent_from_local;
+	}
+
+	/// Moves `id` to be a child of `parent`, or a root node if `None`. If `keep_world_transform` is set, the node's
+	/// local transform is adjusted to keep it in place, otherwise it moves with its new parent.
+	pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>, keep_world_transform: bool) -> anyhow::Result<()> {
+		if let Some(parent) = parent.filter(|parent| self.ancestors(*parent).any(|ancestor| ancestor == id)) {
+			anyhow::bail!(
+				"Node {:?} cannot become a child of its descendant {:?}",
+				self.path(id),
+				self.path(parent)
+			);
+		}
+
+		let world_from_local = self.world_from_local(id);
+		match self.node(id).parent {
+			None => self.roots.retain(|root| *root != id),
+			Some(old) => self.nodes[old as usize].children.retain(|child| *child != id),
+		}
+		match parent {
+			None => self.roots.push(id),
+			Some(parent) => self.nodes[parent as usize].children.push(id),
+		}
+		self.nodes[id as usize].parent = parent;
+
+		if keep_world_transform {
+			let world_from_parent = parent.map_or(Affine3A::IDENTITY, |parent| self.world_from_local(parent));
+			self.nodes[id as usize].parent_from_local = world_from_parent.inverse() * world_from_local;
+		}
+		Ok(())
+	}
+
+	/// Writes the world transforms of all nodes into the instances they created. The transforms written previously are
+	/// kept as [`MeshInstance::prev_world_from_local`] for motion vectors, so this should be called once per frame.
+	pub fn update_instances(&self, instances: &mut [MeshInstance]) {
+		profiling::function_scope!();
+		let mut stack = self
+			.roots
+			.iter()
+			.map(|root| (*root, Affine3A::IDENTITY))
+			.collect::<Vec<_>>();
+		while let Some((id, world_from_parent)) = stack.pop() {
+			let node = self.node(id);
+			let world_from_local = world_from_parent * node.parent_from_local;
+			let transform = AffineTransform::new(world_from_local);
+			for instance in &mut instances[node.instances.start as usize..node.instances.end as usize] {
+				instance.prev_world_from_local = instance.world_from_local;
+				instance.world_from_local = transform;
+			}
+			stack.extend(node.children.iter().map(|child| (*child, world_from_local)));
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use glam::{Vec3, Vec3A};
+	use space_asset_disk::meshlet::scene_graph::{SceneGraphDisk, SceneNodeDisk};
+
+	fn node(name: Option<&str>, parent: Option<u32>, translation: Vec3, instances: Range<u32>) -> SceneNodeDisk {
+		SceneNodeDisk {
+			name: name.map(str::to_string),
+			parent,
+			parent_from_local: Affine3A::from_translation(translation),
+			mesh: None,
+			instances: instances.into(),
+			extras: None,
+		}
+	}
+
+	/// `house/door/<unnamed>` and a second root named `door`, with the first `door` preceding its parent
+	fn scene_graph() -> SceneGraphCpu {
+		new_scene_graph(vec![
+			node(Some("door"), Some(1), Vec3::Z, 1..2),
+			node(Some("house"), None, Vec3::new(10., 0., 0.), 0..1),
+			node(None, Some(0), Vec3::ZERO, 3..3),
+			node(Some("door"), None, Vec3::new(0., 5., 0.), 2..3),
+		])
+		.unwrap()
+	}
+
+	fn new_scene_graph(nodes: Vec<SceneNodeDisk>) -> Result<SceneGraphCpu, MeshletSceneLoadError> {
+		let disk = SceneGraphDisk {
+			nodes,
+			meshes: Vec::new(),
+		};
+		let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&disk).unwrap();
+		SceneGraphCpu::new(rkyv::access::<ArchivedSceneGraphDisk, rkyv::rancor::Error>(&bytes).unwrap())
+	}
+
+	fn translation(instance: &AffineTransform) -> Vec3 {
+		Vec3::from(instance.affine.translation)
+	}
+
+	#[test]
+	fn test_find_by_name() {
+		let graph = scene_graph();
+		assert_eq!(graph.roots(), [1, 3]);
+		assert_eq!(graph.find_by_name("door"), [0, 3]);
+		assert_eq!(graph.find_by_name("house"), [1]);
+		assert!(graph.find_by_name("window").is_empty());
+		assert_eq!(graph.instances_by_name("house"), [0, 1]);
+	}
+
+	#[test]
+	fn test_invalid_parent() {
+		let out_of_bounds = new_scene_graph(vec![
+			node(None, None, Vec3::ZERO, 0..0),
+			node(None, Some(7), Vec3::ZERO, 0..0),
+		]);
+		assert!(matches!(
+			out_of_bounds,
+			Err(MeshletSceneLoadError::InvalidParent { node: 1, parent: 7 })
+		));
+
+		let cycle = new_scene_graph(vec![
+			node(None, None, Vec3::ZERO, 0..0),
+			node(None, Some(2), Vec3::ZERO, 0..0),
+			node(None, Some(1), Vec3::ZERO, 0..0),
+		]);
+		assert!(matches!(
+			cycle,
+			Err(MeshletSceneLoadError::InvalidParent { node: 1, parent: 2 })
+		));
+		let own_parent = new_scene_graph(vec![node(None, Some(0), Vec3::ZERO, 0..0)]);
+		assert!(matches!(
+			own_parent,
+			Err(MeshletSceneLoadError::InvalidParent { node: 0, parent: 0 })
+		));
+	}
+
+	#[test]
+	fn test_path() {
+		let graph = scene_graph();
+		assert_eq!(graph.path(0), "house/door");
+		assert_eq!(graph.path(2), "house/door/");
+		assert_eq!(graph.path(3), "door");
+		assert_eq!(graph.find_by_path("house/door"), Some(0));
+		assert_eq!(graph.find_by_path("door"), Some(3));
+		assert_eq!(graph.find_by_path("house/window"), None);
+		assert_eq!(graph.find_by_path(""), None);
+	}
+
+	#[test]
+	fn test_set_parent() -> anyhow::Result<()> {
+		let mut graph = scene_graph();
+		// a node cannot become a child of itself or its descendants
+		assert!(graph.set_parent(1, Some(1), false).is_err());
+		assert!(graph.set_parent(1, Some(2), false).is_err());
+		assert_eq!(graph.path(2), "house/door/");
+
+		graph.set_parent(0, Some(3), true)?;
+		assert_eq!(graph.path(2), "door/door/");
+		assert!(graph.node(1).children.is_empty());
+		assert_eq!(graph.node(3).children, [0]);
+		assert_eq!(graph.world_from_local(0).translation, Vec3A::new(10., 0., 1.));
+
+		graph.set_parent(0, None, false)?;
+		assert_eq!(graph.roots(), [1, 3, 0]);
+		assert!(graph.node(3).children.is_empty());
+		assert_eq!(graph.world_from_local(0).translation, Vec3A::new(10., -5., 1.));
+		Ok(())
+	}
+
+	#[test]
+	fn test_update_instances() -> anyhow::Result<()> {
+		let mut graph = scene_graph();
+		let mut instances = [MeshInstance::default(); 3];
+		graph.update_instances(&mut instances);
+		assert_eq!(translation(&instances[0].world_from_local), Vec3::new(10., 0., 0.));
+		assert_eq!(translation(&instances[1].world_from_local), Vec3::new(10., 0., 1.));
+		assert_eq!(translation(&instances[2].world_from_local), Vec3::new(0., 5., 0.));
+		assert_eq!(translation(&instances[1].prev_world_from_local), Vec3::ZERO);
+
+		// moving a parent moves its children, the previous transforms are kept
+		graph.set_parent_from_local(1, Affine3A::from_translation(Vec3::new(20., 0., 0.)));
+		graph.update_instances(&mut instances);
+		assert_eq!(translation(&instances[1].world_from_local), Vec3::new(20., 0., 1.));
+		assert_eq!(translation(&instances[1].prev_world_from_local), Vec3::new(10., 0., 1.));
+
+		graph.set_parent(0, Some(3), false)?;
+		graph.update_instances(&mut instances);
+		assert_eq!(translation(&instances[0].world_from_local), Vec3::new(20., 0., 0.));
+		assert_eq!(translation(&instances[1].world_from_local), Vec3::new(0., 5., 1.));
+		Ok(())
+	}
+}
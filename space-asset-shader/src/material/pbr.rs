@@ -5,13 +5,36 @@ use rust_gpu_bindless_shaders::descriptor::{Desc, DescRef, Image, Image2d};
 #[derive(Copy, Clone, Debug, BufferStruct)]
 pub struct PbrMaterial<R: DescRef> {
 	pub base_color: Desc<R, Image<Image2d>>,
+	pub base_color_tex_coord: TextureCoordinates,
 	pub base_color_factor: [f32; 4],
 	pub normal: Desc<R, Image<Image2d>>,
+	pub normal_tex_coord: TextureCoordinates,
 	pub normal_scale: f32,
-	pub occlusion_roughness_metallic: Desc<R, Image<Image2d>>,
-	pub occlusion_strength: f32,
+	pub metallic_roughness: Desc<R, Image<Image2d>>,
+	pub metallic_roughness_tex_coord: TextureCoordinates,
 	pub metallic_factor: f32,
 	pub roughness_factor: f32,
+	pub occlusion: Desc<R, Image<Image2d>>,
+	pub occlusion_tex_coord: TextureCoordinates,
+	pub occlusion_strength: f32,
+	pub emissive: Desc<R, Image<Image2d>>,
+	pub emissive_tex_coord: TextureCoordinates,
+	pub emissive_factor: [f32; 3],
+	/// see [`Self::alpha_mode`]
+	pub alpha_mode: u32,
+	pub alpha_cutoff: f32,
+	/// bool
+	pub double_sided: u32,
+}
+
+impl<R: DescRef> PbrMaterial<R> {
+	pub fn alpha_mode(&self) -> AlphaMode {
+		AlphaMode::try_from(self.alpha_mode).unwrap_or(AlphaMode::Opaque)
+	}
+
+	pub fn double_sided(&self) -> bool {
+		self.double_sided != 0
+	}
 }
 
 pub use space_asset_disk_shader::material::pbr::*;
@@ -6,5 +6,7 @@ use space_asset_disk_shader::range::RangeU32;
 #[derive(Copy, Clone, Default, Debug, BufferStructPlain)]
 pub struct MeshInstance {
 	pub world_from_local: AffineTransform,
+	/// `world_from_local` of the previous frame, for motion vectors
+	pub prev_world_from_local: AffineTransform,
 	pub mesh_ids: RangeU32,
 }
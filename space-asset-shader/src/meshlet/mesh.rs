@@ -2,6 +2,7 @@ use crate::material::pbr::PbrMaterial;
 use crate::material::pbr::PbrVertex;
 use crate::meshlet::indices::{CompressedIndices, triangle_indices_load};
 use crate::meshlet::vertex::{DrawVertex, MaterialVertexId};
+use crate::shape::sphere::Sphere;
 use core::ops::Deref;
 use glam::UVec3;
 use rust_gpu_bindless_macros::BufferStruct;
@@ -42,6 +43,8 @@ pub struct MeshletMesh<R: DescRef> {
 	pub num_meshlets: u32,
 	pub pbr_material: PbrMaterial<R>,
 	pub pbr_material_vertices: Desc<R, Buffer<[PbrVertex]>>,
+	/// bounds of all vertices in local space
+	pub bounds: Sphere,
 }
 
 impl<R: AliveDescRef> MeshletMesh<R> {
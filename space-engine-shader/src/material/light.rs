@@ -1,6 +1,7 @@
 use crate::material::radiance::Radiance;
 use glam::Vec3;
 use rust_gpu_bindless_macros::{BufferStruct, assert_transfer_size};
+use space_asset_shader::light::PunctualLight;
 
 #[derive(Copy, Clone, Debug, BufferStruct)]
 pub struct DirectionalLight {
@@ -13,5 +14,45 @@ assert_transfer_size!(DirectionalLight, 6 * 4);
 pub struct PointLight {
 	pub position: Vec3,
 	pub color: Radiance,
+	/// distance at which the light smoothly falls off to zero, may be infinite
+	pub range: f32,
+}
+assert_transfer_size!(PointLight, 7 * 4);
+
+#[derive(Copy, Clone, BufferStruct)]
+pub struct SpotLight {
+	pub position: Vec3,
+	pub color: Radiance,
+	/// distance at which the light smoothly falls off to zero, may be infinite
+	pub range: f32,
+	/// unit vector the light is pointing towards
+	pub direction: Vec3,
+	/// cosine of the angle within which the light is at full intensity
+	pub inner_cone_cos: f32,
+	/// cosine of the angle beyond which the light emits nothing
+	pub outer_cone_cos: f32,
+}
+assert_transfer_size!(SpotLight, 12 * 4);
+
+impl From<PunctualLight> for PointLight {
+	fn from(light: PunctualLight) -> Self {
+		Self {
+			position: light.position,
+			color: Radiance(light.color),
+			range: light.range,
+		}
+	}
+}
+
+impl From<PunctualLight> for SpotLight {
+	fn from(light: PunctualLight) -> Self {
+		Self {
+			position: light.position,
+			color: Radiance(light.color),
+			range: light.range,
+			direction: light.direction,
+			inner_cone_cos: light.inner_cone_cos,
+			outer_cone_cos: light.outer_cone_cos,
+		}
+	}
 }
-assert_transfer_size!(PointLight, 6 * 4);
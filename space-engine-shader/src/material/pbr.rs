@@ -1,10 +1,11 @@
-use crate::material::light::{DirectionalLight, PointLight};
+use crate::material::light::{DirectionalLight, PointLight, SpotLight};
 use crate::material::radiance::Radiance;
 use core::f32::consts::PI;
 use core::ops::{Deref, DerefMut};
 use glam::{Mat3, Vec2, Vec3, Vec4, Vec4Swizzles};
-use rust_gpu_bindless_shaders::descriptor::{AliveDescRef, Descriptors};
-use space_asset_shader::material::pbr::PbrMaterial;
+use rust_gpu_bindless_shaders::descriptor::{AliveDescRef, Descriptors, Image2d, ImageType};
+use space_asset_shader::light::{PunctualLight, PunctualLightKind};
+use space_asset_shader::material::pbr::{AlphaMode, PbrMaterial, TextureCoordinates};
 use spirv_std::Sampler;
 
 /// camera direction unit vector, relative to fragment position
@@ -35,6 +36,8 @@ impl DerefMut for V {
 pub struct SurfaceLocation {
 	pub world_pos: Vec3,
 	pub tex_coord: Vec2,
+	pub tex_coord_1: Vec2,
+	pub vertex_color: Vec4,
 	pub vertex_normal: Vec3,
 	pub vertex_tangent: Vec4,
 	/// camera direction unit vector, relative to fragment position
@@ -42,15 +45,97 @@ pub struct SurfaceLocation {
 }
 
 impl SurfaceLocation {
-	pub fn new(world_pos: Vec3, camera_pos: Vec3, vertex_normal: Vec3, vertex_tangent: Vec4, tex_coord: Vec2) -> Self {
+	pub fn new(
+		world_pos: Vec3,
+		camera_pos: Vec3,
+		vertex_normal: Vec3,
+		vertex_tangent: Vec4,
+		tex_coord: Vec2,
+		tex_coord_1: Vec2,
+		vertex_color: Vec4,
+	) -> Self {
 		Self {
 			world_pos,
 			tex_coord,
+			tex_coord_1,
+			vertex_color,
 			vertex_normal,
 			vertex_tangent,
 			v: V::new(world_pos, camera_pos),
 		}
 	}
+
+	/// The texture coordinates a texture should be sampled at
+	pub fn tex_coord_for(&self, coords: TextureCoordinates) -> Vec2 {
+		let tex_coord = if coords.set == 1 {
+			self.tex_coord_1
+		} else {
+			self.tex_coord
+		};
+		coords.transform(tex_coord)
+	}
+}
+
+pub type SampledImage2d = <Image2d as ImageType>::SampledSpvImage;
+
+/// How material textures are sampled. Implicit derivatives are only available in fragment shaders, all other shaders
+/// have to supply the screen-space gradients of the texture coordinates themselves.
+pub trait TextureSampling: Copy {
+	fn sample(
+		&self,
+		image: &SampledImage2d,
+		sampler: Sampler,
+		loc: &SurfaceLocation,
+		coords: TextureCoordinates,
+	) -> Vec4;
+}
+
+/// Sample with implicit derivatives, fragment shaders only
+#[derive(Copy, Clone, Default)]
+pub struct ImplicitLod;
+
+impl TextureSampling for ImplicitLod {
+	fn sample(
+		&self,
+		image: &SampledImage2d,
+		sampler: Sampler,
+		loc: &SurfaceLocation,
+		coords: TextureCoordinates,
+	) -> Vec4 {
+		image.sample(sampler, loc.tex_coord_for(coords))
+	}
+}
+
+/// Sample with the screen-space gradients of both texture coordinate sets, as [`SurfaceLocation::tex_coord`] changes
+/// when moving one pixel along x or y
+#[derive(Copy, Clone, Default)]
+pub struct TexCoordGradients {
+	pub tex_coord_ddx: Vec2,
+	pub tex_coord_ddy: Vec2,
+	pub tex_coord_1_ddx: Vec2,
+	pub tex_coord_1_ddy: Vec2,
+}
+
+impl TextureSampling for TexCoordGradients {
+	fn sample(
+		&self,
+		image: &SampledImage2d,
+		sampler: Sampler,
+		loc: &SurfaceLocation,
+		coords: TextureCoordinates,
+	) -> Vec4 {
+		let (ddx, ddy) = if coords.set == 1 {
+			(self.tex_coord_1_ddx, self.tex_coord_1_ddy)
+		} else {
+			(self.tex_coord_ddx, self.tex_coord_ddy)
+		};
+		image.sample_by_gradient(
+			sampler,
+			loc.tex_coord_for(coords),
+			coords.transform_vector(ddx),
+			coords.transform_vector(ddy),
+		)
+	}
 }
 
 #[derive(Copy, Clone)]
@@ -62,21 +147,46 @@ pub struct SampledMaterial {
 	pub normal: Vec3,
 	pub roughness: f32,
 	pub metallic: f32,
+	/// ambient occlusion, 1 if unoccluded
+	pub occlusion: f32,
+	pub emissive: Vec3,
 }
 
 pub trait PbrMaterialSample {
-	fn sample(&self, descriptors: &Descriptors, sampler: Sampler, loc: SurfaceLocation) -> SampledMaterial;
+	fn sample(
+		&self,
+		descriptors: &Descriptors,
+		sampler: Sampler,
+		loc: SurfaceLocation,
+		sampling: impl TextureSampling,
+	) -> SampledMaterial;
+
+	/// Only sample the alpha of the material, for alpha testing
+	fn sample_alpha(
+		&self,
+		descriptors: &Descriptors,
+		sampler: Sampler,
+		loc: SurfaceLocation,
+		sampling: impl TextureSampling,
+	) -> f32;
 }
 
 impl<R: AliveDescRef> PbrMaterialSample for PbrMaterial<R> {
 	/// Sample the material's textures at some texture coordinates.
 	/// The sampled values can then be reused for multiple light evaluations.
-	fn sample(&self, descriptors: &Descriptors, sampler: Sampler, loc: SurfaceLocation) -> SampledMaterial {
-		let tex_coord = loc.tex_coord;
-		let base_color: Vec4 =
-			self.base_color.access(descriptors).sample(sampler, tex_coord) * Vec4::from(self.base_color_factor);
+	///
+	/// The returned alpha is already resolved according to the material's [`AlphaMode`]: Opaque surfaces always
+	/// return 1 and masked surfaces either 0 or 1.
+	fn sample(
+		&self,
+		descriptors: &Descriptors,
+		sampler: Sampler,
+		loc: SurfaceLocation,
+		sampling: impl TextureSampling,
+	) -> SampledMaterial {
+		let base_color = sample_base_color(self, descriptors, sampler, &loc, sampling);
 		let albedo = base_color.xyz();
-		let alpha = base_color.w;
+		let alpha = resolve_alpha(self, base_color.w);
 
 		let normal = {
 			let normal = loc.vertex_normal;
@@ -84,19 +194,42 @@ impl<R: AliveDescRef> PbrMaterialSample for PbrMaterial<R> {
 			let bi_tangent = tangent.w * Vec3::cross(normal, tangent.xyz());
 			let tbn = Mat3::from_cols(tangent.xyz(), bi_tangent, normal);
 			// normal in tangent space
-			let normal_ts: Vec4 = self.normal.access(descriptors).sample(sampler, tex_coord);
-			let normal_ts = normal_ts.xy() * 2.0 - 1.0;
+			let normal_ts = sampling.sample(self.normal.access(descriptors), sampler, &loc, self.normal_tex_coord);
+			let normal_ts = (normal_ts.xy() * 2.0 - 1.0) * self.normal_scale;
 			let normal_ts = Vec3::from((normal_ts, 1. - normal_ts.length()));
-			Vec3::normalize(tbn * normal_ts)
+			let normal = Vec3::normalize(tbn * normal_ts);
+			// the back side of double sided surfaces is lit like its front side
+			if self.double_sided() && Vec3::dot(loc.vertex_normal, *loc.v) < 0. {
+				-normal
+			} else {
+				normal
+			}
 		};
 
-		let orm: Vec4 = self
-			.occlusion_roughness_metallic
-			.access(descriptors)
-			.sample(sampler, tex_coord);
-		// let ao = orm.x * pbr_material.occlusion_strength;
-		let roughness = orm.y * self.roughness_factor;
-		let metallic = orm.z * self.metallic_factor;
+		let metallic_roughness = sampling.sample(
+			self.metallic_roughness.access(descriptors),
+			sampler,
+			&loc,
+			self.metallic_roughness_tex_coord,
+		);
+		let roughness = metallic_roughness.y * self.roughness_factor;
+		let metallic = metallic_roughness.z * self.metallic_factor;
+
+		let occlusion = sampling.sample(
+			self.occlusion.access(descriptors),
+			sampler,
+			&loc,
+			self.occlusion_tex_coord,
+		);
+		let occlusion = 1. + self.occlusion_strength * (occlusion.x - 1.);
+
+		let emissive = sampling.sample(
+			self.emissive.access(descriptors),
+			sampler,
+			&loc,
+			self.emissive_tex_coord,
+		);
+		let emissive = emissive.xyz() * Vec3::from(self.emissive_factor);
 
 		SampledMaterial {
 			world_pos: loc.world_pos,
@@ -106,8 +239,53 @@ impl<R: AliveDescRef> PbrMaterialSample for PbrMaterial<R> {
 			normal,
 			roughness,
 			metallic,
+			occlusion,
+			emissive,
 		}
 	}
+
+	fn sample_alpha(
+		&self,
+		descriptors: &Descriptors,
+		sampler: Sampler,
+		loc: SurfaceLocation,
+		sampling: impl TextureSampling,
+	) -> f32 {
+		match self.alpha_mode() {
+			AlphaMode::Opaque => 1.,
+			_ => resolve_alpha(self, sample_base_color(self, descriptors, sampler, &loc, sampling).w),
+		}
+	}
+}
+
+fn sample_base_color<R: AliveDescRef>(
+	material: &PbrMaterial<R>,
+	descriptors: &Descriptors,
+	sampler: Sampler,
+	loc: &SurfaceLocation,
+	sampling: impl TextureSampling,
+) -> Vec4 {
+	sampling.sample(
+		material.base_color.access(descriptors),
+		sampler,
+		loc,
+		material.base_color_tex_coord,
+	) * Vec4::from(material.base_color_factor)
+		* loc.vertex_color
+}
+
+fn resolve_alpha<R: AliveDescRef>(material: &PbrMaterial<R>, alpha: f32) -> f32 {
+	match material.alpha_mode() {
+		AlphaMode::Opaque => 1.,
+		AlphaMode::Mask => {
+			if alpha >= material.alpha_cutoff {
+				1.
+			} else {
+				0.
+			}
+		}
+		AlphaMode::Blend => alpha,
+	}
 }
 
 impl SampledMaterial {
@@ -121,11 +299,32 @@ impl SampledMaterial {
 		let light_rel = light.position - self.world_pos;
 		let l = light_rel.normalize();
 		let distance = light_rel.length();
-		let attenuation = 1.0 / (distance * distance);
+		let attenuation = range_attenuation(distance, light.range) / (distance * distance);
 		let radiance = light.color * attenuation;
 		self.evaluate_light(l, radiance)
 	}
 
+	pub fn evaluate_spot_light(&self, light: SpotLight) -> Radiance {
+		let light_rel = light.position - self.world_pos;
+		let l = light_rel.normalize();
+		let distance = light_rel.length();
+		let cone = cone_attenuation(
+			Vec3::dot(light.direction, -l),
+			light.inner_cone_cos,
+			light.outer_cone_cos,
+		);
+		let attenuation = cone * range_attenuation(distance, light.range) / (distance * distance);
+		let radiance = light.color * attenuation;
+		self.evaluate_light(l, radiance)
+	}
+
+	pub fn evaluate_punctual_light(&self, light: PunctualLight) -> Radiance {
+		match light.kind() {
+			PunctualLightKind::Point => self.evaluate_point_light(light.into()),
+			PunctualLightKind::Spot => self.evaluate_spot_light(light.into()),
+		}
+	}
+
 	/// Evaluate the light contribution a light has, not considering visibility.
 	///
 	/// * `l`: light direction unit vector, relative to fragment position
@@ -155,15 +354,62 @@ impl SampledMaterial {
 		Radiance((k_diffuse * albedo / PI + specular) * radiance.0 * n_dot_l)
 	}
 
+	/// The view direction reflected about the normal
+	pub fn reflection(&self) -> Vec3 {
+		2. * Vec3::dot(self.normal, *self.v) * self.normal - *self.v
+	}
+
+	/// Evaluate the ambient light of an environment with the split-sum approximation, see
+	/// [`ibl`](crate::renderer::lighting::ibl).
+	///
+	/// * `irradiance`: radiance arriving from the environment, cosine-weighted around the normal
+	/// * `prefiltered`: radiance of the environment around the [`Self::reflection`], prefiltered with the GGX lobe of
+	///   the material's roughness
+	/// * `brdf`: scale and bias to `f0` from the BRDF LUT, see
+	///   [`integrate_brdf`](crate::renderer::lighting::ibl::integrate_brdf)
+	pub fn image_based_light(&self, irradiance: Radiance, prefiltered: Radiance, brdf: Vec2) -> Radiance {
+		let n_dot_v = Vec3::dot(self.normal, *self.v).max(0.0);
+		let f0 = Vec3::lerp(Vec3::splat(0.04), self.albedo, self.metallic);
+		let f = fresnel_schlick_roughness(n_dot_v, f0, self.roughness);
+
+		let k_diffuse = (Vec3::splat(1.0) - f) * (1.0 - self.metallic);
+		let diffuse = k_diffuse * self.albedo * irradiance.0;
+		let specular = prefiltered.0 * (f0 * brdf.x + brdf.y);
+		Radiance((diffuse + specular) * self.occlusion)
+	}
+
 	pub fn ambient_light(&self, radiance: Radiance) -> Radiance {
-		Radiance(self.albedo * radiance.0)
+		Radiance(self.albedo * radiance.0 * self.occlusion)
+	}
+
+	pub fn emitted_light(&self) -> Radiance {
+		Radiance(self.emissive)
 	}
 }
 
+/// Smooth window reaching zero at `range`, as recommended by `KHR_lights_punctual`
+fn range_attenuation(distance: f32, range: f32) -> f32 {
+	let ratio = distance / range;
+	let ratio2 = ratio * ratio;
+	f32::clamp(1. - ratio2 * ratio2, 0., 1.)
+}
+
+/// Smooth falloff between the inner and outer cone of a spot light, as recommended by `KHR_lights_punctual`
+fn cone_attenuation(cos_angle: f32, inner_cone_cos: f32, outer_cone_cos: f32) -> f32 {
+	let scale = 1. / f32::max(inner_cone_cos - outer_cone_cos, 0.001);
+	let t = f32::clamp((cos_angle - outer_cone_cos) * scale, 0., 1.);
+	t * t
+}
+
 fn fresnel_schlick(cos_theta: f32, f0: Vec3) -> Vec3 {
 	f0 + (1.0 - f0) * libm::powf(f32::clamp(1.0 - cos_theta, 0.0, 1.0), 5.0)
 }
 
+/// [`fresnel_schlick`] averaged over the specular lobe, rough surfaces reflect less at grazing angles
+fn fresnel_schlick_roughness(cos_theta: f32, f0: Vec3, roughness: f32) -> Vec3 {
+	f0 + (Vec3::max(Vec3::splat(1.0 - roughness), f0) - f0) * libm::powf(f32::clamp(1.0 - cos_theta, 0.0, 1.0), 5.0)
+}
+
 fn distribution_ggx(n: Vec3, h: Vec3, roughness: f32) -> f32 {
 	let a = roughness * roughness;
 	let a2 = a * a;
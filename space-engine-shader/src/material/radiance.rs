@@ -5,12 +5,6 @@ use rust_gpu_bindless_macros::BufferStruct;
 #[derive(Copy, Clone, Debug, BufferStruct)]
 pub struct Radiance(pub Vec3);
 
-impl Radiance {
-	pub fn tone_map_reinhard(&self) -> Vec3 {
-		self.0 / (self.0 + Vec3::splat(1.0))
-	}
-}
-
 impl From<Vec3> for Radiance {
 	fn from(value: Vec3) -> Self {
 		Radiance(value)
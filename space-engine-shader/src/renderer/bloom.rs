@@ -0,0 +1,329 @@
+//! Physically based bloom, following Jimenez's "Next Generation Post Processing in Call of Duty: Advanced Warfare". The
+//! HDR frame is repeatedly downsampled into a chain of [`BLOOM_LEVELS`] half-sized images by [`downsample_13_tap`],
+//! then upsampled back up the chain by [`upsample_tent`], adding each level's downsampled image along the way. Both
+//! kernels preserve energy, so the result is the average of increasingly wide blurs of the frame.
+//!
+//! There is no threshold: the bloom is mixed into the entire frame by [`BloomSettings::intensity`], which keeps the
+//! total energy of the frame constant and lets bright areas bleed in proportion to their brightness, see
+//! [`Bloom::apply`].
+
+use crate::renderer::frame_data::FrameData;
+use crate::renderer::tonemap::luminance;
+use glam::{UVec2, UVec3, Vec2, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles};
+use rust_gpu_bindless_macros::{BufferStruct, bindless};
+use rust_gpu_bindless_shaders::descriptor::{Descriptors, Image, Image2d, MutImage, TransientDesc};
+use spirv_std::Sampler;
+use static_assertions::const_assert_eq;
+
+/// number of downsampled images, each half the size of the previous one
+pub const BLOOM_LEVELS: u32 = 6;
+
+#[derive(Copy, Clone, BufferStruct)]
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[repr(C)]
+pub struct BloomSettings {
+	/// bool, see [`Self::enabled`]
+	pub enabled: u32,
+	/// fraction of the frame replaced by the bloom
+	pub intensity: f32,
+}
+
+impl BloomSettings {
+	pub fn enabled(&self) -> bool {
+		self.enabled != 0
+	}
+}
+
+/// The size of the downsampled image of `level`, the first level being half the `viewport_size`
+pub fn bloom_level_size(viewport_size: UVec2, level: u32) -> UVec2 {
+	(viewport_size >> (level + 1)).max(UVec2::ONE)
+}
+
+/// Downsamples the image bilinearly sampled by `sample` around `uv` with the 13-tap filter, where `texel` is the size
+/// of a texel of the sampled image in uv. The taps form 5 overlapping boxes of 2x2 texels, which are weighted by
+/// their inverse luminance with `karis_average` to suppress fireflies of single very bright pixels.
+pub fn downsample_13_tap(sample: impl Fn(Vec2) -> Vec3, uv: Vec2, texel: Vec2, karis_average: bool) -> Vec3 {
+	let tap = |x: f32, y: f32| sample(uv + Vec2::new(x, y) * texel);
+	let a = tap(-2., 2.);
+	let b = tap(0., 2.);
+	let c = tap(2., 2.);
+	let d = tap(-2., 0.);
+	let e = tap(0., 0.);
+	let f = tap(2., 0.);
+	let g = tap(-2., -2.);
+	let h = tap(0., -2.);
+	let i = tap(2., -2.);
+	let j = tap(-1., 1.);
+	let k = tap(1., 1.);
+	let l = tap(-1., -1.);
+	let m = tap(1., -1.);
+
+	let boxes = [
+		((j + k + l + m) * 0.25, 0.5),
+		((a + b + d + e) * 0.25, 0.125),
+		((b + c + e + f) * 0.25, 0.125),
+		((d + e + g + h) * 0.25, 0.125),
+		((e + f + h + i) * 0.25, 0.125),
+	];
+	let mut sum = Vec3::ZERO;
+	let mut weight_sum = 0.;
+	let mut index = 0;
+	while index < boxes.len() {
+		let (color, weight) = boxes[index];
+		let weight = if karis_average {
+			weight / (1. + luminance(color))
+		} else {
+			weight
+		};
+		sum += color * weight;
+		weight_sum += weight;
+		index += 1;
+	}
+	sum / weight_sum
+}
+
+/// Upsamples the image bilinearly sampled by `sample` at `uv` with a 3x3 tent filter, where `texel` is the size of a
+/// texel of the sampled image in uv
+pub fn upsample_tent(sample: impl Fn(Vec2) -> Vec3, uv: Vec2, texel: Vec2) -> Vec3 {
+	let tap = |x: f32, y: f32| sample(uv + Vec2::new(x, y) * texel);
+	let corners = tap(-1., 1.) + tap(1., 1.) + tap(-1., -1.) + tap(1., -1.);
+	let edges = tap(0., 1.) + tap(-1., 0.) + tap(1., 0.) + tap(0., -1.);
+	(tap(0., 0.) * 4. + edges * 2. + corners) / 16.
+}
+
+/// The upsampled bloom, ready to be mixed into the frame
+#[derive(Copy, Clone, BufferStruct)]
+pub struct Bloom<'a> {
+	/// the sum of all upsampled levels
+	pub image: TransientDesc<'a, Image<Image2d>>,
+	pub sampler: TransientDesc<'a, Sampler>,
+}
+
+impl Bloom<'_> {
+	/// Mixes the bloom into the `color` at `uv`, if it is enabled
+	pub fn apply(&self, descriptors: &Descriptors, frame_data: FrameData, uv: Vec2, color: Vec3) -> Vec3 {
+		let settings = frame_data.bloom;
+		if settings.enabled() {
+			let bloom: Vec4 = self
+				.image
+				.access(descriptors)
+				.sample_by_lod(self.sampler.access(descriptors), uv, 0.);
+			Vec3::lerp(color, bloom.xyz() / BLOOM_LEVELS as f32, settings.intensity)
+		} else {
+			color
+		}
+	}
+}
+
+#[derive(Copy, Clone, BufferStruct)]
+pub struct DownsampleParam<'a> {
+	pub input: TransientDesc<'a, Image<Image2d>>,
+	pub input_size: UVec2,
+	pub sampler: TransientDesc<'a, Sampler>,
+	/// bool, whether to apply the karis average, only on the first level
+	pub karis_average: u32,
+	pub output: TransientDesc<'a, MutImage<Image2d>>,
+	pub output_size: UVec2,
+}
+
+pub const BLOOM_WG_SIZE: UVec2 = UVec2::new(8, 8);
+
+const_assert_eq!(BLOOM_WG_SIZE.x, 8);
+const_assert_eq!(BLOOM_WG_SIZE.y, 8);
+#[bindless(compute(threads(8, 8)))]
+pub fn bloom_downsample_cs(
+	#[bindless(descriptors)] descriptors: Descriptors,
+	#[bindless(param)] param: &DownsampleParam<'static>,
+	#[spirv(global_invocation_id)] inv_id: UVec3,
+) {
+	let pixel = inv_id.xy();
+	if !(pixel.x < param.output_size.x && pixel.y < param.output_size.y) {
+		return;
+	}
+
+	let input = param.input.access(&descriptors);
+	let sampler = param.sampler.access(&descriptors);
+	let sample = |uv: Vec2| {
+		let color: Vec4 = input.sample_by_lod(sampler, uv, 0.);
+		color.xyz()
+	};
+	let uv = (pixel.as_vec2() + 0.5) / param.output_size.as_vec2();
+	let color = downsample_13_tap(sample, uv, 1. / param.input_size.as_vec2(), param.karis_average != 0);
+	unsafe {
+		param.output.access(&descriptors).write(pixel, color.extend(1.));
+	}
+}
+
+#[derive(Copy, Clone, BufferStruct)]
+pub struct UpsampleParam<'a> {
+	/// the upsampled next smaller level
+	pub input: TransientDesc<'a, Image<Image2d>>,
+	pub input_size: UVec2,
+	pub sampler: TransientDesc<'a, Sampler>,
+	/// the downsampled image of this level, added to the upsampled `input`
+	pub downsampled: TransientDesc<'a, Image<Image2d>>,
+	pub output: TransientDesc<'a, MutImage<Image2d>>,
+	pub output_size: UVec2,
+}
+
+const_assert_eq!(BLOOM_WG_SIZE.x, 8);
+const_assert_eq!(BLOOM_WG_SIZE.y, 8);
+#[bindless(compute(threads(8, 8)))]
+pub fn bloom_upsample_cs(
+	#[bindless(descriptors)] descriptors: Descriptors,
+	#[bindless(param)] param: &UpsampleParam<'static>,
+	#[spirv(global_invocation_id)] inv_id: UVec3,
+) {
+	let pixel = inv_id.xy();
+	if !(pixel.x < param.output_size.x && pixel.y < param.output_size.y) {
+		return;
+	}
+
+	let input = param.input.access(&descriptors);
+	let sampler = param.sampler.access(&descriptors);
+	let sample = |uv: Vec2| {
+		let color: Vec4 = input.sample_by_lod(sampler, uv, 0.);
+		color.xyz()
+	};
+	let uv = (pixel.as_vec2() + 0.5) / param.output_size.as_vec2();
+	let upsampled = upsample_tent(sample, uv, 1. / param.input_size.as_vec2());
+	#[allow(clippy::useless_conversion)]
+	let downsampled = Vec4::from(param.downsampled.access(&descriptors).fetch(pixel)).xyz();
+	unsafe {
+		param
+			.output
+			.access(&descriptors)
+			.write(pixel, (downsampled + upsampled).extend(1.));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use glam::IVec2;
+
+	/// An image on the CPU, sampled bilinearly and clamped to its edges like the bloom's sampler
+	struct CpuImage {
+		size: UVec2,
+		pixels: Vec<Vec3>,
+	}
+
+	impl CpuImage {
+		fn new(size: UVec2) -> Self {
+			Self {
+				size,
+				pixels: vec![Vec3::ZERO; (size.x * size.y) as usize],
+			}
+		}
+
+		fn fetch(&self, pixel: IVec2) -> Vec3 {
+			let pixel = pixel.clamp(IVec2::ZERO, self.size.as_ivec2() - 1).as_uvec2();
+			self.pixels[(pixel.y * self.size.x + pixel.x) as usize]
+		}
+
+		fn set(&mut self, pixel: UVec2, color: Vec3) {
+			self.pixels[(pixel.y * self.size.x + pixel.x) as usize] = color;
+		}
+
+		fn sample(&self, uv: Vec2) -> Vec3 {
+			let pos = uv * self.size.as_vec2() - 0.5;
+			let base = pos.floor();
+			let f = pos - base;
+			let base = base.as_ivec2();
+			let top = Vec3::lerp(self.fetch(base), self.fetch(base + IVec2::X), f.x);
+			let bottom = Vec3::lerp(self.fetch(base + IVec2::Y), self.fetch(base + IVec2::ONE), f.x);
+			Vec3::lerp(top, bottom, f.y)
+		}
+
+		fn texel(&self) -> Vec2 {
+			1. / self.size.as_vec2()
+		}
+
+		fn sum(&self) -> Vec3 {
+			self.pixels.iter().copied().sum()
+		}
+
+		fn downsample(&self, karis_average: bool) -> CpuImage {
+			let mut out = CpuImage::new(self.size / 2);
+			for y in 0..out.size.y {
+				for x in 0..out.size.x {
+					let uv = (UVec2::new(x, y).as_vec2() + 0.5) / out.size.as_vec2();
+					let color = downsample_13_tap(|uv| self.sample(uv), uv, self.texel(), karis_average);
+					out.set(UVec2::new(x, y), color);
+				}
+			}
+			out
+		}
+
+		fn upsample(&self) -> CpuImage {
+			let mut out = CpuImage::new(self.size * 2);
+			for y in 0..out.size.y {
+				for x in 0..out.size.x {
+					let uv = (UVec2::new(x, y).as_vec2() + 0.5) / out.size.as_vec2();
+					out.set(UVec2::new(x, y), upsample_tent(|uv| self.sample(uv), uv, self.texel()));
+				}
+			}
+			out
+		}
+	}
+
+	fn assert_close(a: Vec3, b: Vec3) {
+		assert!((a - b).abs().max_element() < 1e-4, "{a:?} != {b:?}");
+	}
+
+	#[test]
+	fn test_constant_image_is_preserved() {
+		let mut image = CpuImage::new(UVec2::splat(16));
+		image.pixels.fill(Vec3::new(0.5, 2., 8.));
+		for pixel in image.downsample(false).pixels {
+			assert_close(pixel, Vec3::new(0.5, 2., 8.));
+		}
+		for pixel in image.downsample(true).pixels {
+			assert_close(pixel, Vec3::new(0.5, 2., 8.));
+		}
+		for pixel in image.upsample().pixels {
+			assert_close(pixel, Vec3::new(0.5, 2., 8.));
+		}
+	}
+
+	#[test]
+	fn test_downsample_preserves_energy() {
+		let mut image = CpuImage::new(UVec2::splat(16));
+		image.set(UVec2::new(9, 7), Vec3::ONE);
+		let down = image.downsample(false);
+		// each texel of the downsampled image covers 4 texels
+		assert_close(down.sum() * 4., image.sum());
+		// and spreads the light over more than the texel it falls into
+		assert!(down.pixels.iter().filter(|p| p.x > 0.).count() > 1);
+	}
+
+	#[test]
+	fn test_upsample_preserves_energy() {
+		let mut image = CpuImage::new(UVec2::splat(8));
+		image.set(UVec2::new(4, 3), Vec3::ONE);
+		let up = image.upsample();
+		assert_close(up.sum() / 4., image.sum());
+	}
+
+	#[test]
+	fn test_karis_average_suppresses_fireflies() {
+		let mut image = CpuImage::new(UVec2::splat(16));
+		image.pixels.fill(Vec3::splat(0.1));
+		image.set(UVec2::new(8, 8), Vec3::splat(1000.));
+		let plain = image.downsample(false);
+		let karis = image.downsample(true);
+		// a texel whose outermost taps reach the firefly
+		let neighbour = UVec2::new(5, 5);
+		let index = (neighbour.y * plain.size.x + neighbour.x) as usize;
+		assert!(karis.pixels[index].x < plain.pixels[index].x / 10.);
+		assert!(karis.pixels[index].x > 0.1);
+	}
+
+	#[test]
+	fn test_bloom_level_size() {
+		let size = UVec2::new(1920, 1080);
+		assert_eq!(bloom_level_size(size, 0), UVec2::new(960, 540));
+		assert_eq!(bloom_level_size(size, BLOOM_LEVELS - 1), UVec2::new(30, 16));
+		assert_eq!(bloom_level_size(UVec2::new(4, 4), 5), UVec2::ONE);
+	}
+}
@@ -1,8 +1,74 @@
 use crate::utils::affine::AffineTranspose;
 use bytemuck_derive::AnyBitPattern;
-use glam::{Mat4, UVec2, Vec2, Vec3, Vec4, Vec4Swizzles, vec4};
+use glam::{Affine3A, Mat4, UVec2, Vec2, Vec3, Vec4, Vec4Swizzles, vec4};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
 use rust_gpu_bindless_macros::BufferStruct;
 use space_asset_shader::affine_transform::AffineTransform;
+use space_asset_shader::shape::sphere::Sphere;
+#[cfg(target_arch = "spirv")]
+use spirv_std::num_traits::Float;
+
+/// How a [`Camera`] projects, with all parameters needed to build its projection matrix
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+	/// A symmetric perspective frustum with a vertical field of view of `fov_y` radians, the horizontal one following
+	/// the aspect ratio of the viewport
+	Perspective { fov_y: f32 },
+	/// An off-center perspective frustum for VR or tiled rendering, given by the angles in radians of its sides to
+	/// the view direction. `left` and `down` are usually negative, like the field of view of an OpenXR view.
+	AsymmetricPerspective { left: f32, right: f32, down: f32, up: f32 },
+	/// An orthographic projection covering `height` world units vertically, the width following the aspect ratio of
+	/// the viewport
+	Orthographic { height: f32 },
+}
+
+/// The kind of [`Projection`] of a [`Camera`], which decides how sizes shrink with distance
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, TryFromPrimitive, IntoPrimitive)]
+pub enum ProjectionKind {
+	Perspective,
+	Orthographic,
+}
+
+impl Projection {
+	pub fn kind(&self) -> ProjectionKind {
+		match self {
+			Projection::Perspective { .. } | Projection::AsymmetricPerspective { .. } => ProjectionKind::Perspective,
+			Projection::Orthographic { .. } => ProjectionKind::Orthographic,
+		}
+	}
+
+	/// The vertical field of view in radians, 0 for orthographic projections
+	pub fn fov_y(&self) -> f32 {
+		match *self {
+			Projection::Perspective { fov_y } => fov_y,
+			Projection::AsymmetricPerspective { down, up, .. } => up - down,
+			Projection::Orthographic { .. } => 0.,
+		}
+	}
+
+	/// The right-handed projection matrix with a depth range of `[0, 1]`, without the y flip
+	pub fn clip_from_view(&self, aspect_ratio: f32, z_near: f32, z_far: f32) -> Mat4 {
+		match *self {
+			Projection::Perspective { fov_y } => Mat4::perspective_rh(fov_y, aspect_ratio, z_near, z_far),
+			Projection::AsymmetricPerspective { left, right, down, up } => {
+				let (left, right, down, up) = (left.tan(), right.tan(), down.tan(), up.tan());
+				let depth = z_far / (z_near - z_far);
+				Mat4::from_cols(
+					vec4(2. / (right - left), 0., 0., 0.),
+					vec4(0., 2. / (up - down), 0., 0.),
+					vec4((right + left) / (right - left), (up + down) / (up - down), depth, -1.),
+					vec4(0., 0., depth * z_near, 0.),
+				)
+			}
+			Projection::Orthographic { height } => {
+				let half_height = height * 0.5;
+				let half_width = half_height * aspect_ratio;
+				Mat4::orthographic_rh(-half_width, half_width, -half_height, half_height, z_near, z_far)
+			}
+		}
+	}
+}
 
 #[derive(Copy, Clone, BufferStruct)]
 #[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
@@ -12,8 +78,13 @@ pub struct Camera {
 	pub view_from_clip: Mat4,
 	pub view_from_world: AffineTransform,
 	pub viewport_size: UVec2,
+	/// [`ProjectionKind`], see [`Self::projection`]
+	pub projection: u32,
+	/// 0 for orthographic projections
 	pub fov_y: f32,
 	pub z_near: f32,
+	/// subpixel offset in NDC baked into `clip_from_view`, see [`Self::new_perspective_rh_y_flip`]
+	pub jitter: Vec2,
 }
 
 #[derive(Copy, Clone, AnyBitPattern)]
@@ -33,33 +104,111 @@ pub struct TransformedNormal {
 	pub view_space: Vec3,
 }
 
+/// Flips the y axis of clip space, so that up in view space is the top of the image in vulkan's framebuffer coordinates
+const Y_FLIP: Mat4 = Mat4::from_cols(
+	vec4(1., 0., 0., 0.),
+	vec4(0., -1., 0., 0.),
+	vec4(0., 0., 1., 0.),
+	vec4(0., 0., 0., 1.),
+);
+
 impl Camera {
-	pub fn new(perspective: Mat4, viewport_size: UVec2, fov_y: f32, z_near: f32, transform: AffineTransform) -> Self {
+	pub fn new(
+		clip_from_view: Mat4,
+		projection: ProjectionKind,
+		viewport_size: UVec2,
+		fov_y: f32,
+		z_near: f32,
+		transform: AffineTransform,
+	) -> Self {
 		Self {
-			clip_from_view: perspective,
-			view_from_clip: perspective.inverse(),
+			clip_from_view,
+			view_from_clip: clip_from_view.inverse(),
 			view_from_world: transform,
 			viewport_size,
+			projection: projection.into(),
 			fov_y,
 			z_near,
+			jitter: Vec2::ZERO,
 		}
 	}
 
+	/// A camera with any `projection`, offset by `jitter` pixels for temporal anti-aliasing, see
+	/// [`taa_jitter`](crate::renderer::taa::taa_jitter)
+	pub fn new_rh_y_flip(
+		viewport_size: UVec2,
+		projection: Projection,
+		z_near: f32,
+		z_far: f32,
+		transform: AffineTransform,
+		jitter: Vec2,
+	) -> Self {
+		let jitter = jitter * 2. / viewport_size.as_vec2();
+		let clip_from_view =
+			Mat4::from_translation(Vec3::from((jitter, 0.)))
+				* Y_FLIP * projection.clip_from_view(viewport_size.x as f32 / viewport_size.y as f32, z_near, z_far);
+		Self {
+			jitter,
+			..Self::new(
+				clip_from_view,
+				projection.kind(),
+				viewport_size,
+				projection.fov_y(),
+				z_near,
+				transform,
+			)
+		}
+	}
+
+	/// A perspective camera, with its projection offset by `jitter` pixels for temporal anti-aliasing, see
+	/// [`taa_jitter`](crate::renderer::taa::taa_jitter)
 	pub fn new_perspective_rh_y_flip(
 		viewport_size: UVec2,
 		fov_y: f32,
 		z_near: f32,
 		z_far: f32,
 		transform: AffineTransform,
+		jitter: Vec2,
+	) -> Self {
+		let projection = Projection::Perspective { fov_y };
+		Self::new_rh_y_flip(viewport_size, projection, z_near, z_far, transform, jitter)
+	}
+
+	/// An orthographic camera covering `height` world units vertically, with the width following the aspect ratio of
+	/// the viewport. Its `fov_y` is 0.
+	pub fn new_orthographic_rh_y_flip(
+		viewport_size: UVec2,
+		height: f32,
+		z_near: f32,
+		z_far: f32,
+		transform: AffineTransform,
 	) -> Self {
-		let projection = Mat4::perspective_rh(fov_y, viewport_size.x as f32 / viewport_size.y as f32, z_near, z_far)
-			* Mat4::from_cols(
-				vec4(1., 0., 0., 0.),
-				vec4(0., -1., 0., 0.),
-				vec4(0., 0., 1., 0.),
-				vec4(0., 0., 0., 1.),
-			);
-		Self::new(projection, viewport_size, fov_y, z_near, transform)
+		let projection = Projection::Orthographic { height };
+		Self::new_rh_y_flip(viewport_size, projection, z_near, z_far, transform, Vec2::ZERO)
+	}
+
+	pub fn projection(&self) -> ProjectionKind {
+		ProjectionKind::try_from(self.projection).unwrap_or(ProjectionKind::Perspective)
+	}
+
+	/// Whether this is an orthographic projection, which does not divide by depth
+	pub fn is_orthographic(&self) -> bool {
+		self.projection() == ProjectionKind::Orthographic
+	}
+
+	/// Projects an `error` in world units of the sphere at `center` in world space with `radius` to its size in
+	/// pixels. Perspective projections take the error at the point of the sphere closest to the camera, orthographic
+	/// ones don't shrink with distance.
+	pub fn project_error_to_pixels(&self, center: Vec3, radius: f32, error: f32) -> f32 {
+		// the y flip makes the scale negative, and off-center projections only offset it
+		let pixels_per_unit = self.clip_from_view.y_axis.y.abs() * self.viewport_size.y as f32 * 0.5;
+		match self.projection() {
+			ProjectionKind::Perspective => {
+				let distance = center.distance(self.view_from_world.translation()) - radius;
+				error / f32::max(distance, self.z_near) * pixels_per_unit
+			}
+			ProjectionKind::Orthographic => error * pixels_per_unit,
+		}
 	}
 
 	pub fn transform_vertex(&self, world_from_local: AffineTransform, vertex_pos: Vec3) -> TransformedPosition {
@@ -82,6 +231,14 @@ impl Camera {
 		}
 	}
 
+	/// Projects `world_pos` to its fragment position [0, 1] without the [`Self::jitter`], so that positions of
+	/// different frames can be compared
+	pub fn project_unjittered(&self, world_pos: Vec3) -> Vec2 {
+		let view_space = self.view_from_world.affine.transform_point3_transposed(world_pos);
+		let clip_space = self.clip_from_view * Vec4::from((view_space, 1.));
+		(clip_space.xy() / clip_space.w - self.jitter) * 0.5 + 0.5
+	}
+
 	/// Reconstruct positions from fragment position [0, 1] and depth value
 	pub fn reconstruct_from_depth(&self, fragment_pos: Vec2, depth: f32) -> TransformedPosition {
 		let clip_space = Vec4::from((fragment_pos * 2. - 1., depth, 1.));
@@ -95,10 +252,56 @@ impl Camera {
 		}
 	}
 
+	/// The 6 planes of the view frustum in view space as `(normal, distance)`, with normals facing inwards: left,
+	/// right, bottom, top, near and far. Extracted from `clip_from_view` using the Gribb-Hartmann method for a depth
+	/// range of `[0, 1]`.
+	pub fn frustum_planes(&self) -> [Vec4; 6] {
+		let m = self.clip_from_view;
+		let [r0, r1, r2, r3] = [m.row(0), m.row(1), m.row(2), m.row(3)];
+		let normalize = |plane: Vec4| plane / plane.xyz().length();
+		[
+			normalize(r3 + r0),
+			normalize(r3 - r0),
+			normalize(r3 + r1),
+			normalize(r3 - r1),
+			normalize(r2),
+			normalize(r3 - r2),
+		]
+	}
+
+	/// Whether the `sphere` in local space transformed by `world_from_local` is at least partially within the view
+	/// frustum. Conservative, may report spheres near the frustum corners as visible.
+	pub fn is_sphere_in_frustum(&self, world_from_local: Affine3A, sphere: Sphere) -> bool {
+		let (center, radius) = self.sphere_to_view_space(world_from_local, sphere);
+		let planes = self.frustum_planes();
+		let inside = |plane: Vec4| Vec3::dot(plane.xyz(), center) + plane.w >= -radius;
+		inside(planes[0])
+			&& inside(planes[1])
+			&& inside(planes[2])
+			&& inside(planes[3])
+			&& inside(planes[4])
+			&& inside(planes[5])
+	}
+
+	/// Transforms the `sphere` in local space by `world_from_local` into view space, returning its center and radius.
+	pub fn sphere_to_view_space(&self, world_from_local: Affine3A, sphere: Sphere) -> (Vec3, f32) {
+		let center_world = world_from_local.transform_point3(sphere.center());
+		let center = self.view_from_world.affine.transform_point3_transposed(center_world);
+		let mat = world_from_local.matrix3;
+		// the sphere scaled by the largest axis contains the ellipsoid of a non-uniform scale
+		let max_scale = f32::max(f32::max(mat.x_axis.length(), mat.y_axis.length()), mat.z_axis.length());
+		(center, sphere.radius() * max_scale)
+	}
+
 	pub fn reconstruct_direction(&self, fragment_pos: Vec2) -> TransformedNormal {
 		let clip_pos = fragment_pos * 2. - 1.;
 		let clip_space = Vec4::from((clip_pos, (1. - clip_pos.length()).max(0.), 1.));
-		let camera_space = (self.view_from_clip * clip_space).xyz().normalize();
+		// all rays of orthographic projections are parallel
+		let camera_space = if self.is_orthographic() {
+			Vec3::NEG_Z
+		} else {
+			(self.view_from_clip * clip_space).xyz().normalize()
+		};
 		let world_space = self.view_from_world.normal * camera_space;
 		TransformedNormal {
 			world_space,
@@ -106,3 +309,165 @@ impl Camera {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+
+	fn new_camera(transform: Affine3A) -> Camera {
+		Camera::new_perspective_rh_y_flip(
+			UVec2::new(100, 100),
+			FRAC_PI_2,
+			0.1,
+			100.,
+			AffineTransform::new(transform),
+			Vec2::ZERO,
+		)
+	}
+
+	fn visible(camera: &Camera, center: Vec3, radius: f32) -> bool {
+		camera.is_sphere_in_frustum(Affine3A::IDENTITY, Sphere::new(center, radius))
+	}
+
+	fn project(camera: &Camera, view_pos: Vec3) -> Vec2 {
+		camera.project_unjittered(view_pos)
+	}
+
+	#[test]
+	fn test_asymmetric_matches_symmetric_perspective() {
+		let size = UVec2::new(200, 100);
+		let symmetric = Projection::Perspective { fov_y: FRAC_PI_2 };
+		let half_x = f32::atan(2.);
+		let asymmetric = Projection::AsymmetricPerspective {
+			left: -half_x,
+			right: half_x,
+			down: -FRAC_PI_4,
+			up: FRAC_PI_4,
+		};
+		let a = Camera::new_rh_y_flip(size, symmetric, 0.1, 100., AffineTransform::default(), Vec2::ZERO);
+		let b = Camera::new_rh_y_flip(size, asymmetric, 0.1, 100., AffineTransform::default(), Vec2::ZERO);
+		assert!(a.clip_from_view.abs_diff_eq(b.clip_from_view, 1e-5), "{a:?} {b:?}");
+		assert!((a.fov_y - b.fov_y).abs() < 1e-5);
+		assert_eq!(b.projection(), ProjectionKind::Perspective);
+	}
+
+	#[test]
+	fn test_asymmetric_projection() {
+		let (left, right, down, up) = (-0.3, 0.6, -0.2, 0.5);
+		let projection = Projection::AsymmetricPerspective { left, right, down, up };
+		let camera = Camera::new_rh_y_flip(
+			UVec2::new(100, 100),
+			projection,
+			0.1,
+			100.,
+			AffineTransform::default(),
+			Vec2::ZERO,
+		);
+		let d = 10.;
+		// up in view space is the top of the image
+		let top_right = project(&camera, Vec3::new(right.tan() * d, up.tan() * d, -d));
+		assert!(top_right.abs_diff_eq(Vec2::new(1., 0.), 1e-5), "{top_right:?}");
+		let bottom_left = project(&camera, Vec3::new(left.tan() * d, down.tan() * d, -d));
+		assert!(bottom_left.abs_diff_eq(Vec2::new(0., 1.), 1e-5), "{bottom_left:?}");
+		// the frustum extends further right and up, so the view direction is left of and below the image center
+		let center = project(&camera, Vec3::new(0., 0., -d));
+		assert!(center.x < 0.5 && center.y > 0.5, "{center:?}");
+		assert!((camera.fov_y - 0.7).abs() < 1e-5);
+	}
+
+	#[test]
+	fn test_orthographic_projection() {
+		let camera =
+			Camera::new_orthographic_rh_y_flip(UVec2::new(200, 100), 10., 0.1, 100., AffineTransform::default());
+		assert!(camera.is_orthographic());
+		assert_eq!(camera.fov_y, 0.);
+		for depth in [1., 50.] {
+			let top_right = project(&camera, Vec3::new(10., 5., -depth));
+			assert!(top_right.abs_diff_eq(Vec2::new(1., 0.), 1e-5), "{top_right:?}");
+		}
+		let position = camera.reconstruct_from_depth(Vec2::new(0.75, 0.5), 0.5);
+		assert!((position.view_space.x - 5.).abs() < 1e-4, "{position:?}");
+		let direction = camera.reconstruct_direction(Vec2::new(0.9, 0.1));
+		assert_eq!(direction.view_space, Vec3::NEG_Z);
+	}
+
+	#[test]
+	fn test_project_error_to_pixels() {
+		let size = UVec2::new(100, 100);
+		let perspective = new_camera(Affine3A::IDENTITY);
+		// a 90° fov covers 20 units vertically at a distance of 10
+		let pixels = perspective.project_error_to_pixels(Vec3::new(0., 0., -10.), 0., 1.);
+		assert!((pixels - 5.).abs() < 1e-4, "{pixels}");
+		let pixels = perspective.project_error_to_pixels(Vec3::new(0., 10., 0.), 5., 1.);
+		assert!((pixels - 10.).abs() < 1e-4, "{pixels}");
+		// clamped to the near plane if the camera is within the sphere
+		let pixels = perspective.project_error_to_pixels(Vec3::new(0., 0., -1.), 2., 1.);
+		assert!((pixels - 500.).abs() < 1e-2, "{pixels}");
+
+		let projection = Projection::AsymmetricPerspective {
+			left: -0.1,
+			right: 1.,
+			down: -FRAC_PI_4,
+			up: FRAC_PI_4,
+		};
+		let asymmetric = Camera::new_rh_y_flip(size, projection, 0.1, 100., AffineTransform::default(), Vec2::ZERO);
+		let pixels = asymmetric.project_error_to_pixels(Vec3::new(0., 0., -10.), 0., 1.);
+		assert!((pixels - 5.).abs() < 1e-4, "{pixels}");
+
+		// 10 pixels per unit, at any distance
+		let ortho = Camera::new_orthographic_rh_y_flip(size, 10., 0.1, 100., AffineTransform::default());
+		for distance in [1., 10., 90.] {
+			let pixels = ortho.project_error_to_pixels(Vec3::new(0., 0., -distance), 1., 0.5);
+			assert!((pixels - 5.).abs() < 1e-4, "{pixels}");
+		}
+	}
+
+	#[test]
+	fn test_frustum_planes() {
+		let camera = new_camera(Affine3A::IDENTITY);
+		let planes = camera.frustum_planes();
+		let inside = |p: Vec3| planes.iter().all(|plane| plane.xyz().dot(p) + plane.w >= 0.);
+		assert!(inside(Vec3::new(0., 0., -1.)));
+		assert!(inside(Vec3::new(9.9, -9.9, -10.)));
+		assert!(!inside(Vec3::new(0., 0., 1.)));
+		assert!(!inside(Vec3::new(0., 0., -0.05)));
+		assert!(!inside(Vec3::new(0., 0., -101.)));
+		assert!(!inside(Vec3::new(10.1, 0., -10.)));
+		assert!(!inside(Vec3::new(0., -10.1, -10.)));
+		for plane in planes {
+			assert!((plane.xyz().length() - 1.).abs() < 1e-5, "{plane:?}");
+		}
+	}
+
+	#[test]
+	fn test_sphere_in_frustum() {
+		let camera = new_camera(Affine3A::IDENTITY);
+		assert!(visible(&camera, Vec3::new(0., 0., -10.), 1.));
+		assert!(!visible(&camera, Vec3::new(0., 0., 10.), 1.));
+		assert!(!visible(&camera, Vec3::new(20., 0., -10.), 1.));
+		// partially intersecting the right plane
+		assert!(visible(&camera, Vec3::new(10.5, 0., -10.), 1.));
+		assert!(!visible(&camera, Vec3::new(0., 0., -200.), 1.));
+		assert!(visible(&camera, Vec3::new(0., 0., -200.), 150.));
+	}
+
+	#[test]
+	fn test_sphere_in_frustum_transformed() {
+		// camera moved back, looking at the origin
+		let camera = new_camera(Affine3A::from_translation(Vec3::new(0., 0., 20.)));
+		assert!(visible(&camera, Vec3::ZERO, 1.));
+		assert!(!visible(&camera, Vec3::new(0., 0., 30.), 1.));
+
+		// camera turned around, looking down +Z
+		let camera = new_camera(Affine3A::from_rotation_y(core::f32::consts::PI));
+		assert!(visible(&camera, Vec3::new(0., 0., 10.), 1.));
+		assert!(!visible(&camera, Vec3::new(0., 0., -10.), 1.));
+
+		// instance moving the sphere from beyond the far plane into the frustum
+		let camera = new_camera(Affine3A::IDENTITY);
+		let sphere = Sphere::new(Vec3::new(0., 0., -200.), 1.);
+		assert!(!camera.is_sphere_in_frustum(Affine3A::IDENTITY, sphere));
+		assert!(camera.is_sphere_in_frustum(Affine3A::from_scale(Vec3::splat(0.4)), sphere));
+	}
+}
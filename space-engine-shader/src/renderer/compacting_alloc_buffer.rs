@@ -11,6 +11,10 @@ use spirv_std::memory::{Scope, Semantics};
 pub struct CompactingAllocBufferWriter<'a, T: BufferStructPlain> {
 	pub buffer: TransientDesc<'a, MutBuffer<[T]>>,
 	pub indirect_args: TransientDesc<'a, MutBuffer<[u32; 3]>>,
+	/// start of the partition of `buffer` allocated from, allowing multiple views to share a buffer
+	pub offset: u32,
+	/// length of the partition of `buffer` allocated from
+	pub capacity: u32,
 }
 
 impl<'a, T: BufferStructPlain> CompactingAllocBufferWriter<'a, T> {
@@ -33,9 +37,9 @@ impl<'a, T: BufferStructPlain> CompactingAllocBufferWriter<'a, T> {
 			base_index + inv_index
 		} as usize;
 
-		let mut buffer = self.buffer.access(descriptors);
-		if index < buffer.len() {
-			unsafe { buffer.store(index, t) };
+		if index < self.capacity as usize {
+			let mut buffer = self.buffer.access(descriptors);
+			unsafe { buffer.store(self.offset as usize + index, t) };
 			true
 		} else {
 			false
@@ -47,6 +51,8 @@ impl<'a, T: BufferStructPlain> CompactingAllocBufferWriter<'a, T> {
 pub struct CompactingAllocBufferReader<'a, T: BufferStructPlain> {
 	pub buffer: TransientDesc<'a, Buffer<[T]>>,
 	pub indirect_args: TransientDesc<'a, Buffer<[u32; 3]>>,
+	/// start of the partition of `buffer` written by the [`CompactingAllocBufferWriter`]
+	pub offset: u32,
 }
 
 impl<'a, T: BufferStructPlain> CompactingAllocBufferReader<'a, T> {
@@ -54,6 +60,7 @@ impl<'a, T: BufferStructPlain> CompactingAllocBufferReader<'a, T> {
 		let slice = self.buffer.access(descriptors);
 		CompactingAllocBufferReaderAccessed {
 			buffer: slice,
+			offset: self.offset,
 			len: self.indirect_args.access(descriptors).load()[0],
 		}
 	}
@@ -61,6 +68,7 @@ impl<'a, T: BufferStructPlain> CompactingAllocBufferReader<'a, T> {
 
 pub struct CompactingAllocBufferReaderAccessed<'a, T: BufferStructPlain> {
 	pub buffer: BufferSlice<'a, [T]>,
+	pub offset: u32,
 	pub len: u32,
 }
 
@@ -91,6 +99,6 @@ impl<'a, T: BufferStructPlain> CompactingAllocBufferReaderAccessed<'a, T> {
 	/// # Safety
 	/// index must be in bounds
 	pub unsafe fn read_unchecked(&self, index: u32) -> T {
-		unsafe { self.buffer.load_unchecked(index as usize) }
+		unsafe { self.buffer.load_unchecked((self.offset + index) as usize) }
 	}
 }
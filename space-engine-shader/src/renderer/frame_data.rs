@@ -1,7 +1,13 @@
 use crate::material::light::DirectionalLight;
 use crate::material::radiance::Radiance;
+use crate::renderer::bloom::BloomSettings;
 use crate::renderer::camera::Camera;
+use crate::renderer::lighting::ambient_occlusion::AoSettings;
+use crate::renderer::lighting::ibl::IblSettings;
+use crate::renderer::lighting::shadow::ShadowSettings;
 use crate::renderer::lod_selection::LodSelection;
+use crate::renderer::taa::TaaSettings;
+use crate::renderer::tonemap::TonemapSettings;
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 use rust_gpu_bindless_macros::BufferStruct;
 
@@ -16,10 +22,11 @@ pub enum DebugSettings {
 	Normals,
 	VertexNormals,
 	RoughnessMetallic,
+	AmbientOcclusion,
 }
 
 impl DebugSettings {
-	pub const MAX_VALUE: DebugSettings = DebugSettings::RoughnessMetallic;
+	pub const MAX_VALUE: DebugSettings = DebugSettings::AmbientOcclusion;
 	pub const LEN: u32 = Self::MAX_VALUE as u32 + 1;
 }
 
@@ -27,8 +34,52 @@ impl DebugSettings {
 #[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
 #[repr(C)]
 pub struct NaniteSettings {
+	/// meshlets are drawn at the coarsest LOD whose error projects to at most this many half pixels, see
+	/// [`LOD_ERROR_PIXEL_SCALE`](crate::renderer::meshlet::meshlet_select::LOD_ERROR_PIXEL_SCALE)
 	pub error_threshold: f32,
 	pub bounding_sphere_scale: f32,
+	/// bool, see [`Self::frustum_culling`]
+	pub frustum_culling: u32,
+	/// bool, see [`Self::occlusion_culling`]
+	pub occlusion_culling: u32,
+	/// bool, see [`Self::backface_culling`]
+	pub backface_culling: u32,
+	/// bool, see [`Self::visibility_buffer`]
+	pub visibility_buffer: u32,
+	/// bool, see [`Self::software_rasterization`]
+	pub software_rasterization: u32,
+	/// meshlets whose triangles are estimated to be at most this many pixels large are rasterized in software
+	pub software_raster_threshold: f32,
+}
+
+impl NaniteSettings {
+	/// Whether instances and meshlets outside the view frustum are culled
+	pub fn frustum_culling(&self) -> bool {
+		self.frustum_culling != 0
+	}
+
+	/// Whether meshlets occluded by the depth of previously drawn meshlets are culled, using a two-pass HZB
+	pub fn occlusion_culling(&self) -> bool {
+		self.occlusion_culling != 0
+	}
+
+	/// Whether meshlets of single-sided materials are culled if all their triangles face away from the camera
+	pub fn backface_culling(&self) -> bool {
+		self.backface_culling != 0
+	}
+
+	/// Whether meshlets are drawn into a visibility buffer and shaded by a compute material pass, instead of writing
+	/// their sampled materials into the g-buffer
+	pub fn visibility_buffer(&self) -> bool {
+		self.visibility_buffer != 0
+	}
+
+	/// Whether meshlets with tiny triangles are rasterized by a compute shader instead of the mesh shader. Requires the
+	/// [`Self::visibility_buffer`], as only its material pass merges the software
+	/// rasterized pixels.
+	pub fn software_rasterization(&self) -> bool {
+		self.visibility_buffer() && self.software_rasterization != 0
+	}
 }
 
 #[derive(Copy, Clone, BufferStruct)]
@@ -36,12 +87,25 @@ pub struct NaniteSettings {
 #[repr(C)]
 pub struct FrameData {
 	pub camera: Camera,
+	/// camera of the previous frame for motion vectors, the same as `camera` if there is none
+	pub prev_camera: Camera,
 	pub debug_settings: u32,
 	pub debug_mix: f32,
 	pub debug_lod_level: LodSelection,
 	pub sun: DirectionalLight,
+	/// shadows cast by the [`Self::sun`]
+	pub shadow: ShadowSettings,
 	pub ambient_light: Radiance,
+	/// ambient light of the sky, replacing [`Self::ambient_light`] if enabled
+	pub ibl: IblSettings,
+	/// screen space ambient occlusion, darkening the ambient light and IBL
+	pub ao: AoSettings,
 	pub nanite: NaniteSettings,
+	pub taa: TaaSettings,
+	/// bloom mixed into the lit HDR frame before tonemapping
+	pub bloom: BloomSettings,
+	/// exposure and tonemapping of the lit HDR frame into the output image
+	pub tonemap: TonemapSettings,
 }
 
 impl FrameData {
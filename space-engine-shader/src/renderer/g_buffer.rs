@@ -6,5 +6,9 @@ pub struct GBuffer<R: DescRef> {
 	pub g_albedo: Desc<R, Image<Image2d>>,
 	pub g_normal: Desc<R, Image<Image2d>>,
 	pub g_roughness_metallic: Desc<R, Image<Image2d>>,
+	/// emitted radiance in rgb, ambient occlusion in alpha
+	pub g_emissive: Desc<R, Image<Image2d>>,
+	/// motion of each pixel since the previous frame in rg, see [`motion_vector`](crate::renderer::taa::motion_vector)
+	pub g_velocity: Desc<R, Image<Image2d>>,
 	pub depth_image: Desc<R, Image<Image2d>>,
 }
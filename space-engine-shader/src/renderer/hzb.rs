@@ -0,0 +1,206 @@
+//! Hierarchical-Z buffer (HZB): a mip chain of the depth buffer, where each texel holds the farthest depth of all the
+//! texels it covers. No per-mip image views are available, so the levels are packed into two atlas images, one holding
+//! the even and one the odd levels. That way, building a level always reads from one image while writing the other.
+//!
+//! Even levels: level 0 at the origin, followed by levels 2, 4, ... in a row below it.
+//! Odd levels: levels 1, 3, 5, ... in a row at the origin.
+
+use crate::renderer::camera::Camera;
+use crate::renderer::meshlet::mesh_shader::leading_zeros;
+use crate::renderer::meshlet::meshlet_select::{hzb_mip_level, project_sphere_to_screen_rect};
+use crate::renderer::visibility_buffer::software_raster::software_raster_index;
+use glam::{Affine3A, UVec2, UVec3, Vec2, Vec3Swizzles, Vec4};
+use rust_gpu_bindless_macros::{BufferStruct, bindless};
+use rust_gpu_bindless_shaders::descriptor::{Buffer, Descriptors, Image, Image2d, MutImage, TransientDesc};
+use space_asset_shader::shape::sphere::Sphere;
+use static_assertions::const_assert_eq;
+
+#[derive(Copy, Clone, BufferStruct)]
+pub struct Hzb<'a> {
+	pub even_levels: TransientDesc<'a, Image<Image2d>>,
+	pub odd_levels: TransientDesc<'a, Image<Image2d>>,
+	/// the camera the depth buffer was rendered with, its viewport size is the size of level 0
+	pub camera: Camera,
+	/// bool, see [`Self::enabled`]
+	pub enabled: u32,
+}
+
+impl Hzb<'_> {
+	/// Whether this HZB contains valid depth. If not, nothing is occluded.
+	pub fn enabled(&self) -> bool {
+		self.enabled != 0
+	}
+
+	/// Whether the `sphere` in local space transformed by `world_from_local` is entirely behind the depth stored in
+	/// this HZB. Conservative, may report occluded spheres as visible.
+	pub fn is_sphere_occluded(&self, descriptors: &Descriptors, world_from_local: Affine3A, sphere: Sphere) -> bool {
+		if !self.enabled() {
+			return false;
+		}
+		let Some(rect) = project_sphere_to_screen_rect(self.camera, world_from_local, sphere) else {
+			return false;
+		};
+
+		let size = self.camera.viewport_size;
+		let to_pixel = |uv: Vec2| {
+			(uv.clamp(Vec2::ZERO, Vec2::ONE) * size.as_vec2())
+				.as_uvec2()
+				.min(size - 1)
+		};
+		let min = to_pixel(rect.min);
+		let max = to_pixel(rect.max);
+		let level = hzb_mip_level(min, max);
+		if level >= hzb_level_count(size) {
+			return false;
+		}
+
+		let level_size = hzb_level_size(size, level);
+		let offset = hzb_level_offset(size, level);
+		let min = (min >> level).min(level_size - 1) + offset;
+		let max = (max >> level).min(level_size - 1) + offset;
+		let image = if level % 2 == 0 {
+			self.even_levels
+		} else {
+			self.odd_levels
+		};
+		let image = image.access(descriptors);
+		#[allow(clippy::useless_conversion)]
+		let fetch = |pixel: UVec2| Vec4::from(image.fetch(pixel)).x;
+		let depth = f32::max(
+			f32::max(fetch(min), fetch(UVec2::new(max.x, min.y))),
+			f32::max(fetch(UVec2::new(min.x, max.y)), fetch(max)),
+		);
+		rect.depth > depth
+	}
+}
+
+/// Number of levels until the HZB is reduced to a single texel
+pub fn hzb_level_count(size: UVec2) -> u32 {
+	32 - leading_zeros(u32::max(size.x, size.y))
+}
+
+/// Size of a level, halving the size of the previous level rounded down
+pub fn hzb_level_size(size: UVec2, level: u32) -> UVec2 {
+	UVec2::max(size >> level, UVec2::ONE)
+}
+
+/// Offset of a level within its atlas image, see module docs
+pub fn hzb_level_offset(size: UVec2, level: u32) -> UVec2 {
+	let mut x = 0;
+	let mut i = if level % 2 == 0 { 2 } else { 1 };
+	while i < level {
+		x += hzb_level_size(size, i).x;
+		i += 2;
+	}
+	let y = if level % 2 == 0 && level != 0 { size.y } else { 0 };
+	UVec2::new(x, y)
+}
+
+/// Size of the atlas image containing either the even or odd levels
+pub fn hzb_atlas_size(size: UVec2, odd: bool) -> UVec2 {
+	let mut atlas = UVec2::ONE;
+	let mut level = odd as u32;
+	while level < hzb_level_count(size) {
+		atlas = atlas.max(hzb_level_offset(size, level) + hzb_level_size(size, level));
+		level += 2;
+	}
+	atlas
+}
+
+#[derive(Copy, Clone, BufferStruct)]
+pub struct Param<'a> {
+	/// the depth image or the atlas containing the previous level
+	pub src: TransientDesc<'a, Image<Image2d>>,
+	pub src_offset: UVec2,
+	pub src_size: UVec2,
+	pub dst: TransientDesc<'a, MutImage<Image2d>>,
+	pub dst_offset: UVec2,
+	pub dst_size: UVec2,
+	/// depth of the software rasterizer, see [`Self::merge_software_depth`]
+	pub software_depth: TransientDesc<'a, Buffer<[u32]>>,
+	/// bool, whether the `src` depth image is merged with the `software_depth` of the same size
+	pub merge_software_depth: u32,
+}
+
+impl Param<'_> {
+	pub fn merge_software_depth(&self) -> bool {
+		self.merge_software_depth != 0
+	}
+}
+
+pub const HZB_BUILD_WG_SIZE: UVec2 = UVec2::new(8, 8);
+
+const_assert_eq!(HZB_BUILD_WG_SIZE.x, 8);
+const_assert_eq!(HZB_BUILD_WG_SIZE.y, 8);
+#[bindless(compute(threads(8, 8)))]
+pub fn hzb_build_cs(
+	#[bindless(descriptors)] descriptors: Descriptors,
+	#[bindless(param)] param: &Param<'static>,
+	#[spirv(global_invocation_id)] inv_id: UVec3,
+) {
+	let pixel = inv_id.xy();
+	if pixel.x < param.dst_size.x && pixel.y < param.dst_size.y {
+		let (x_start, x_end) = src_texels(param.src_size.x, param.dst_size.x, pixel.x);
+		let (y_start, y_end) = src_texels(param.src_size.y, param.dst_size.y, pixel.y);
+		let src = param.src.access(&descriptors);
+		let mut depth = 0.;
+		for y in y_start..y_end {
+			for x in x_start..x_end {
+				#[allow(clippy::useless_conversion)]
+				let texel = Vec4::from(src.fetch(param.src_offset + UVec2::new(x, y))).x;
+				depth = f32::max(depth, texel);
+			}
+		}
+		if param.merge_software_depth() {
+			let software_depth = param
+				.software_depth
+				.access(&descriptors)
+				.load(software_raster_index(pixel, param.src_size));
+			depth = f32::min(depth, f32::from_bits(software_depth));
+		}
+		unsafe {
+			param
+				.dst
+				.access(&descriptors)
+				.write(param.dst_offset + pixel, Vec4::new(depth, 0., 0., 0.));
+		}
+	}
+}
+
+/// The range of src texels covered by the dst texel along one axis: 2 texels if the level halves in size or 1 texel if
+/// it doesn't. The last dst texel also covers the remaining src texel of an odd src size, to stay conservative.
+fn src_texels(src_size: u32, dst_size: u32, dst: u32) -> (u32, u32) {
+	let scale = if src_size == dst_size { 1 } else { 2 };
+	let start = dst * scale;
+	let end = if dst + 1 == dst_size { src_size } else { start + scale };
+	(start, end)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_hzb_layout() {
+		let size = UVec2::new(100, 60);
+		assert_eq!(hzb_level_count(size), 7);
+		assert_eq!(hzb_level_size(size, 1), UVec2::new(50, 30));
+		assert_eq!(hzb_level_size(size, 6), UVec2::new(1, 1));
+		assert_eq!(hzb_level_offset(size, 0), UVec2::new(0, 0));
+		assert_eq!(hzb_level_offset(size, 1), UVec2::new(0, 0));
+		assert_eq!(hzb_level_offset(size, 2), UVec2::new(0, 60));
+		assert_eq!(hzb_level_offset(size, 3), UVec2::new(50, 0));
+		assert_eq!(hzb_level_offset(size, 4), UVec2::new(25, 60));
+		assert_eq!(hzb_atlas_size(size, false), UVec2::new(100, 75));
+		assert_eq!(hzb_atlas_size(size, true), UVec2::new(65, 30));
+	}
+
+	#[test]
+	fn test_src_texels() {
+		assert_eq!(src_texels(100, 100, 7), (7, 8));
+		assert_eq!(src_texels(100, 50, 7), (14, 16));
+		assert_eq!(src_texels(5, 2, 0), (0, 2));
+		assert_eq!(src_texels(5, 2, 1), (2, 5));
+		assert_eq!(src_texels(1, 1, 0), (0, 1));
+	}
+}
@@ -0,0 +1,449 @@
+//! Screen space ambient occlusion, following "Practical Realtime Strategies for Accurate Indirect Occlusion", Jimenez
+//! et al. 2016 (GTAO). For every pixel, [`AoSettings::sample_count`] slices of the hemisphere around the view vector
+//! are searched for the highest horizon on both sides within [`AoSettings::radius`], and the cosine weighted visible
+//! arc between both horizons is integrated analytically, see [`GtaoSlice`]. The noisy result of [`ao_cs`] is then
+//! smoothed by the depth aware [`ao_denoise_cs`].
+//!
+//! The visibility only darkens the ambient light and IBL, by multiplying it into the material's
+//! [`SampledMaterial::occlusion`](crate::material::pbr::SampledMaterial::occlusion).
+
+use crate::renderer::camera::Camera;
+use crate::renderer::frame_data::FrameData;
+use crate::renderer::lighting::is_skybox;
+use core::f32::consts::{FRAC_PI_2, PI};
+use glam::{IVec2, UVec2, UVec3, Vec2, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles};
+use rust_gpu_bindless_macros::{BufferStruct, bindless};
+use rust_gpu_bindless_shaders::descriptor::{Buffer, Descriptors, Image, Image2d, MutImage, TransientDesc};
+#[cfg(target_arch = "spirv")]
+use spirv_std::num_traits::Float;
+use static_assertions::const_assert_eq;
+
+/// steps taken along each side of a slice
+pub const AO_SLICE_STEPS: u32 = 4;
+
+/// fraction of the [`AoSettings::radius`] over which the influence of occluders fades out towards its end
+pub const AO_FALLOFF_RANGE: f32 = 0.6;
+
+/// pixels sampled in every direction by the [`ao_denoise_cs`]
+pub const AO_DENOISE_RADIUS: i32 = 2;
+
+/// relative view depth difference at which the weight of a neighbour in the [`ao_denoise_cs`] falls to 1/e
+pub const AO_DENOISE_DEPTH_SIGMA: f32 = 0.05;
+
+#[derive(Copy, Clone, BufferStruct)]
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[repr(C)]
+pub struct AoSettings {
+	/// bool, see [`Self::enabled`]
+	pub enabled: u32,
+	/// world space radius around each pixel that is searched for occluders
+	pub radius: f32,
+	/// exponent applied to the visibility, values above 1 darken the occlusion
+	pub intensity: f32,
+	/// number of slices searched per pixel, each taking [`AO_SLICE_STEPS`] samples on both sides
+	pub sample_count: u32,
+}
+
+impl AoSettings {
+	/// Whether screen space ambient occlusion is computed, otherwise only the material's occlusion is applied
+	pub fn enabled(&self) -> bool {
+		self.enabled != 0
+	}
+}
+
+/// The denoised ambient occlusion of the current frame, sampled by the lighting
+#[derive(Copy, Clone, BufferStruct)]
+pub struct AmbientOcclusion<'a> {
+	/// visibility of each pixel, only valid if [`AoSettings::enabled`]
+	pub visibility: TransientDesc<'a, Image<Image2d>>,
+}
+
+impl AmbientOcclusion<'_> {
+	/// The visibility of the ambient light at `pixel`, 1 if unoccluded or disabled
+	pub fn visibility(&self, descriptors: &Descriptors, frame_data: FrameData, pixel: UVec2) -> f32 {
+		if frame_data.ao.enabled() {
+			#[allow(clippy::useless_conversion)]
+			Vec4::from(self.visibility.access(descriptors).fetch(pixel)).x
+		} else {
+			1.
+		}
+	}
+}
+
+/// A slice of the hemisphere, spanned by the view vector and a direction perpendicular to it. Angles within the slice
+/// are measured from the view vector, positive towards the slice direction.
+#[derive(Copy, Clone, Debug)]
+pub struct GtaoSlice {
+	/// angle of the normal projected onto the slice
+	pub n: f32,
+	/// length of the normal projected onto the slice, weighting the slice's contribution
+	pub projected_normal_length: f32,
+}
+
+impl GtaoSlice {
+	/// The slice of the hemisphere around `normal` containing `v` and `direction`, all in the same space
+	pub fn new(v: Vec3, normal: Vec3, direction: Vec3) -> Self {
+		let ortho_direction = direction - Vec3::dot(direction, v) * v;
+		let axis = Vec3::cross(ortho_direction, v).normalize_or_zero();
+		let projected_normal = normal - axis * Vec3::dot(normal, axis);
+		let projected_normal_length = projected_normal.length();
+		if projected_normal_length < 1e-6 {
+			return Self {
+				n: 0.,
+				projected_normal_length: 0.,
+			};
+		}
+		let cos_n = f32::clamp(Vec3::dot(projected_normal, v) / projected_normal_length, -1., 1.);
+		let sign = if Vec3::dot(ortho_direction, projected_normal) < 0. {
+			-1.
+		} else {
+			1.
+		};
+		Self {
+			n: sign * f32::acos(cos_n),
+			projected_normal_length,
+		}
+	}
+
+	/// The cosines of the lowest possible horizons, lying in the tangent plane. `x` is the horizon towards the slice
+	/// direction, `y` the one opposite to it.
+	pub fn tangent_horizon_cos(&self) -> Vec2 {
+		Vec2::new(f32::cos(self.n + FRAC_PI_2), f32::cos(self.n - FRAC_PI_2))
+	}
+
+	/// The cosine weighted visibility between both horizons, given as the cosines of their angles to the view vector.
+	/// `x` is the horizon towards the slice direction, `y` the one opposite to it. Averaged over all slices around the
+	/// view vector, an unoccluded hemisphere has a visibility of 1.
+	pub fn visibility(&self, horizon_cos: Vec2) -> f32 {
+		let n = self.n;
+		let h_pos = f32::acos(f32::clamp(horizon_cos.x, -1., 1.));
+		let h_neg = -f32::acos(f32::clamp(horizon_cos.y, -1., 1.));
+		// horizons can't lie below the tangent plane
+		let h_pos = n + f32::min(h_pos - n, FRAC_PI_2);
+		let h_neg = n + f32::max(h_neg - n, -FRAC_PI_2);
+		let arc = |h: f32| (f32::cos(n) + 2. * h * f32::sin(n) - f32::cos(2. * h - n)) / 4.;
+		self.projected_normal_length * (arc(h_pos) + arc(h_neg))
+	}
+}
+
+/// Pixels covered by one world unit at a view space distance of `view_depth` from the camera
+pub fn pixels_per_unit(camera: Camera, view_depth: f32) -> f32 {
+	let scale = camera.clip_from_view.y_axis.y.abs() * camera.viewport_size.y as f32 * 0.5;
+	if camera.is_orthographic() {
+		scale
+	} else {
+		scale / view_depth
+	}
+}
+
+/// Interleaved gradient noise of "Next Generation Post Processing in Call of Duty: Advanced Warfare", Jimenez 2014
+pub fn interleaved_gradient_noise(pixel: UVec2) -> f32 {
+	let pixel = pixel.as_vec2();
+	let f = 0.06711056 * pixel.x + 0.00583715 * pixel.y;
+	(52.982_918 * (f - f32::floor(f))).fract()
+}
+
+/// Reconstructs the normal of a pixel at `center` from the positions of its neighbours, preferring the neighbour on
+/// the same surface along each axis. The normal is flipped to face `v`.
+pub fn reconstruct_normal(center: Vec3, left: Vec3, right: Vec3, up: Vec3, down: Vec3, v: Vec3) -> Vec3 {
+	let closest = |a: Vec3, b: Vec3| {
+		if (a - center).length_squared() < (b - center).length_squared() {
+			center - a
+		} else {
+			b - center
+		}
+	};
+	let dx = closest(left, right);
+	let dy = closest(up, down);
+	let normal = Vec3::cross(dx, dy).normalize_or_zero();
+	if Vec3::dot(normal, v) < 0. { -normal } else { normal }
+}
+
+/// Weight of a neighbour at `offset` in the [`ao_denoise_cs`], given the view depths of the center and the neighbour
+pub fn bilateral_weight(offset: IVec2, center_depth: f32, sample_depth: f32) -> f32 {
+	let sigma = AO_DENOISE_RADIUS as f32;
+	let spatial = f32::exp(-(offset.length_squared() as f32) / (2. * sigma * sigma));
+	let depth = f32::exp(-f32::abs(sample_depth - center_depth) / (AO_DENOISE_DEPTH_SIGMA * center_depth));
+	spatial * depth
+}
+
+#[derive(Copy, Clone, BufferStruct)]
+pub struct Param<'a> {
+	pub frame_data: TransientDesc<'a, Buffer<FrameData>>,
+	pub depth_image: TransientDesc<'a, Image<Image2d>>,
+	/// normals of the g-buffer, ignored if [`Self::reconstruct_normals`]
+	pub g_normal: TransientDesc<'a, Image<Image2d>>,
+	/// bool, whether normals are reconstructed from depth, as the visibility buffer doesn't write the g-buffer
+	pub reconstruct_normals: u32,
+	pub output: TransientDesc<'a, MutImage<Image2d>>,
+}
+
+pub const AO_WG_SIZE: UVec2 = UVec2::new(8, 8);
+
+const_assert_eq!(AO_WG_SIZE.x, 8);
+const_assert_eq!(AO_WG_SIZE.y, 8);
+#[bindless(compute(threads(8, 8)))]
+pub fn ao_cs(
+	#[bindless(descriptors)] descriptors: Descriptors,
+	#[bindless(param)] param: &Param<'static>,
+	#[spirv(global_invocation_id)] inv_id: UVec3,
+) {
+	let frame_data = param.frame_data.access(&descriptors).load();
+	let settings = frame_data.ao;
+	let camera = frame_data.camera;
+	let size = camera.viewport_size;
+	let pixel = inv_id.xy();
+	if !(pixel.x < size.x && pixel.y < size.y) {
+		return;
+	}
+
+	let depth_image = param.depth_image.access(&descriptors);
+	#[allow(clippy::useless_conversion)]
+	let fetch_depth = |pixel: UVec2| Vec4::from(depth_image.fetch(pixel)).x;
+	let position = |pixel: UVec2| {
+		camera
+			.reconstruct_from_depth(pixel.as_vec2() / size.as_vec2(), fetch_depth(pixel))
+			.world_space
+	};
+	let clamp_pixel = |pixel: Vec2| pixel.floor().clamp(Vec2::ZERO, (size - 1).as_vec2()).as_uvec2();
+
+	let depth = fetch_depth(pixel);
+	let visibility = if is_skybox(depth) {
+		1.
+	} else {
+		let center = camera.reconstruct_from_depth(pixel.as_vec2() / size.as_vec2(), depth);
+		let p = center.world_space;
+		let v = (camera.view_from_world.translation() - p).normalize();
+		let normal = if param.reconstruct_normals != 0 {
+			let offset = |x: i32, y: i32| position(clamp_pixel(pixel.as_vec2() + Vec2::new(x as f32, y as f32)));
+			reconstruct_normal(p, offset(-1, 0), offset(1, 0), offset(0, -1), offset(0, 1), v)
+		} else {
+			#[allow(clippy::useless_conversion)]
+			let normal = Vec4::from(param.g_normal.access(&descriptors).fetch(pixel)).xyz();
+			(normal * 2. - 1.).normalize_or_zero()
+		};
+
+		let radius = settings.radius;
+		let radius_pixels = radius * pixels_per_unit(camera, -center.view_space.z);
+		if radius_pixels < 1. {
+			1.
+		} else {
+			let noise = interleaved_gradient_noise(pixel);
+			let slice_count = u32::max(settings.sample_count, 1);
+			let center_pixel = pixel.as_vec2() + 0.5;
+			let mut visibility = 0.;
+			for slice in 0..slice_count {
+				let phi = (slice as f32 + noise) / slice_count as f32 * PI;
+				let omega = Vec2::new(f32::cos(phi), f32::sin(phi));
+				// screen space y points down, view space y up
+				let direction = camera
+					.view_from_world
+					.affine
+					.transform_vector3(Vec3::new(omega.x, -omega.y, 0.));
+				let slice = GtaoSlice::new(v, normal, direction);
+
+				let tangent_horizon_cos = slice.tangent_horizon_cos();
+				let mut horizon_cos = tangent_horizon_cos;
+				for step in 0..AO_SLICE_STEPS {
+					let t = (step as f32 + noise) / AO_SLICE_STEPS as f32;
+					let offset = omega * f32::max(t * radius_pixels, step as f32 + 1.);
+					let sample_cos = |sample_pixel: Vec2, tangent_cos: f32| {
+						let delta = position(clamp_pixel(sample_pixel)) - p;
+						let distance = delta.length();
+						let falloff = f32::clamp((radius - distance) / (radius * AO_FALLOFF_RANGE), 0., 1.);
+						let cos = Vec3::dot(delta, v) / f32::max(distance, 1e-6);
+						tangent_cos + (cos - tangent_cos) * falloff
+					};
+					horizon_cos = horizon_cos.max(Vec2::new(
+						sample_cos(center_pixel + offset, tangent_horizon_cos.x),
+						sample_cos(center_pixel - offset, tangent_horizon_cos.y),
+					));
+				}
+				visibility += slice.visibility(horizon_cos);
+			}
+			f32::powf(f32::clamp(visibility / slice_count as f32, 0., 1.), settings.intensity)
+		}
+	};
+	unsafe {
+		param
+			.output
+			.access(&descriptors)
+			.write(pixel, Vec4::new(visibility, 0., 0., 1.));
+	}
+}
+
+/// Smooths the noise of the [`ao_cs`] with a bilateral filter of [`AO_DENOISE_RADIUS`], ignoring neighbours on other
+/// surfaces, see [`bilateral_weight`].
+#[derive(Copy, Clone, BufferStruct)]
+pub struct DenoiseParam<'a> {
+	pub frame_data: TransientDesc<'a, Buffer<FrameData>>,
+	pub depth_image: TransientDesc<'a, Image<Image2d>>,
+	/// visibility written by the [`ao_cs`]
+	pub input: TransientDesc<'a, Image<Image2d>>,
+	pub output: TransientDesc<'a, MutImage<Image2d>>,
+}
+
+#[bindless(compute(threads(8, 8)))]
+pub fn ao_denoise_cs(
+	#[bindless(descriptors)] descriptors: Descriptors,
+	#[bindless(param)] param: &DenoiseParam<'static>,
+	#[spirv(global_invocation_id)] inv_id: UVec3,
+) {
+	let frame_data = param.frame_data.access(&descriptors).load();
+	let camera = frame_data.camera;
+	let size = camera.viewport_size;
+	let pixel = inv_id.xy();
+	if !(pixel.x < size.x && pixel.y < size.y) {
+		return;
+	}
+
+	let depth_image = param.depth_image.access(&descriptors);
+	let input = param.input.access(&descriptors);
+	#[allow(clippy::useless_conversion)]
+	let fetch_depth = |pixel: UVec2| Vec4::from(depth_image.fetch(pixel)).x;
+	let view_depth = |pixel: UVec2, depth: f32| {
+		-camera
+			.reconstruct_from_depth(pixel.as_vec2() / size.as_vec2(), depth)
+			.view_space
+			.z
+	};
+
+	let depth = fetch_depth(pixel);
+	let visibility = if is_skybox(depth) {
+		1.
+	} else {
+		let center_depth = view_depth(pixel, depth);
+		let mut sum = 0.;
+		let mut weight_sum = 0.;
+		for y in -AO_DENOISE_RADIUS..=AO_DENOISE_RADIUS {
+			for x in -AO_DENOISE_RADIUS..=AO_DENOISE_RADIUS {
+				let offset = IVec2::new(x, y);
+				let sample = pixel.as_ivec2() + offset;
+				if sample.x >= 0 && sample.y >= 0 && sample.x < size.x as i32 && sample.y < size.y as i32 {
+					let sample = sample.as_uvec2();
+					let sample_depth = fetch_depth(sample);
+					if !is_skybox(sample_depth) {
+						let weight = bilateral_weight(offset, center_depth, view_depth(sample, sample_depth));
+						#[allow(clippy::useless_conversion)]
+						let visibility = Vec4::from(input.fetch(sample)).x;
+						sum += visibility * weight;
+						weight_sum += weight;
+					}
+				}
+			}
+		}
+		// the center always has a weight of 1
+		sum / weight_sum
+	};
+	unsafe {
+		param
+			.output
+			.access(&descriptors)
+			.write(pixel, Vec4::new(visibility, 0., 0., 1.));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use glam::vec3;
+	use space_asset_shader::affine_transform::AffineTransform;
+
+	/// Averages the visibility of an unoccluded hemisphere over many slices around the view vector
+	fn unoccluded_visibility(v: Vec3, normal: Vec3) -> f32 {
+		let count = 64;
+		let mut sum = 0.;
+		for i in 0..count {
+			let phi = (i as f32 + 0.5) / count as f32 * PI;
+			let slice = GtaoSlice::new(v, normal, vec3(f32::cos(phi), f32::sin(phi), 0.));
+			sum += slice.visibility(slice.tangent_horizon_cos());
+		}
+		sum / count as f32
+	}
+
+	#[test]
+	fn test_unoccluded_facing() {
+		let slice = GtaoSlice::new(Vec3::Z, Vec3::Z, Vec3::X);
+		let visibility = slice.visibility(slice.tangent_horizon_cos());
+		assert!((visibility - 1.).abs() < 1e-5, "{visibility}");
+	}
+
+	#[test]
+	fn test_unoccluded_tilted() {
+		for angle in [0.3, 0.8, 1.2] {
+			let normal = vec3(f32::sin(angle), 0., f32::cos(angle));
+			let visibility = unoccluded_visibility(Vec3::Z, normal);
+			assert!((visibility - 1.).abs() < 1e-3, "{angle} {visibility}");
+		}
+	}
+
+	#[test]
+	fn test_fully_occluded() {
+		// both horizons collapse onto the view vector
+		let slice = GtaoSlice::new(Vec3::Z, Vec3::Z, Vec3::X);
+		let visibility = slice.visibility(Vec2::ONE);
+		assert!(visibility.abs() < 1e-5, "{visibility}");
+	}
+
+	#[test]
+	fn test_half_occluded() {
+		let slice = GtaoSlice::new(Vec3::Z, Vec3::Z, Vec3::X);
+		let visibility = slice.visibility(Vec2::new(1., slice.tangent_horizon_cos().y));
+		assert!((visibility - 0.5).abs() < 1e-5, "{visibility}");
+	}
+
+	#[test]
+	fn test_horizon_below_tangent_plane() {
+		// horizons below the tangent plane don't add any visibility
+		let slice = GtaoSlice::new(Vec3::Z, Vec3::Z, Vec3::X);
+		let visibility = slice.visibility(Vec2::splat(-1.));
+		assert!((visibility - 1.).abs() < 1e-5, "{visibility}");
+	}
+
+	#[test]
+	fn test_pixels_per_unit() {
+		let size = UVec2::new(1920, 1080);
+		let perspective =
+			Camera::new_perspective_rh_y_flip(size, FRAC_PI_2, 0.1, 100., AffineTransform::default(), Vec2::ZERO);
+		// a 90° fov covers 2 units vertically at a distance of 1
+		let ppu = pixels_per_unit(perspective, 1.);
+		assert!((ppu - 540.).abs() < 0.01, "{ppu}");
+		let ppu = pixels_per_unit(perspective, 4.);
+		assert!((ppu - 135.).abs() < 0.01, "{ppu}");
+
+		let ortho = Camera::new_orthographic_rh_y_flip(size, 10., 0.1, 100., AffineTransform::default());
+		let ppu = pixels_per_unit(ortho, 4.);
+		assert!((ppu - 108.).abs() < 0.01, "{ppu}");
+	}
+
+	#[test]
+	fn test_reconstruct_normal() {
+		let v = Vec3::Z;
+		let normal = reconstruct_normal(Vec3::ZERO, -Vec3::X, Vec3::X, Vec3::Y, -Vec3::Y, v);
+		assert!(normal.distance(Vec3::Z) < 1e-5, "{normal:?}");
+
+		// the right neighbour lies on a surface far behind, so the left one is used
+		let normal = reconstruct_normal(Vec3::ZERO, -Vec3::X, vec3(1., 0., -10.), Vec3::Y, -Vec3::Y, v);
+		assert!(normal.distance(Vec3::Z) < 1e-5, "{normal:?}");
+	}
+
+	#[test]
+	fn test_bilateral_weight() {
+		assert_eq!(bilateral_weight(IVec2::ZERO, 10., 10.), 1.);
+		let neighbour = bilateral_weight(IVec2::new(1, 0), 10., 10.);
+		let far_neighbour = bilateral_weight(IVec2::new(2, 2), 10., 10.);
+		assert!(0. < far_neighbour && far_neighbour < neighbour && neighbour < 1.);
+		// another surface behind
+		assert!(bilateral_weight(IVec2::new(1, 0), 10., 15.) < 1e-3);
+	}
+
+	#[test]
+	fn test_interleaved_gradient_noise() {
+		for y in 0..16 {
+			for x in 0..16 {
+				let noise = interleaved_gradient_noise(UVec2::new(x, y));
+				assert!((0. ..1.).contains(&noise), "{noise}");
+			}
+		}
+	}
+}
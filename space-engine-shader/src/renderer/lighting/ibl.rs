@@ -0,0 +1,492 @@
+//! Image-based lighting of the sky, using the split-sum approximation of "Real Shading in Unreal Engine 4", Karis
+//! 2013. The sky is rendered into an octahedral environment map of [`ENVIRONMENT_SIZE`], which is then convolved
+//! into:
+//! * the diffuse irradiance, projected onto [`SphericalHarmonics9`]
+//! * [`SPECULAR_LEVELS`] octahedral maps prefiltered with the GGX lobe of increasing roughness, see [`prefilter_ggx`]
+//! * the BRDF LUT of scale and bias to `f0`, depending on `n·v` and roughness, see [`integrate_brdf`]
+//!
+//! Octahedral maps are used instead of cubemaps, as cubemaps and individual mip levels can't be written by compute
+//! shaders. All specular levels are packed side by side into a single atlas image, level `i` starting at
+//! [`specular_level_offset`].
+
+use crate::material::pbr::SampledMaterial;
+use crate::material::radiance::Radiance;
+use crate::renderer::frame_data::FrameData;
+use core::f32::consts::PI;
+use glam::{UVec2, Vec2, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles};
+use rust_gpu_bindless_macros::BufferStruct;
+use rust_gpu_bindless_shaders::descriptor::{Buffer, Descriptors, Image, Image2d, TransientDesc};
+use spirv_std::Sampler;
+#[cfg(target_arch = "spirv")]
+use spirv_std::num_traits::Float;
+
+/// width and height of the octahedral environment map and the first specular level
+pub const ENVIRONMENT_SIZE: u32 = 128;
+
+/// number of prefiltered specular levels, from roughness 0 to 1, each half the size of the previous
+pub const SPECULAR_LEVELS: u32 = 6;
+
+/// GGX samples taken per texel of a specular level
+pub const SPECULAR_SAMPLES: u32 = 128;
+
+/// width and height of the BRDF LUT
+pub const BRDF_LUT_SIZE: u32 = 64;
+
+/// GGX samples taken per texel of the BRDF LUT
+pub const BRDF_LUT_SAMPLES: u32 = 256;
+
+/// the environment map is sampled on a grid of this size when projecting it onto [`SphericalHarmonics9`]
+pub const IRRADIANCE_GRID_SIZE: u32 = 32;
+
+pub const SH_COEFFICIENTS: usize = 9;
+
+#[derive(Copy, Clone, BufferStruct)]
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[repr(C)]
+pub struct IblSettings {
+	/// bool, see [`Self::enabled`]
+	pub enabled: u32,
+	/// factor applied to all image-based light
+	pub intensity: f32,
+}
+
+impl IblSettings {
+	/// Whether ambient light is sampled from the sky's environment map, otherwise the flat
+	/// [`FrameData::ambient_light`] is used
+	pub fn enabled(&self) -> bool {
+		self.enabled != 0
+	}
+}
+
+fn sign_not_zero(v: Vec2) -> Vec2 {
+	Vec2::new(if v.x >= 0. { 1. } else { -1. }, if v.y >= 0. { 1. } else { -1. })
+}
+
+/// Maps a unit direction onto the octahedral map, returning uv in `[0, 1]²`. The upper hemisphere of `+z` covers the
+/// inner diamond, the lower hemisphere is folded over the corners.
+pub fn oct_encode(dir: Vec3) -> Vec2 {
+	let p = dir / (dir.x.abs() + dir.y.abs() + dir.z.abs());
+	let xy = if p.z >= 0. {
+		p.xy()
+	} else {
+		(1. - p.yx().abs()) * sign_not_zero(p.xy())
+	};
+	xy * 0.5 + 0.5
+}
+
+/// The point on the octahedron `|x| + |y| + |z| = 1` at `uv` of the octahedral map
+fn oct_point(uv: Vec2) -> Vec3 {
+	let f = uv * 2. - 1.;
+	let z = 1. - f.x.abs() - f.y.abs();
+	let t = f32::max(-z, 0.);
+	Vec3::new(
+		f.x + if f.x >= 0. { -t } else { t },
+		f.y + if f.y >= 0. { -t } else { t },
+		z,
+	)
+}
+
+/// The unit direction at `uv` of the octahedral map, inverse of [`oct_encode`]
+pub fn oct_decode(uv: Vec2) -> Vec3 {
+	oct_point(uv).normalize()
+}
+
+/// Solid angle covered by a texel of an octahedral map of `size` centered at `uv`. Projecting the octahedron onto the
+/// unit sphere scales area by `1 / |p|³`, which sums up to `4π` over the entire map.
+pub fn oct_texel_solid_angle(uv: Vec2, size: u32) -> f32 {
+	let p = oct_point(uv);
+	let texel_area = 4. / (size * size) as f32;
+	texel_area / (p.length_squared() * p.length())
+}
+
+/// x offset of specular `level` within the atlas
+pub fn specular_level_offset(level: u32) -> u32 {
+	2 * ENVIRONMENT_SIZE - ((2 * ENVIRONMENT_SIZE) >> level)
+}
+
+/// width and height of specular `level`
+pub fn specular_level_size(level: u32) -> u32 {
+	ENVIRONMENT_SIZE >> level
+}
+
+/// Size of the atlas containing all specular levels
+pub fn specular_atlas_size() -> UVec2 {
+	let last = SPECULAR_LEVELS - 1;
+	UVec2::new(
+		specular_level_offset(last) + specular_level_size(last),
+		ENVIRONMENT_SIZE,
+	)
+}
+
+/// Roughness the specular `level` is prefiltered with
+pub fn specular_level_roughness(level: u32) -> f32 {
+	level as f32 / (SPECULAR_LEVELS - 1) as f32
+}
+
+/// uv within the specular atlas to sample `dir` from `level` at. The uv is kept half a texel away from the level's
+/// edges, so bilinear filtering never bleeds into neighbouring levels.
+pub fn specular_atlas_uv(dir: Vec3, level: u32) -> Vec2 {
+	let size = specular_level_size(level) as f32;
+	let half_texel = 0.5 / size;
+	let uv = oct_encode(dir).clamp(Vec2::splat(half_texel), Vec2::splat(1. - half_texel));
+	let pixel = Vec2::new(specular_level_offset(level) as f32, 0.) + uv * size;
+	pixel / specular_atlas_size().as_vec2()
+}
+
+/// Radiance of an environment projected onto the first 3 bands of real spherical harmonics
+#[derive(Copy, Clone, Default)]
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+pub struct SphericalHarmonics9 {
+	pub coefficients: [Vec3; SH_COEFFICIENTS],
+}
+
+#[allow(clippy::needless_range_loop)]
+impl SphericalHarmonics9 {
+	/// The SH basis functions evaluated in the unit direction `dir`
+	pub fn basis(dir: Vec3) -> [f32; SH_COEFFICIENTS] {
+		let Vec3 { x, y, z } = dir;
+		[
+			0.282095,
+			0.488603 * y,
+			0.488603 * z,
+			0.488603 * x,
+			1.092548 * x * y,
+			1.092548 * y * z,
+			0.315392 * (3. * z * z - 1.),
+			1.092548 * x * z,
+			0.546274 * (x * x - y * y),
+		]
+	}
+
+	/// Adds the `radiance` arriving from `dir` over a `solid_angle`
+	pub fn add_sample(&mut self, dir: Vec3, radiance: Vec3, solid_angle: f32) {
+		let basis = Self::basis(dir);
+		for i in 0..SH_COEFFICIENTS {
+			self.coefficients[i] += radiance * basis[i] * solid_angle;
+		}
+	}
+
+	pub fn scale(&mut self, factor: f32) {
+		for i in 0..SH_COEFFICIENTS {
+			self.coefficients[i] *= factor;
+		}
+	}
+
+	/// Irradiance arriving at a surface facing `normal`, by convolving the radiance with the clamped cosine lobe, see
+	/// "An Efficient Representation for Irradiance Environment Maps", Ramamoorthi and Hanrahan 2001
+	pub fn irradiance(&self, normal: Vec3) -> Vec3 {
+		const BAND_FACTOR: [f32; SH_COEFFICIENTS] = [
+			PI,
+			2. * PI / 3.,
+			2. * PI / 3.,
+			2. * PI / 3.,
+			PI / 4.,
+			PI / 4.,
+			PI / 4.,
+			PI / 4.,
+			PI / 4.,
+		];
+		let basis = Self::basis(normal);
+		let mut irradiance = Vec3::ZERO;
+		for i in 0..SH_COEFFICIENTS {
+			irradiance += self.coefficients[i] * BAND_FACTOR[i] * basis[i];
+		}
+		// ringing may cause slightly negative values opposite of bright light
+		irradiance.max(Vec3::ZERO)
+	}
+
+	pub fn load(descriptors: &Descriptors, buffer: TransientDesc<Buffer<[Vec4]>>) -> Self {
+		let buffer = buffer.access(descriptors);
+		let mut sh = Self::default();
+		for i in 0..SH_COEFFICIENTS {
+			sh.coefficients[i] = buffer.load(i).xyz();
+		}
+		sh
+	}
+}
+
+/// Point `i` of `n` of the Hammersley sequence in `[0, 1)²`
+pub fn hammersley(i: u32, n: u32) -> Vec2 {
+	let mut bits = i;
+	bits = (bits << 16) | (bits >> 16);
+	bits = ((bits & 0x55555555) << 1) | ((bits & 0xAAAAAAAA) >> 1);
+	bits = ((bits & 0x33333333) << 2) | ((bits & 0xCCCCCCCC) >> 2);
+	bits = ((bits & 0x0F0F0F0F) << 4) | ((bits & 0xF0F0F0F0) >> 4);
+	bits = ((bits & 0x00FF00FF) << 8) | ((bits & 0xFF00FF00) >> 8);
+	Vec2::new(i as f32 / n as f32, bits as f32 * 2.328_306_4e-10)
+}
+
+/// Samples a half vector around the unit normal `n`, distributed according to the GGX NDF of `roughness`
+pub fn importance_sample_ggx(xi: Vec2, n: Vec3, roughness: f32) -> Vec3 {
+	let a = roughness * roughness;
+	let phi = 2. * PI * xi.x;
+	let cos_theta = f32::sqrt((1. - xi.y) / (1. + (a * a - 1.) * xi.y));
+	let sin_theta = f32::sqrt(1. - cos_theta * cos_theta);
+	let h = Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta);
+
+	let up = if n.z.abs() < 0.999 { Vec3::Z } else { Vec3::X };
+	let tangent = Vec3::cross(up, n).normalize();
+	let bitangent = Vec3::cross(n, tangent);
+	(tangent * h.x + bitangent * h.y + n * h.z).normalize()
+}
+
+/// Smith geometry term with the `k` remapping for image-based lighting
+fn geometry_smith_ibl(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+	let k = roughness * roughness / 2.;
+	let ggx_v = n_dot_v / (n_dot_v * (1. - k) + k);
+	let ggx_l = n_dot_l / (n_dot_l * (1. - k) + k);
+	ggx_v * ggx_l
+}
+
+/// Integrates the specular BRDF over the hemisphere for a view at `n_dot_v` with `roughness`, returning the scale and
+/// bias to `f0` of the second sum of the split-sum approximation.
+pub fn integrate_brdf(n_dot_v: f32, roughness: f32, sample_count: u32) -> Vec2 {
+	let v = Vec3::new(f32::sqrt(1. - n_dot_v * n_dot_v), 0., n_dot_v);
+	let n = Vec3::Z;
+	let mut scale = 0.;
+	let mut bias = 0.;
+	for i in 0..sample_count {
+		let h = importance_sample_ggx(hammersley(i, sample_count), n, roughness);
+		let l = 2. * Vec3::dot(v, h) * h - v;
+		let n_dot_l = l.z.max(0.);
+		let n_dot_h = h.z.max(0.);
+		let v_dot_h = Vec3::dot(v, h).max(0.);
+		if n_dot_l > 0. {
+			let g = geometry_smith_ibl(n_dot_v, n_dot_l, roughness);
+			let g_vis = g * v_dot_h / (n_dot_h * n_dot_v);
+			let fc = libm::powf(1. - v_dot_h, 5.);
+			scale += (1. - fc) * g_vis;
+			bias += fc * g_vis;
+		}
+	}
+	Vec2::new(scale, bias) / sample_count as f32
+}
+
+/// Prefilters the environment `sample`d by direction with the GGX lobe of `roughness` around `n`, assuming the view
+/// and reflection direction equal the normal.
+pub fn prefilter_ggx(n: Vec3, roughness: f32, sample_count: u32, sample: impl Fn(Vec3) -> Vec3) -> Vec3 {
+	let mut sum = Vec3::ZERO;
+	let mut weight = 0.;
+	for i in 0..sample_count {
+		let h = importance_sample_ggx(hammersley(i, sample_count), n, roughness);
+		let l = 2. * Vec3::dot(n, h) * h - n;
+		let n_dot_l = Vec3::dot(n, l);
+		if n_dot_l > 0. {
+			sum += sample(l) * n_dot_l;
+			weight += n_dot_l;
+		}
+	}
+	sum / f32::max(weight, 0.0001)
+}
+
+/// The baked image-based lighting of the sky, see [module docs](self)
+#[derive(Copy, Clone, BufferStruct)]
+pub struct Ibl<'a> {
+	/// all [`SPECULAR_LEVELS`] side by side, see [`specular_atlas_uv`]
+	pub specular: TransientDesc<'a, Image<Image2d>>,
+	/// scale and bias to `f0` by `n·v` along x and roughness along y, see [`integrate_brdf`]
+	pub brdf_lut: TransientDesc<'a, Image<Image2d>>,
+	/// [`SH_COEFFICIENTS`] coefficients of [`SphericalHarmonics9`]
+	pub irradiance: TransientDesc<'a, Buffer<[Vec4]>>,
+	/// linear sampler clamping to edge
+	pub sampler: TransientDesc<'a, Sampler>,
+}
+
+impl Ibl<'_> {
+	/// The ambient light reflected by the `sampled` material, falling back to [`FrameData::ambient_light`] if IBL is
+	/// disabled
+	pub fn ambient_light(
+		&self,
+		descriptors: &Descriptors,
+		frame_data: FrameData,
+		sampled: SampledMaterial,
+	) -> Radiance {
+		let settings = frame_data.ibl;
+		if !settings.enabled() {
+			return sampled.ambient_light(frame_data.ambient_light);
+		}
+		let sampler = self.sampler.access(descriptors);
+
+		let irradiance = SphericalHarmonics9::load(descriptors, self.irradiance).irradiance(sampled.normal) / PI;
+
+		let specular = self.specular.access(descriptors);
+		let reflection = sampled.reflection();
+		let lod = sampled.roughness.clamp(0., 1.) * (SPECULAR_LEVELS - 1) as f32;
+		let level = lod.floor() as u32;
+		let next_level = u32::min(level + 1, SPECULAR_LEVELS - 1);
+		let sample_level =
+			|level: u32| -> Vec4 { specular.sample_by_lod(sampler, specular_atlas_uv(reflection, level), 0.) };
+		let prefiltered = Vec3::lerp(
+			sample_level(level).xyz(),
+			sample_level(next_level).xyz(),
+			lod - level as f32,
+		);
+
+		let n_dot_v = Vec3::dot(sampled.normal, *sampled.v).max(0.);
+		let brdf: Vec4 =
+			self.brdf_lut
+				.access(descriptors)
+				.sample_by_lod(sampler, Vec2::new(n_dot_v, sampled.roughness), 0.);
+
+		sampled.image_based_light(Radiance(irradiance), Radiance(prefiltered), brdf.xy()) * settings.intensity
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn directions() -> impl Iterator<Item = Vec3> {
+		[
+			Vec3::X,
+			-Vec3::X,
+			Vec3::Y,
+			-Vec3::Y,
+			Vec3::Z,
+			-Vec3::Z,
+			Vec3::new(0.3, -0.5, 0.8),
+			Vec3::new(-0.7, 0.2, -0.4),
+			Vec3::new(0.1, 0.9, -0.05),
+			Vec3::new(-0.6, -0.6, -0.6),
+		]
+		.into_iter()
+		.map(Vec3::normalize)
+	}
+
+	/// Projects `radiance` onto SH, sampling it at the texel centers of an octahedral map of `size`
+	fn project(size: u32, radiance: impl Fn(Vec3) -> Vec3) -> SphericalHarmonics9 {
+		let mut sh = SphericalHarmonics9::default();
+		for y in 0..size {
+			for x in 0..size {
+				let uv = (UVec2::new(x, y).as_vec2() + 0.5) / size as f32;
+				let dir = oct_decode(uv);
+				sh.add_sample(dir, radiance(dir), oct_texel_solid_angle(uv, size));
+			}
+		}
+		sh
+	}
+
+	#[test]
+	fn test_oct_roundtrip() {
+		for dir in directions() {
+			let uv = oct_encode(dir);
+			assert!(
+				uv.cmpge(Vec2::ZERO).all() && uv.cmple(Vec2::ONE).all(),
+				"{dir:?} {uv:?}"
+			);
+			let decoded = oct_decode(uv);
+			assert!(decoded.distance(dir) < 1e-5, "{dir:?} {decoded:?}");
+		}
+	}
+
+	#[test]
+	fn test_oct_solid_angle() {
+		let size = 64;
+		let mut sum = 0.;
+		for y in 0..size {
+			for x in 0..size {
+				sum += oct_texel_solid_angle((UVec2::new(x, y).as_vec2() + 0.5) / size as f32, size);
+			}
+		}
+		assert!((sum - 4. * PI).abs() < 0.01, "{sum}");
+	}
+
+	#[test]
+	fn test_specular_atlas() {
+		assert_eq!(specular_level_offset(0), 0);
+		for level in 1..SPECULAR_LEVELS {
+			assert_eq!(
+				specular_level_offset(level),
+				specular_level_offset(level - 1) + specular_level_size(level - 1)
+			);
+		}
+		assert_eq!(specular_atlas_size(), UVec2::new(252, 128));
+		assert_eq!(specular_level_roughness(0), 0.);
+		assert_eq!(specular_level_roughness(SPECULAR_LEVELS - 1), 1.);
+	}
+
+	#[test]
+	fn test_sh_constant_environment() {
+		let radiance = Vec3::new(1., 2., 0.5);
+		let sh = project(64, |_| radiance);
+		for normal in directions() {
+			let irradiance = sh.irradiance(normal);
+			assert!(irradiance.distance(PI * radiance) < 0.02, "{normal:?} {irradiance:?}");
+		}
+	}
+
+	#[test]
+	fn test_sh_hemisphere_environment() {
+		// light only arriving from above
+		let sh = project(64, |dir| if dir.y > 0. { Vec3::ONE } else { Vec3::ZERO });
+		let up = sh.irradiance(Vec3::Y);
+		assert!((up - PI).abs().max_element() < 0.05, "{up:?}");
+		let down = sh.irradiance(-Vec3::Y);
+		assert!(down.max_element() < 0.05, "{down:?}");
+		let side = sh.irradiance(Vec3::X);
+		assert!((side - PI / 2.).abs().max_element() < 0.05, "{side:?}");
+	}
+
+	#[test]
+	fn test_hammersley() {
+		let n = 64;
+		for i in 0..n {
+			let xi = hammersley(i, n);
+			assert_eq!(xi.x, i as f32 / n as f32);
+			assert!((0. ..1.).contains(&xi.y), "{xi:?}");
+		}
+		assert_eq!(hammersley(1, n).y, 0.5);
+		assert_eq!(hammersley(2, n).y, 0.25);
+		assert_eq!(hammersley(3, n).y, 0.75);
+	}
+
+	#[test]
+	fn test_importance_sample_ggx() {
+		for n in directions() {
+			for i in 0..32 {
+				let xi = hammersley(i, 32);
+				let smooth = importance_sample_ggx(xi, n, 0.);
+				assert!(smooth.distance(n) < 1e-4, "{n:?} {smooth:?}");
+				let rough = importance_sample_ggx(xi, n, 0.8);
+				assert!((rough.length() - 1.).abs() < 1e-4, "{rough:?}");
+				assert!(Vec3::dot(rough, n) >= 0., "{n:?} {rough:?}");
+			}
+		}
+	}
+
+	#[test]
+	fn test_integrate_brdf() {
+		for n_dot_v in [0.05, 0.25, 0.5, 0.75, 1.] {
+			// a perfect mirror reflects everything
+			let smooth = integrate_brdf(n_dot_v, 0., 64);
+			assert!((smooth.x + smooth.y - 1.).abs() < 1e-3, "{n_dot_v} {smooth:?}");
+
+			for roughness in [0.25, 0.5, 0.75] {
+				let brdf = integrate_brdf(n_dot_v, roughness, 1024);
+				assert!(brdf.cmpge(Vec2::ZERO).all(), "{n_dot_v} {roughness} {brdf:?}");
+				assert!(brdf.x + brdf.y < 1., "{n_dot_v} {roughness} {brdf:?}");
+			}
+			// rough surfaces lose energy to shadowing and masking
+			let rough = integrate_brdf(n_dot_v, 1., 1024);
+			assert!(rough.x + rough.y < 0.7, "{n_dot_v} {rough:?}");
+		}
+		let head_on = integrate_brdf(1., 0., 64);
+		assert!(head_on.distance(Vec2::X) < 1e-3, "{head_on:?}");
+	}
+
+	#[test]
+	fn test_prefilter_ggx() {
+		let radiance = Vec3::new(0.2, 0.4, 0.8);
+		for n in directions() {
+			let prefiltered = prefilter_ggx(n, 0.7, 64, |_| radiance);
+			assert!(prefiltered.distance(radiance) < 1e-4, "{prefiltered:?}");
+			let mirror = prefilter_ggx(n, 0., 16, |l| l);
+			assert!(mirror.distance(n) < 1e-4, "{n:?} {mirror:?}");
+		}
+		// rougher lobes gather light from further away
+		let spot = |l: Vec3| if l.y > 0.99 { Vec3::ONE } else { Vec3::ZERO };
+		let n = Vec3::new(0.3, 1., 0.).normalize();
+		assert_eq!(prefilter_ggx(n, 0.05, 256, spot), Vec3::ZERO);
+		assert!(prefilter_ggx(n, 0.8, 256, spot).x > 0.);
+	}
+}
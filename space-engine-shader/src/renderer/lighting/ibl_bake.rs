@@ -0,0 +1,169 @@
+//! Compute shaders baking the [`Ibl`](crate::renderer::lighting::ibl::Ibl) of the sky, see
+//! [`ibl`](crate::renderer::lighting::ibl). The environment map is rendered first, all other shaders only read from it.
+
+use crate::renderer::frame_data::FrameData;
+use crate::renderer::lighting::ibl::{
+	BRDF_LUT_SAMPLES, BRDF_LUT_SIZE, ENVIRONMENT_SIZE, IRRADIANCE_GRID_SIZE, SH_COEFFICIENTS, SPECULAR_LEVELS,
+	SPECULAR_SAMPLES, SphericalHarmonics9, integrate_brdf, oct_decode, oct_encode, oct_texel_solid_angle,
+	prefilter_ggx, specular_level_offset, specular_level_roughness, specular_level_size,
+};
+use crate::renderer::lighting::sky_shader::preetham_sky_no_sun_disk;
+use glam::{UVec2, UVec3, Vec2, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles};
+use rust_gpu_bindless_macros::{BufferStruct, bindless};
+use rust_gpu_bindless_shaders::descriptor::{Buffer, Descriptors, Image, Image2d, MutBuffer, MutImage, TransientDesc};
+use spirv_std::Sampler;
+use static_assertions::const_assert_eq;
+
+/// Brings the [`preetham_sky`](crate::renderer::lighting::sky_shader::preetham_sky) into the same range as the sun's
+/// radiance
+pub const SKY_RADIANCE_SCALE: f32 = 0.01;
+
+/// Radiance of the sky arriving from `dir`. The flat [`FrameData::ambient_light`] is kept as a floor, so the
+/// environment doesn't turn black at night.
+pub fn sky_radiance(frame_data: FrameData, dir: Vec3) -> Vec3 {
+	preetham_sky_no_sun_disk(dir, frame_data.sun.direction) * SKY_RADIANCE_SCALE + frame_data.ambient_light.0
+}
+
+pub const IBL_BAKE_WG_SIZE: UVec2 = UVec2::new(8, 8);
+
+#[derive(Copy, Clone, BufferStruct)]
+pub struct EnvironmentParam<'a> {
+	pub frame_data: TransientDesc<'a, Buffer<FrameData>>,
+	pub environment: TransientDesc<'a, MutImage<Image2d>>,
+}
+
+const_assert_eq!(IBL_BAKE_WG_SIZE.x, 8);
+const_assert_eq!(IBL_BAKE_WG_SIZE.y, 8);
+#[bindless(compute(threads(8, 8)))]
+pub fn ibl_environment_cs(
+	#[bindless(descriptors)] descriptors: Descriptors,
+	#[bindless(param)] param: &EnvironmentParam<'static>,
+	#[spirv(global_invocation_id)] inv_id: UVec3,
+) {
+	let frame_data = param.frame_data.access(&descriptors).load();
+	let pixel = inv_id.xy();
+	if !(pixel.x < ENVIRONMENT_SIZE && pixel.y < ENVIRONMENT_SIZE) {
+		return;
+	}
+
+	let dir = oct_decode((pixel.as_vec2() + 0.5) / ENVIRONMENT_SIZE as f32);
+	let radiance = sky_radiance(frame_data, dir);
+	unsafe {
+		param.environment.access(&descriptors).write(pixel, radiance.extend(1.));
+	}
+}
+
+/// Projects the environment onto [`SphericalHarmonics9`] in a single invocation, sampling it on a grid of
+/// [`IRRADIANCE_GRID_SIZE`]. Irradiance is smooth enough that the grid doesn't need to match the environment's
+/// resolution.
+#[derive(Copy, Clone, BufferStruct)]
+pub struct IrradianceParam<'a> {
+	pub environment: TransientDesc<'a, Image<Image2d>>,
+	pub sampler: TransientDesc<'a, Sampler>,
+	/// [`SH_COEFFICIENTS`] coefficients of [`SphericalHarmonics9`]
+	pub irradiance: TransientDesc<'a, MutBuffer<[Vec4]>>,
+}
+
+#[bindless(compute(threads(1)))]
+pub fn ibl_irradiance_cs(
+	#[bindless(descriptors)] mut descriptors: Descriptors,
+	#[bindless(param)] param: &IrradianceParam<'static>,
+) {
+	let environment = param.environment.access(&descriptors);
+	let sampler = param.sampler.access(&descriptors);
+	let mut sh = SphericalHarmonics9::default();
+	let mut total_solid_angle = 0.;
+	for y in 0..IRRADIANCE_GRID_SIZE {
+		for x in 0..IRRADIANCE_GRID_SIZE {
+			let uv = (Vec2::new(x as f32, y as f32) + 0.5) / IRRADIANCE_GRID_SIZE as f32;
+			let radiance: Vec4 = environment.sample_by_lod(sampler, uv, 0.);
+			let solid_angle = oct_texel_solid_angle(uv, IRRADIANCE_GRID_SIZE);
+			sh.add_sample(oct_decode(uv), radiance.xyz(), solid_angle);
+			total_solid_angle += solid_angle;
+		}
+	}
+	// compensate the discretization of the solid angles, they should sum up to the full sphere
+	sh.scale(4. * core::f32::consts::PI / total_solid_angle);
+
+	#[allow(clippy::needless_range_loop)]
+	for i in 0..SH_COEFFICIENTS {
+		unsafe {
+			param
+				.irradiance
+				.access(&mut descriptors)
+				.store(i, sh.coefficients[i].extend(0.));
+		}
+	}
+}
+
+/// Prefilters all [`SPECULAR_LEVELS`] at once, each level being a z slice of the dispatch. All levels are filtered
+/// from the environment map instead of the previous level, so they don't depend on each other.
+#[derive(Copy, Clone, BufferStruct)]
+pub struct SpecularParam<'a> {
+	pub environment: TransientDesc<'a, Image<Image2d>>,
+	pub sampler: TransientDesc<'a, Sampler>,
+	/// atlas of all [`SPECULAR_LEVELS`], see [`specular_level_offset`]
+	pub specular: TransientDesc<'a, MutImage<Image2d>>,
+}
+
+#[bindless(compute(threads(8, 8)))]
+pub fn ibl_specular_cs(
+	#[bindless(descriptors)] descriptors: Descriptors,
+	#[bindless(param)] param: &SpecularParam<'static>,
+	#[spirv(global_invocation_id)] inv_id: UVec3,
+) {
+	let level = inv_id.z;
+	let size = specular_level_size(level);
+	let pixel = inv_id.xy();
+	if !(level < SPECULAR_LEVELS && pixel.x < size && pixel.y < size) {
+		return;
+	}
+
+	let environment = param.environment.access(&descriptors);
+	let sampler = param.sampler.access(&descriptors);
+	let sample = |dir: Vec3| -> Vec3 {
+		let radiance: Vec4 = environment.sample_by_lod(sampler, oct_encode(dir), 0.);
+		radiance.xyz()
+	};
+	let n = oct_decode((pixel.as_vec2() + 0.5) / size as f32);
+	let radiance = if level == 0 {
+		sample(n)
+	} else {
+		prefilter_ggx(n, specular_level_roughness(level), SPECULAR_SAMPLES, sample)
+	};
+	unsafe {
+		param
+			.specular
+			.access(&descriptors)
+			.write(UVec2::new(specular_level_offset(level), 0) + pixel, radiance.extend(1.));
+	}
+}
+
+/// Integrates the BRDF LUT, see [`integrate_brdf`]. It does not depend on the environment and only has to be baked
+/// once.
+#[derive(Copy, Clone, BufferStruct)]
+pub struct BrdfLutParam<'a> {
+	pub brdf_lut: TransientDesc<'a, MutImage<Image2d>>,
+}
+
+#[bindless(compute(threads(8, 8)))]
+pub fn ibl_brdf_lut_cs(
+	#[bindless(descriptors)] descriptors: Descriptors,
+	#[bindless(param)] param: &BrdfLutParam<'static>,
+	#[spirv(global_invocation_id)] inv_id: UVec3,
+) {
+	let pixel = inv_id.xy();
+	if !(pixel.x < BRDF_LUT_SIZE && pixel.y < BRDF_LUT_SIZE) {
+		return;
+	}
+
+	// texel centers, so linear sampling at (n·v, roughness) matches
+	let coord = (pixel.as_vec2() + 0.5) / BRDF_LUT_SIZE as f32;
+	let brdf = integrate_brdf(coord.x, coord.y, BRDF_LUT_SAMPLES);
+	unsafe {
+		param
+			.brdf_lut
+			.access(&descriptors)
+			.write(pixel, Vec4::new(brdf.x, brdf.y, 0., 1.));
+	}
+}
@@ -0,0 +1,366 @@
+//! Clustered shading of the scene's [`PunctualLight`]s. The view frustum is divided into a grid of clusters: screen
+//! space tiles of [`CLUSTER_TILE_SIZE`] pixels, each split into [`CLUSTER_Z_SLICES`] exponentially spaced depth slices.
+//! The [`light_cluster_cs`] assigns every light to all clusters its bounds intersect, so that shading a pixel only has
+//! to evaluate the lights of the cluster it lies in.
+
+use crate::material::pbr::SampledMaterial;
+use crate::material::radiance::Radiance;
+use crate::renderer::camera::Camera;
+use crate::renderer::frame_data::FrameData;
+use core::f32::consts::FRAC_1_SQRT_2;
+use glam::{BVec2, UVec2, UVec3, Vec2, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles};
+use rust_gpu_bindless_macros::{BufferStruct, bindless};
+use rust_gpu_bindless_shaders::descriptor::{Buffer, Descriptors, MutBuffer, TransientDesc};
+use space_asset_shader::light::{PunctualLight, PunctualLightKind};
+#[cfg(target_arch = "spirv")]
+use spirv_std::num_traits::Float;
+use static_assertions::const_assert_eq;
+
+/// width and height of the screen space tile of a cluster in pixels
+pub const CLUSTER_TILE_SIZE: u32 = 64;
+
+/// number of depth slices between [`ClusterGrid::z_near`] and [`ClusterGrid::z_far`]
+pub const CLUSTER_Z_SLICES: u32 = 24;
+
+/// view space distance at which the last depth slice ends. Lights beyond are ignored and surfaces beyond are shaded
+/// with the lights of the last slice.
+pub const CLUSTER_Z_FAR: f32 = 1000.;
+
+/// lights a single cluster can hold, any further lights are dropped
+pub const MAX_CLUSTER_LIGHTS: u32 = 63;
+
+/// u32s per cluster: the light count followed by [`MAX_CLUSTER_LIGHTS`] light indices
+pub const CLUSTER_STRIDE: u32 = MAX_CLUSTER_LIGHTS + 1;
+
+/// The clusters of a camera, see [module docs](self)
+#[derive(Copy, Clone)]
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+pub struct ClusterGrid {
+	/// number of tiles along x and y
+	pub tiles: UVec2,
+	/// view space distance at which the first depth slice begins
+	pub z_near: f32,
+	/// view space distance at which the last depth slice ends
+	pub z_far: f32,
+}
+
+impl ClusterGrid {
+	pub fn new(camera: &Camera) -> Self {
+		// orthographic cameras may have their near plane at 0, which exponential slices can't start at
+		let z_near = f32::max(camera.z_near, 0.01);
+		Self {
+			tiles: Self::tiles(camera.viewport_size),
+			z_near,
+			z_far: f32::max(CLUSTER_Z_FAR, z_near * 2.),
+		}
+	}
+
+	/// Number of tiles along x and y covering a viewport of `viewport_size`
+	pub fn tiles(viewport_size: UVec2) -> UVec2 {
+		(viewport_size + CLUSTER_TILE_SIZE - 1) / CLUSTER_TILE_SIZE
+	}
+
+	pub fn cluster_count(&self) -> u32 {
+		self.tiles.x * self.tiles.y * CLUSTER_Z_SLICES
+	}
+
+	/// The depth slice containing the view space distance `depth`, clamped to the existing slices
+	pub fn slice_from_depth(&self, depth: f32) -> u32 {
+		let t = libm::logf(f32::max(depth, self.z_near) / self.z_near) / libm::logf(self.z_far / self.z_near);
+		u32::min((t * CLUSTER_Z_SLICES as f32) as u32, CLUSTER_Z_SLICES - 1)
+	}
+
+	/// The view space distance at which `slice` begins, with `CLUSTER_Z_SLICES` returning the end of the last slice
+	pub fn slice_depth(&self, slice: u32) -> f32 {
+		self.z_near * libm::powf(self.z_far / self.z_near, slice as f32 / CLUSTER_Z_SLICES as f32)
+	}
+
+	pub fn cluster_id(&self, cluster: UVec3) -> u32 {
+		(cluster.z * self.tiles.y + cluster.y) * self.tiles.x + cluster.x
+	}
+
+	pub fn cluster_from_id(&self, id: u32) -> UVec3 {
+		let slice_len = self.tiles.x * self.tiles.y;
+		UVec3::new(id % self.tiles.x, id % slice_len / self.tiles.x, id / slice_len)
+	}
+
+	/// The cluster containing `view_pos` in view space of `camera`, clamped to the grid
+	pub fn cluster_at(&self, camera: &Camera, view_pos: Vec3) -> UVec3 {
+		let clip = camera.clip_from_view * Vec4::from((view_pos, 1.));
+		let uv = clip.xy() / clip.w * 0.5 + 0.5;
+		let tile = (uv * camera.viewport_size.as_vec2() / CLUSTER_TILE_SIZE as f32)
+			.max(Vec2::ZERO)
+			.as_uvec2()
+			.min(self.tiles - 1);
+		UVec3::from((tile, self.slice_from_depth(-view_pos.z)))
+	}
+
+	/// Axis aligned bounding box of `cluster` in view space of `camera`, as its min and max corner
+	pub fn cluster_bounds(&self, camera: &Camera, cluster: UVec3) -> (Vec3, Vec3) {
+		let viewport = camera.viewport_size.as_vec2();
+		let uv_min = (cluster.xy() * CLUSTER_TILE_SIZE).as_vec2() / viewport;
+		let uv_max = Vec2::min(((cluster.xy() + 1) * CLUSTER_TILE_SIZE).as_vec2() / viewport, Vec2::ONE);
+		let near = self.slice_depth(cluster.z);
+		let far = self.slice_depth(cluster.z + 1);
+
+		let mut min = Vec3::splat(f32::INFINITY);
+		let mut max = Vec3::splat(f32::NEG_INFINITY);
+		for i in 0..4 {
+			let uv = Vec2::select(BVec2::new(i & 1 != 0, i & 2 != 0), uv_max, uv_min);
+			// the ray through the tile's corner, intersected with the planes of the slice
+			let ndc = uv * 2. - 1.;
+			let a = camera.view_from_clip * Vec4::from((ndc, 0., 1.));
+			let b = camera.view_from_clip * Vec4::from((ndc, 1., 1.));
+			let (a, b) = (a.xyz() / a.w, b.xyz() / b.w);
+			let near_pos = a.lerp(b, (-near - a.z) / (b.z - a.z));
+			let far_pos = a.lerp(b, (-far - a.z) / (b.z - a.z));
+			min = min.min(near_pos).min(far_pos);
+			max = max.max(near_pos).max(far_pos);
+		}
+		(min, max)
+	}
+}
+
+/// Bounding sphere of the volume lit by `light` in world space, as its center and radius
+pub fn light_bounds(light: PunctualLight) -> (Vec3, f32) {
+	let cos = light.outer_cone_cos;
+	match light.kind() {
+		PunctualLightKind::Spot if cos >= FRAC_1_SQRT_2 => {
+			// narrow cones are bounded by the sphere through the apex and the rim of the cone's base
+			let radius = light.range / (2. * cos);
+			(light.position + light.direction * radius, radius)
+		}
+		PunctualLightKind::Spot if cos > 0. => {
+			// wide cones are bounded by the sphere around the rim of the cone's base
+			let sin = f32::sqrt(1. - cos * cos);
+			(
+				light.position + light.direction * (light.range * cos),
+				light.range * sin,
+			)
+		}
+		_ => (light.position, light.range),
+	}
+}
+
+pub fn sphere_intersects_aabb(center: Vec3, radius: f32, min: Vec3, max: Vec3) -> bool {
+	let closest = center.clamp(min, max);
+	closest.distance_squared(center) <= radius * radius
+}
+
+#[derive(Copy, Clone, BufferStruct)]
+pub struct Param<'a> {
+	pub frame_data: TransientDesc<'a, Buffer<FrameData>>,
+	pub lights: TransientDesc<'a, Buffer<[PunctualLight]>>,
+	pub num_lights: u32,
+	/// [`CLUSTER_STRIDE`] u32s per cluster of the [`ClusterGrid`]
+	pub clusters: TransientDesc<'a, MutBuffer<[u32]>>,
+}
+
+pub const LIGHT_CLUSTER_WG_SIZE: u32 = 64;
+
+const_assert_eq!(LIGHT_CLUSTER_WG_SIZE, 64);
+#[bindless(compute(threads(64)))]
+pub fn light_cluster_cs(
+	#[bindless(descriptors)] mut descriptors: Descriptors,
+	#[bindless(param)] param: &Param<'static>,
+	#[spirv(global_invocation_id)] inv_id: UVec3,
+) {
+	let camera = param.frame_data.access(&descriptors).load().camera;
+	let grid = ClusterGrid::new(&camera);
+	let cluster_id = inv_id.x;
+	if cluster_id >= grid.cluster_count() {
+		return;
+	}
+
+	let (min, max) = grid.cluster_bounds(&camera, grid.cluster_from_id(cluster_id));
+	let base = (cluster_id * CLUSTER_STRIDE) as usize;
+	let mut count = 0;
+	for i in 0..param.num_lights {
+		// Safety: the lights buffer contains at least `num_lights` lights
+		let light = unsafe { param.lights.access(&descriptors).load_unchecked(i as usize) };
+		let (center, radius) = light_bounds(light);
+		let center = camera.view_from_world.affine.transform_point3_transposed(center);
+		if count < MAX_CLUSTER_LIGHTS && sphere_intersects_aabb(center, radius, min, max) {
+			unsafe {
+				param
+					.clusters
+					.access(&mut descriptors)
+					.store(base + 1 + count as usize, i)
+			};
+			count += 1;
+		}
+	}
+	unsafe { param.clusters.access(&mut descriptors).store(base, count) };
+}
+
+/// The lights assigned to each cluster by [`light_cluster_cs`], ready for shading
+#[derive(Copy, Clone, BufferStruct)]
+pub struct LightClusters<'a> {
+	pub lights: TransientDesc<'a, Buffer<[PunctualLight]>>,
+	/// [`CLUSTER_STRIDE`] u32s per cluster of the [`ClusterGrid`]
+	pub clusters: TransientDesc<'a, Buffer<[u32]>>,
+}
+
+impl LightClusters<'_> {
+	/// The light all [`PunctualLight`]s of the cluster containing the `sampled` surface reflect towards the camera
+	pub fn evaluate(&self, descriptors: &Descriptors, camera: Camera, sampled: SampledMaterial) -> Radiance {
+		let grid = ClusterGrid::new(&camera);
+		let view_pos = camera
+			.view_from_world
+			.affine
+			.transform_point3_transposed(sampled.world_pos);
+		let cluster_id = grid.cluster_id(grid.cluster_at(&camera, view_pos));
+		let clusters = self.clusters.access(descriptors);
+		let lights = self.lights.access(descriptors);
+		let base = (cluster_id * CLUSTER_STRIDE) as usize;
+		let count = u32::min(clusters.load(base), MAX_CLUSTER_LIGHTS);
+		let mut lo = Radiance(Vec3::ZERO);
+		for i in 0..count {
+			// Safety: a cluster holds at most MAX_CLUSTER_LIGHTS valid light indices
+			unsafe {
+				let light_id = clusters.load_unchecked(base + 1 + i as usize);
+				lo += sampled.evaluate_punctual_light(lights.load_unchecked(light_id as usize));
+			}
+		}
+		lo
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::f32::consts::FRAC_PI_2;
+	use glam::{Affine3A, Quat};
+	use space_asset_shader::affine_transform::AffineTransform;
+
+	fn new_camera(transform: Affine3A) -> Camera {
+		Camera::new_perspective_rh_y_flip(
+			UVec2::new(1920, 1080),
+			FRAC_PI_2,
+			0.1,
+			500.,
+			AffineTransform::new(transform),
+			Vec2::ZERO,
+		)
+	}
+
+	fn spot_light(direction: Vec3, range: f32, outer_cone_angle: f32) -> PunctualLight {
+		PunctualLight {
+			position: Vec3::new(1., 2., 3.),
+			range,
+			direction,
+			kind: PunctualLightKind::Spot.into(),
+			color: Vec3::ONE,
+			inner_cone_cos: 1.,
+			outer_cone_cos: f32::cos(outer_cone_angle),
+		}
+	}
+
+	#[test]
+	fn test_grid_size() {
+		let grid = ClusterGrid::new(&new_camera(Affine3A::IDENTITY));
+		assert_eq!(grid.tiles, UVec2::new(30, 17));
+		assert_eq!(grid.cluster_count(), 30 * 17 * CLUSTER_Z_SLICES);
+		for id in [0, 1, 29, 30, 30 * 17, 30 * 17 + 31, grid.cluster_count() - 1] {
+			let cluster = grid.cluster_from_id(id);
+			assert!(
+				cluster.xy().cmplt(grid.tiles).all() && cluster.z < CLUSTER_Z_SLICES,
+				"{cluster:?}"
+			);
+			assert_eq!(grid.cluster_id(cluster), id);
+		}
+	}
+
+	#[test]
+	fn test_depth_slices() {
+		let grid = ClusterGrid::new(&new_camera(Affine3A::IDENTITY));
+		assert!((grid.slice_depth(0) - 0.1).abs() < 1e-6);
+		assert!((grid.slice_depth(CLUSTER_Z_SLICES) - CLUSTER_Z_FAR).abs() < 1e-2);
+		for slice in 0..CLUSTER_Z_SLICES {
+			let (near, far) = (grid.slice_depth(slice), grid.slice_depth(slice + 1));
+			assert!(near < far);
+			// slices grow exponentially
+			if slice > 0 {
+				let ratio = far / near;
+				let prev_ratio = near / grid.slice_depth(slice - 1);
+				assert!((ratio - prev_ratio).abs() < 1e-3, "{ratio} {prev_ratio}");
+			}
+			assert_eq!(grid.slice_from_depth(near * 1.001), slice);
+			assert_eq!(grid.slice_from_depth(far * 0.999), slice);
+		}
+		// out of range depths are clamped
+		assert_eq!(grid.slice_from_depth(0.), 0);
+		assert_eq!(grid.slice_from_depth(-5.), 0);
+		assert_eq!(grid.slice_from_depth(1e6), CLUSTER_Z_SLICES - 1);
+	}
+
+	#[test]
+	fn test_cluster_bounds_contain_points() {
+		let transform = Affine3A::from_rotation_translation(Quat::from_rotation_y(0.6), Vec3::new(-3., 1., 7.));
+		let camera = new_camera(transform);
+		let grid = ClusterGrid::new(&camera);
+		for y in (0..1080).step_by(97) {
+			for x in (0..1920).step_by(131) {
+				for depth in [0.15, 0.9, 3., 17.5, 120., 480.] {
+					let uv = (UVec2::new(x, y).as_vec2() + 0.5) / camera.viewport_size.as_vec2();
+					let clip = camera.clip_from_view * Vec4::new(0., 0., -depth, 1.);
+					let world_pos = camera.reconstruct_from_depth(uv, clip.z / clip.w).world_space;
+					let view_pos = transform.inverse().transform_point3(world_pos);
+					assert!((view_pos.z + depth).abs() < depth * 1e-3, "{view_pos:?} {depth}");
+
+					let cluster = grid.cluster_at(&camera, view_pos);
+					assert_eq!(cluster.xy(), UVec2::new(x, y) / CLUSTER_TILE_SIZE);
+					let (min, max) = grid.cluster_bounds(&camera, cluster);
+					let epsilon = Vec3::splat(depth * 1e-3);
+					assert!(
+						view_pos.cmpge(min - epsilon).all() && view_pos.cmple(max + epsilon).all(),
+						"{view_pos:?} not in {cluster:?} {min:?} {max:?}"
+					);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn test_sphere_intersects_aabb() {
+		let (min, max) = (Vec3::new(-1., -1., -1.), Vec3::new(1., 2., 3.));
+		assert!(sphere_intersects_aabb(Vec3::ZERO, 0.1, min, max));
+		assert!(sphere_intersects_aabb(Vec3::new(2., 0., 0.), 1.01, min, max));
+		assert!(!sphere_intersects_aabb(Vec3::new(2., 0., 0.), 0.99, min, max));
+		// near the corner, the sphere is further away than along each axis
+		assert!(!sphere_intersects_aabb(Vec3::new(2., 3., 4.), 1.5, min, max));
+		assert!(sphere_intersects_aabb(Vec3::new(2., 3., 4.), 1.75, min, max));
+	}
+
+	#[test]
+	fn test_light_bounds() {
+		let point = PunctualLight {
+			kind: PunctualLightKind::Point.into(),
+			..spot_light(Vec3::X, 5., 0.)
+		};
+		assert_eq!(light_bounds(point), (point.position, 5.));
+
+		for angle in [0.1, 0.5, 0.78, 0.8, 1.2, FRAC_PI_2 - 0.01] {
+			let direction = Vec3::new(0.3, -1., 0.2).normalize();
+			let light = spot_light(direction, 10., angle);
+			let (center, radius) = light_bounds(light);
+			assert!(radius <= 10. + 1e-4, "{angle}: {radius}");
+			// sample the cone's surface and base cap
+			let rotation = Quat::from_rotation_arc(Vec3::Z, direction);
+			for i in 0..16 {
+				let around = i as f32 / 16. * core::f32::consts::TAU;
+				for t in [0., 0.5, 1.] {
+					let theta = angle * t;
+					let local = Vec3::new(theta.sin() * around.cos(), theta.sin() * around.sin(), theta.cos());
+					for distance in [0., 5., 10.] {
+						let p = light.position + rotation * local * distance;
+						assert!(
+							p.distance(center) <= radius + 1e-3,
+							"{angle}: {p:?} {center:?} {radius}"
+						);
+					}
+				}
+			}
+		}
+	}
+}
@@ -3,20 +3,27 @@ use crate::material::radiance::Radiance;
 use crate::renderer::camera::Camera;
 use crate::renderer::frame_data::{DebugSettings, FrameData};
 use crate::renderer::g_buffer::GBuffer;
+use crate::renderer::lighting::ambient_occlusion::AmbientOcclusion;
+use crate::renderer::lighting::ibl::Ibl;
 use crate::renderer::lighting::is_skybox;
+use crate::renderer::lighting::light_cluster::LightClusters;
+use crate::renderer::lighting::shadow::ShadowMap;
 use crate::utils::hsv::hsv2rgb_smooth;
 use glam::{UVec2, UVec3, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles, uvec2, vec3};
 use rust_gpu_bindless_macros::{BufferStruct, bindless};
 use rust_gpu_bindless_shaders::descriptor::{
 	AliveDescRef, Buffer, Descriptors, Image2d, MutImage, Transient, TransientDesc,
 };
-use rust_gpu_bindless_shaders::utils::srgb::linear_to_srgb_alpha;
 use static_assertions::const_assert_eq;
 
 #[derive(Copy, Clone, BufferStruct)]
 pub struct Param<'a> {
 	pub frame_data: TransientDesc<'a, Buffer<FrameData>>,
 	pub g_buffer: GBuffer<Transient<'a>>,
+	pub shadow_map: ShadowMap<'a>,
+	pub light_clusters: LightClusters<'a>,
+	pub ibl: Ibl<'a>,
+	pub ambient_occlusion: AmbientOcclusion<'a>,
 	pub output_image: TransientDesc<'a, MutImage<Image2d>>,
 }
 
@@ -36,35 +43,65 @@ pub fn lighting_cs(
 	let pixel = pixel_wg_start + uvec2(inv_id.x, 0);
 	let pixel_inbounds = pixel.x < size.x && pixel.y < size.y;
 
-	let (sampled, debug_hue) =
+	let (mut sampled, debug_hue, depth) =
 		sampled_material_from_g_buffer(frame_data.camera, &descriptors, param.g_buffer, pixel, size);
-	let skybox = is_skybox(sampled.alpha);
+	let skybox = is_skybox(depth);
+	if pixel_inbounds {
+		sampled.occlusion *= param.ambient_occlusion.visibility(&descriptors, frame_data, pixel);
+	}
+
+	let out_color = shade(
+		&descriptors,
+		frame_data,
+		param.shadow_map,
+		param.light_clusters,
+		param.ibl,
+		sampled,
+		debug_hue,
+	);
+	if pixel_inbounds && !skybox {
+		unsafe {
+			param.output_image.access(&descriptors).write(pixel, out_color);
+		}
+	}
+}
 
+/// The linear HDR color of a pixel covered by geometry, mixing the lit material with the debug view, to be
+/// [tonemapped](crate::renderer::tonemap)
+pub fn shade(
+	descriptors: &Descriptors,
+	frame_data: FrameData,
+	shadow_map: ShadowMap,
+	light_clusters: LightClusters,
+	ibl: Ibl,
+	sampled: SampledMaterial,
+	debug_hue: f32,
+) -> Vec4 {
 	let debug_color = || match frame_data.debug_settings() {
 		DebugSettings::None => Vec3::ZERO,
 		DebugSettings::MeshletId | DebugSettings::TriangleId | DebugSettings::LodLevel => debug_color(debug_hue),
 		DebugSettings::BaseColor => sampled.albedo,
 		DebugSettings::Normals | DebugSettings::VertexNormals => sampled.normal,
 		DebugSettings::RoughnessMetallic => vec3(0., sampled.roughness, sampled.metallic),
+		DebugSettings::AmbientOcclusion => Vec3::splat(sampled.occlusion),
 	};
 
 	let out_color = if frame_data.debug_mix < 0.01 {
-		material_eval(frame_data, sampled)
+		material_eval(descriptors, frame_data, shadow_map, light_clusters, ibl, sampled)
 	} else if frame_data.debug_mix > 0.99 {
 		debug_color()
 	} else {
-		Vec3::lerp(material_eval(frame_data, sampled), debug_color(), frame_data.debug_mix)
+		Vec3::lerp(
+			material_eval(descriptors, frame_data, shadow_map, light_clusters, ibl, sampled),
+			debug_color(),
+			frame_data.debug_mix,
+		)
 	};
 
-	let out_color = Vec4::from((out_color, 1.));
-	let out_color = linear_to_srgb_alpha(out_color);
-	if pixel_inbounds && !skybox {
-		unsafe {
-			param.output_image.access(&descriptors).write(pixel, out_color);
-		}
-	}
+	Vec4::from((out_color, 1.))
 }
 
+/// Returns the sampled material, the meshlet debug hue and the depth of a pixel
 #[allow(clippy::useless_conversion)]
 fn sampled_material_from_g_buffer(
 	camera: Camera,
@@ -72,7 +109,7 @@ fn sampled_material_from_g_buffer(
 	g_buffer: GBuffer<impl AliveDescRef>,
 	pixel: UVec2,
 	size: UVec2,
-) -> (SampledMaterial, f32) {
+) -> (SampledMaterial, f32, f32) {
 	let albedo = Vec4::from(g_buffer.g_albedo.access(descriptors).fetch(pixel));
 	let alpha = albedo.w;
 	let albedo = albedo.xyz();
@@ -82,6 +119,9 @@ fn sampled_material_from_g_buffer(
 	let [roughness, metallic] = Vec4::from(g_buffer.g_roughness_metallic.access(descriptors).fetch(pixel))
 		.xy()
 		.to_array();
+	let emissive = Vec4::from(g_buffer.g_emissive.access(descriptors).fetch(pixel));
+	let occlusion = emissive.w;
+	let emissive = emissive.xyz();
 	let depth = Vec4::from(g_buffer.depth_image.access(descriptors).fetch(pixel)).x;
 
 	let position = camera.reconstruct_from_depth(pixel.as_vec2() / size.as_vec2(), depth);
@@ -93,15 +133,27 @@ fn sampled_material_from_g_buffer(
 		normal,
 		roughness,
 		metallic,
+		occlusion,
+		emissive,
 	};
-	(sampled, meshlet_debug_hue)
+	(sampled, meshlet_debug_hue, depth)
 }
 
-fn material_eval(frame_data: FrameData, sampled: SampledMaterial) -> Vec3 {
+fn material_eval(
+	descriptors: &Descriptors,
+	frame_data: FrameData,
+	shadow_map: ShadowMap,
+	light_clusters: LightClusters,
+	ibl: Ibl,
+	sampled: SampledMaterial,
+) -> Vec3 {
+	let sun_visibility = shadow_map.sun_visibility(descriptors, frame_data, sampled.world_pos, sampled.normal);
 	let mut lo = Radiance(Vec3::ZERO);
-	lo += sampled.evaluate_directional_light(frame_data.sun);
-	lo += sampled.ambient_light(frame_data.ambient_light);
-	lo.tone_map_reinhard()
+	lo += sampled.evaluate_directional_light(frame_data.sun) * sun_visibility;
+	lo += light_clusters.evaluate(descriptors, frame_data.camera, sampled);
+	lo += ibl.ambient_light(descriptors, frame_data, sampled);
+	lo += sampled.emitted_light();
+	lo.0
 }
 
 fn debug_color(meshlet_debug_hue: f32) -> Vec3 {
@@ -1,6 +1,12 @@
+pub mod ambient_occlusion;
+pub mod ibl;
+pub mod ibl_bake;
+pub mod light_cluster;
 pub mod lighting_compute;
+pub mod shadow;
 pub mod sky_shader;
 
-pub fn is_skybox(albedo_alpha: f32) -> bool {
-	albedo_alpha < 0.01
+/// Whether no geometry was drawn at a pixel with this depth, as the depth image is cleared to the far plane
+pub fn is_skybox(depth: f32) -> bool {
+	depth >= 1.
 }
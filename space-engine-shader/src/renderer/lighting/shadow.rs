@@ -0,0 +1,437 @@
+//! Cascaded shadow maps of the sun [`DirectionalLight`](crate::material::light::DirectionalLight). The view frustum is
+//! split along its depth into up to [`MAX_SHADOW_CASCADES`] slices, and each slice is covered by its own orthographic
+//! light camera. All cascades are drawn side by side into a single depth atlas, cascade `i` occupying the square at
+//! `(i * resolution, 0)`.
+
+use crate::renderer::camera::Camera;
+use crate::renderer::frame_data::FrameData;
+use glam::{Affine3A, IVec2, UVec2, Vec2, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles};
+use rust_gpu_bindless_macros::BufferStruct;
+use rust_gpu_bindless_shaders::descriptor::{Buffer, Descriptors, Image, Image2d, TransientDesc};
+use space_asset_shader::affine_transform::AffineTransform;
+use space_asset_shader::shape::sphere::Sphere;
+#[cfg(target_arch = "spirv")]
+use spirv_std::num_traits::Float;
+
+pub const MAX_SHADOW_CASCADES: u32 = 4;
+
+/// Smallest [`ShadowSettings::resolution`], leaving at least one texel within the margin of [`light_texel_size`]
+pub const MIN_SHADOW_RESOLUTION: u32 = 3;
+
+/// Blends between uniform (0) and logarithmic (1) cascade splits, see [`cascade_splits`]
+pub const SHADOW_SPLIT_LAMBDA: f32 = 0.75;
+
+#[derive(Copy, Clone, BufferStruct)]
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[repr(C)]
+pub struct ShadowSettings {
+	/// bool, see [`Self::enabled`]
+	pub enabled: u32,
+	/// see [`Self::cascade_count`]
+	pub cascade_count: u32,
+	/// see [`Self::resolution`]
+	pub resolution: u32,
+	/// view space distance from the camera up to which shadows are drawn
+	pub max_distance: f32,
+	/// constant depth bias in world units
+	pub depth_bias: f32,
+	/// offset along the surface normal in texels of the cascade
+	pub normal_bias: f32,
+}
+
+impl ShadowSettings {
+	/// Whether the sun casts shadows, otherwise it is never occluded
+	pub fn enabled(&self) -> bool {
+		self.enabled != 0
+	}
+
+	/// Number of cascades, between 1 and [`MAX_SHADOW_CASCADES`]
+	pub fn cascade_count(&self) -> u32 {
+		u32::clamp(self.cascade_count, 1, MAX_SHADOW_CASCADES)
+	}
+
+	/// Width and height of each cascade in texels, at least [`MIN_SHADOW_RESOLUTION`]
+	pub fn resolution(&self) -> u32 {
+		u32::max(self.resolution, MIN_SHADOW_RESOLUTION)
+	}
+
+	/// Size of the depth atlas containing all cascades
+	pub fn atlas_size(&self) -> UVec2 {
+		UVec2::new(self.resolution() * self.cascade_count(), self.resolution())
+	}
+}
+
+#[derive(Copy, Clone, BufferStruct)]
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[repr(C)]
+pub struct ShadowCascade {
+	/// orthographic camera looking along the light direction, see [`fit_light_camera`]
+	pub camera: Camera,
+	/// [`ShadowSettings::depth_bias`] in depth units of `camera`
+	pub depth_bias: f32,
+	/// [`ShadowSettings::normal_bias`] in world units
+	pub normal_offset: f32,
+}
+
+impl ShadowCascade {
+	/// The cascade covering `bounds`, see [`fit_light_camera`]
+	pub fn new(light_direction: Vec3, bounds: Sphere, settings: ShadowSettings) -> Self {
+		let caster_distance = settings.max_distance;
+		let camera = fit_light_camera(light_direction, bounds, settings.resolution(), caster_distance);
+		let depth_range = 2. * bounds.radius() + caster_distance;
+		let texel_size = light_texel_size(bounds.radius(), settings.resolution());
+		Self {
+			camera,
+			depth_bias: settings.depth_bias / depth_range,
+			normal_offset: settings.normal_bias * texel_size,
+		}
+	}
+}
+
+/// All cascades of a frame, computed on the host
+#[derive(Copy, Clone)]
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+pub struct ShadowCascades {
+	/// only the first [`ShadowSettings::cascade_count`] cascades are valid
+	pub cascades: [ShadowCascade; MAX_SHADOW_CASCADES as usize],
+	/// view space distance at which each cascade ends, see [`ShadowMap::cascade_far`]
+	pub cascade_far: Vec4,
+}
+
+impl ShadowCascades {
+	/// Splits the view frustum of `frame_data.camera` and fits a light camera of the sun around each slice
+	pub fn new(frame_data: &FrameData) -> Self {
+		let settings = frame_data.shadow;
+		let camera = frame_data.camera;
+		let count = settings.cascade_count();
+		let far = cascade_splits(camera.z_near, settings.max_distance, count, SHADOW_SPLIT_LAMBDA);
+		let cascade = |i: usize| {
+			let near = if i == 0 { camera.z_near } else { far[i - 1] };
+			let bounds = frustum_slice_bounds(camera, near, far[i]);
+			ShadowCascade::new(frame_data.sun.direction, bounds, settings)
+		};
+		Self {
+			cascades: [cascade(0), cascade(1), cascade(2), cascade(3)],
+			cascade_far: Vec4::from_array(far),
+		}
+	}
+}
+
+/// Splits the view space depth range from `near` to `far` into `count` cascades, returning the far distance of each
+/// cascade. Cascades beyond `count` end at `far`.
+///
+/// Blends logarithmic splits, which keep the size of shadow texels on screen constant, with uniform splits by `lambda`,
+/// the "practical split scheme" of Parallel-Split Shadow Maps, Zhang et al. 2006.
+pub fn cascade_splits(near: f32, far: f32, count: u32, lambda: f32) -> [f32; MAX_SHADOW_CASCADES as usize] {
+	let mut splits = [far; MAX_SHADOW_CASCADES as usize];
+	for (i, split) in splits.iter_mut().enumerate().take(count as usize - 1) {
+		let t = (i + 1) as f32 / count as f32;
+		let log = near * libm::powf(far / near, t);
+		let uniform = near + (far - near) * t;
+		*split = lambda * log + (1. - lambda) * uniform;
+	}
+	splits
+}
+
+/// Bounding sphere in world space of the slice of the `camera`'s view frustum between the view space distances `near`
+/// and `far`. The sphere only depends on the shape of the frustum, not its rotation, so cascades don't change size
+/// while the camera turns.
+pub fn frustum_slice_bounds(camera: Camera, near: f32, far: f32) -> Sphere {
+	let corner = |ndc: Vec2, distance: f32| {
+		let p = camera.view_from_clip * Vec4::from((ndc, 0.5, 1.));
+		let ray = p.xyz() / p.w;
+		if camera.is_orthographic() {
+			// rays are parallel, only their depth changes
+			Vec3::from((ray.xy(), -distance))
+		} else {
+			ray / -ray.z * distance
+		}
+	};
+	let corners = [
+		Vec2::new(-1., -1.),
+		Vec2::new(1., -1.),
+		Vec2::new(-1., 1.),
+		Vec2::new(1., 1.),
+	];
+	let mut center = Vec3::ZERO;
+	for ndc in corners {
+		center += corner(ndc, near) + corner(ndc, far);
+	}
+	center /= 8.;
+	let mut radius: f32 = 0.;
+	for ndc in corners {
+		radius = radius
+			.max(corner(ndc, near).distance(center))
+			.max(corner(ndc, far).distance(center));
+	}
+	// round up, so float inaccuracies don't cause the cascade to change size
+	let radius = (radius * 16.).ceil() / 16.;
+	Sphere::new(camera.view_from_world.affine.transform_point3(center), radius)
+}
+
+/// World space size of a texel of a light camera fit around a sphere of `radius`, see [`fit_light_camera`]. The
+/// `resolution` must be at least [`MIN_SHADOW_RESOLUTION`].
+pub fn light_texel_size(radius: f32, resolution: u32) -> f32 {
+	// a margin of one texel on each side absorbs the texel snapping
+	2. * radius / (resolution - 2) as f32
+}
+
+/// An orthographic camera looking along `-light_direction` whose square viewport of `resolution` texels covers the
+/// `bounds`. It moves in whole texel increments, so shadow edges don't shimmer while the main camera moves, and is
+/// pulled back towards the light by `caster_distance` to include shadow casters outside of `bounds`.
+pub fn fit_light_camera(light_direction: Vec3, bounds: Sphere, resolution: u32, caster_distance: f32) -> Camera {
+	let direction = -light_direction.normalize();
+	let up = if direction.y.abs() > 0.99 { Vec3::Z } else { Vec3::Y };
+	let radius = bounds.radius();
+	let texel_size = light_texel_size(radius, resolution);
+
+	let light_from_world = Affine3A::look_to_rh(Vec3::ZERO, direction, up);
+	let center = light_from_world.transform_point3(bounds.center());
+	let snapped = (center.xy() / texel_size).floor() * texel_size;
+	let center = light_from_world
+		.inverse()
+		.transform_point3(Vec3::from((snapped, center.z)));
+
+	let eye = center - direction * (radius + caster_distance);
+	let world_from_view = Affine3A::look_to_rh(eye, direction, up).inverse();
+	Camera::new_orthographic_rh_y_flip(
+		UVec2::splat(resolution),
+		texel_size * resolution as f32,
+		0.,
+		2. * radius + caster_distance,
+		AffineTransform::new(world_from_view),
+	)
+}
+
+#[derive(Copy, Clone, BufferStruct)]
+pub struct ShadowMap<'a> {
+	/// depth atlas of all cascades, see [module docs](self)
+	pub atlas: TransientDesc<'a, Image<Image2d>>,
+	pub cascades: TransientDesc<'a, Buffer<[ShadowCascade]>>,
+	/// view space distance at which each cascade ends
+	pub cascade_far: Vec4,
+}
+
+impl ShadowMap<'_> {
+	/// How much of the sun's light reaches `world_pos` with the surface `normal`, from 0 if fully shadowed to 1 if
+	/// fully lit, filtered over 3x3 texels.
+	pub fn sun_visibility(
+		&self,
+		descriptors: &Descriptors,
+		frame_data: FrameData,
+		world_pos: Vec3,
+		normal: Vec3,
+	) -> f32 {
+		let settings = frame_data.shadow;
+		if !settings.enabled() {
+			return 1.;
+		}
+		let view_pos = frame_data
+			.camera
+			.view_from_world
+			.affine
+			.transform_point3_transposed(world_pos);
+		let depth = -view_pos.z;
+		let count = settings.cascade_count();
+		let far = self.cascade_far;
+		let cascade_id =
+			(depth > far.x) as u32 + (depth > far.y) as u32 + (depth > far.z) as u32 + (depth > far.w) as u32;
+		if cascade_id >= count {
+			return 1.;
+		}
+
+		let cascade = self.cascades.access(descriptors).load(cascade_id as usize);
+		let position = cascade
+			.camera
+			.transform_vertex(AffineTransform::default(), world_pos + normal * cascade.normal_offset);
+		let uv = position.clip_space.xy() * 0.5 + 0.5;
+		if !(uv.cmpge(Vec2::ZERO).all() && uv.cmple(Vec2::ONE).all()) {
+			return 1.;
+		}
+		let receiver_depth = position.clip_space.z - cascade.depth_bias;
+
+		let resolution = settings.resolution();
+		let texel = (uv * resolution as f32).as_ivec2();
+		let offset = cascade_id * resolution;
+		let atlas = self.atlas.access(descriptors);
+		let mut lit = 0.;
+		for y in 0..3 {
+			for x in 0..3 {
+				let sample = (texel + IVec2::new(x - 1, y - 1)).clamp(IVec2::ZERO, IVec2::splat(resolution as i32 - 1));
+				let pixel = UVec2::new(sample.x as u32 + offset, sample.y as u32);
+				#[allow(clippy::useless_conversion)]
+				let occluder_depth = Vec4::from(atlas.fetch(pixel)).x;
+				if receiver_depth <= occluder_depth {
+					lit += 1.;
+				}
+			}
+		}
+		lit / 9.
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::f32::consts::FRAC_PI_2;
+
+	fn new_camera(transform: Affine3A) -> Camera {
+		Camera::new_perspective_rh_y_flip(
+			UVec2::new(160, 90),
+			FRAC_PI_2,
+			0.1,
+			1000.,
+			AffineTransform::new(transform),
+			Vec2::ZERO,
+		)
+	}
+
+	fn to_clip(camera: &Camera, world_pos: Vec3) -> Vec3 {
+		let clip = camera
+			.transform_vertex(AffineTransform::default(), world_pos)
+			.clip_space;
+		clip.xyz() / clip.w
+	}
+
+	#[test]
+	fn test_cascade_splits() {
+		let splits = cascade_splits(0.1, 100., 4, 0.5);
+		assert_eq!(splits[3], 100.);
+		for i in 0..3 {
+			assert!(splits[i] < splits[i + 1], "{splits:?}");
+		}
+		assert!(splits[0] > 0.1, "{splits:?}");
+
+		let uniform = cascade_splits(0., 100., 4, 0.);
+		for (split, expected) in uniform.iter().zip([25., 50., 75., 100.]) {
+			assert!((split - expected).abs() < 1e-4, "{uniform:?}");
+		}
+		let log = cascade_splits(1., 1000., 3, 1.);
+		for (split, expected) in log.iter().zip([10., 100., 1000., 1000.]) {
+			assert!((split - expected).abs() < 1e-2, "{log:?}");
+		}
+		assert_eq!(cascade_splits(0.1, 50., 1, 0.5), [50.; 4]);
+	}
+
+	#[test]
+	fn test_frustum_slice_bounds() {
+		let transform = Affine3A::from_rotation_translation(
+			glam::Quat::from_rotation_y(1.) * glam::Quat::from_rotation_x(-0.3),
+			Vec3::new(3., 4., 5.),
+		);
+		let camera = new_camera(transform);
+		let (near, far) = (5., 20.);
+		let bounds = frustum_slice_bounds(camera, near, far);
+		// the corners of the slice, reconstructed from depth
+		for distance in [near, far] {
+			let depth = to_clip(&camera, transform.transform_point3(Vec3::new(0., 0., -distance))).z;
+			for uv in [Vec2::ZERO, Vec2::X, Vec2::Y, Vec2::ONE, Vec2::splat(0.5)] {
+				let corner = camera.reconstruct_from_depth(uv, depth).world_space;
+				assert!(
+					corner.distance(bounds.center()) <= bounds.radius() + 1e-3,
+					"{corner:?} {bounds:?}"
+				);
+			}
+		}
+
+		// turning the camera does not change the size
+		let turned = frustum_slice_bounds(new_camera(Affine3A::from_rotation_z(0.7)), near, far);
+		assert_eq!(turned.radius(), bounds.radius());
+	}
+
+	#[test]
+	fn test_frustum_slice_bounds_orthographic() {
+		let transform = Affine3A::from_rotation_translation(glam::Quat::from_rotation_y(1.), Vec3::new(3., 4., 5.));
+		let camera =
+			Camera::new_orthographic_rh_y_flip(UVec2::new(160, 90), 10., 0.1, 1000., AffineTransform::new(transform));
+		let (near, far) = (5., 20.);
+		let bounds = frustum_slice_bounds(camera, near, far);
+
+		// the slice is a box, whose corners are all on the sphere
+		let half_size = Vec2::new(10. * 160. / 90., 10.) / 2.;
+		assert!(
+			(bounds.center() - transform.transform_point3(Vec3::new(0., 0., -(near + far) / 2.))).length() < 1e-3,
+			"{bounds:?}"
+		);
+		let expected_radius = Vec3::from((half_size, (far - near) / 2.)).length();
+		assert!(
+			(expected_radius..expected_radius + 1. / 16.).contains(&bounds.radius()),
+			"{bounds:?}"
+		);
+		for distance in [near, far] {
+			for sign in [Vec2::new(-1., -1.), Vec2::new(1., -1.), Vec2::new(-1., 1.), Vec2::ONE] {
+				let corner = transform.transform_point3(Vec3::from((half_size * sign, -distance)));
+				assert!(
+					corner.distance(bounds.center()) <= bounds.radius() + 1e-3,
+					"{corner:?} {bounds:?}"
+				);
+			}
+		}
+	}
+
+	#[test]
+	fn test_min_resolution() {
+		for resolution in 0..MIN_SHADOW_RESOLUTION {
+			let settings = ShadowSettings {
+				enabled: 1,
+				cascade_count: 2,
+				resolution,
+				max_distance: 100.,
+				depth_bias: 0.05,
+				normal_bias: 1.5,
+			};
+			assert_eq!(settings.resolution(), MIN_SHADOW_RESOLUTION);
+			assert_eq!(
+				settings.atlas_size(),
+				UVec2::new(2 * MIN_SHADOW_RESOLUTION, MIN_SHADOW_RESOLUTION)
+			);
+			let cascade = ShadowCascade::new(Vec3::Y, Sphere::new(Vec3::ZERO, 8.), settings);
+			assert!(cascade.normal_offset.is_finite(), "{cascade:?}");
+			assert!(cascade.camera.clip_from_view.is_finite(), "{cascade:?}");
+		}
+	}
+
+	#[test]
+	fn test_fit_light_camera() {
+		let light = Vec3::new(0.3, 1., 0.2).normalize();
+		let bounds = Sphere::new(Vec3::new(10., 2., -7.), 8.);
+		let camera = fit_light_camera(light, bounds, 1024, 50.);
+		assert!(camera.is_orthographic());
+
+		// the entire sphere is within the viewport
+		let offsets = [Vec3::X, -Vec3::X, Vec3::Y, -Vec3::Y, Vec3::Z, -Vec3::Z];
+		for offset in offsets {
+			let clip = to_clip(&camera, bounds.center() + offset * bounds.radius());
+			assert!(clip.xy().abs().cmple(Vec2::splat(1. + 1e-5)).all(), "{clip:?}");
+			assert!((0. ..=1.).contains(&clip.z), "{clip:?}");
+		}
+		// casters towards the light are still within the depth range, the light is closer to them
+		let caster = to_clip(&camera, bounds.center() + light * (bounds.radius() + 45.));
+		let receiver = to_clip(&camera, bounds.center());
+		assert!((0. ..=1.).contains(&caster.z) && caster.z < receiver.z, "{caster:?}");
+		let behind = to_clip(&camera, bounds.center() + light * (bounds.radius() + 55.));
+		assert!(behind.z < 0., "{behind:?}");
+	}
+
+	#[test]
+	fn test_fit_light_camera_texel_snapping() {
+		let light = Vec3::new(-0.5, 1., 0.4).normalize();
+		let resolution = 512;
+		let point = Vec3::new(1., 0.5, 2.);
+		let texel_offset = |center: Vec3| {
+			let camera = fit_light_camera(light, Sphere::new(center, 16.), resolution, 10.);
+			(to_clip(&camera, point).xy() * 0.5 + 0.5) * resolution as f32
+		};
+		// a fixed point always lands at the same position within a texel, no matter how the bounds move
+		let expected = texel_offset(Vec3::ZERO);
+		for center in [
+			Vec3::new(0.01, 0., 0.),
+			Vec3::new(0.3, -0.2, 0.05),
+			Vec3::new(-4.1, 2.7, 9.3),
+		] {
+			let offset = texel_offset(center) - expected;
+			let fract = offset - offset.round();
+			assert!(fract.abs().cmplt(Vec2::splat(1e-2)).all(), "{offset:?}");
+		}
+	}
+}
@@ -2,12 +2,12 @@
 //! Ported to Rust from <https://github.com/Tw1ddle/Sky-Shader/blob/master/src/shaders/glsl/sky.fragment>
 
 use crate::renderer::frame_data::FrameData;
-use crate::renderer::g_buffer::GBuffer;
+use crate::renderer::lighting::ibl_bake::SKY_RADIANCE_SCALE;
 use crate::renderer::lighting::is_skybox;
 use core::f32::consts::PI;
 use glam::{UVec2, UVec3, Vec3, Vec3Swizzles, Vec4, vec3};
 use rust_gpu_bindless_macros::{BufferStruct, bindless};
-use rust_gpu_bindless_shaders::descriptor::{Buffer, Descriptors, Image2d, MutImage, Transient, TransientDesc};
+use rust_gpu_bindless_shaders::descriptor::{Buffer, Descriptors, Image, Image2d, MutImage, TransientDesc};
 #[cfg(target_arch = "spirv")]
 use spirv_std::num_traits::Float;
 use static_assertions::const_assert_eq;
@@ -48,17 +48,6 @@ const SUN_INTENSITY_FACTOR: f32 = 1000.0;
 const SUN_INTENSITY_FALLOFF_STEEPNESS: f32 = 1.5;
 const TURBIDITY: f32 = 2.0;
 
-pub fn tonemap(col: Vec3) -> Vec3 {
-	// see https://www.desmos.com/calculator/0eo9pzo1at
-	const A: f32 = 2.35;
-	const B: f32 = 2.8826666;
-	const C: f32 = 789.7459;
-	const D: f32 = 0.935;
-
-	let z = Vec3::powf(col, A);
-	z / (Vec3::powf(z, D) * B + Vec3::splat(C))
-}
-
 fn total_rayleigh(lambda: Vec3) -> Vec3 {
 	(8.0 * PI.powf(3.0) * (REFRACTIVE_INDEX.powf(2.0) - 1.0).powf(2.0) * (6.0 + 3.0 * DEPOLARIZATION_FACTOR))
 		/ (3.0 * NUM_MOLECULES * Vec3::powf(lambda, 4.0) * (6.0 - 7.0 * DEPOLARIZATION_FACTOR))
@@ -86,6 +75,16 @@ fn sun_intensity(zenith_angle_cos: f32) -> f32 {
 }
 
 pub fn preetham_sky(dir: Vec3, sun_position: Vec3) -> Vec3 {
+	preetham_sky_impl(dir, sun_position, true)
+}
+
+/// The [`preetham_sky`] without the solar disc, whose light is already accounted for by the sun's
+/// [`DirectionalLight`](crate::material::light::DirectionalLight)
+pub fn preetham_sky_no_sun_disk(dir: Vec3, sun_position: Vec3) -> Vec3 {
+	preetham_sky_impl(dir, sun_position, false)
+}
+
+fn preetham_sky_impl(dir: Vec3, sun_position: Vec3, sun_disk: bool) -> Vec3 {
 	let up = vec3(0.0, 1.0, 0.0);
 	let sunfade = 1.0 - (1.0 - saturate(sun_position.y / 450000.0).exp());
 	let rayleigh_coefficient = RAYLEIGH - (1.0 * (1.0 - sunfade));
@@ -120,10 +119,12 @@ pub fn preetham_sky(dir: Vec3, sun_position: Vec3) -> Vec3 {
 	lin *= Vec3::splat(1.0).lerp(Vec3::powf(v, 0.5), saturate((1.0 - up.dot(sun_direction)).powf(5.0)));
 
 	// Composition + solar disc
-	let sun_angular_diameter_cos = SUN_ANGULAR_DIAMETER_DEGREES.cos();
-	let sundisk = smoothstep(sun_angular_diameter_cos, sun_angular_diameter_cos + 0.00002, cos_theta);
 	let mut l0 = 0.1 * fex;
-	l0 += sun_e * 19000.0 * fex * sundisk;
+	if sun_disk {
+		let sun_angular_diameter_cos = SUN_ANGULAR_DIAMETER_DEGREES.cos();
+		let sundisk = smoothstep(sun_angular_diameter_cos, sun_angular_diameter_cos + 0.00002, cos_theta);
+		l0 += sun_e * 19000.0 * fex * sundisk;
+	}
 
 	lin + l0
 }
@@ -131,7 +132,7 @@ pub fn preetham_sky(dir: Vec3, sun_position: Vec3) -> Vec3 {
 #[derive(Copy, Clone, BufferStruct)]
 pub struct Param<'a> {
 	pub frame_data: TransientDesc<'a, Buffer<FrameData>>,
-	pub g_buffer: GBuffer<Transient<'a>>,
+	pub depth_image: TransientDesc<'a, Image<Image2d>>,
 	pub output_image: TransientDesc<'a, MutImage<Image2d>>,
 }
 
@@ -151,15 +152,16 @@ pub fn sky_shader_cs(
 	let pixel_inbounds = pixel.x < size.x && pixel.y < size.y;
 
 	#[allow(clippy::useless_conversion)]
-	let albedo_alpha = Vec4::from(param.g_buffer.g_albedo.access(&descriptors).fetch(pixel)).w;
-	let skybox = is_skybox(albedo_alpha);
+	let depth = Vec4::from(param.depth_image.access(&descriptors).fetch(pixel)).x;
+	let skybox = is_skybox(depth);
 
 	let normal = frame_data
 		.camera
 		.reconstruct_direction(pixel.as_vec2() / size.as_vec2());
 
 	let color = preetham_sky(normal.world_space, frame_data.sun.direction);
-	let color = tonemap(color.clamp(Vec3::splat(0.0), Vec3::splat(1024.0)));
+	// clamped so the solar disc doesn't overwhelm the exposure
+	let color = color.clamp(Vec3::splat(0.0), Vec3::splat(1024.0)) * SKY_RADIANCE_SCALE;
 	if pixel_inbounds && skybox {
 		unsafe {
 			param.output_image.access(&descriptors).write(pixel, color.extend(1.0));
@@ -1,4 +1,3 @@
-use crate::renderer::camera::Camera;
 use crate::renderer::compacting_alloc_buffer::CompactingAllocBufferWriter;
 use crate::renderer::frame_data::FrameData;
 use crate::renderer::meshlet::intermediate::MeshletGroupInstance;
@@ -36,9 +35,9 @@ pub fn instance_cull_compute(
 	let frame_data = param.frame_data.access(&descriptors).load();
 	let scene = param.scene.access(&descriptors).load();
 	let instance = scene.instances.access(&descriptors).load(instance_id as usize);
-	if !cull_instance(frame_data.camera, instance) {
-		for mesh_id in Range::<u32>::from(instance.mesh_ids) {
-			let mesh: MeshletMesh<Strong> = scene.meshes.access(&descriptors).load(mesh_id as usize);
+	for mesh_id in Range::<u32>::from(instance.mesh_ids) {
+		let mesh: MeshletMesh<Strong> = scene.meshes.access(&descriptors).load(mesh_id as usize);
+		if !cull_instance(frame_data, instance, &mesh) {
 			let mut meshlet_start = meshlet_offset * MAX_MESHLET_CNT;
 			while meshlet_start < mesh.num_meshlets {
 				let meshlet_cnt = u32::clamp(meshlet_start + MAX_MESHLET_CNT, 0, mesh.num_meshlets) - meshlet_start;
@@ -57,6 +56,10 @@ pub fn instance_cull_compute(
 	}
 }
 
-fn cull_instance(_camera: Camera, _instance: MeshInstance) -> bool {
-	false
+/// Culls a mesh of an instance if its bounds are outside the view frustum
+fn cull_instance(frame_data: FrameData, instance: MeshInstance, mesh: &MeshletMesh<Strong>) -> bool {
+	frame_data.nanite.frustum_culling()
+		&& !frame_data
+			.camera
+			.is_sphere_in_frustum(instance.world_from_local.affine, mesh.bounds)
 }
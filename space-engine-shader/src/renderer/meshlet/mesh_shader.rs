@@ -1,12 +1,18 @@
-use crate::material::pbr::{PbrMaterialSample, SurfaceLocation};
+use crate::material::pbr::{ImplicitLod, PbrMaterialSample, SurfaceLocation};
+use crate::renderer::camera::Camera;
 use crate::renderer::compacting_alloc_buffer::CompactingAllocBufferReader;
 use crate::renderer::frame_data::{DebugSettings, FrameData};
 use crate::renderer::meshlet::intermediate::MeshletInstance;
+use crate::renderer::meshlet::meshlet_select::MeshletSelectPass;
+use crate::renderer::taa::motion_vector;
+use crate::renderer::visibility_buffer::VisibilityId;
 use crate::utils::gpurng::GpuRng;
 use glam::{UVec3, Vec2, Vec3, Vec4};
 use rust_gpu_bindless_macros::{BufferStruct, bindless};
-use rust_gpu_bindless_shaders::descriptor::{Buffer, Descriptors, Strong, TransientDesc};
-use space_asset_shader::meshlet::mesh::MeshletMesh;
+use rust_gpu_bindless_shaders::descriptor::{AliveDescRef, Buffer, Descriptors, Strong, TransientDesc};
+use space_asset_shader::material::pbr::AlphaMode;
+use space_asset_shader::meshlet::instance::MeshInstance;
+use space_asset_shader::meshlet::mesh::{MeshletData, MeshletMesh, MeshletReader};
 use space_asset_shader::meshlet::scene::MeshletScene;
 use space_asset_shader::meshlet::{MESHLET_MAX_TRIANGLES, MESHLET_MAX_VERTICES};
 use spirv_std::Sampler;
@@ -19,16 +25,27 @@ pub struct Param<'a> {
 	pub scene: TransientDesc<'a, Buffer<MeshletScene<Strong>>>,
 	pub sampler: TransientDesc<'a, Sampler>,
 	pub compacting_alloc_buffer: CompactingAllocBufferReader<'a, MeshletInstance>,
+	/// [`MeshletSelectPass`] the meshlets were selected in, see [`Self::pass`]
+	pub pass: u32,
+}
+
+impl Param<'_> {
+	pub fn pass(&self) -> MeshletSelectPass {
+		MeshletSelectPass::try_from(self.pass).unwrap_or(MeshletSelectPass::First)
+	}
 }
 
-#[allow(dead_code)]
 #[derive(Copy, Clone)]
 #[repr(C)]
-struct InterpolationVertex {
-	tangent: Vec4,
-	world_pos: Vec3,
-	normal: Vec3,
-	tex_coord: Vec2,
+pub struct InterpolationVertex {
+	pub tangent: Vec4,
+	pub color: Vec4,
+	pub world_pos: Vec3,
+	/// `world_pos` in the previous frame, see [`MeshInstance::prev_world_from_local`]
+	pub prev_world_pos: Vec3,
+	pub normal: Vec3,
+	pub tex_coord: Vec2,
+	pub tex_coord_1: Vec2,
 }
 
 pub const MESH_WG_SIZE: usize = 32;
@@ -47,6 +64,7 @@ pub fn meshlet_mesh(
 	#[spirv(location = 0, per_primitive_ext)] out_debug_hue: &mut [f32; MESHLET_MAX_TRIANGLES as usize],
 	#[spirv(location = 1)] out_mesh_id: &mut [u32; MESHLET_MAX_VERTICES as usize],
 	#[spirv(location = 2)] out_vertex: &mut [InterpolationVertex; MESHLET_MAX_VERTICES as usize],
+	#[spirv(location = 3, per_primitive_ext)] out_visibility_id: &mut [u32; MESHLET_MAX_TRIANGLES as usize],
 ) {
 	let meshlet_instance_id = wg_id.x;
 	let inv_id = inv_id.x as usize;
@@ -81,36 +99,16 @@ pub fn meshlet_mesh(
 			let inbounds = i < vertex_count;
 			let i = if inbounds { i } else { vertex_count - 1 };
 
-			let draw_vertex = meshlet.load_draw_vertex(&descriptors, i);
-			let position = frame_data
-				.camera
-				.transform_vertex(instance.world_from_local, draw_vertex.position);
-			let pbr_vertex = meshlet.load_pbr_material_vertex(&descriptors, draw_vertex.material_vertex_id);
-			let vertex = InterpolationVertex {
-				world_pos: position.world_space,
-				normal: pbr_vertex.normal,
-				tangent: pbr_vertex.tangent,
-				tex_coord: pbr_vertex.tex_coord,
-			};
+			let (clip_space, vertex) = load_vertex(&descriptors, frame_data.camera, instance, &meshlet, i);
 
 			if inbounds {
-				*out_positions.index_unchecked_mut(i) = position.clip_space;
+				*out_positions.index_unchecked_mut(i) = clip_space;
 				*out_mesh_id.index_unchecked_mut(i) = meshlet_instance.mesh_id;
 				*out_vertex.index_unchecked_mut(i) = vertex;
 			}
 		}
 	}
 
-	let debug_hue = |primitive_id: u32| {
-		let seed = match frame_data.debug_settings() {
-			DebugSettings::MeshletId => meshlet_instance.meshlet_id,
-			DebugSettings::TriangleId => meshlet_instance.meshlet_id.wrapping_add(primitive_id),
-			DebugSettings::LodLevel => 32 - leading_zeros(meshlet.lod_level_bitmask.0),
-			_ => return 0.,
-		};
-		GpuRng(seed.wrapping_add(1)).next_f32()
-	};
-
 	// process primitives
 	// Safety: panics within pools mispile
 	unsafe {
@@ -120,16 +118,60 @@ pub fn meshlet_mesh(
 			let i = if inbounds { i } else { triangle_count - 1 };
 
 			let indices = meshlet.load_triangle(&descriptors, i);
-			let debug_hue = debug_hue(i as u32);
+			let debug_hue = meshlet_debug_hue(frame_data, meshlet_instance, &meshlet, i as u32);
+			let visibility_id = VisibilityId::new(param.pass(), meshlet_instance_id, i as u32);
 
 			if i < triangle_count {
 				*prim_indices.index_unchecked_mut(i) = indices;
 				*out_debug_hue.index_unchecked_mut(i) = debug_hue;
+				*out_visibility_id.index_unchecked_mut(i) = visibility_id.0;
 			}
 		}
 	}
 }
 
+/// Loads vertex `i` of a meshlet of `instance` and transforms it into clip space
+pub fn load_vertex<R: AliveDescRef>(
+	descriptors: &Descriptors,
+	camera: Camera,
+	instance: MeshInstance,
+	meshlet: &MeshletReader<R>,
+	i: usize,
+) -> (Vec4, InterpolationVertex) {
+	let draw_vertex = meshlet.load_draw_vertex(descriptors, i);
+	let position = camera.transform_vertex(instance.world_from_local, draw_vertex.position);
+	let pbr_vertex = meshlet.load_pbr_material_vertex(descriptors, draw_vertex.material_vertex_id);
+	let vertex = InterpolationVertex {
+		world_pos: position.world_space,
+		prev_world_pos: instance
+			.prev_world_from_local
+			.affine
+			.transform_point3(draw_vertex.position),
+		normal: pbr_vertex.normal,
+		tangent: pbr_vertex.tangent,
+		color: pbr_vertex.color,
+		tex_coord: pbr_vertex.tex_coord,
+		tex_coord_1: pbr_vertex.tex_coord_1,
+	};
+	(position.clip_space, vertex)
+}
+
+/// The hue a triangle is colored with in meshlet debug views, or 0 if no such debug view is active
+pub fn meshlet_debug_hue(
+	frame_data: FrameData,
+	meshlet_instance: MeshletInstance,
+	meshlet: &MeshletData,
+	primitive_id: u32,
+) -> f32 {
+	let seed = match frame_data.debug_settings() {
+		DebugSettings::MeshletId => meshlet_instance.meshlet_id,
+		DebugSettings::TriangleId => meshlet_instance.meshlet_id.wrapping_add(primitive_id),
+		DebugSettings::LodLevel => 32 - leading_zeros(meshlet.lod_level_bitmask.0),
+		_ => return 0.,
+	};
+	GpuRng(seed.wrapping_add(1)).next_f32()
+}
+
 pub fn leading_zeros(mut x: u32) -> u32 {
 	// Keep shifting x by one until leftmost bit
 	// does not become 1.
@@ -155,6 +197,8 @@ pub fn meshlet_fragment_g_buffer(
 	frag_albedo: &mut Vec4,
 	frag_normal: &mut Vec4,
 	frag_roughness_metallic: &mut Vec4,
+	frag_emissive: &mut Vec4,
+	frag_velocity: &mut Vec4,
 ) {
 	let scene = param.scene.access(&descriptors).load();
 	let mesh: MeshletMesh<Strong> = scene.meshes.access(&descriptors).load(out_mesh_id as usize);
@@ -165,10 +209,12 @@ pub fn meshlet_fragment_g_buffer(
 		out_vertex.normal,
 		out_vertex.tangent,
 		out_vertex.tex_coord,
+		out_vertex.tex_coord_1,
+		out_vertex.color,
 	);
 	let mut sampled = mesh
 		.pbr_material
-		.sample(&descriptors, param.sampler.access(&descriptors), loc);
+		.sample(&descriptors, param.sampler.access(&descriptors), loc, ImplicitLod);
 	if frame_data.debug_settings() == DebugSettings::VertexNormals {
 		sampled.normal = loc.vertex_normal.normalize()
 	}
@@ -180,4 +226,61 @@ pub fn meshlet_fragment_g_buffer(
 	*frag_albedo = Vec4::from((sampled.albedo, sampled.alpha));
 	*frag_normal = Vec4::from((sampled.normal * 0.5 + 0.5, out_debug_hue));
 	*frag_roughness_metallic = Vec4::from((sampled.roughness, sampled.metallic, 1., 1.));
+	*frag_emissive = Vec4::from((sampled.emissive, sampled.occlusion));
+	let velocity = motion_vector(
+		frame_data.camera,
+		frame_data.prev_camera,
+		out_vertex.world_pos,
+		out_vertex.prev_world_pos,
+	);
+	*frag_velocity = Vec4::from((velocity, 0., 0.));
+}
+
+#[bindless(fragment())]
+pub fn meshlet_fragment_visibility(
+	#[bindless(descriptors)] descriptors: Descriptors,
+	#[bindless(param)] param: &Param<'static>,
+	#[spirv(location = 1, flat)] out_mesh_id: u32,
+	#[spirv(location = 2)] out_vertex: InterpolationVertex,
+	#[spirv(location = 3, per_primitive_ext)] out_visibility_id: u32,
+	frag_visibility_id: &mut u32,
+) {
+	// only alpha testing needs to sample the material here, everything else happens in the material pass
+	alpha_test(&descriptors, param, out_mesh_id, out_vertex);
+	*frag_visibility_id = out_visibility_id;
+}
+
+/// Only writes depth, into a cascade of the [`ShadowMap`](crate::renderer::lighting::shadow::ShadowMap)
+#[bindless(fragment())]
+pub fn meshlet_fragment_shadow(
+	#[bindless(descriptors)] descriptors: Descriptors,
+	#[bindless(param)] param: &Param<'static>,
+	#[spirv(location = 1, flat)] out_mesh_id: u32,
+	#[spirv(location = 2)] out_vertex: InterpolationVertex,
+) {
+	alpha_test(&descriptors, param, out_mesh_id, out_vertex);
+}
+
+/// Discards the fragment if the material of a non-opaque mesh is transparent at this location
+fn alpha_test(descriptors: &Descriptors, param: &Param<'static>, mesh_id: u32, vertex: InterpolationVertex) {
+	let scene = param.scene.access(descriptors).load();
+	let mesh: MeshletMesh<Strong> = scene.meshes.access(descriptors).load(mesh_id as usize);
+	if mesh.pbr_material.alpha_mode() != AlphaMode::Opaque {
+		let frame_data = param.frame_data.access(descriptors).load();
+		let loc = SurfaceLocation::new(
+			vertex.world_pos,
+			frame_data.camera.view_from_world.translation(),
+			vertex.normal,
+			vertex.tangent,
+			vertex.tex_coord,
+			vertex.tex_coord_1,
+			vertex.color,
+		);
+		let alpha = mesh
+			.pbr_material
+			.sample_alpha(descriptors, param.sampler.access(descriptors), loc, ImplicitLod);
+		if alpha < 0.01 {
+			spirv_std::arch::kill();
+		}
+	}
 }
@@ -1,21 +1,54 @@
+use crate::renderer::camera::Camera;
 use crate::renderer::compacting_alloc_buffer::{CompactingAllocBufferReader, CompactingAllocBufferWriter};
 use crate::renderer::frame_data::FrameData;
+use crate::renderer::hzb::Hzb;
 use crate::renderer::lod_selection::LodType;
 use crate::renderer::meshlet::intermediate::{MeshletGroupInstance, MeshletInstance};
-use glam::{Affine3A, UVec3, Vec3A};
+use crate::renderer::meshlet::mesh_shader::leading_zeros;
+use crate::renderer::visibility_buffer::software_raster::is_software_rasterized;
+use glam::{Affine3A, UVec2, UVec3, Vec2, Vec3, Vec3A, Vec4};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
 use rust_gpu_bindless_macros::{BufferStruct, bindless};
 use rust_gpu_bindless_shaders::descriptor::{Buffer, Descriptors, Strong, TransientDesc};
+use space_asset_shader::affine_transform::AffineTransform;
+use space_asset_shader::material::pbr::AlphaMode;
 use space_asset_shader::meshlet::mesh::MeshletMesh;
 use space_asset_shader::meshlet::scene::MeshletScene;
+use space_asset_shader::shape::cone::NormalCone;
 use space_asset_shader::shape::sphere::Sphere;
+#[cfg(target_arch = "spirv")]
+use spirv_std::num_traits::Float;
 use static_assertions::const_assert_eq;
 
+/// Two-pass occlusion culling: The first pass selects the meshlets that were visible in the HZB of the last frame.
+/// After drawing them, the HZB is built from their depth and the second pass selects all the remaining meshlets that
+/// are visible in this new HZB.
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, TryFromPrimitive, IntoPrimitive)]
+pub enum MeshletSelectPass {
+	First,
+	Second,
+}
+
 #[derive(Copy, Clone, BufferStruct)]
 pub struct Param<'a> {
 	pub frame_data: TransientDesc<'a, Buffer<FrameData>>,
 	pub scene: TransientDesc<'a, Buffer<MeshletScene<Strong>>>,
 	pub compacting_groups_in: CompactingAllocBufferReader<'a, MeshletGroupInstance>,
 	pub compacting_instances_out: CompactingAllocBufferWriter<'a, MeshletInstance>,
+	/// meshlets with tiny triangles, only written if `NaniteSettings::software_rasterization` is enabled
+	pub compacting_software_instances_out: CompactingAllocBufferWriter<'a, MeshletInstance>,
+	pub hzb_last_frame: Hzb<'a>,
+	/// only valid in the second pass
+	pub hzb_current: Hzb<'a>,
+	/// [`MeshletSelectPass`], see [`Self::pass`]
+	pub pass: u32,
+}
+
+impl Param<'_> {
+	pub fn pass(&self) -> MeshletSelectPass {
+		MeshletSelectPass::try_from(self.pass).unwrap_or(MeshletSelectPass::First)
+	}
 }
 
 pub const MESHLET_SELECT_WG_SIZE: u32 = 32;
@@ -39,27 +72,48 @@ pub fn meshlet_select_compute(
 			mesh_id: group_instance.mesh_id,
 			meshlet_id: group_instance.meshlet_start + instance_id,
 		};
-		if !cull_meshlet(&descriptors, frame_data, param.scene, instance) {
-			param.compacting_instances_out.allocate(&mut descriptors, instance);
+		if !cull_meshlet(&descriptors, frame_data, param, instance) {
+			if software_rasterize_meshlet(&descriptors, frame_data, param, instance) {
+				param
+					.compacting_software_instances_out
+					.allocate(&mut descriptors, instance);
+			} else {
+				param.compacting_instances_out.allocate(&mut descriptors, instance);
+			}
 		}
 	}
 }
 
-fn cull_meshlet(
-	descriptors: &Descriptors,
-	frame_data: FrameData,
-	scene: TransientDesc<Buffer<MeshletScene<Strong>>>,
-	instance: MeshletInstance,
-) -> bool {
-	let scene = scene.access(descriptors).load();
+fn cull_meshlet(descriptors: &Descriptors, frame_data: FrameData, param: &Param, instance: MeshletInstance) -> bool {
+	let scene = param.scene.access(descriptors).load();
 	let mesh: MeshletMesh<Strong> = scene.meshes.access(descriptors).load(instance.mesh_id as usize);
 	let m = mesh.meshlet(descriptors, instance.meshlet_id as usize);
-	match frame_data.debug_lod_level.lod_type() {
+	let instance_transform = scene.instances.access(descriptors).load(instance.instance_id as usize);
+	if frame_data.nanite.frustum_culling()
+		&& !frame_data
+			.camera
+			.is_sphere_in_frustum(instance_transform.world_from_local.affine, m.bounds)
+	{
+		return true;
+	}
+
+	if frame_data.nanite.backface_culling()
+		&& !mesh.pbr_material.double_sided()
+		&& is_backfacing(frame_data.camera, instance_transform.world_from_local, m.normal_cone)
+	{
+		return true;
+	}
+
+	let lod_culled = match frame_data.debug_lod_level.lod_type() {
 		LodType::Nanite => {
-			let instance_transform = scene.instances.access(descriptors).load(instance.instance_id as usize);
 			let transform = |sphere: Sphere, radius: f32| {
-				project_to_screen_area(frame_data, instance_transform.world_from_local.affine, sphere, radius)
-					* (frame_data.camera.viewport_size.y as f32 * 0.5)
+				project_to_screen_area(
+					frame_data.camera,
+					frame_data.nanite.bounding_sphere_scale,
+					instance_transform.world_from_local.affine,
+					sphere,
+					radius,
+				)
 			};
 			let ss_error = transform(m.bounds, m.error);
 			let ss_error_parent = transform(m.parent_bounds, m.parent_error);
@@ -70,6 +124,67 @@ fn cull_meshlet(
 		LodType::Static => m
 			.lod_level_bitmask
 			.contains(frame_data.debug_lod_level.lod_level_bitmask()),
+	};
+	if lod_culled {
+		return true;
+	}
+
+	let world_from_local = instance_transform.world_from_local.affine;
+	let visible_last_frame = !param
+		.hzb_last_frame
+		.is_sphere_occluded(descriptors, world_from_local, m.bounds);
+	match param.pass() {
+		MeshletSelectPass::First => !visible_last_frame,
+		MeshletSelectPass::Second => {
+			visible_last_frame
+				|| param
+					.hzb_current
+					.is_sphere_occluded(descriptors, world_from_local, m.bounds)
+		}
+	}
+}
+
+/// Whether all triangles within the `normal_cone` in local space transformed by `world_from_local` face away from the
+/// `camera`. Orthographic projections have no position all rays originate from, so they test against their view
+/// direction instead.
+pub fn is_backfacing(camera: Camera, world_from_local: AffineTransform, normal_cone: NormalCone) -> bool {
+	// the normal matrix is the inverse transpose, so its transpose is the inverse without translation
+	let local_from_world = world_from_local.normal.transpose();
+	if camera.is_orthographic() {
+		let view_direction = camera.view_from_world.normal * Vec3::NEG_Z;
+		normal_cone.is_backfacing_direction((local_from_world * view_direction).normalize())
+	} else {
+		let camera_local = local_from_world * (camera.view_from_world.translation() - world_from_local.translation());
+		normal_cone.is_backfacing(camera_local)
+	}
+}
+
+/// Whether the meshlet's triangles are small enough on screen to be rasterized in software. Only opaque materials are,
+/// as the software rasterizer does no alpha testing.
+fn software_rasterize_meshlet(
+	descriptors: &Descriptors,
+	frame_data: FrameData,
+	param: &Param,
+	instance: MeshletInstance,
+) -> bool {
+	if !frame_data.nanite.software_rasterization() {
+		return false;
+	}
+	let scene = param.scene.access(descriptors).load();
+	let mesh: MeshletMesh<Strong> = scene.meshes.access(descriptors).load(instance.mesh_id as usize);
+	if !matches!(mesh.pbr_material.alpha_mode(), AlphaMode::Opaque) {
+		return false;
+	}
+	let m = mesh.meshlet(descriptors, instance.meshlet_id as usize);
+	let instance_transform = scene.instances.access(descriptors).load(instance.instance_id as usize);
+	match project_sphere_to_screen_rect(frame_data.camera, instance_transform.world_from_local.affine, m.bounds) {
+		Some(rect) => is_software_rasterized(
+			rect,
+			frame_data.camera.viewport_size,
+			m.triangles() as u32,
+			frame_data.nanite.software_raster_threshold,
+		),
+		None => false,
 	}
 }
 
@@ -86,10 +201,22 @@ fn cull_meshlet(
 // 	camera_proj * error / f32::sqrt(d2 - error * error)
 // }
 
+/// The projected LOD error is measured in half pixels, the scale [`NaniteSettings::error_threshold`] was tuned for.
+///
+/// [`NaniteSettings::error_threshold`]: crate::renderer::frame_data::NaniteSettings::error_threshold
+pub const LOD_ERROR_PIXEL_SCALE: f32 = 0.5;
+
+/// Projects the `error` of the `sphere` in local space transformed by `world_from_local` to half pixels, see
+/// [`Camera::project_error_to_pixels`] and [`LOD_ERROR_PIXEL_SCALE`]
+///
 /// https://github.com/zeux/meshoptimizer/blob/1e48e96c7e8059321de492865165e9ef071bffba/demo/nanite.cpp#L115
-pub fn project_to_screen_area(frame_data: FrameData, world_from_local: Affine3A, sphere: Sphere, error: f32) -> f32 {
-	let camera = frame_data.camera;
-	let nanite = frame_data.nanite;
+pub fn project_to_screen_area(
+	camera: Camera,
+	bounding_sphere_scale: f32,
+	world_from_local: Affine3A,
+	sphere: Sphere,
+	error: f32,
+) -> f32 {
 	if !error.is_finite() {
 		return error;
 	}
@@ -101,12 +228,242 @@ pub fn project_to_screen_area(frame_data: FrameData, world_from_local: Affine3A,
 		let mat = world_from_local.matrix3;
 		f32::max(f32::max(sum(mat.x_axis), sum(mat.y_axis)), sum(mat.z_axis))
 	};
-	let radius = sphere.radius() * max_scale_factor * nanite.bounding_sphere_scale;
-	let error = error * max_scale_factor;
-
-	let center_world = world_from_local.transform_point3(sphere.center());
-	let d = center_world.distance(camera.view_from_world.translation()) - radius;
-	let d = f32::max(d, camera.clip_from_view.to_cols_array_2d()[3][2]);
-	let camera_proj = camera.clip_from_view.to_cols_array_2d()[1][1];
-	error / d * (camera_proj * 0.5)
+	let radius = sphere.radius() * max_scale_factor * bounding_sphere_scale;
+	let center = world_from_local.transform_point3(sphere.center());
+	camera.project_error_to_pixels(center, radius, error * max_scale_factor) * LOD_ERROR_PIXEL_SCALE
+}
+
+/// Screen space bounds of a projected sphere, see [`project_sphere_to_screen_rect`]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ScreenRect {
+	/// top left corner in uv coordinates, may lie outside `[0, 1]`
+	pub min: Vec2,
+	/// bottom right corner in uv coordinates, may lie outside `[0, 1]`
+	pub max: Vec2,
+	/// depth of the point on the sphere closest to the camera
+	pub depth: f32,
+}
+
+/// Projects the `sphere` in local space transformed by `world_from_local` onto the screen of `camera`. Returns `None`
+/// if the sphere intersects the near plane, as its perspective projection is unbounded.
+///
+/// Perspective projections use the 2D Polyhedral Bounds of a Clipped, Perspective-Projected 3D Sphere, Mara and
+/// McGuire 2013, as implemented in
+/// https://github.com/zeux/niagara/blob/3fafe000ba8fe6e309b41e915b81242b4ca3db28/src/shaders/math.h
+pub fn project_sphere_to_screen_rect(camera: Camera, world_from_local: Affine3A, sphere: Sphere) -> Option<ScreenRect> {
+	let (center, radius) = camera.sphere_to_view_space(world_from_local, sphere);
+	// view space looks down -Z, the derivation expects +Z
+	let c = Vec3::new(center.x, center.y, -center.z);
+	if c.z < radius + camera.z_near {
+		return None;
+	}
+
+	// the y flip of the projection may swap min and max
+	let m = camera.clip_from_view;
+	let scale = Vec2::new(m.x_axis.x, m.y_axis.y);
+	let (a, b) = if camera.is_orthographic() {
+		let offset = Vec2::new(m.w_axis.x, m.w_axis.y);
+		(
+			(Vec2::new(c.x, c.y) - radius) * scale + offset,
+			(Vec2::new(c.x, c.y) + radius) * scale + offset,
+		)
+	} else {
+		let cr = c * radius;
+		let czr2 = c.z * c.z - radius * radius;
+		let vx = (c.x * c.x + czr2).sqrt();
+		let min_x = (vx * c.x - cr.z) / (vx * c.z + cr.x);
+		let max_x = (vx * c.x + cr.z) / (vx * c.z - cr.x);
+		let vy = (c.y * c.y + czr2).sqrt();
+		let min_y = (vy * c.y - cr.z) / (vy * c.z + cr.y);
+		let max_y = (vy * c.y + cr.z) / (vy * c.z - cr.y);
+		// off-center projections and the jitter shift the frustum by the z axis, as w is the negated z
+		let offset = -Vec2::new(m.z_axis.x, m.z_axis.y);
+		(
+			Vec2::new(min_x, min_y) * scale + offset,
+			Vec2::new(max_x, max_y) * scale + offset,
+		)
+	};
+	let nearest = m * Vec4::new(0., 0., -(c.z - radius), 1.);
+	Some(ScreenRect {
+		min: Vec2::min(a, b) * 0.5 + 0.5,
+		max: Vec2::max(a, b) * 0.5 + 0.5,
+		depth: nearest.z / nearest.w,
+	})
+}
+
+/// The lowest HZB mip level at which the pixel rect from `min` to `max` (inclusive) is covered by at most 2x2 texels.
+pub fn hzb_mip_level(min: UVec2, max: UVec2) -> u32 {
+	let extent = max - min;
+	32 - leading_zeros(u32::max(extent.x, extent.y))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::renderer::camera::Projection;
+	use core::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+	fn new_camera() -> Camera {
+		Camera::new_perspective_rh_y_flip(
+			UVec2::new(100, 100),
+			FRAC_PI_2,
+			0.1,
+			100.,
+			AffineTransform::new(Affine3A::IDENTITY),
+			Vec2::ZERO,
+		)
+	}
+
+	#[test]
+	fn test_project_sphere_to_screen_rect() {
+		let camera = new_camera();
+		let rect = project_sphere_to_screen_rect(camera, Affine3A::IDENTITY, Sphere::new(Vec3::new(0., 0., -10.), 1.))
+			.unwrap();
+		// tangent of the half angle covered by the sphere
+		let extent = 0.5 / f32::sqrt(99.);
+		assert!(rect.min.abs_diff_eq(Vec2::splat(0.5 - extent), 1e-5), "{rect:?}");
+		assert!(rect.max.abs_diff_eq(Vec2::splat(0.5 + extent), 1e-5), "{rect:?}");
+		let nearest = camera.clip_from_view * Vec4::new(0., 0., -9., 1.);
+		assert!((rect.depth - nearest.z / nearest.w).abs() < 1e-5, "{rect:?}");
+
+		// y is flipped: up in view space is the top of the screen
+		let rect = project_sphere_to_screen_rect(camera, Affine3A::IDENTITY, Sphere::new(Vec3::new(5., 5., -10.), 1.))
+			.unwrap();
+		assert!(rect.min.x > 0.5 && rect.max.y < 0.5, "{rect:?}");
+
+		// twice the size at twice the distance
+		let scaled = project_sphere_to_screen_rect(
+			camera,
+			Affine3A::from_scale(Vec3::splat(2.)),
+			Sphere::new(Vec3::new(0., 0., -10.), 1.),
+		)
+		.unwrap();
+		assert!((scaled.max.x - scaled.min.x - 2. * extent).abs() < 1e-5, "{scaled:?}");
+
+		assert_eq!(
+			project_sphere_to_screen_rect(camera, Affine3A::IDENTITY, Sphere::new(Vec3::new(0., 0., -1.), 1.)),
+			None
+		);
+		assert_eq!(
+			project_sphere_to_screen_rect(camera, Affine3A::IDENTITY, Sphere::new(Vec3::new(0., 0., 10.), 1.)),
+			None
+		);
+	}
+
+	#[test]
+	fn test_project_sphere_to_screen_rect_asymmetric() {
+		// the view direction is the left edge of the frustum
+		let projection = Projection::AsymmetricPerspective {
+			left: 0.,
+			right: FRAC_PI_4,
+			down: -FRAC_PI_4,
+			up: FRAC_PI_4,
+		};
+		let camera = Camera::new_rh_y_flip(
+			UVec2::new(100, 100),
+			projection,
+			0.1,
+			100.,
+			AffineTransform::new(Affine3A::IDENTITY),
+			Vec2::ZERO,
+		);
+		let rect = project_sphere_to_screen_rect(camera, Affine3A::IDENTITY, Sphere::new(Vec3::new(0., 0., -10.), 1.))
+			.unwrap();
+		let extent = 1. / f32::sqrt(99.);
+		assert!(
+			rect.min.abs_diff_eq(Vec2::new(-extent, 0.5 - extent * 0.5), 1e-5),
+			"{rect:?}"
+		);
+		assert!(
+			rect.max.abs_diff_eq(Vec2::new(extent, 0.5 + extent * 0.5), 1e-5),
+			"{rect:?}"
+		);
+	}
+
+	#[test]
+	fn test_project_sphere_to_screen_rect_orthographic() {
+		let camera = Camera::new_orthographic_rh_y_flip(
+			UVec2::new(100, 100),
+			10.,
+			0.1,
+			100.,
+			AffineTransform::new(Affine3A::IDENTITY),
+		);
+		let rect = project_sphere_to_screen_rect(camera, Affine3A::IDENTITY, Sphere::new(Vec3::new(2., 0., -10.), 1.))
+			.unwrap();
+		assert!(rect.min.abs_diff_eq(Vec2::new(0.6, 0.4), 1e-5), "{rect:?}");
+		assert!(rect.max.abs_diff_eq(Vec2::new(0.8, 0.6), 1e-5), "{rect:?}");
+		let nearest = camera.clip_from_view * Vec4::new(0., 0., -9., 1.);
+		assert!((rect.depth - nearest.z).abs() < 1e-5, "{rect:?}");
+
+		// the same size at any distance
+		let far = project_sphere_to_screen_rect(camera, Affine3A::IDENTITY, Sphere::new(Vec3::new(2., 0., -80.), 1.))
+			.unwrap();
+		assert!(
+			far.min.abs_diff_eq(rect.min, 1e-5) && far.max.abs_diff_eq(rect.max, 1e-5),
+			"{far:?}"
+		);
+	}
+
+	#[test]
+	fn test_project_to_screen_area() {
+		let camera = new_camera();
+		// the scale before projecting to pixels: `error / d * (clip_from_view[1][1] * 0.5) * (viewport_height * 0.5)`
+		let expected = 0.5 / 9. * 0.5 * 50.;
+		let sphere = Sphere::new(Vec3::new(0., 0., -10.), 1.);
+		let error = project_to_screen_area(camera, 1., Affine3A::IDENTITY, sphere, 0.5);
+		assert!((error - expected).abs() < 1e-5, "{error}");
+
+		// scaling the instance scales the distance, radius and error alike
+		let scaled = project_to_screen_area(camera, 1., Affine3A::from_scale(Vec3::splat(2.)), sphere, 0.5);
+		assert!((scaled - expected).abs() < 1e-5, "{scaled}");
+
+		assert_eq!(
+			project_to_screen_area(camera, 1., Affine3A::IDENTITY, sphere, f32::INFINITY),
+			f32::INFINITY
+		);
+	}
+
+	#[test]
+	fn test_is_backfacing() {
+		let perspective = new_camera();
+		let orthographic = Camera::new_orthographic_rh_y_flip(
+			UVec2::new(100, 100),
+			10.,
+			0.1,
+			100.,
+			AffineTransform::new(Affine3A::IDENTITY),
+		);
+		let identity = AffineTransform::new(Affine3A::IDENTITY);
+		// facing away from the camera, far off to the side
+		let cone = NormalCone::new(Vec3::new(100., 0., -1.), Vec3::NEG_Z, 0.5);
+		// seen from the side by the perspective camera, but straight from behind by all rays of the orthographic one
+		assert!(!is_backfacing(perspective, identity, cone));
+		assert!(is_backfacing(orthographic, identity, cone));
+
+		let facing = NormalCone::new(Vec3::new(100., 0., -1.), Vec3::Z, 0.5);
+		assert!(!is_backfacing(orthographic, identity, facing));
+		// turning the instance around makes it face the camera
+		let turned = AffineTransform::new(Affine3A::from_rotation_y(PI));
+		assert!(!is_backfacing(orthographic, turned, cone));
+		assert!(is_backfacing(orthographic, turned, facing));
+
+		// the view direction follows the camera's rotation
+		let turned_camera = Camera::new_orthographic_rh_y_flip(UVec2::new(100, 100), 10., 0.1, 100., turned);
+		assert!(!is_backfacing(turned_camera, identity, cone));
+		assert!(is_backfacing(turned_camera, identity, facing));
+		assert!(!is_backfacing(orthographic, identity, NormalCone::never_culled()));
+	}
+
+	#[test]
+	fn test_hzb_mip_level() {
+		assert_eq!(hzb_mip_level(UVec2::new(5, 5), UVec2::new(5, 5)), 0);
+		assert_eq!(hzb_mip_level(UVec2::new(4, 5), UVec2::new(5, 5)), 1);
+		assert_eq!(hzb_mip_level(UVec2::new(3, 0), UVec2::new(4, 2)), 2);
+		assert_eq!(hzb_mip_level(UVec2::new(0, 0), UVec2::new(7, 3)), 3);
+		for (min, max) in [(3, 4), (1, 6), (7, 14), (10, 50)] {
+			let level = hzb_mip_level(UVec2::new(min, 0), UVec2::new(max, 0));
+			assert!((max >> level) - (min >> level) <= 1);
+		}
+	}
 }
@@ -1,7 +1,12 @@
+pub mod bloom;
 pub mod camera;
 pub mod compacting_alloc_buffer;
 pub mod frame_data;
 pub mod g_buffer;
+pub mod hzb;
 pub mod lighting;
 pub mod lod_selection;
 pub mod meshlet;
+pub mod taa;
+pub mod tonemap;
+pub mod visibility_buffer;
@@ -0,0 +1,309 @@
+//! Temporal anti-aliasing. The camera's projection is offset by a different subpixel [`taa_jitter`] every frame, and
+//! the [`taa_resolve_cs`] accumulates the jittered frames in a history image. Each pixel reads its history at the
+//! position its surface had in the previous frame, following the motion vectors of
+//! [`GBuffer::g_velocity`](crate::renderer::g_buffer::GBuffer::g_velocity), see [`motion_vector`].
+//!
+//! Stale history of disoccluded or changed surfaces is rejected by clamping it to the colors of the pixel's current
+//! neighbourhood in YCoCg space, see [`resolve_history`]. History that was off-screen in the previous frame or has been
+//! invalidated is discarded entirely. The resolve works on the HDR colors of the lighting, before they are
+//! [tonemapped](crate::renderer::tonemap).
+
+use crate::renderer::camera::Camera;
+use crate::renderer::frame_data::FrameData;
+use crate::renderer::lighting::is_skybox;
+use glam::{IVec2, UVec2, UVec3, Vec2, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles};
+use rust_gpu_bindless_macros::{BufferStruct, bindless};
+use rust_gpu_bindless_shaders::descriptor::{Buffer, Descriptors, Image, Image2d, MutImage, TransientDesc};
+use spirv_std::Sampler;
+use static_assertions::const_assert_eq;
+
+/// number of [`taa_jitter`] offsets before the sequence repeats
+pub const TAA_JITTER_SEQUENCE_LEN: u32 = 8;
+
+#[derive(Copy, Clone, BufferStruct)]
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[repr(C)]
+pub struct TaaSettings {
+	/// bool, see [`Self::enabled`]
+	pub enabled: u32,
+	/// weight of the history when blending it with the current frame, higher values converge to a smoother but
+	/// blurrier image
+	pub history_weight: f32,
+}
+
+impl TaaSettings {
+	/// Whether the camera is jittered and frames are accumulated, otherwise the lit frame is presented as is
+	pub fn enabled(&self) -> bool {
+		self.enabled != 0
+	}
+}
+
+/// Element `index` of the Halton low discrepancy sequence of `base`, within [0, 1)
+pub fn halton(index: u32, base: u32) -> f32 {
+	let mut fraction = 1.;
+	let mut result = 0.;
+	let mut index = index;
+	while index > 0 {
+		fraction /= base as f32;
+		result += fraction * (index % base) as f32;
+		index /= base;
+	}
+	result
+}
+
+/// The subpixel offset of `frame` in pixels, within [-0.5, 0.5). Cycles through the first [`TAA_JITTER_SEQUENCE_LEN`]
+/// points of the Halton(2, 3) sequence, skipping the point at the origin.
+pub fn taa_jitter(frame: u32) -> Vec2 {
+	let index = frame % TAA_JITTER_SEQUENCE_LEN + 1;
+	Vec2::new(halton(index, 2), halton(index, 3)) - 0.5
+}
+
+/// The motion of a surface point in fragment positions [0, 1] from the previous to the current frame, ignoring the
+/// jitter of both cameras. `prev_world_pos` is where the point was in the previous frame.
+pub fn motion_vector(camera: Camera, prev_camera: Camera, world_pos: Vec3, prev_world_pos: Vec3) -> Vec2 {
+	camera.project_unjittered(world_pos) - prev_camera.project_unjittered(prev_world_pos)
+}
+
+pub fn rgb_to_ycocg(rgb: Vec3) -> Vec3 {
+	Vec3::new(
+		0.25 * rgb.x + 0.5 * rgb.y + 0.25 * rgb.z,
+		0.5 * rgb.x - 0.5 * rgb.z,
+		-0.25 * rgb.x + 0.5 * rgb.y - 0.25 * rgb.z,
+	)
+}
+
+pub fn ycocg_to_rgb(ycocg: Vec3) -> Vec3 {
+	let tmp = ycocg.x - ycocg.z;
+	Vec3::new(tmp + ycocg.y, ycocg.x + ycocg.z, tmp - ycocg.y)
+}
+
+/// Blends the `history` into the `current` color after clamping it to the range of colors `[neighbourhood_min,
+/// neighbourhood_max]` around the current pixel. All colors are in YCoCg space.
+pub fn resolve_history(
+	current: Vec3,
+	history: Vec3,
+	neighbourhood_min: Vec3,
+	neighbourhood_max: Vec3,
+	history_weight: f32,
+) -> Vec3 {
+	let history = history.clamp(neighbourhood_min, neighbourhood_max);
+	Vec3::lerp(current, history, history_weight)
+}
+
+#[derive(Copy, Clone, BufferStruct)]
+pub struct Param<'a> {
+	pub frame_data: TransientDesc<'a, Buffer<FrameData>>,
+	/// the lit frame
+	pub color: TransientDesc<'a, Image<Image2d>>,
+	pub depth_image: TransientDesc<'a, Image<Image2d>>,
+	pub velocity: TransientDesc<'a, Image<Image2d>>,
+	/// the resolved image of the previous frame, only valid if [`Self::history_valid`]
+	pub history: TransientDesc<'a, Image<Image2d>>,
+	pub sampler: TransientDesc<'a, Sampler>,
+	/// bool, whether the `history` contains the previous frame, otherwise it is discarded
+	pub history_valid: u32,
+	/// the resolved image, to be used as `history` next frame
+	pub history_out: TransientDesc<'a, MutImage<Image2d>>,
+	pub output_image: TransientDesc<'a, MutImage<Image2d>>,
+}
+
+pub const TAA_RESOLVE_WG_SIZE: UVec2 = UVec2::new(8, 8);
+
+const_assert_eq!(TAA_RESOLVE_WG_SIZE.x, 8);
+const_assert_eq!(TAA_RESOLVE_WG_SIZE.y, 8);
+#[bindless(compute(threads(8, 8)))]
+pub fn taa_resolve_cs(
+	#[bindless(descriptors)] descriptors: Descriptors,
+	#[bindless(param)] param: &Param<'static>,
+	#[spirv(global_invocation_id)] inv_id: UVec3,
+) {
+	let frame_data = param.frame_data.access(&descriptors).load();
+	let camera = frame_data.camera;
+	let size = camera.viewport_size;
+	let pixel = inv_id.xy();
+	if !(pixel.x < size.x && pixel.y < size.y) {
+		return;
+	}
+
+	let color = param.color.access(&descriptors);
+	let depth_image = param.depth_image.access(&descriptors);
+	#[allow(clippy::useless_conversion)]
+	let fetch_color = |pixel: UVec2| Vec4::from(color.fetch(pixel)).xyz();
+	#[allow(clippy::useless_conversion)]
+	let fetch_depth = |pixel: UVec2| Vec4::from(depth_image.fetch(pixel)).x;
+
+	// the neighbourhood's color range, and the pixel closest to the camera whose motion is followed, so that edges of
+	// moving objects don't leave a trail
+	let current = fetch_color(pixel);
+	let mut neighbourhood_min = rgb_to_ycocg(current);
+	let mut neighbourhood_max = neighbourhood_min;
+	let mut closest = pixel;
+	let mut closest_depth = fetch_depth(pixel);
+	for y in -1..=1 {
+		for x in -1..=1 {
+			let neighbour = (pixel.as_ivec2() + IVec2::new(x, y))
+				.clamp(IVec2::ZERO, size.as_ivec2() - 1)
+				.as_uvec2();
+			let ycocg = rgb_to_ycocg(fetch_color(neighbour));
+			neighbourhood_min = neighbourhood_min.min(ycocg);
+			neighbourhood_max = neighbourhood_max.max(ycocg);
+			let depth = fetch_depth(neighbour);
+			if depth < closest_depth {
+				closest = neighbour;
+				closest_depth = depth;
+			}
+		}
+	}
+
+	let uv = (pixel.as_vec2() + 0.5) / size.as_vec2();
+	let velocity = if is_skybox(closest_depth) {
+		// the velocity isn't written for the sky, which only moves with the camera
+		let world_pos = camera.reconstruct_from_depth(uv, closest_depth).world_space;
+		motion_vector(camera, frame_data.prev_camera, world_pos, world_pos)
+	} else {
+		#[allow(clippy::useless_conversion)]
+		Vec4::from(param.velocity.access(&descriptors).fetch(closest)).xy()
+	};
+
+	let history_uv = uv - velocity;
+	let history_inbounds = history_uv.cmpge(Vec2::ZERO).all() && history_uv.cmple(Vec2::ONE).all();
+	let resolved = if param.history_valid != 0 && history_inbounds {
+		let history: Vec4 =
+			param
+				.history
+				.access(&descriptors)
+				.sample_by_lod(param.sampler.access(&descriptors), history_uv, 0.);
+		ycocg_to_rgb(resolve_history(
+			rgb_to_ycocg(current),
+			rgb_to_ycocg(history.xyz()),
+			neighbourhood_min,
+			neighbourhood_max,
+			frame_data.taa.history_weight,
+		))
+	} else {
+		current
+	};
+
+	unsafe {
+		param.history_out.access(&descriptors).write(pixel, resolved.extend(1.));
+		param
+			.output_image
+			.access(&descriptors)
+			.write(pixel, resolved.extend(1.));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::f32::consts::FRAC_PI_2;
+	use glam::Affine3A;
+	use space_asset_shader::affine_transform::AffineTransform;
+
+	fn new_camera(transform: Affine3A, jitter: Vec2) -> Camera {
+		Camera::new_perspective_rh_y_flip(
+			UVec2::new(100, 100),
+			FRAC_PI_2,
+			0.1,
+			100.,
+			AffineTransform::new(transform),
+			jitter,
+		)
+	}
+
+	#[test]
+	fn test_halton() {
+		let base2 = [0., 0.5, 0.25, 0.75, 0.125];
+		let base3 = [0., 1. / 3., 2. / 3., 1. / 9., 4. / 9.];
+		for i in 0..5 {
+			assert!((halton(i, 2) - base2[i as usize]).abs() < 1e-6);
+			assert!((halton(i, 3) - base3[i as usize]).abs() < 1e-6);
+		}
+	}
+
+	#[test]
+	fn test_jitter_sequence() {
+		let jitters = (0..TAA_JITTER_SEQUENCE_LEN).map(taa_jitter).collect::<Vec<_>>();
+		for (i, jitter) in jitters.iter().enumerate() {
+			assert!(
+				jitter.cmpge(Vec2::splat(-0.5)).all() && jitter.cmplt(Vec2::splat(0.5)).all(),
+				"{jitter:?}"
+			);
+			for other in &jitters[..i] {
+				assert_ne!(jitter, other);
+			}
+		}
+		// well distributed around the pixel center
+		let mean = jitters.iter().sum::<Vec2>() / TAA_JITTER_SEQUENCE_LEN as f32;
+		assert!(mean.length() < 0.1, "{mean:?}");
+		assert_eq!(taa_jitter(TAA_JITTER_SEQUENCE_LEN), taa_jitter(0));
+	}
+
+	#[test]
+	fn test_jitter_projection() {
+		let jitter = Vec2::new(0.5, -0.25);
+		let camera = new_camera(Affine3A::IDENTITY, Vec2::ZERO);
+		let jittered = new_camera(Affine3A::IDENTITY, jitter);
+		let pos = Vec3::new(1., 2., -5.);
+		let project = |camera: Camera| {
+			let clip = camera.transform_vertex(AffineTransform::default(), pos).clip_space;
+			(clip.xy() / clip.w * 0.5 + 0.5) * camera.viewport_size.as_vec2()
+		};
+		let offset = project(jittered) - project(camera);
+		assert!(offset.distance(jitter) < 1e-4, "{offset:?}");
+		assert!(
+			camera
+				.project_unjittered(pos)
+				.distance(jittered.project_unjittered(pos))
+				< 1e-6
+		);
+	}
+
+	#[test]
+	fn test_motion_vector_static() {
+		let camera = new_camera(Affine3A::from_translation(Vec3::new(1., 2., 3.)), taa_jitter(1));
+		let prev_camera = new_camera(Affine3A::from_translation(Vec3::new(1., 2., 3.)), taa_jitter(0));
+		let pos = Vec3::new(0.5, -1., -10.);
+		let motion = motion_vector(camera, prev_camera, pos, pos);
+		assert!(motion.length() < 1e-6, "{motion:?}");
+	}
+
+	#[test]
+	fn test_motion_vector_reprojection() {
+		let prev_camera = new_camera(Affine3A::IDENTITY, Vec2::ZERO);
+		let camera = new_camera(Affine3A::from_translation(Vec3::new(1., 0., 0.)), Vec2::ZERO);
+		let pos = Vec3::new(0., 0., -10.);
+		let motion = motion_vector(camera, prev_camera, pos, pos);
+		// moving the camera right moves the point left on screen
+		assert!(motion.x < 0. && motion.y.abs() < 1e-6, "{motion:?}");
+		// following the motion back lands on the point's previous position
+		let uv = camera.project_unjittered(pos);
+		assert!((uv - motion).distance(prev_camera.project_unjittered(pos)) < 1e-6);
+
+		// an object moving along with the camera stays in place
+		let motion = motion_vector(camera, prev_camera, pos + Vec3::X, pos);
+		assert!(motion.length() < 1e-6, "{motion:?}");
+	}
+
+	#[test]
+	fn test_ycocg_roundtrip() {
+		for rgb in [Vec3::ZERO, Vec3::ONE, Vec3::new(1., 0., 0.), Vec3::new(0.2, 0.7, 0.4)] {
+			let roundtrip = ycocg_to_rgb(rgb_to_ycocg(rgb));
+			assert!(roundtrip.distance(rgb) < 1e-6, "{rgb:?} {roundtrip:?}");
+		}
+	}
+
+	#[test]
+	fn test_resolve_history() {
+		let current = rgb_to_ycocg(Vec3::splat(0.5));
+		let min = rgb_to_ycocg(Vec3::splat(0.4));
+		let max = rgb_to_ycocg(Vec3::splat(0.6));
+		// history within the neighbourhood is kept
+		let history = rgb_to_ycocg(Vec3::splat(0.55));
+		let resolved = resolve_history(current, history, min, max, 0.9);
+		assert!(resolved.distance(current.lerp(history, 0.9)) < 1e-6);
+		// stale history of another surface is clamped
+		let resolved = ycocg_to_rgb(resolve_history(current, rgb_to_ycocg(Vec3::ONE), min, max, 1.));
+		assert!(resolved.distance(Vec3::splat(0.6)) < 1e-6, "{resolved:?}");
+	}
+}
@@ -0,0 +1,594 @@
+//! Tonemapping of the HDR frame into the output image. The lighting writes scene-referred radiance, which is scaled by
+//! an exposure and compressed into the displayable range by one of the [`TonemapOperator`]s, then encoded for the
+//! [`TonemapOutput`] the swapchain negotiated.
+//!
+//! The exposure is either set manually or adapts automatically to the scene: [`tonemap_histogram_cs`] counts the
+//! pixels of the frame by their log2 luminance, [`tonemap_exposure_cs`] exposes the histogram's average luminance as
+//! [`MIDDLE_GREY`] and smoothly adapts the previous frame's exposure towards it, see
+//! [`histogram_average_log2_luminance`].
+
+use crate::renderer::bloom::Bloom;
+use crate::renderer::frame_data::{DebugSettings, FrameData};
+use glam::{Mat3, UVec2, UVec3, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles, vec3};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use rust_gpu_bindless_macros::{BufferStruct, bindless};
+use rust_gpu_bindless_shaders::descriptor::{Buffer, Descriptors, Image, Image2d, MutBuffer, MutImage, TransientDesc};
+use rust_gpu_bindless_shaders::utils::srgb::linear_to_srgb;
+use spirv_std::arch::{IndexUnchecked, atomic_i_add};
+use spirv_std::memory::{Scope, Semantics};
+#[cfg(target_arch = "spirv")]
+use spirv_std::num_traits::Float;
+use static_assertions::const_assert_eq;
+
+/// number of bins of the luminance histogram, bin 0 collects all pixels too dark to be considered
+pub const HISTOGRAM_BINS: u32 = 256;
+/// log2 luminance of the lower end of bin 1
+pub const HISTOGRAM_MIN_LOG2_LUMINANCE: f32 = -10.;
+/// log2 luminance range covered by the bins `1..HISTOGRAM_BINS`
+pub const HISTOGRAM_LOG2_LUMINANCE_RANGE: f32 = 16.;
+/// fraction of the darkest pixels ignored by the auto exposure
+pub const HISTOGRAM_LOW_FRACTION: f32 = 0.1;
+/// fraction of the brightest pixels ignored by the auto exposure
+pub const HISTOGRAM_HIGH_FRACTION: f32 = 0.05;
+/// the luminance the average luminance of the scene is exposed as by the auto exposure
+pub const MIDDLE_GREY: f32 = 0.18;
+/// nits of an scRGB value of 1.0
+pub const SCRGB_WHITE_NITS: f32 = 80.;
+/// nits of a PQ value of 1.0
+pub const PQ_MAX_NITS: f32 = 10000.;
+
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, TryFromPrimitive, IntoPrimitive)]
+pub enum TonemapOperator {
+	Reinhard,
+	AcesFitted,
+	AgX,
+	PbrNeutral,
+}
+
+impl TonemapOperator {
+	pub const MAX_VALUE: TonemapOperator = TonemapOperator::PbrNeutral;
+	pub const LEN: u32 = Self::MAX_VALUE as u32 + 1;
+
+	/// Maps linear Rec.709 radiance to linear display values within [0, 1]
+	pub fn apply(&self, color: Vec3) -> Vec3 {
+		let color = color.max(Vec3::ZERO);
+		match self {
+			TonemapOperator::Reinhard => reinhard(color),
+			TonemapOperator::AcesFitted => aces_fitted(color),
+			TonemapOperator::AgX => agx(color),
+			TonemapOperator::PbrNeutral => pbr_neutral(color),
+		}
+	}
+}
+
+/// The color space and transfer function of the output image
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, TryFromPrimitive, IntoPrimitive)]
+pub enum TonemapOutput {
+	/// sRGB encoded values in a UNORM image
+	Sdr,
+	/// Rec.2020 primaries with the PQ transfer function
+	Hdr10,
+	/// linear Rec.709 with 1.0 at [`SCRGB_WHITE_NITS`], values may exceed 1.0
+	ScRgb,
+}
+
+impl TonemapOutput {
+	pub fn is_hdr(&self) -> bool {
+		*self != TonemapOutput::Sdr
+	}
+}
+
+#[derive(Copy, Clone, BufferStruct)]
+#[cfg_attr(not(target_arch = "spirv"), derive(Debug))]
+#[repr(C)]
+pub struct TonemapSettings {
+	/// see [`Self::operator`]
+	pub operator: u32,
+	/// bool, see [`Self::auto_exposure`]
+	pub auto_exposure: u32,
+	/// exposure in stops, or the compensation added to the auto exposure
+	pub exposure_ev: f32,
+	/// how quickly the auto exposure adapts to a change in brightness, per second
+	pub adaptation_speed: f32,
+	/// seconds since the previous frame, for the adaptation of the auto exposure
+	pub delta_time: f32,
+	/// see [`Self::output`]
+	pub output: u32,
+	/// nits of diffuse white on HDR outputs
+	pub paper_white_nits: f32,
+	/// nits of the brightest value the display can show on HDR outputs
+	pub peak_nits: f32,
+}
+
+impl TonemapSettings {
+	pub fn operator(&self) -> TonemapOperator {
+		TonemapOperator::try_from(self.operator).unwrap_or(TonemapOperator::Reinhard)
+	}
+
+	/// Whether the exposure adapts to the luminance histogram of the frame, otherwise [`Self::exposure_ev`] is the
+	/// exposure
+	pub fn auto_exposure(&self) -> bool {
+		self.auto_exposure != 0
+	}
+
+	pub fn output(&self) -> TonemapOutput {
+		TonemapOutput::try_from(self.output).unwrap_or(TonemapOutput::Sdr)
+	}
+
+	/// How many times brighter than paper white the output can get, 1.0 on SDR outputs
+	pub fn headroom(&self) -> f32 {
+		if self.output().is_hdr() {
+			(self.peak_nits / self.paper_white_nits).max(1.)
+		} else {
+			1.
+		}
+	}
+}
+
+pub fn luminance(color: Vec3) -> f32 {
+	color.dot(vec3(0.2126, 0.7152, 0.0722))
+}
+
+pub fn reinhard(color: Vec3) -> Vec3 {
+	color / (color + 1.)
+}
+
+/// Stephen Hill's fit of the ACES RRT and sRGB ODT, see
+/// <https://github.com/TheRealMJP/BakingLab/blob/master/BakingLab/ACES.hlsl>
+pub fn aces_fitted(color: Vec3) -> Vec3 {
+	const INPUT: Mat3 = Mat3::from_cols(
+		vec3(0.59719, 0.07600, 0.02840),
+		vec3(0.35458, 0.90834, 0.13383),
+		vec3(0.04823, 0.01566, 0.83777),
+	);
+	const OUTPUT: Mat3 = Mat3::from_cols(
+		vec3(1.60475, -0.10208, -0.00327),
+		vec3(-0.53108, 1.10813, -0.07276),
+		vec3(-0.07367, -0.00605, 1.07602),
+	);
+	let v = INPUT * color;
+	let a = v * (v + 0.0245786) - 0.000090537;
+	let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+	(OUTPUT * (a / b)).clamp(Vec3::ZERO, Vec3::ONE)
+}
+
+/// Minimal AgX with the default contrast approximation, see
+/// <https://iolite-engine.com/blog_posts/minimal_agx_implementation>
+pub fn agx(color: Vec3) -> Vec3 {
+	const INSET: Mat3 = Mat3::from_cols(
+		vec3(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+		vec3(0.0784335999999992, 0.878468636469772, 0.0784336),
+		vec3(0.0792237451477643, 0.0791661274605434, 0.879142973793104),
+	);
+	const OUTSET: Mat3 = Mat3::from_cols(
+		vec3(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+		vec3(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+		vec3(-0.0990297440797205, -0.0989611768448433, 1.15107367264116),
+	);
+	const MIN_EV: f32 = -12.47393;
+	const MAX_EV: f32 = 4.026069;
+
+	let v = INSET * color;
+	let log2 = |x: f32| x.max(f32::MIN_POSITIVE).log2();
+	let v = vec3(log2(v.x), log2(v.y), log2(v.z)).clamp(Vec3::splat(MIN_EV), Vec3::splat(MAX_EV));
+	let x = (v - MIN_EV) / (MAX_EV - MIN_EV);
+	let x2 = x * x;
+	let x4 = x2 * x2;
+	let v = 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+	// the sigmoid targets a display with a 2.2 gamma
+	Vec3::powf((OUTSET * v).max(Vec3::ZERO), 2.2).min(Vec3::ONE)
+}
+
+/// Khronos PBR Neutral, see <https://github.com/KhronosGroup/ToneMapping/blob/main/PBR_Neutral/README.md>
+pub fn pbr_neutral(color: Vec3) -> Vec3 {
+	const START_COMPRESSION: f32 = 0.8 - 0.04;
+	const DESATURATION: f32 = 0.15;
+
+	let x = color.min_element();
+	let offset = if x < 0.08 { x - 6.25 * x * x } else { 0.04 };
+	let color = color - offset;
+	let peak = color.max_element();
+	if peak < START_COMPRESSION {
+		color
+	} else {
+		let d = 1. - START_COMPRESSION;
+		let new_peak = 1. - d * d / (peak + d - START_COMPRESSION);
+		let color = color * (new_peak / peak);
+		let g = 1. - 1. / (DESATURATION * (peak - new_peak) + 1.);
+		Vec3::lerp(color, Vec3::splat(new_peak), g)
+	}
+}
+
+/// Tonemaps `color` into [0, `headroom`], where `headroom` is how many times brighter than paper white the output
+/// may be. The operators are designed for SDR, so their curve is stretched to cover the headroom.
+pub fn tonemap(operator: TonemapOperator, color: Vec3, headroom: f32) -> Vec3 {
+	operator.apply(color / headroom) * headroom
+}
+
+/// The SMPTE ST 2084 perceptual quantizer, encoding `nits` into [0, 1]
+pub fn pq_encode(nits: Vec3) -> Vec3 {
+	const M1: f32 = 2610. / 16384.;
+	const M2: f32 = 2523. / 4096. * 128.;
+	const C1: f32 = 3424. / 4096.;
+	const C2: f32 = 2413. / 4096. * 32.;
+	const C3: f32 = 2392. / 4096. * 32.;
+	let y = Vec3::powf((nits / PQ_MAX_NITS).clamp(Vec3::ZERO, Vec3::ONE), M1);
+	Vec3::powf((C1 + C2 * y) / (1. + C3 * y), M2)
+}
+
+/// Encodes a tonemapped `color`, where 1.0 is paper white, for the `output`
+pub fn encode_output(settings: TonemapSettings, color: Vec3) -> Vec3 {
+	const REC709_TO_REC2020: Mat3 = Mat3::from_cols(
+		vec3(0.6274040, 0.0690970, 0.0163916),
+		vec3(0.3292820, 0.9195400, 0.0880132),
+		vec3(0.0433136, 0.0113612, 0.8955950),
+	);
+	match settings.output() {
+		TonemapOutput::Sdr => linear_to_srgb(color.clamp(Vec3::ZERO, Vec3::ONE)),
+		TonemapOutput::Hdr10 => pq_encode(REC709_TO_REC2020 * color * settings.paper_white_nits),
+		TonemapOutput::ScRgb => color * (settings.paper_white_nits / SCRGB_WHITE_NITS),
+	}
+}
+
+/// The histogram bin of a pixel with `luminance`. Bin 0 collects all pixels darker than
+/// [`HISTOGRAM_MIN_LOG2_LUMINANCE`], the other bins evenly cover the [`HISTOGRAM_LOG2_LUMINANCE_RANGE`], clamping
+/// brighter pixels into the last bin.
+pub fn luminance_bin(luminance: f32) -> u32 {
+	let log2 = luminance.max(0.).log2();
+	if log2 < HISTOGRAM_MIN_LOG2_LUMINANCE {
+		0
+	} else {
+		let t = ((log2 - HISTOGRAM_MIN_LOG2_LUMINANCE) / HISTOGRAM_LOG2_LUMINANCE_RANGE).clamp(0., 1.);
+		let bins = (HISTOGRAM_BINS - 1) as f32;
+		1 + ((t * bins) as u32).min(HISTOGRAM_BINS - 2)
+	}
+}
+
+/// The log2 luminance at the center of a `bin` of at least 1
+pub fn bin_log2_luminance(bin: u32) -> f32 {
+	let bins = (HISTOGRAM_BINS - 1) as f32;
+	HISTOGRAM_MIN_LOG2_LUMINANCE + (bin as f32 - 0.5) / bins * HISTOGRAM_LOG2_LUMINANCE_RANGE
+}
+
+/// The average log2 luminance of the pixels counted in the histogram, returned by `bin` for each bin index. Ignores
+/// bin 0 as well as the darkest `low_fraction` and the brightest `high_fraction` of pixels, so that small bright
+/// lights or dark corners don't sway the exposure. `None` if no pixel is left.
+pub fn histogram_average_log2_luminance(
+	bin: impl Fn(u32) -> u32,
+	low_fraction: f32,
+	high_fraction: f32,
+) -> Option<f32> {
+	let mut total = 0;
+	let mut i = 1;
+	while i < HISTOGRAM_BINS {
+		total += bin(i);
+		i += 1;
+	}
+	let low = total as f32 * low_fraction;
+	let high = total as f32 * (1. - high_fraction);
+
+	let mut seen = 0.;
+	let mut sum = 0.;
+	let mut weight = 0.;
+	let mut i = 1;
+	while i < HISTOGRAM_BINS {
+		let start = seen;
+		seen += bin(i) as f32;
+		let counted = (seen.min(high) - start.max(low)).max(0.);
+		sum += counted * bin_log2_luminance(i);
+		weight += counted;
+		i += 1;
+	}
+	if weight > 0. { Some(sum / weight) } else { None }
+}
+
+/// The log2 exposure that maps the `average_log2_luminance` to [`MIDDLE_GREY`], adjusted by `compensation_ev`
+pub fn auto_exposure_log2(average_log2_luminance: f32, compensation_ev: f32) -> f32 {
+	MIDDLE_GREY.log2() - average_log2_luminance + compensation_ev
+}
+
+/// Moves the `current` log2 exposure towards the `target` exponentially, covering `1 - e^-1` of the distance after
+/// `1 / speed` seconds independent of the frame rate
+pub fn adapt_exposure_log2(current: f32, target: f32, speed: f32, delta_time: f32) -> f32 {
+	current + (target - current) * (1. - (-speed * delta_time).exp())
+}
+
+#[derive(Copy, Clone, BufferStruct)]
+pub struct HistogramParam<'a> {
+	pub frame_data: TransientDesc<'a, Buffer<FrameData>>,
+	pub hdr_image: TransientDesc<'a, Image<Image2d>>,
+	/// [`HISTOGRAM_BINS`] pixel counts, expected to be cleared
+	pub histogram: TransientDesc<'a, MutBuffer<[u32]>>,
+}
+
+pub const TONEMAP_WG_SIZE: UVec2 = UVec2::new(8, 8);
+
+const_assert_eq!(TONEMAP_WG_SIZE.x, 8);
+const_assert_eq!(TONEMAP_WG_SIZE.y, 8);
+#[bindless(compute(threads(8, 8)))]
+pub fn tonemap_histogram_cs(
+	#[bindless(descriptors)] mut descriptors: Descriptors,
+	#[bindless(param)] param: &HistogramParam<'static>,
+	#[spirv(global_invocation_id)] inv_id: UVec3,
+) {
+	let size = param.frame_data.access(&descriptors).load().camera.viewport_size;
+	let pixel = inv_id.xy();
+	if !(pixel.x < size.x && pixel.y < size.y) {
+		return;
+	}
+
+	#[allow(clippy::useless_conversion)]
+	let color = Vec4::from(param.hdr_image.access(&descriptors).fetch(pixel)).xyz();
+	let bin = luminance_bin(luminance(color));
+	// Safety: the bin is always within the histogram
+	unsafe {
+		let histogram = param.histogram.access(&mut descriptors).into_raw_mut();
+		atomic_i_add::<_, { Scope::QueueFamily as u32 }, { Semantics::NONE.bits() }>(
+			histogram.index_unchecked_mut(bin as usize),
+			1,
+		);
+	}
+}
+
+#[derive(Copy, Clone, BufferStruct)]
+pub struct ExposureParam<'a> {
+	pub frame_data: TransientDesc<'a, Buffer<FrameData>>,
+	/// [`HISTOGRAM_BINS`] pixel counts, cleared afterward for the next frame
+	pub histogram: TransientDesc<'a, MutBuffer<[u32]>>,
+	/// the log2 exposure, adapted from the previous frame if [`Self::adapt`]
+	pub exposure: TransientDesc<'a, MutBuffer<f32>>,
+	/// bool, whether `exposure` contains the previous frame's exposure, otherwise it jumps to the target exposure
+	pub adapt: u32,
+}
+
+#[bindless(compute(threads(1)))]
+pub fn tonemap_exposure_cs(
+	#[bindless(descriptors)] mut descriptors: Descriptors,
+	#[bindless(param)] param: &ExposureParam<'static>,
+) {
+	let settings = param.frame_data.access(&descriptors).load().tonemap;
+	let histogram = param.histogram.access(&descriptors);
+	// Safety: panics within loops miscompile, all bins are within the histogram
+	let average = histogram_average_log2_luminance(
+		|i| unsafe { histogram.load_unchecked(i as usize) },
+		HISTOGRAM_LOW_FRACTION,
+		HISTOGRAM_HIGH_FRACTION,
+	);
+
+	let current = param.exposure.access(&descriptors).load();
+	let exposure = match average {
+		Some(average) => {
+			let target = auto_exposure_log2(average, settings.exposure_ev);
+			if param.adapt != 0 {
+				adapt_exposure_log2(current, target, settings.adaptation_speed, settings.delta_time)
+			} else {
+				target
+			}
+		}
+		// an entirely black frame keeps its exposure
+		None if param.adapt != 0 => current,
+		None => settings.exposure_ev,
+	};
+	unsafe {
+		param.exposure.access(&mut descriptors).store(exposure);
+		let mut i = 0;
+		while i < HISTOGRAM_BINS {
+			param.histogram.access(&mut descriptors).store_unchecked(i as usize, 0);
+			i += 1;
+		}
+	}
+}
+
+#[derive(Copy, Clone, BufferStruct)]
+pub struct Param<'a> {
+	pub frame_data: TransientDesc<'a, Buffer<FrameData>>,
+	pub hdr_image: TransientDesc<'a, Image<Image2d>>,
+	/// the log2 exposure computed by [`tonemap_exposure_cs`], only read with auto exposure
+	pub exposure: TransientDesc<'a, Buffer<f32>>,
+	pub bloom: Bloom<'a>,
+	pub output_image: TransientDesc<'a, MutImage<Image2d>>,
+	/// the top left corner of the viewport in the `output_image`, which may be shared by multiple views
+	pub output_offset: UVec2,
+}
+
+const_assert_eq!(TONEMAP_WG_SIZE.x, 8);
+const_assert_eq!(TONEMAP_WG_SIZE.y, 8);
+#[bindless(compute(threads(8, 8)))]
+pub fn tonemap_cs(
+	#[bindless(descriptors)] descriptors: Descriptors,
+	#[bindless(param)] param: &Param<'static>,
+	#[spirv(global_invocation_id)] inv_id: UVec3,
+) {
+	let frame_data = param.frame_data.access(&descriptors).load();
+	let size = frame_data.camera.viewport_size;
+	let pixel = inv_id.xy();
+	if !(pixel.x < size.x && pixel.y < size.y) {
+		return;
+	}
+
+	let settings = frame_data.tonemap;
+	#[allow(clippy::useless_conversion)]
+	let color = Vec4::from(param.hdr_image.access(&descriptors).fetch(pixel)).xyz();
+	let color = if frame_data.debug_settings() != DebugSettings::None {
+		// debug colors are already display values
+		color
+	} else {
+		let uv = (pixel.as_vec2() + 0.5) / size.as_vec2();
+		let color = param.bloom.apply(&descriptors, frame_data, uv, color);
+		let exposure_log2 = if settings.auto_exposure() {
+			param.exposure.access(&descriptors).load()
+		} else {
+			settings.exposure_ev
+		};
+		tonemap(settings.operator(), color * exposure_log2.exp2(), settings.headroom())
+	};
+	let color = encode_output(settings, color);
+	unsafe {
+		param
+			.output_image
+			.access(&descriptors)
+			.write(pixel + param.output_offset, color.extend(1.));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const OPERATORS: [TonemapOperator; TonemapOperator::LEN as usize] = [
+		TonemapOperator::Reinhard,
+		TonemapOperator::AcesFitted,
+		TonemapOperator::AgX,
+		TonemapOperator::PbrNeutral,
+	];
+
+	#[test]
+	fn test_operators_black_and_range() {
+		for operator in OPERATORS {
+			let black = operator.apply(Vec3::ZERO);
+			assert!(black.max_element() < 1e-3, "{operator:?} {black:?}");
+			let bright = operator.apply(Vec3::splat(1000.));
+			assert!(bright.cmpge(Vec3::splat(0.9)).all(), "{operator:?} {bright:?}");
+			assert!(bright.cmple(Vec3::splat(1. + 1e-4)).all(), "{operator:?} {bright:?}");
+		}
+	}
+
+	#[test]
+	fn test_operators_monotonic() {
+		for operator in OPERATORS {
+			let mut last = operator.apply(Vec3::ZERO);
+			for i in 1..200 {
+				let x = 2f32.powf(i as f32 / 10. - 10.);
+				let current = operator.apply(Vec3::splat(x));
+				assert!(
+					current.x + 1e-5 >= last.x,
+					"{operator:?} at {x}: {current:?} < {last:?}"
+				);
+				last = current;
+			}
+		}
+	}
+
+	#[test]
+	fn test_operator_values() {
+		assert!((reinhard(Vec3::ONE) - 0.5).abs().max_element() < 1e-6);
+		// the ACES fit darkens mid grey, AgX brightens it
+		let aces = aces_fitted(Vec3::splat(MIDDLE_GREY));
+		assert!((aces - 0.1056).abs().max_element() < 1e-3, "{aces:?}");
+		let agx_grey = agx(Vec3::splat(MIDDLE_GREY));
+		assert!((agx_grey - 0.2145).abs().max_element() < 1e-3, "{agx_grey:?}");
+		// PBR Neutral keeps dark colors almost untouched and only compresses highlights
+		let neutral = pbr_neutral(vec3(0.5, 0.3, 0.2));
+		assert!(
+			(neutral - vec3(0.46, 0.26, 0.16)).abs().max_element() < 1e-5,
+			"{neutral:?}"
+		);
+	}
+
+	#[test]
+	fn test_tonemap_headroom() {
+		let color = Vec3::splat(4.);
+		assert_eq!(tonemap(TonemapOperator::Reinhard, color, 1.), reinhard(color));
+		let hdr = tonemap(TonemapOperator::Reinhard, color, 4.);
+		assert!((hdr - 2.).abs().max_element() < 1e-6, "{hdr:?}");
+	}
+
+	#[test]
+	fn test_pq_encode() {
+		assert!(pq_encode(Vec3::ZERO).max_element() < 1e-5);
+		assert!((pq_encode(Vec3::splat(PQ_MAX_NITS)) - 1.).abs().max_element() < 1e-5);
+		// 100 nits are encoded at about half the signal range
+		let sdr_white = pq_encode(Vec3::splat(100.)).x;
+		assert!((sdr_white - 0.508).abs() < 0.001, "{sdr_white}");
+	}
+
+	#[test]
+	fn test_encode_output() {
+		let settings = |output: TonemapOutput| TonemapSettings {
+			operator: TonemapOperator::Reinhard.into(),
+			auto_exposure: 0,
+			exposure_ev: 0.,
+			adaptation_speed: 1.,
+			delta_time: 0.,
+			output: output.into(),
+			paper_white_nits: 200.,
+			peak_nits: 1000.,
+		};
+		assert_eq!(settings(TonemapOutput::Sdr).headroom(), 1.);
+		assert_eq!(settings(TonemapOutput::Hdr10).headroom(), 5.);
+		let sdr = encode_output(settings(TonemapOutput::Sdr), Vec3::splat(2.));
+		assert!((sdr - 1.).abs().max_element() < 1e-5, "{sdr:?}");
+		assert_eq!(
+			encode_output(settings(TonemapOutput::ScRgb), Vec3::ONE),
+			Vec3::splat(2.5)
+		);
+		// white keeps its chromaticity in Rec.2020
+		let hdr10 = encode_output(settings(TonemapOutput::Hdr10), Vec3::ONE);
+		assert!(
+			(hdr10 - pq_encode(Vec3::splat(200.))).abs().max_element() < 1e-4,
+			"{hdr10:?}"
+		);
+	}
+
+	#[test]
+	fn test_luminance_bin() {
+		assert_eq!(luminance_bin(0.), 0);
+		assert_eq!(luminance_bin(-1.), 0);
+		assert_eq!(luminance_bin(f32::NAN), 0);
+		assert_eq!(luminance_bin(HISTOGRAM_MIN_LOG2_LUMINANCE.exp2() * 0.9), 0);
+		assert_eq!(luminance_bin(HISTOGRAM_MIN_LOG2_LUMINANCE.exp2() * 1.001), 1);
+		assert_eq!(luminance_bin(1e30), HISTOGRAM_BINS - 1);
+		for bin in 1..HISTOGRAM_BINS {
+			assert_eq!(luminance_bin(bin_log2_luminance(bin).exp2()), bin);
+		}
+	}
+
+	#[test]
+	fn test_histogram_average() {
+		let mut histogram = [0; HISTOGRAM_BINS as usize];
+		assert_eq!(
+			histogram_average_log2_luminance(|i| histogram[i as usize], 0., 0.),
+			None
+		);
+
+		// pixels too dark for the histogram are ignored
+		histogram[0] = 1000;
+		let bin = luminance_bin(0.5);
+		histogram[bin as usize] = 10;
+		let average = histogram_average_log2_luminance(|i| histogram[i as usize], 0., 0.).unwrap();
+		assert!((average - bin_log2_luminance(bin)).abs() < 1e-5);
+
+		// a few very bright pixels are cut off by the high fraction
+		histogram[HISTOGRAM_BINS as usize - 1] = 1;
+		let with_outlier = histogram_average_log2_luminance(|i| histogram[i as usize], 0., 0.).unwrap();
+		assert!(with_outlier > average);
+		let cut = histogram_average_log2_luminance(|i| histogram[i as usize], 0., 0.1).unwrap();
+		assert!((cut - average).abs() < 1e-5, "{cut} {average}");
+
+		// half the pixels in each of two bins average to their middle
+		let mut histogram = [0; HISTOGRAM_BINS as usize];
+		histogram[10] = 50;
+		histogram[30] = 50;
+		let average = histogram_average_log2_luminance(|i| histogram[i as usize], 0., 0.).unwrap();
+		assert!((average - bin_log2_luminance(20)).abs() < 1e-5);
+		let low_cut = histogram_average_log2_luminance(|i| histogram[i as usize], 0.5, 0.).unwrap();
+		assert!((low_cut - bin_log2_luminance(30)).abs() < 1e-5);
+	}
+
+	#[test]
+	fn test_auto_exposure() {
+		// a scene averaging middle grey needs no exposure
+		assert!(auto_exposure_log2(MIDDLE_GREY.log2(), 0.).abs() < 1e-6);
+		assert!((auto_exposure_log2(MIDDLE_GREY.log2() + 3., 1.) - -2.).abs() < 1e-6);
+
+		assert_eq!(adapt_exposure_log2(1., 5., 2., 0.), 1.);
+		let adapted = adapt_exposure_log2(1., 5., 2., 0.5);
+		assert!((adapted - (1. + 4. * (1. - (-1f32).exp()))).abs() < 1e-5, "{adapted}");
+		assert!((adapt_exposure_log2(1., 5., 2., 100.) - 5.).abs() < 1e-5);
+		// two half steps adapt as far as one full step
+		let halves = adapt_exposure_log2(adapt_exposure_log2(1., 5., 2., 0.25), 5., 2., 0.25);
+		assert!((halves - adapted).abs() < 1e-5, "{halves} {adapted}");
+	}
+}
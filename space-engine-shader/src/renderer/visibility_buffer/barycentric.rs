@@ -0,0 +1,140 @@
+//! Analytic reconstruction of perspective-correct barycentrics and their screen-space derivatives from the clip-space
+//! positions of a triangle, as described in "The Forge: Triangle Visibility Buffer" by Wolfgang Engel:
+//! <https://github.com/ConfettiFX/The-Forge/blob/master/Examples_3/Visibility_Buffer/src/Shaders/FSL/shading.h.fsl>
+
+use core::ops::{Add, Mul};
+use glam::{UVec2, Vec2, Vec3, Vec4, Vec4Swizzles};
+
+/// Perspective-correct barycentrics of a pixel and how they change when moving one pixel along x or y
+#[derive(Copy, Clone, Debug)]
+pub struct BarycentricDeriv {
+	pub lambda: Vec3,
+	pub ddx: Vec3,
+	pub ddy: Vec3,
+}
+
+/// An attribute interpolated at a pixel and how it changes when moving one pixel along x or y
+#[derive(Copy, Clone, Debug)]
+pub struct InterpolatedDeriv<T> {
+	pub value: T,
+	pub ddx: T,
+	pub ddy: T,
+}
+
+impl BarycentricDeriv {
+	/// Barycentrics of the triangle `clip` at the center of `pixel`, for a viewport of `size`
+	pub fn new(clip: [Vec4; 3], pixel: UVec2, size: UVec2) -> Self {
+		let size = size.as_vec2();
+		let pixel_ndc = (pixel.as_vec2() + 0.5) / size * 2. - 1.;
+		Self::from_ndc(clip, pixel_ndc, size)
+	}
+
+	/// Barycentrics of the triangle `clip` at `pixel_ndc` in normalized device coordinates, for a viewport of `size`.
+	/// Unlike D3D, NDC y and pixel y point in the same direction in Vulkan.
+	pub fn from_ndc(clip: [Vec4; 3], pixel_ndc: Vec2, size: Vec2) -> Self {
+		let inv_w = Vec3::new(clip[0].w, clip[1].w, clip[2].w).recip();
+		let ndc0 = clip[0].xy() * inv_w.x;
+		let ndc1 = clip[1].xy() * inv_w.y;
+		let ndc2 = clip[2].xy() * inv_w.z;
+
+		let inv_det = 1. / (ndc2 - ndc1).perp_dot(ndc0 - ndc1);
+		let ddx = Vec3::new(ndc1.y - ndc2.y, ndc2.y - ndc0.y, ndc0.y - ndc1.y) * inv_det * inv_w;
+		let ddy = Vec3::new(ndc2.x - ndc1.x, ndc0.x - ndc2.x, ndc1.x - ndc0.x) * inv_det * inv_w;
+		let ddx_sum = ddx.element_sum();
+		let ddy_sum = ddy.element_sum();
+
+		let delta = pixel_ndc - ndc0;
+		let interp_inv_w = inv_w.x + delta.x * ddx_sum + delta.y * ddy_sum;
+		let interp_w = 1. / interp_inv_w;
+		let lambda = interp_w * (Vec3::new(inv_w.x, 0., 0.) + delta.x * ddx + delta.y * ddy);
+
+		// scale from NDC to pixels
+		let pixel_scale = 2. / size;
+		let ddx = ddx * pixel_scale.x;
+		let ddy = ddy * pixel_scale.y;
+		let ddx_sum = ddx_sum * pixel_scale.x;
+		let ddy_sum = ddy_sum * pixel_scale.y;
+
+		let interp_w_ddx = 1. / (interp_inv_w + ddx_sum);
+		let interp_w_ddy = 1. / (interp_inv_w + ddy_sum);
+		Self {
+			lambda,
+			ddx: interp_w_ddx * (lambda * interp_inv_w + ddx) - lambda,
+			ddy: interp_w_ddy * (lambda * interp_inv_w + ddy) - lambda,
+		}
+	}
+
+	/// Interpolates the per-vertex `values` of the triangle
+	pub fn interpolate<T>(&self, values: [T; 3]) -> InterpolatedDeriv<T>
+	where
+		T: Copy + Add<Output = T> + Mul<f32, Output = T>,
+	{
+		let weigh = |w: Vec3| values[0] * w.x + values[1] * w.y + values[2] * w.z;
+		InterpolatedDeriv {
+			value: weigh(self.lambda),
+			ddx: weigh(self.ddx),
+			ddy: weigh(self.ddy),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use glam::Mat4;
+
+	fn assert_approx(a: Vec3, b: Vec3) {
+		assert!(a.abs_diff_eq(b, 1e-4), "{a} != {b}");
+	}
+
+	fn triangle() -> [Vec4; 3] {
+		let clip_from_view = Mat4::perspective_rh(1., 1., 0.1, 100.);
+		[
+			Vec4::new(-1., -1., -2., 1.),
+			Vec4::new(2., -0.5, -6., 1.),
+			Vec4::new(0., 1.5, -4., 1.),
+		]
+		.map(|view| clip_from_view * view)
+	}
+
+	#[test]
+	fn test_barycentrics_at_vertices() {
+		let clip = triangle();
+		let size = Vec2::new(800., 600.);
+		for (i, expected) in [Vec3::X, Vec3::Y, Vec3::Z].into_iter().enumerate() {
+			let bary = BarycentricDeriv::from_ndc(clip, clip[i].xy() / clip[i].w, size);
+			assert_approx(bary.lambda, expected);
+		}
+	}
+
+	#[test]
+	fn test_barycentrics_perspective_correct() {
+		let clip = triangle();
+		let size = UVec2::new(800, 600);
+		let pixel = UVec2::new(420, 290);
+		let bary = BarycentricDeriv::new(clip, pixel, size);
+		assert!((bary.lambda.element_sum() - 1.).abs() < 1e-5);
+
+		// the interpolated clip position must project onto the pixel center
+		let pos = bary.interpolate(clip).value;
+		let pixel_ndc = (pixel.as_vec2() + 0.5) / size.as_vec2() * 2. - 1.;
+		assert!((pos.xy() / pos.w).abs_diff_eq(pixel_ndc, 1e-5));
+	}
+
+	#[test]
+	fn test_barycentric_derivatives() {
+		let clip = triangle();
+		let size = UVec2::new(800, 600);
+		let pixel = UVec2::new(420, 290);
+		let bary = BarycentricDeriv::new(clip, pixel, size);
+		let right = BarycentricDeriv::new(clip, pixel + UVec2::X, size);
+		let below = BarycentricDeriv::new(clip, pixel + UVec2::Y, size);
+		assert_approx(bary.ddx, right.lambda - bary.lambda);
+		assert_approx(bary.ddy, below.lambda - bary.lambda);
+
+		let uv = [Vec2::new(0., 0.), Vec2::new(1., 0.), Vec2::new(0., 1.)];
+		let interpolated = bary.interpolate(uv);
+		let right_uv = right.interpolate(uv).value;
+		assert!(interpolated.ddx.abs_diff_eq(right_uv - interpolated.value, 1e-5));
+	}
+}
@@ -0,0 +1,156 @@
+use crate::material::pbr::{PbrMaterialSample, SurfaceLocation, TexCoordGradients};
+use crate::renderer::frame_data::{DebugSettings, FrameData};
+use crate::renderer::lighting::ambient_occlusion::AmbientOcclusion;
+use crate::renderer::lighting::ibl::Ibl;
+use crate::renderer::lighting::light_cluster::LightClusters;
+use crate::renderer::lighting::lighting_compute::shade;
+use crate::renderer::lighting::shadow::ShadowMap;
+use crate::renderer::meshlet::mesh_shader::{load_vertex, meshlet_debug_hue};
+use crate::renderer::taa::motion_vector;
+use crate::renderer::visibility_buffer::barycentric::BarycentricDeriv;
+use crate::renderer::visibility_buffer::software_raster::software_raster_index;
+use crate::renderer::visibility_buffer::{MeshletInstanceBuffers, VisibilityId};
+use glam::{UVec2, UVec3, UVec4, Vec3Swizzles, Vec4};
+use rust_gpu_bindless_macros::{BufferStruct, bindless};
+use rust_gpu_bindless_shaders::descriptor::{
+	Buffer, Descriptors, Image, Image2d, Image2dU, MutImage, Strong, TransientDesc,
+};
+use space_asset_shader::meshlet::mesh::MeshletMesh;
+use space_asset_shader::meshlet::scene::MeshletScene;
+use spirv_std::Sampler;
+use static_assertions::const_assert_eq;
+
+#[derive(Copy, Clone, BufferStruct)]
+pub struct Param<'a> {
+	pub frame_data: TransientDesc<'a, Buffer<FrameData>>,
+	pub scene: TransientDesc<'a, Buffer<MeshletScene<Strong>>>,
+	pub sampler: TransientDesc<'a, Sampler>,
+	/// the meshlet instances drawn by the mesh shader
+	pub meshlet_instances: MeshletInstanceBuffers<'a>,
+	pub visibility_buffer: TransientDesc<'a, Image<Image2dU>>,
+	pub depth_image: TransientDesc<'a, Image<Image2d>>,
+	/// the meshlet instances rasterized in software, only valid if `NaniteSettings::software_rasterization` is enabled
+	pub software_meshlet_instances: MeshletInstanceBuffers<'a>,
+	/// see [`software_raster`](crate::renderer::visibility_buffer::software_raster)
+	pub software_depth: TransientDesc<'a, Buffer<[u32]>>,
+	/// see [`software_raster`](crate::renderer::visibility_buffer::software_raster)
+	pub software_visibility: TransientDesc<'a, Buffer<[u32]>>,
+	pub shadow_map: ShadowMap<'a>,
+	pub light_clusters: LightClusters<'a>,
+	pub ibl: Ibl<'a>,
+	pub ambient_occlusion: AmbientOcclusion<'a>,
+	pub output_image: TransientDesc<'a, MutImage<Image2d>>,
+	/// see [`GBuffer::g_velocity`](crate::renderer::g_buffer::GBuffer::g_velocity)
+	pub g_velocity: TransientDesc<'a, MutImage<Image2d>>,
+}
+
+pub const MATERIAL_PASS_WG_SIZE: UVec2 = UVec2::new(8, 8);
+
+const_assert_eq!(MATERIAL_PASS_WG_SIZE.x, 8);
+const_assert_eq!(MATERIAL_PASS_WG_SIZE.y, 8);
+#[bindless(compute(threads(8, 8)))]
+pub fn visibility_material_cs(
+	#[bindless(descriptors)] descriptors: Descriptors,
+	#[bindless(param)] param: &Param<'static>,
+	#[spirv(global_invocation_id)] inv_id: UVec3,
+) {
+	let frame_data = param.frame_data.access(&descriptors).load();
+	let size = frame_data.camera.viewport_size;
+	let pixel = inv_id.xy();
+	if !(pixel.x < size.x && pixel.y < size.y) {
+		return;
+	}
+
+	#[allow(clippy::useless_conversion)]
+	let visibility_id = VisibilityId(UVec4::from(param.visibility_buffer.access(&descriptors).fetch(pixel)).x);
+	let (visibility_id, meshlet_instances) = if frame_data.nanite.software_rasterization() {
+		// whichever rasterizer drew the triangle closest to the camera wins
+		let index = software_raster_index(pixel, size);
+		let software_depth = f32::from_bits(param.software_depth.access(&descriptors).load(index));
+		#[allow(clippy::useless_conversion)]
+		let depth = Vec4::from(param.depth_image.access(&descriptors).fetch(pixel)).x;
+		if software_depth < depth {
+			let software_id = VisibilityId(param.software_visibility.access(&descriptors).load(index));
+			(software_id, param.software_meshlet_instances)
+		} else {
+			(visibility_id, param.meshlet_instances)
+		}
+	} else {
+		(visibility_id, param.meshlet_instances)
+	};
+	// the sky shader fills empty pixels
+	if visibility_id.is_empty() {
+		return;
+	}
+
+	let meshlet_instance = meshlet_instances
+		.pass(visibility_id.pass())
+		.access(&descriptors)
+		.read(visibility_id.meshlet_instance_id());
+	let scene = param.scene.access(&descriptors).load();
+	let instance = scene
+		.instances
+		.access(&descriptors)
+		.load(meshlet_instance.instance_id as usize);
+	let mesh: MeshletMesh<Strong> = scene
+		.meshes
+		.access(&descriptors)
+		.load(meshlet_instance.mesh_id as usize);
+	let meshlet = mesh.meshlet(&descriptors, meshlet_instance.meshlet_id as usize);
+	let indices = meshlet.load_triangle(&descriptors, visibility_id.triangle_id() as usize);
+
+	let camera = frame_data.camera;
+	let load = |i: u32| load_vertex(&descriptors, camera, instance, &meshlet, i as usize);
+	let (clip0, v0) = load(indices.x);
+	let (clip1, v1) = load(indices.y);
+	let (clip2, v2) = load(indices.z);
+	let bary = BarycentricDeriv::new([clip0, clip1, clip2], pixel, size);
+	let world_pos = bary.interpolate([v0.world_pos, v1.world_pos, v2.world_pos]).value;
+	let prev_world_pos = bary
+		.interpolate([v0.prev_world_pos, v1.prev_world_pos, v2.prev_world_pos])
+		.value;
+	let tex_coord = bary.interpolate([v0.tex_coord, v1.tex_coord, v2.tex_coord]);
+	let tex_coord_1 = bary.interpolate([v0.tex_coord_1, v1.tex_coord_1, v2.tex_coord_1]);
+	let gradients = TexCoordGradients {
+		tex_coord_ddx: tex_coord.ddx,
+		tex_coord_ddy: tex_coord.ddy,
+		tex_coord_1_ddx: tex_coord_1.ddx,
+		tex_coord_1_ddy: tex_coord_1.ddy,
+	};
+
+	let loc = SurfaceLocation::new(
+		world_pos,
+		camera.view_from_world.translation(),
+		bary.interpolate([v0.normal, v1.normal, v2.normal]).value,
+		bary.interpolate([v0.tangent, v1.tangent, v2.tangent]).value,
+		tex_coord.value,
+		tex_coord_1.value,
+		bary.interpolate([v0.color, v1.color, v2.color]).value,
+	);
+	let mut sampled = mesh
+		.pbr_material
+		.sample(&descriptors, param.sampler.access(&descriptors), loc, gradients);
+	if frame_data.debug_settings() == DebugSettings::VertexNormals {
+		sampled.normal = loc.vertex_normal.normalize()
+	}
+	sampled.occlusion *= param.ambient_occlusion.visibility(&descriptors, frame_data, pixel);
+
+	let debug_hue = meshlet_debug_hue(frame_data, meshlet_instance, &meshlet, visibility_id.triangle_id());
+	let out_color = shade(
+		&descriptors,
+		frame_data,
+		param.shadow_map,
+		param.light_clusters,
+		param.ibl,
+		sampled,
+		debug_hue,
+	);
+	let velocity = motion_vector(camera, frame_data.prev_camera, world_pos, prev_world_pos);
+	unsafe {
+		param.output_image.access(&descriptors).write(pixel, out_color);
+		param
+			.g_velocity
+			.access(&descriptors)
+			.write(pixel, Vec4::from((velocity, 0., 0.)));
+	}
+}
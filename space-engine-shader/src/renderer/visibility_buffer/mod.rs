@@ -0,0 +1,94 @@
+//! Visibility buffer rendering: Instead of writing the sampled material into a g-buffer, the mesh shader only writes
+//! which triangle covers a pixel as a [`VisibilityId`]. A compute pass then reconstructs the triangle's attributes,
+//! samples its material and shades the pixel, so material sampling happens exactly once per pixel.
+
+use crate::renderer::compacting_alloc_buffer::CompactingAllocBufferReader;
+use crate::renderer::meshlet::intermediate::MeshletInstance;
+use crate::renderer::meshlet::meshlet_select::MeshletSelectPass;
+use rust_gpu_bindless_macros::BufferStruct;
+use space_asset_shader::meshlet::MESHLET_MAX_TRIANGLES;
+use static_assertions::const_assert;
+
+pub mod barycentric;
+pub mod material_pass;
+pub mod software_raster;
+
+/// Identifies the triangle covering a pixel, packed into a single `u32`:
+/// * bit 31: the [`MeshletSelectPass`] that drew the triangle, selecting the meshlet instance buffer
+/// * bits 7..31: the index of the meshlet instance within that pass's meshlet instance buffer
+/// * bits 0..7: the triangle within the meshlet
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct VisibilityId(pub u32);
+
+const_assert!(MESHLET_MAX_TRIANGLES <= VisibilityId::TRIANGLE_MASK + 1);
+
+impl VisibilityId {
+	/// The value the visibility buffer is cleared with, for pixels not covered by any triangle
+	pub const EMPTY: Self = Self(!0);
+	const TRIANGLE_BITS: u32 = 7;
+	const TRIANGLE_MASK: u32 = (1 << Self::TRIANGLE_BITS) - 1;
+	const PASS_SHIFT: u32 = 31;
+	const MESHLET_INSTANCE_MASK: u32 = (1 << (Self::PASS_SHIFT - Self::TRIANGLE_BITS)) - 1;
+	/// Meshlet instance buffers may not hold more meshlet instances than this
+	pub const MAX_MESHLET_INSTANCES: u32 = Self::MESHLET_INSTANCE_MASK;
+
+	pub fn new(pass: MeshletSelectPass, meshlet_instance_id: u32, triangle_id: u32) -> Self {
+		Self(
+			(u32::from(pass) << Self::PASS_SHIFT)
+				| ((meshlet_instance_id & Self::MESHLET_INSTANCE_MASK) << Self::TRIANGLE_BITS)
+				| (triangle_id & Self::TRIANGLE_MASK),
+		)
+	}
+
+	pub fn is_empty(&self) -> bool {
+		*self == Self::EMPTY
+	}
+
+	pub fn pass(&self) -> MeshletSelectPass {
+		MeshletSelectPass::try_from(self.0 >> Self::PASS_SHIFT).unwrap_or(MeshletSelectPass::First)
+	}
+
+	pub fn meshlet_instance_id(&self) -> u32 {
+		(self.0 >> Self::TRIANGLE_BITS) & Self::MESHLET_INSTANCE_MASK
+	}
+
+	pub fn triangle_id(&self) -> u32 {
+		self.0 & Self::TRIANGLE_MASK
+	}
+}
+
+/// The meshlet instances of both [`MeshletSelectPass`]es, which a [`VisibilityId`] refers to
+#[derive(Copy, Clone, BufferStruct)]
+pub struct MeshletInstanceBuffers<'a> {
+	pub first: CompactingAllocBufferReader<'a, MeshletInstance>,
+	pub second: CompactingAllocBufferReader<'a, MeshletInstance>,
+}
+
+impl<'a> MeshletInstanceBuffers<'a> {
+	pub fn pass(&self, pass: MeshletSelectPass) -> CompactingAllocBufferReader<'a, MeshletInstance> {
+		match pass {
+			MeshletSelectPass::First => self.first,
+			MeshletSelectPass::Second => self.second,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_visibility_id_roundtrip() {
+		for pass in [MeshletSelectPass::First, MeshletSelectPass::Second] {
+			for meshlet_instance_id in [0, 1, 42, VisibilityId::MAX_MESHLET_INSTANCES - 1] {
+				for triangle_id in [0, 1, MESHLET_MAX_TRIANGLES - 1] {
+					let id = VisibilityId::new(pass, meshlet_instance_id, triangle_id);
+					assert!(!id.is_empty());
+					assert_eq!(id.pass(), pass);
+					assert_eq!(id.meshlet_instance_id(), meshlet_instance_id);
+					assert_eq!(id.triangle_id(), triangle_id);
+				}
+			}
+		}
+	}
+}
@@ -0,0 +1,460 @@
+//! Software rasterization of meshlets whose triangles are only a few pixels large on screen, which hardware rasterizers
+//! process inefficiently as they shade pixels in 2x2 quads and set up triangles at a fixed rate. Each workgroup
+//! rasterizes one meshlet instance, each invocation its own triangles.
+//!
+//! Depth and [`VisibilityId`] would have to be written together with a single 64-bit atomic, which bindless buffers
+//! and images do not support. Instead, triangles are rasterized twice: [`software_raster_depth_cs`] writes the nearest
+//! depth of each pixel with an atomic min, then [`software_raster_visibility_cs`] writes the [`VisibilityId`] of the
+//! triangles whose depth matches the nearest depth. The material pass then picks whichever of the hardware and the
+//! software rasterized triangle is closer to the camera.
+
+use crate::renderer::camera::Camera;
+use crate::renderer::compacting_alloc_buffer::CompactingAllocBufferReader;
+use crate::renderer::frame_data::FrameData;
+use crate::renderer::meshlet::intermediate::MeshletInstance;
+use crate::renderer::meshlet::meshlet_select::{MeshletSelectPass, ScreenRect};
+use crate::renderer::visibility_buffer::VisibilityId;
+use glam::{IVec2, UVec2, UVec3, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles};
+use rust_gpu_bindless_macros::{BufferStruct, bindless};
+use rust_gpu_bindless_shaders::descriptor::{AliveDescRef, Buffer, Descriptors, MutBuffer, Strong, TransientDesc};
+use space_asset_shader::affine_transform::AffineTransform;
+use space_asset_shader::meshlet::mesh::{MeshletMesh, MeshletReader};
+use space_asset_shader::meshlet::scene::MeshletScene;
+use spirv_std::arch::{IndexUnchecked, atomic_exchange, atomic_u_min};
+use spirv_std::memory::{Scope, Semantics};
+#[cfg(target_arch = "spirv")]
+use spirv_std::num_traits::Float;
+use static_assertions::const_assert_eq;
+
+/// Vertices are snapped to 1/16th of a pixel
+pub const SUBPIXEL_BITS: u32 = 4;
+const SUBPIXEL_SCALE: i32 = 1 << SUBPIXEL_BITS;
+
+/// Meshlets are only rasterized in software if their projected bounds are at most this many pixels wide and tall. That
+/// bounds the number of pixels a triangle may cover and keeps its edge functions exact in 32-bit integers.
+pub const SOFTWARE_RASTER_MAX_EXTENT: u32 = 64;
+
+/// Whether a meshlet of `triangle_count` triangles covering the screen `rect` should be rasterized in software, for a
+/// viewport of `size`. The triangle size is estimated as if the triangles were evenly spread across the rect.
+pub fn is_software_rasterized(rect: ScreenRect, size: UVec2, triangle_count: u32, threshold: f32) -> bool {
+	let extent = (rect.max - rect.min) * size.as_vec2();
+	let extent = f32::max(extent.x, extent.y);
+	extent <= SOFTWARE_RASTER_MAX_EXTENT as f32 && extent / f32::sqrt(triangle_count as f32) <= threshold
+}
+
+/// A triangle snapped to subpixel precision, ready to be tested against pixel centers
+#[derive(Copy, Clone, Debug)]
+pub struct TriangleSetup {
+	/// first pixel of the bounds, clamped to the viewport
+	pub min: UVec2,
+	/// last pixel of the bounds (inclusive), clamped to the viewport
+	pub max: UVec2,
+	v0: IVec2,
+	v1: IVec2,
+	v2: IVec2,
+	/// depth of each vertex
+	depth: Vec3,
+	/// twice the area in subpixels, always positive
+	area: i32,
+}
+
+impl TriangleSetup {
+	/// Sets up the triangle of the clip space positions `clip` for a viewport of `size`. Returns `None` if the triangle
+	/// covers no pixel centers, crosses the near plane or its bounds exceed [`SOFTWARE_RASTER_MAX_EXTENT`].
+	pub fn new(clip: [Vec4; 3], size: UVec2) -> Option<Self> {
+		if !(clip[0].w > 0. && clip[1].w > 0. && clip[2].w > 0.) {
+			return None;
+		}
+		let snap = |clip: Vec4| {
+			let subpixel = (clip.xy() / clip.w * 0.5 + 0.5) * size.as_vec2() * SUBPIXEL_SCALE as f32;
+			IVec2::new(f32::round(subpixel.x) as i32, f32::round(subpixel.y) as i32)
+		};
+		let v0 = snap(clip[0]);
+		let v1 = snap(clip[1]);
+		let v2 = snap(clip[2]);
+
+		let min = v0.min(v1).min(v2);
+		let max = v0.max(v1).max(v2);
+		let max_extent = (SOFTWARE_RASTER_MAX_EXTENT as i32) * SUBPIXEL_SCALE;
+		if (max - min).max_element() > max_extent {
+			return None;
+		}
+		// pixels whose centers lie within the bounds, rounding down with arithmetic shifts
+		let half = SUBPIXEL_SCALE / 2;
+		let min = ((min - half + SUBPIXEL_SCALE - 1) >> SUBPIXEL_BITS).max(IVec2::ZERO);
+		let max = ((max - half) >> SUBPIXEL_BITS).min(size.as_ivec2() - 1);
+		if min.x > max.x || min.y > max.y {
+			return None;
+		}
+
+		let depth = Vec3::new(clip[0].z / clip[0].w, clip[1].z / clip[1].w, clip[2].z / clip[2].w);
+		let area = (v1 - v0).perp_dot(v2 - v0);
+		// flip the winding of back facing triangles, so their edge functions are positive inside as well
+		let (v1, v2, depth, area) = if area < 0 {
+			(v2, v1, depth.xzy(), -area)
+		} else {
+			(v1, v2, depth, area)
+		};
+		if area == 0 {
+			return None;
+		}
+		Some(Self {
+			min: min.as_uvec2(),
+			max: max.as_uvec2(),
+			v0,
+			v1,
+			v2,
+			depth,
+			area,
+		})
+	}
+
+	/// The depth of the triangle at the center of `pixel`, or `None` if the triangle doesn't cover it.
+	///
+	/// Pixel centers lying exactly on an edge are only covered if the edge is a top or left edge, so that a pixel on an
+	/// edge shared by two triangles is covered by exactly one of them.
+	pub fn coverage(&self, pixel: UVec2) -> Option<f32> {
+		let p = pixel.as_ivec2() * SUBPIXEL_SCALE + SUBPIXEL_SCALE / 2;
+		let w0 = edge_function(self.v1, self.v2, p);
+		let w1 = edge_function(self.v2, self.v0, p);
+		let w2 = edge_function(self.v0, self.v1, p);
+		if is_inside(w0, self.v2 - self.v1) && is_inside(w1, self.v0 - self.v2) && is_inside(w2, self.v1 - self.v0) {
+			let lambda = Vec3::new(w0 as f32, w1 as f32, w2 as f32) / self.area as f32;
+			Some(lambda.dot(self.depth))
+		} else {
+			None
+		}
+	}
+}
+
+/// Twice the signed area of the triangle `a`, `b`, `p`, positive if `p` lies left of the edge from `a` to `b`
+fn edge_function(a: IVec2, b: IVec2, p: IVec2) -> i32 {
+	(b - a).perp_dot(p - a)
+}
+
+/// The top-left fill rule: for any edge shared by two triangles, exactly one of them sees it in a direction `edge` this
+/// accepts
+fn is_inside(w: i32, edge: IVec2) -> bool {
+	w > 0 || (w == 0 && (edge.y > 0 || (edge.y == 0 && edge.x < 0)))
+}
+
+/// Sets up `triangle` of a meshlet drawn with `camera`
+pub fn load_triangle_setup<R: AliveDescRef>(
+	descriptors: &Descriptors,
+	camera: Camera,
+	world_from_local: AffineTransform,
+	meshlet: &MeshletReader<R>,
+	triangle: usize,
+) -> Option<TriangleSetup> {
+	let indices = meshlet.load_triangle(descriptors, triangle);
+	let load = |i: u32| {
+		let vertex = meshlet.load_draw_vertex(descriptors, i as usize);
+		camera.transform_vertex(world_from_local, vertex.position).clip_space
+	};
+	TriangleSetup::new(
+		[load(indices.x), load(indices.y), load(indices.z)],
+		camera.viewport_size,
+	)
+}
+
+/// The software rasterized depth as `f32` bits. Depth is never negative, so comparing the bits as `u32` orders them
+/// like the depth itself.
+pub fn software_depth_bits(depth: f32) -> u32 {
+	depth.to_bits()
+}
+
+/// The value the software depth buffer is cleared with
+pub const SOFTWARE_DEPTH_CLEAR: f32 = 1.;
+
+/// Index of `pixel` within the software depth and visibility buffers
+pub fn software_raster_index(pixel: UVec2, size: UVec2) -> usize {
+	(pixel.y * size.x + pixel.x) as usize
+}
+
+#[derive(Copy, Clone, BufferStruct)]
+pub struct DepthParam<'a> {
+	pub frame_data: TransientDesc<'a, Buffer<FrameData>>,
+	pub scene: TransientDesc<'a, Buffer<MeshletScene<Strong>>>,
+	pub meshlet_instances: CompactingAllocBufferReader<'a, MeshletInstance>,
+	/// `f32` depth bits per pixel, see [`software_depth_bits`]
+	pub depth: TransientDesc<'a, MutBuffer<[u32]>>,
+}
+
+#[derive(Copy, Clone, BufferStruct)]
+pub struct VisibilityParam<'a> {
+	pub frame_data: TransientDesc<'a, Buffer<FrameData>>,
+	pub scene: TransientDesc<'a, Buffer<MeshletScene<Strong>>>,
+	pub meshlet_instances: CompactingAllocBufferReader<'a, MeshletInstance>,
+	/// [`MeshletSelectPass`] the meshlets were selected in, see [`Self::pass`]
+	pub pass: u32,
+	/// `f32` depth bits per pixel, see [`software_depth_bits`]
+	pub depth: TransientDesc<'a, Buffer<[u32]>>,
+	/// [`VisibilityId`] per pixel
+	pub visibility: TransientDesc<'a, MutBuffer<[u32]>>,
+}
+
+impl VisibilityParam<'_> {
+	pub fn pass(&self) -> MeshletSelectPass {
+		MeshletSelectPass::try_from(self.pass).unwrap_or(MeshletSelectPass::First)
+	}
+}
+
+pub const SOFTWARE_RASTER_WG_SIZE: u32 = 32;
+
+const_assert_eq!(SOFTWARE_RASTER_WG_SIZE, 32);
+#[bindless(compute(threads(32)))]
+pub fn software_raster_depth_cs(
+	#[bindless(descriptors)] mut descriptors: Descriptors,
+	#[bindless(param)] param: &DepthParam<'static>,
+	#[spirv(workgroup_id)] wg_id: UVec3,
+	#[spirv(local_invocation_id)] inv_id: UVec3,
+) {
+	let frame_data = param.frame_data.access(&descriptors).load();
+	let camera = frame_data.camera;
+	let scene = param.scene.access(&descriptors).load();
+	let meshlet_instance = param.meshlet_instances.access(&descriptors).read(wg_id.x);
+	let instance = scene
+		.instances
+		.access(&descriptors)
+		.load(meshlet_instance.instance_id as usize);
+	let mesh: MeshletMesh<Strong> = scene
+		.meshes
+		.access(&descriptors)
+		.load(meshlet_instance.mesh_id as usize);
+	let meshlet = mesh.meshlet(&descriptors, meshlet_instance.meshlet_id as usize);
+
+	let mut triangle = inv_id.x as usize;
+	while triangle < meshlet.triangles() {
+		let setup = load_triangle_setup(&descriptors, camera, instance.world_from_local, &meshlet, triangle);
+		if let Some(setup) = setup {
+			for y in setup.min.y..setup.max.y + 1 {
+				for x in setup.min.x..setup.max.x + 1 {
+					let pixel = UVec2::new(x, y);
+					if let Some(depth) = setup.coverage(pixel) {
+						let index = software_raster_index(pixel, camera.viewport_size);
+						// Safety: panics within loops miscompile, pixel is within the viewport
+						unsafe {
+							let depth_buffer = param.depth.access(&mut descriptors).into_raw_mut();
+							atomic_u_min::<_, { Scope::QueueFamily as u32 }, { Semantics::NONE.bits() }>(
+								depth_buffer.index_unchecked_mut(index),
+								software_depth_bits(depth),
+							);
+						}
+					}
+				}
+			}
+		}
+		triangle += SOFTWARE_RASTER_WG_SIZE as usize;
+	}
+}
+
+const_assert_eq!(SOFTWARE_RASTER_WG_SIZE, 32);
+#[bindless(compute(threads(32)))]
+pub fn software_raster_visibility_cs(
+	#[bindless(descriptors)] mut descriptors: Descriptors,
+	#[bindless(param)] param: &VisibilityParam<'static>,
+	#[spirv(workgroup_id)] wg_id: UVec3,
+	#[spirv(local_invocation_id)] inv_id: UVec3,
+) {
+	let meshlet_instance_id = wg_id.x;
+	let frame_data = param.frame_data.access(&descriptors).load();
+	let camera = frame_data.camera;
+	let scene = param.scene.access(&descriptors).load();
+	let meshlet_instance = param.meshlet_instances.access(&descriptors).read(meshlet_instance_id);
+	let instance = scene
+		.instances
+		.access(&descriptors)
+		.load(meshlet_instance.instance_id as usize);
+	let mesh: MeshletMesh<Strong> = scene
+		.meshes
+		.access(&descriptors)
+		.load(meshlet_instance.mesh_id as usize);
+	let meshlet = mesh.meshlet(&descriptors, meshlet_instance.meshlet_id as usize);
+
+	let mut triangle = inv_id.x as usize;
+	while triangle < meshlet.triangles() {
+		let setup = load_triangle_setup(&descriptors, camera, instance.world_from_local, &meshlet, triangle);
+		if let Some(setup) = setup {
+			let visibility_id = VisibilityId::new(param.pass(), meshlet_instance_id, triangle as u32);
+			for y in setup.min.y..setup.max.y + 1 {
+				for x in setup.min.x..setup.max.x + 1 {
+					let pixel = UVec2::new(x, y);
+					if let Some(depth) = setup.coverage(pixel) {
+						let index = software_raster_index(pixel, camera.viewport_size);
+						// Safety: panics within loops miscompile, pixel is within the viewport
+						unsafe {
+							let nearest = param.depth.access(&descriptors).load_unchecked(index);
+							if nearest == software_depth_bits(depth) {
+								// any of multiple triangles with exactly the same depth may win
+								let visibility_buffer = param.visibility.access(&mut descriptors).into_raw_mut();
+								atomic_exchange::<_, { Scope::QueueFamily as u32 }, { Semantics::NONE.bits() }>(
+									visibility_buffer.index_unchecked_mut(index),
+									visibility_id.0,
+								);
+							}
+						}
+					}
+				}
+			}
+		}
+		triangle += SOFTWARE_RASTER_WG_SIZE as usize;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use glam::{I64Vec2, Vec2};
+
+	const SIZE: UVec2 = UVec2::new(32, 24);
+
+	/// clip space position of a point given in pixels
+	fn clip(x: f32, y: f32, depth: f32) -> Vec4 {
+		let ndc = Vec2::new(x, y) / SIZE.as_vec2() * 2. - 1.;
+		Vec4::new(ndc.x, ndc.y, depth, 1.) * 2.
+	}
+
+	/// Tests every pixel center of the viewport against the triangle in 64-bit integers, without any bounds or winding
+	/// normalization, returning the covered pixels and their depth
+	fn reference_raster(clip: [Vec4; 3]) -> Vec<(UVec2, f64)> {
+		let snap = |c: Vec4| {
+			let uv = (c.xy() / c.w).as_dvec2() * 0.5 + 0.5;
+			(uv * SIZE.as_dvec2() * SUBPIXEL_SCALE as f64).round().as_i64vec2()
+		};
+		let v = clip.map(snap);
+		let z = clip.map(|c| (c.z / c.w) as f64);
+		let area = (v[1] - v[0]).perp_dot(v[2] - v[0]);
+		let sign = area.signum();
+		let mut covered = Vec::new();
+		if area == 0 {
+			return covered;
+		}
+		for y in 0..SIZE.y {
+			for x in 0..SIZE.x {
+				let p = I64Vec2::new(x as i64, y as i64) * SUBPIXEL_SCALE as i64 + SUBPIXEL_SCALE as i64 / 2;
+				let mut lambda = [0.; 3];
+				let mut inside = true;
+				for i in 0..3 {
+					let (a, b) = (v[(i + 1) % 3], v[(i + 2) % 3]);
+					let w = (b - a).perp_dot(p - a) * sign;
+					// the edge as seen by the triangle with positive winding
+					let edge = (b - a) * sign;
+					let top_left = edge.y > 0 || (edge.y == 0 && edge.x < 0);
+					inside &= w > 0 || (w == 0 && top_left);
+					lambda[i] = w as f64 / (area * sign) as f64;
+				}
+				if inside {
+					let depth = lambda[0] * z[0] + lambda[1] * z[1] + lambda[2] * z[2];
+					covered.push((UVec2::new(x, y), depth));
+				}
+			}
+		}
+		covered
+	}
+
+	fn raster(clip: [Vec4; 3]) -> Vec<(UVec2, f32)> {
+		let mut covered = Vec::new();
+		if let Some(setup) = TriangleSetup::new(clip, SIZE) {
+			for y in setup.min.y..=setup.max.y {
+				for x in setup.min.x..=setup.max.x {
+					let pixel = UVec2::new(x, y);
+					if let Some(depth) = setup.coverage(pixel) {
+						covered.push((pixel, depth));
+					}
+				}
+			}
+		}
+		covered
+	}
+
+	fn assert_matches_reference(clip: [Vec4; 3]) {
+		let reference = reference_raster(clip);
+		let covered = raster(clip);
+		assert_eq!(
+			covered.iter().map(|(p, _)| *p).collect::<Vec<_>>(),
+			reference.iter().map(|(p, _)| *p).collect::<Vec<_>>(),
+			"{clip:?}"
+		);
+		for ((_, depth), (_, expected)) in covered.iter().zip(reference.iter()) {
+			assert!((*depth as f64 - expected).abs() < 1e-5, "{depth} != {expected}");
+		}
+	}
+
+	#[test]
+	fn test_matches_reference_raster() {
+		let triangles = [
+			[clip(2., 3., 0.2), clip(12.5, 4.25, 0.4), clip(5.1, 14.9, 0.6)],
+			// back facing
+			[clip(2., 3., 0.2), clip(5.1, 14.9, 0.6), clip(12.5, 4.25, 0.4)],
+			// edges through pixel centers
+			[clip(4.5, 4.5, 0.5), clip(10.5, 4.5, 0.5), clip(4.5, 10.5, 0.5)],
+			// partially outside the viewport
+			[clip(-3., -2., 0.1), clip(9., 1., 0.9), clip(2., 7.3, 0.5)],
+			[clip(28., 20., 0.3), clip(40., 22., 0.3), clip(30., 30., 0.7)],
+			// thin sliver
+			[clip(1., 1., 0.5), clip(30., 2., 0.5), clip(1.2, 1.3, 0.5)],
+			// tiny, covering at most one pixel center
+			[clip(7.4, 7.4, 0.5), clip(7.7, 7.45, 0.5), clip(7.5, 7.8, 0.5)],
+		];
+		for triangle in triangles {
+			assert_matches_reference(triangle);
+		}
+	}
+
+	#[test]
+	fn test_rejects_triangles() {
+		// degenerate
+		assert!(TriangleSetup::new([clip(1., 1., 0.5), clip(5., 5., 0.5), clip(9., 9., 0.5)], SIZE).is_none());
+		// entirely outside the viewport
+		assert!(TriangleSetup::new([clip(-9., 1., 0.5), clip(-5., 5., 0.5), clip(-2., 1., 0.5)], SIZE).is_none());
+		// crossing the near plane
+		let behind = Vec4::new(0., 0., 0.5, -1.);
+		assert!(TriangleSetup::new([clip(1., 1., 0.5), clip(5., 1., 0.5), behind], SIZE).is_none());
+		// too large
+		let large = SOFTWARE_RASTER_MAX_EXTENT as f32 + 1.;
+		assert!(TriangleSetup::new([clip(0., 0., 0.5), clip(large, 0., 0.5), clip(0., 5., 0.5)], SIZE).is_none());
+	}
+
+	#[test]
+	fn test_shared_edges_watertight() {
+		// a fan of triangles around a center on a pixel center, with edges running through other pixel centers
+		let center = (15.5, 11.5);
+		let ring = [
+			(3.5, 1.5),
+			(15.5, 0.5),
+			(29.5, 2.5),
+			(30.5, 11.5),
+			(27.5, 22.5),
+			(15.5, 21.5),
+			(2.5, 23.),
+			(0.5, 11.5),
+		];
+		let mut count = vec![0; (SIZE.x * SIZE.y) as usize];
+		for i in 0..ring.len() {
+			let (a, b) = (ring[i], ring[(i + 1) % ring.len()]);
+			let triangle = [clip(center.0, center.1, 0.5), clip(a.0, a.1, 0.5), clip(b.0, b.1, 0.5)];
+			for (pixel, _) in raster(triangle) {
+				count[software_raster_index(pixel, SIZE)] += 1;
+			}
+		}
+		assert!(count.iter().all(|c| *c <= 1), "pixels covered more than once");
+		assert_eq!(count[software_raster_index(UVec2::new(15, 11), SIZE)], 1);
+		assert_eq!(count[software_raster_index(UVec2::new(15, 4), SIZE)], 1);
+		assert_eq!(count[software_raster_index(UVec2::new(6, 11), SIZE)], 1);
+	}
+
+	#[test]
+	fn test_is_software_rasterized() {
+		let size = UVec2::new(1000, 1000);
+		let rect = |extent: f32| ScreenRect {
+			min: Vec2::splat(0.5),
+			max: Vec2::splat(0.5 + extent / 1000.),
+			depth: 0.5,
+		};
+		// 124 triangles across 20 pixels are about 1.8 pixels large
+		assert!(is_software_rasterized(rect(20.), size, 124, 2.));
+		assert!(!is_software_rasterized(rect(20.), size, 124, 1.));
+		assert!(!is_software_rasterized(rect(20.), size, 4, 2.));
+		assert!(!is_software_rasterized(rect(100.), size, 124, 100.));
+	}
+}
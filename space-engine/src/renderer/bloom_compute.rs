@@ -0,0 +1,174 @@
+use crate::renderer::frame_context::FrameContext;
+use glam::UVec2;
+use rust_gpu_bindless::descriptor::{
+	AddressMode, Bindless, BindlessImageCreateInfo, BindlessImageUsage, BindlessSamplerCreateInfo, Filter, Format,
+	Image, Image2d, MutDesc, MutImage, RCDesc, RCDescExt, Sampler, TransientDesc,
+};
+use rust_gpu_bindless::pipeline::{
+	AccessError, BindlessComputePipeline, MutImageAccess, MutImageAccessExt, Recording, RecordingError, SampledRead,
+	StorageReadWrite,
+};
+use space_engine_shader::renderer::bloom::{
+	BLOOM_LEVELS, BLOOM_WG_SIZE, Bloom, DownsampleParam, UpsampleParam, bloom_level_size,
+};
+
+pub const BLOOM_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+/// The downsampled and upsampled chains of the bloom, see [`space_engine_shader::renderer::bloom`]
+pub struct BloomImages {
+	/// [`BLOOM_LEVELS`] images, each half the size of the previous one
+	down: Vec<MutDesc<MutImage<Image2d>>>,
+	/// the same sizes as `down`, without the smallest level
+	up: Vec<MutDesc<MutImage<Image2d>>>,
+}
+
+impl BloomImages {
+	/// Allocates the images for an HDR image of `size`
+	pub fn new(bindless: &Bindless, size: UVec2) -> anyhow::Result<Self> {
+		let alloc = |level: u32, name: &str| {
+			bindless.image().alloc(&BindlessImageCreateInfo {
+				format: BLOOM_FORMAT,
+				extent: bloom_level_size(size, level).into(),
+				usage: BindlessImageUsage::STORAGE | BindlessImageUsage::SAMPLED,
+				name,
+				..Default::default()
+			})
+		};
+		Ok(Self {
+			down: (0..BLOOM_LEVELS)
+				.map(|level| alloc(level, &format!("bloom_down_{level}")))
+				.collect::<Result<_, _>>()?,
+			up: (0..BLOOM_LEVELS - 1)
+				.map(|level| alloc(level, &format!("bloom_up_{level}")))
+				.collect::<Result<_, _>>()?,
+		})
+	}
+}
+
+pub struct BloomAccess<'a> {
+	down: Vec<MutImageAccess<'a, Image2d, SampledRead>>,
+	up: Vec<MutImageAccess<'a, Image2d, SampledRead>>,
+}
+
+impl BloomAccess<'_> {
+	pub fn to_bloom<'b>(&'b self, sampler: TransientDesc<'b, Sampler>) -> Result<Bloom<'b>, AccessError> {
+		Ok(Bloom {
+			image: self.up[0].to_transient_sampled()?,
+			sampler,
+		})
+	}
+
+	pub fn into_images(self) -> BloomImages {
+		BloomImages {
+			down: self.down.into_iter().map(|image| image.into_desc()).collect(),
+			up: self.up.into_iter().map(|image| image.into_desc()).collect(),
+		}
+	}
+}
+
+pub struct BloomCompute {
+	downsample: BindlessComputePipeline<DownsampleParam<'static>>,
+	upsample: BindlessComputePipeline<UpsampleParam<'static>>,
+	sampler: RCDesc<Sampler>,
+}
+
+impl BloomCompute {
+	pub fn new(bindless: &Bindless) -> anyhow::Result<Self> {
+		Ok(Self {
+			downsample: bindless.create_compute_pipeline(crate::shader::renderer::bloom::bloom_downsample_cs::new())?,
+			upsample: bindless.create_compute_pipeline(crate::shader::renderer::bloom::bloom_upsample_cs::new())?,
+			sampler: bindless.sampler().alloc(&BindlessSamplerCreateInfo {
+				min_filter: Filter::Linear,
+				mag_filter: Filter::Linear,
+				address_mode_u: AddressMode::ClampToEdge,
+				address_mode_v: AddressMode::ClampToEdge,
+				address_mode_w: AddressMode::ClampToEdge,
+				..BindlessSamplerCreateInfo::default()
+			})?,
+		})
+	}
+
+	/// The sampler the [`Bloom`] is sampled with
+	pub fn sampler(&self) -> &RCDesc<Sampler> {
+		&self.sampler
+	}
+
+	/// Downsamples the `hdr` image down the chain of `images` and upsamples it back up, if bloom is enabled
+	pub fn dispatch<'a>(
+		&self,
+		cmd: &mut Recording<'a>,
+		frame_context: &FrameContext,
+		hdr: TransientDesc<Image<Image2d>>,
+		images: BloomImages,
+	) -> Result<BloomAccess<'a>, RecordingError> {
+		profiling::function_scope!();
+		if !frame_context.frame_data.bloom.enabled() {
+			// never sampled, as bloom is disabled
+			return Ok(BloomAccess {
+				down: images
+					.down
+					.into_iter()
+					.map(|image| image.access_dont_care(cmd))
+					.collect::<Result<_, _>>()?,
+				up: images
+					.up
+					.into_iter()
+					.map(|image| image.access_dont_care(cmd))
+					.collect::<Result<_, _>>()?,
+			});
+		}
+
+		let image_size = frame_context.frame_data.camera.viewport_size;
+		let groups = |size: UVec2| [size.x.div_ceil(BLOOM_WG_SIZE.x), size.y.div_ceil(BLOOM_WG_SIZE.y), 1];
+		let sampler = self.sampler.to_transient(cmd);
+
+		let mut down: Vec<MutImageAccess<'a, Image2d, SampledRead>> = Vec::with_capacity(BLOOM_LEVELS as usize);
+		for (level, image) in (0..BLOOM_LEVELS).zip(images.down) {
+			let output = image.access_dont_care::<StorageReadWrite>(cmd)?;
+			let output_size = bloom_level_size(image_size, level);
+			let (input, input_size) = match down.last() {
+				Some(prev) => (prev.to_transient_sampled()?, bloom_level_size(image_size, level - 1)),
+				None => (hdr, image_size),
+			};
+			cmd.dispatch(
+				&self.downsample,
+				groups(output_size),
+				DownsampleParam {
+					input,
+					input_size,
+					sampler,
+					karis_average: (level == 0) as u32,
+					output: output.to_mut_transient(),
+					output_size,
+				},
+			)?;
+			down.push(output.transition()?);
+		}
+
+		// upsampled from the smallest level up, so `up` is in reverse order until the end
+		let mut up: Vec<MutImageAccess<'a, Image2d, SampledRead>> = Vec::with_capacity(BLOOM_LEVELS as usize - 1);
+		for (level, image) in (0..BLOOM_LEVELS - 1).zip(images.up).rev() {
+			let output = image.access_dont_care::<StorageReadWrite>(cmd)?;
+			let output_size = bloom_level_size(image_size, level);
+			let input = match up.last() {
+				Some(prev) => prev.to_transient_sampled()?,
+				None => down[level as usize + 1].to_transient_sampled()?,
+			};
+			cmd.dispatch(
+				&self.upsample,
+				groups(output_size),
+				UpsampleParam {
+					input,
+					input_size: bloom_level_size(image_size, level + 1),
+					sampler,
+					downsampled: down[level as usize].to_transient_sampled()?,
+					output: output.to_mut_transient(),
+					output_size,
+				},
+			)?;
+			up.push(output.transition()?);
+		}
+		up.reverse();
+		Ok(BloomAccess { down, up })
+	}
+}
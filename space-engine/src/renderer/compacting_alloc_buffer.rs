@@ -1,6 +1,6 @@
 use rust_gpu_bindless::descriptor::{
-	Bindless, BindlessAllocationScheme, BindlessBufferCreateInfo, BindlessBufferUsage, Buffer, MutBuffer, MutDesc,
-	RCDesc,
+	Bindless, BindlessAllocationScheme, BindlessBufferCreateInfo, BindlessBufferUsage, Buffer, DescBufferLenExt,
+	MutBuffer, MutDesc, RCDesc,
 };
 use rust_gpu_bindless::pipeline::{
 	AccessError, GeneralRead, MutBufferAccess, MutBufferAccessExt, Recording, RecordingError, ShaderRead,
@@ -11,27 +11,38 @@ use space_engine_shader::renderer::compacting_alloc_buffer::{
 	CompactingAllocBufferReader, CompactingAllocBufferWriter,
 };
 
-pub struct CompactingAllocBuffer<T: BufferStructPlain> {
-	buffer: MutDesc<MutBuffer<[T]>>,
+/// The range of a buffer a [`CompactingAllocBuffer`] allocates from, allowing multiple of them to share a buffer
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct CompactingAllocPartition {
+	pub offset: usize,
+	pub capacity: usize,
+}
+
+impl CompactingAllocPartition {
+	/// The entire buffer of `capacity`
+	pub fn full(capacity: usize) -> Self {
+		Self { offset: 0, capacity }
+	}
+
+	/// The `index`th of `count` equally sized partitions of a buffer of `capacity`, any remainder stays unused
+	pub fn split(capacity: usize, index: usize, count: usize) -> Self {
+		assert!(index < count, "partition {index} out of bounds of {count} partitions");
+		let capacity = capacity / count;
+		Self {
+			offset: index * capacity,
+			capacity,
+		}
+	}
+}
+
+/// The indirect args counting the allocations of a [`CompactingAllocBuffer`], and their default they're reset to
+pub struct CompactingAllocArgs {
 	indirect_args: MutDesc<MutBuffer<[u32; 3]>>,
 	indirect_args_default: RCDesc<Buffer<[u32; 3]>>,
 }
 
-impl<T: BufferStructPlain> CompactingAllocBuffer<T> {
-	pub fn new(
-		bindless: &Bindless,
-		capacity: usize,
-		indirect_args_default: [u32; 3],
-		name: &str,
-	) -> anyhow::Result<Self> {
-		let buffer = bindless.buffer().alloc_slice(
-			&BindlessBufferCreateInfo {
-				usage: BindlessBufferUsage::STORAGE_BUFFER,
-				name: &format!("{} buffer", name),
-				allocation_scheme: BindlessAllocationScheme::AllocatorManaged,
-			},
-			capacity,
-		)?;
+impl CompactingAllocArgs {
+	pub fn new(bindless: &Bindless, indirect_args_default: [u32; 3], name: &str) -> anyhow::Result<Self> {
 		let indirect_args = bindless.buffer().alloc_sized(&BindlessBufferCreateInfo {
 			usage: BindlessBufferUsage::STORAGE_BUFFER
 				| BindlessBufferUsage::INDIRECT_BUFFER
@@ -50,28 +61,86 @@ impl<T: BufferStructPlain> CompactingAllocBuffer<T> {
 			indirect_args_default,
 		)?;
 		Ok(Self {
-			buffer,
 			indirect_args,
 			indirect_args_default,
 		})
 	}
+}
+
+pub struct CompactingAllocBuffer<T: BufferStructPlain> {
+	buffer: MutDesc<MutBuffer<[T]>>,
+	partition: CompactingAllocPartition,
+	args: CompactingAllocArgs,
+}
+
+impl<T: BufferStructPlain> CompactingAllocBuffer<T> {
+	pub fn new(
+		bindless: &Bindless,
+		capacity: usize,
+		indirect_args_default: [u32; 3],
+		name: &str,
+	) -> anyhow::Result<Self> {
+		Ok(Self {
+			buffer: Self::alloc_buffer(bindless, capacity, name)?,
+			partition: CompactingAllocPartition::full(capacity),
+			args: CompactingAllocArgs::new(bindless, indirect_args_default, name)?,
+		})
+	}
+
+	/// Allocates a buffer of `capacity` to be shared by multiple partitions, see [`Self::from_partition`]
+	pub fn alloc_buffer(bindless: &Bindless, capacity: usize, name: &str) -> anyhow::Result<MutDesc<MutBuffer<[T]>>> {
+		Ok(bindless.buffer().alloc_slice(
+			&BindlessBufferCreateInfo {
+				usage: BindlessBufferUsage::STORAGE_BUFFER,
+				name: &format!("{} buffer", name),
+				allocation_scheme: BindlessAllocationScheme::AllocatorManaged,
+			},
+			capacity,
+		)?)
+	}
+
+	/// Allocates from the `partition` of `buffer` only, counting the allocations in its own `args`
+	pub fn from_partition(
+		buffer: MutDesc<MutBuffer<[T]>>,
+		partition: CompactingAllocPartition,
+		args: CompactingAllocArgs,
+	) -> Self {
+		assert!(
+			partition.offset + partition.capacity <= buffer.len(),
+			"{:?} exceeds the buffer of len {}",
+			partition,
+			buffer.len()
+		);
+		Self {
+			buffer,
+			partition,
+			args,
+		}
+	}
+
+	/// Splits into the shared buffer and the args of this partition, inverse of [`Self::from_partition`]
+	pub fn into_parts(self) -> (MutDesc<MutBuffer<[T]>>, CompactingAllocArgs) {
+		(self.buffer, self.args)
+	}
 
 	pub fn transition_writing<'a>(
 		self,
 		cmd: &mut Recording<'a>,
 	) -> Result<CompactingAllocBufferWriting<'a, T>, RecordingError> {
-		let indirect_args = self.indirect_args.access::<TransferWrite>(cmd)?;
-		cmd.copy_buffer_to_buffer(&self.indirect_args_default, &indirect_args)?;
+		let indirect_args = self.args.indirect_args.access::<TransferWrite>(cmd)?;
+		cmd.copy_buffer_to_buffer(&self.args.indirect_args_default, &indirect_args)?;
 		Ok(CompactingAllocBufferWriting {
 			buffer: self.buffer.access(cmd)?,
+			partition: self.partition,
 			indirect_args: indirect_args.transition()?,
-			indirect_args_default: self.indirect_args_default,
+			indirect_args_default: self.args.indirect_args_default,
 		})
 	}
 }
 
 pub struct CompactingAllocBufferWriting<'a, T: BufferStructPlain> {
 	buffer: MutBufferAccess<'a, [T], ShaderReadWrite>,
+	partition: CompactingAllocPartition,
 	indirect_args: MutBufferAccess<'a, [u32; 3], ShaderReadWrite>,
 	indirect_args_default: RCDesc<Buffer<[u32; 3]>>,
 }
@@ -81,12 +150,15 @@ impl<'a, T: BufferStructPlain> CompactingAllocBufferWriting<'a, T> {
 		Ok(CompactingAllocBufferWriter {
 			buffer: self.buffer.to_mut_transient()?,
 			indirect_args: self.indirect_args.to_mut_transient()?,
+			offset: self.partition.offset as u32,
+			capacity: self.partition.capacity as u32,
 		})
 	}
 
 	pub fn transition_reading(self) -> anyhow::Result<CompactingAllocBufferReading<'a, T>> {
 		Ok(CompactingAllocBufferReading {
 			buffer: self.buffer.transition()?,
+			partition: self.partition,
 			indirect_args: self.indirect_args.transition()?,
 			indirect_args_default: self.indirect_args_default,
 		})
@@ -95,6 +167,7 @@ impl<'a, T: BufferStructPlain> CompactingAllocBufferWriting<'a, T> {
 
 pub struct CompactingAllocBufferReading<'a, T: BufferStructPlain> {
 	buffer: MutBufferAccess<'a, [T], ShaderRead>,
+	partition: CompactingAllocPartition,
 	indirect_args: MutBufferAccess<'a, [u32; 3], GeneralRead>,
 	indirect_args_default: RCDesc<Buffer<[u32; 3]>>,
 }
@@ -104,6 +177,7 @@ impl<'a, T: BufferStructPlain> CompactingAllocBufferReading<'a, T> {
 		Ok(CompactingAllocBufferReader {
 			buffer: self.buffer.to_transient()?,
 			indirect_args: self.indirect_args.to_transient()?,
+			offset: self.partition.offset as u32,
 		})
 	}
 
@@ -114,8 +188,11 @@ impl<'a, T: BufferStructPlain> CompactingAllocBufferReading<'a, T> {
 	pub fn transition_reset(self) -> CompactingAllocBuffer<T> {
 		CompactingAllocBuffer {
 			buffer: self.buffer.into_desc(),
-			indirect_args: self.indirect_args.into_desc(),
-			indirect_args_default: self.indirect_args_default,
+			partition: self.partition,
+			args: CompactingAllocArgs {
+				indirect_args: self.indirect_args.into_desc(),
+				indirect_args_default: self.indirect_args_default,
+			},
 		}
 	}
 }
@@ -0,0 +1,174 @@
+use glam::UVec2;
+use rust_gpu_bindless::descriptor::{
+	Bindless, BindlessImageCreateInfo, BindlessImageUsage, Buffer, Format, Image, Image2d, ImageDescExt, MutDesc,
+	MutImage, TransientDesc,
+};
+use rust_gpu_bindless::pipeline::{
+	AccessError, BindlessComputePipeline, MutBufferAccess, MutImageAccess, MutImageAccessExt, Recording,
+	RecordingError, SampledRead, ShaderRead, StorageReadWrite,
+};
+use space_engine_shader::renderer::camera::Camera;
+use space_engine_shader::renderer::hzb::{
+	HZB_BUILD_WG_SIZE, Hzb, Param, hzb_atlas_size, hzb_level_count, hzb_level_offset, hzb_level_size,
+};
+
+pub const HZB_FORMAT: Format = Format::R32_SFLOAT;
+
+/// The two atlas images of a HZB, see [`space_engine_shader::renderer::hzb`]
+pub struct HzbImages {
+	pub even_levels: MutDesc<MutImage<Image2d>>,
+	pub odd_levels: MutDesc<MutImage<Image2d>>,
+}
+
+impl HzbImages {
+	/// Allocates a HZB for a depth image of `size`
+	pub fn new(bindless: &Bindless, size: UVec2) -> anyhow::Result<Self> {
+		let alloc = |odd: bool, name: &str| {
+			bindless.image().alloc(&BindlessImageCreateInfo {
+				format: HZB_FORMAT,
+				extent: hzb_atlas_size(size, odd).into(),
+				usage: BindlessImageUsage::STORAGE | BindlessImageUsage::SAMPLED,
+				name,
+				..Default::default()
+			})
+		};
+		Ok(Self {
+			even_levels: alloc(false, "hzb_even_levels")?,
+			odd_levels: alloc(true, "hzb_odd_levels")?,
+		})
+	}
+
+	pub fn access<'a>(self, cmd: &Recording<'a>) -> Result<HzbAccess<'a>, AccessError> {
+		Ok(HzbAccess {
+			even_levels: self.even_levels.access(cmd)?,
+			odd_levels: self.odd_levels.access(cmd)?,
+		})
+	}
+}
+
+pub struct HzbAccess<'a> {
+	pub even_levels: MutImageAccess<'a, Image2d, SampledRead>,
+	pub odd_levels: MutImageAccess<'a, Image2d, SampledRead>,
+}
+
+impl HzbAccess<'_> {
+	/// The HZB for shaders, with `camera` being the camera the depth image was rendered with
+	pub fn to_hzb(&self, camera: Camera, enabled: bool) -> Result<Hzb<'_>, AccessError> {
+		Ok(Hzb {
+			even_levels: self.even_levels.to_transient_sampled()?,
+			odd_levels: self.odd_levels.to_transient_sampled()?,
+			camera,
+			enabled: enabled as u32,
+		})
+	}
+
+	pub fn into_images(self) -> HzbImages {
+		HzbImages {
+			even_levels: self.even_levels.into_desc(),
+			odd_levels: self.odd_levels.into_desc(),
+		}
+	}
+}
+
+pub struct HzbCompute(BindlessComputePipeline<Param<'static>>);
+
+impl HzbCompute {
+	pub fn new(bindless: &Bindless) -> anyhow::Result<Self> {
+		Ok(Self(bindless.create_compute_pipeline(
+			crate::shader::renderer::hzb::hzb_build_cs::new(),
+		)?))
+	}
+
+	/// Builds all levels of the HZB from the `depth_image`, alternating between reading from one atlas image and
+	/// writing the other. If `merge_software_depth`, the depth of the software rasterizer is merged into level 0.
+	pub fn build<'a>(
+		&self,
+		cmd: &mut Recording<'a>,
+		depth_image: &MutImageAccess<'a, Image2d, SampledRead>,
+		software_depth: &MutBufferAccess<'a, [u32], ShaderRead>,
+		merge_software_depth: bool,
+		images: HzbImages,
+	) -> Result<HzbAccess<'a>, RecordingError> {
+		profiling::function_scope!();
+		let extent = depth_image.extent();
+		let size = UVec2::new(extent.width, extent.height);
+		let software_depth = software_depth.to_transient()?;
+
+		let even_levels = images.even_levels.access_dont_care::<StorageReadWrite>(cmd)?;
+		let odd_levels = images.odd_levels.access_dont_care::<StorageReadWrite>(cmd)?;
+		self.dispatch(
+			cmd,
+			depth_image.to_transient_sampled()?,
+			(UVec2::ZERO, size),
+			&even_levels,
+			0,
+			size,
+			(software_depth, merge_software_depth),
+		)?;
+
+		let mut src = even_levels.transition::<SampledRead>()?;
+		let mut dst = odd_levels;
+		for level in 1..hzb_level_count(size) {
+			let src_level = (hzb_level_offset(size, level - 1), hzb_level_size(size, level - 1));
+			self.dispatch(
+				cmd,
+				src.to_transient_sampled()?,
+				src_level,
+				&dst,
+				level,
+				size,
+				(software_depth, false),
+			)?;
+			let written = dst.transition::<SampledRead>()?;
+			dst = src.transition::<StorageReadWrite>()?;
+			src = written;
+		}
+
+		// src contains the last level written
+		let dst = dst.transition::<SampledRead>()?;
+		Ok(if hzb_level_count(size) % 2 == 1 {
+			HzbAccess {
+				even_levels: src,
+				odd_levels: dst,
+			}
+		} else {
+			HzbAccess {
+				even_levels: dst,
+				odd_levels: src,
+			}
+		})
+	}
+
+	#[allow(clippy::too_many_arguments)]
+	fn dispatch(
+		&self,
+		cmd: &mut Recording<'_>,
+		src: TransientDesc<Image<Image2d>>,
+		(src_offset, src_size): (UVec2, UVec2),
+		dst: &MutImageAccess<Image2d, StorageReadWrite>,
+		dst_level: u32,
+		size: UVec2,
+		(software_depth, merge_software_depth): (TransientDesc<Buffer<[u32]>>, bool),
+	) -> Result<(), RecordingError> {
+		let dst_size = hzb_level_size(size, dst_level);
+		let groups = [
+			dst_size.x.div_ceil(HZB_BUILD_WG_SIZE.x),
+			dst_size.y.div_ceil(HZB_BUILD_WG_SIZE.y),
+			1,
+		];
+		cmd.dispatch(
+			&self.0,
+			groups,
+			Param {
+				src,
+				src_offset,
+				src_size,
+				dst: dst.to_mut_transient(),
+				dst_offset: hzb_level_offset(size, dst_level),
+				dst_size,
+				software_depth,
+				merge_software_depth: merge_software_depth as u32,
+			},
+		)
+	}
+}
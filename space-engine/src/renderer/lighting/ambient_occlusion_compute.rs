@@ -0,0 +1,129 @@
+use crate::renderer::frame_context::FrameContext;
+use glam::UVec2;
+use rust_gpu_bindless::descriptor::{
+	Bindless, BindlessImageCreateInfo, BindlessImageUsage, Format, Image, Image2d, MutDesc, MutImage, TransientDesc,
+};
+use rust_gpu_bindless::pipeline::{
+	AccessError, BindlessComputePipeline, MutImageAccess, MutImageAccessExt, Recording, RecordingError, SampledRead,
+	StorageReadWrite,
+};
+use space_engine_shader::renderer::lighting::ambient_occlusion::{AO_WG_SIZE, AmbientOcclusion, DenoiseParam, Param};
+
+pub const AO_FORMAT: Format = Format::R32_SFLOAT;
+
+/// The noisy and the denoised visibility, see [`space_engine_shader::renderer::lighting::ambient_occlusion`]
+pub struct AoImages {
+	noisy: MutDesc<MutImage<Image2d>>,
+	denoised: MutDesc<MutImage<Image2d>>,
+}
+
+impl AoImages {
+	/// Allocates the images for a depth image of `size`
+	pub fn new(bindless: &Bindless, size: UVec2) -> anyhow::Result<Self> {
+		let alloc = |name: &str| {
+			bindless.image().alloc(&BindlessImageCreateInfo {
+				format: AO_FORMAT,
+				extent: size.into(),
+				usage: BindlessImageUsage::STORAGE | BindlessImageUsage::SAMPLED,
+				name,
+				..Default::default()
+			})
+		};
+		Ok(Self {
+			noisy: alloc("ao_noisy")?,
+			denoised: alloc("ao_denoised")?,
+		})
+	}
+}
+
+pub struct AoAccess<'a> {
+	noisy: MutImageAccess<'a, Image2d, SampledRead>,
+	denoised: MutImageAccess<'a, Image2d, SampledRead>,
+}
+
+impl AoAccess<'_> {
+	pub fn to_ambient_occlusion(&self) -> Result<AmbientOcclusion<'_>, AccessError> {
+		Ok(AmbientOcclusion {
+			visibility: self.denoised.to_transient_sampled()?,
+		})
+	}
+
+	pub fn into_images(self) -> AoImages {
+		AoImages {
+			noisy: self.noisy.into_desc(),
+			denoised: self.denoised.into_desc(),
+		}
+	}
+}
+
+pub struct AmbientOcclusionCompute {
+	ao: BindlessComputePipeline<Param<'static>>,
+	denoise: BindlessComputePipeline<DenoiseParam<'static>>,
+}
+
+impl AmbientOcclusionCompute {
+	pub fn new(bindless: &Bindless) -> anyhow::Result<Self> {
+		Ok(Self {
+			ao: bindless.create_compute_pipeline(crate::shader::renderer::lighting::ambient_occlusion::ao_cs::new())?,
+			denoise: bindless
+				.create_compute_pipeline(crate::shader::renderer::lighting::ambient_occlusion::ao_denoise_cs::new())?,
+		})
+	}
+
+	/// Computes the ambient occlusion of the `depth_image` into the `images`, if it is enabled. Without `g_normal`,
+	/// normals are reconstructed from depth.
+	pub fn dispatch<'a>(
+		&self,
+		cmd: &mut Recording<'a>,
+		frame_context: &FrameContext,
+		depth_image: TransientDesc<Image<Image2d>>,
+		g_normal: Option<TransientDesc<Image<Image2d>>>,
+		images: AoImages,
+	) -> Result<AoAccess<'a>, RecordingError> {
+		profiling::function_scope!();
+		if !frame_context.frame_data.ao.enabled() {
+			// never sampled, as the ambient occlusion is disabled
+			return Ok(AoAccess {
+				noisy: images.noisy.access_dont_care(cmd)?,
+				denoised: images.denoised.access_dont_care(cmd)?,
+			});
+		}
+
+		let image_size = frame_context.frame_data.camera.viewport_size;
+		let groups = [
+			image_size.x.div_ceil(AO_WG_SIZE.x),
+			image_size.y.div_ceil(AO_WG_SIZE.y),
+			1,
+		];
+		let noisy = images.noisy.access_dont_care::<StorageReadWrite>(cmd)?;
+		cmd.dispatch(
+			&self.ao,
+			groups,
+			Param {
+				frame_data: frame_context.frame_data_desc,
+				depth_image,
+				// any image will do, it's never read
+				g_normal: g_normal.unwrap_or(depth_image),
+				reconstruct_normals: g_normal.is_none() as u32,
+				output: noisy.to_mut_transient(),
+			},
+		)?;
+		let noisy = noisy.transition::<SampledRead>()?;
+
+		let denoised = images.denoised.access_dont_care::<StorageReadWrite>(cmd)?;
+		cmd.dispatch(
+			&self.denoise,
+			groups,
+			DenoiseParam {
+				frame_data: frame_context.frame_data_desc,
+				depth_image,
+				input: noisy.to_transient_sampled()?,
+				output: denoised.to_mut_transient(),
+			},
+		)?;
+		Ok(AoAccess {
+			noisy,
+			denoised: denoised.transition()?,
+		})
+	}
+}
@@ -0,0 +1,240 @@
+use crate::renderer::frame_context::FrameContext;
+use glam::{UVec2, Vec3, Vec4};
+use rust_gpu_bindless::descriptor::{
+	AddressMode, Bindless, BindlessAllocationScheme, BindlessBufferCreateInfo, BindlessBufferUsage,
+	BindlessImageCreateInfo, BindlessImageUsage, BindlessSamplerCreateInfo, Filter, Format, Image2d, MutBuffer,
+	MutDesc, MutImage, RCDesc, RCDescExt, Sampler, TransientDesc,
+};
+use rust_gpu_bindless::pipeline::{
+	AccessError, BindlessComputePipeline, MutBufferAccess, MutBufferAccessExt, MutImageAccess, MutImageAccessExt,
+	Recording, RecordingError, SampledRead, ShaderRead, ShaderReadWrite, StorageReadWrite,
+};
+use space_engine_shader::renderer::frame_data::FrameData;
+use space_engine_shader::renderer::lighting::ibl::{
+	BRDF_LUT_SIZE, ENVIRONMENT_SIZE, Ibl, SH_COEFFICIENTS, SPECULAR_LEVELS, specular_atlas_size,
+};
+use space_engine_shader::renderer::lighting::ibl_bake::{
+	BrdfLutParam, EnvironmentParam, IBL_BAKE_WG_SIZE, IrradianceParam, SpecularParam,
+};
+
+pub const IBL_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+/// The sky an environment was baked for
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct BakedSky {
+	sun_direction: Vec3,
+	ambient_light: Vec3,
+}
+
+impl BakedSky {
+	fn new(frame_data: &FrameData) -> Self {
+		Self {
+			sun_direction: frame_data.sun.direction,
+			ambient_light: frame_data.ambient_light.0,
+		}
+	}
+}
+
+/// The images and buffers the sky's [`Ibl`] is baked into, see [`space_engine_shader::renderer::lighting::ibl`]
+pub struct IblResources {
+	environment: MutDesc<MutImage<Image2d>>,
+	specular: MutDesc<MutImage<Image2d>>,
+	brdf_lut: MutDesc<MutImage<Image2d>>,
+	irradiance: MutDesc<MutBuffer<[Vec4]>>,
+	/// the sky the environment was last baked for, `None` if it was never baked
+	baked_sky: Option<BakedSky>,
+	brdf_lut_baked: bool,
+}
+
+impl IblResources {
+	pub fn new(bindless: &Bindless) -> anyhow::Result<Self> {
+		let alloc = |size: UVec2, name: &str| {
+			bindless.image().alloc(&BindlessImageCreateInfo {
+				format: IBL_FORMAT,
+				extent: size.into(),
+				usage: BindlessImageUsage::STORAGE | BindlessImageUsage::SAMPLED,
+				name,
+				..Default::default()
+			})
+		};
+		Ok(Self {
+			environment: alloc(UVec2::splat(ENVIRONMENT_SIZE), "ibl_environment")?,
+			specular: alloc(specular_atlas_size(), "ibl_specular")?,
+			brdf_lut: alloc(UVec2::splat(BRDF_LUT_SIZE), "ibl_brdf_lut")?,
+			irradiance: bindless.buffer().alloc_slice(
+				&BindlessBufferCreateInfo {
+					usage: BindlessBufferUsage::STORAGE_BUFFER,
+					name: "ibl_irradiance",
+					allocation_scheme: BindlessAllocationScheme::AllocatorManaged,
+				},
+				SH_COEFFICIENTS,
+			)?,
+			baked_sky: None,
+			brdf_lut_baked: false,
+		})
+	}
+}
+
+/// The baked IBL, ready to be sampled by the lighting
+pub struct IblAccess<'a> {
+	environment: MutImageAccess<'a, Image2d, SampledRead>,
+	specular: MutImageAccess<'a, Image2d, SampledRead>,
+	brdf_lut: MutImageAccess<'a, Image2d, SampledRead>,
+	irradiance: MutBufferAccess<'a, [Vec4], ShaderRead>,
+	baked_sky: Option<BakedSky>,
+}
+
+impl IblAccess<'_> {
+	pub fn to_ibl<'b>(&'b self, sampler: TransientDesc<'b, Sampler>) -> Result<Ibl<'b>, AccessError> {
+		Ok(Ibl {
+			specular: self.specular.to_transient_sampled()?,
+			brdf_lut: self.brdf_lut.to_transient_sampled()?,
+			irradiance: self.irradiance.to_transient()?,
+			sampler,
+		})
+	}
+
+	pub fn into_resources(self) -> IblResources {
+		IblResources {
+			environment: self.environment.into_desc(),
+			specular: self.specular.into_desc(),
+			brdf_lut: self.brdf_lut.into_desc(),
+			irradiance: self.irradiance.into_desc(),
+			baked_sky: self.baked_sky,
+			brdf_lut_baked: true,
+		}
+	}
+}
+
+pub struct IblCompute {
+	environment: BindlessComputePipeline<EnvironmentParam<'static>>,
+	irradiance: BindlessComputePipeline<IrradianceParam<'static>>,
+	specular: BindlessComputePipeline<SpecularParam<'static>>,
+	brdf_lut: BindlessComputePipeline<BrdfLutParam<'static>>,
+	sampler: RCDesc<Sampler>,
+}
+
+impl IblCompute {
+	pub fn new(bindless: &Bindless) -> anyhow::Result<Self> {
+		Ok(Self {
+			environment: bindless
+				.create_compute_pipeline(crate::shader::renderer::lighting::ibl_bake::ibl_environment_cs::new())?,
+			irradiance: bindless
+				.create_compute_pipeline(crate::shader::renderer::lighting::ibl_bake::ibl_irradiance_cs::new())?,
+			specular: bindless
+				.create_compute_pipeline(crate::shader::renderer::lighting::ibl_bake::ibl_specular_cs::new())?,
+			brdf_lut: bindless
+				.create_compute_pipeline(crate::shader::renderer::lighting::ibl_bake::ibl_brdf_lut_cs::new())?,
+			sampler: bindless.sampler().alloc(&BindlessSamplerCreateInfo {
+				min_filter: Filter::Linear,
+				mag_filter: Filter::Linear,
+				mipmap_mode: Filter::Linear,
+				address_mode_u: AddressMode::ClampToEdge,
+				address_mode_v: AddressMode::ClampToEdge,
+				address_mode_w: AddressMode::ClampToEdge,
+				..BindlessSamplerCreateInfo::default()
+			})?,
+		})
+	}
+
+	/// The sampler the [`Ibl`] is sampled with
+	pub fn sampler(&self) -> &RCDesc<Sampler> {
+		&self.sampler
+	}
+
+	/// Bakes the sky of `frame_context` into the `resources`. The environment is only rebaked if IBL is enabled and
+	/// the sky changed since it was last baked, the BRDF LUT only once.
+	pub fn bake<'a>(
+		&self,
+		cmd: &mut Recording<'a>,
+		frame_context: &FrameContext,
+		resources: IblResources,
+	) -> Result<IblAccess<'a>, RecordingError> {
+		profiling::function_scope!();
+		let brdf_lut = if resources.brdf_lut_baked {
+			resources.brdf_lut.access(cmd)?
+		} else {
+			let brdf_lut = resources.brdf_lut.access_dont_care::<StorageReadWrite>(cmd)?;
+			cmd.dispatch(
+				&self.brdf_lut,
+				[
+					BRDF_LUT_SIZE.div_ceil(IBL_BAKE_WG_SIZE.x),
+					BRDF_LUT_SIZE.div_ceil(IBL_BAKE_WG_SIZE.y),
+					1,
+				],
+				BrdfLutParam {
+					brdf_lut: brdf_lut.to_mut_transient(),
+				},
+			)?;
+			brdf_lut.transition()?
+		};
+
+		let frame_data = &frame_context.frame_data;
+		let sky = BakedSky::new(frame_data);
+		let (environment, specular, irradiance, baked_sky) =
+			if frame_data.ibl.enabled() && resources.baked_sky != Some(sky) {
+				let environment = resources.environment.access_dont_care::<StorageReadWrite>(cmd)?;
+				let environment_groups = [
+					ENVIRONMENT_SIZE.div_ceil(IBL_BAKE_WG_SIZE.x),
+					ENVIRONMENT_SIZE.div_ceil(IBL_BAKE_WG_SIZE.y),
+					1,
+				];
+				cmd.dispatch(
+					&self.environment,
+					environment_groups,
+					EnvironmentParam {
+						frame_data: frame_context.frame_data_desc,
+						environment: environment.to_mut_transient(),
+					},
+				)?;
+				let environment = environment.transition::<SampledRead>()?;
+				let sampler = self.sampler.to_transient(cmd);
+
+				let irradiance = resources.irradiance.access::<ShaderReadWrite>(cmd)?;
+				cmd.dispatch(
+					&self.irradiance,
+					[1, 1, 1],
+					IrradianceParam {
+						environment: environment.to_transient_sampled()?,
+						sampler,
+						irradiance: irradiance.to_mut_transient()?,
+					},
+				)?;
+
+				// all levels at once, the larger first level determines the group count
+				let specular = resources.specular.access_dont_care::<StorageReadWrite>(cmd)?;
+				cmd.dispatch(
+					&self.specular,
+					[environment_groups[0], environment_groups[1], SPECULAR_LEVELS],
+					SpecularParam {
+						environment: environment.to_transient_sampled()?,
+						sampler,
+						specular: specular.to_mut_transient(),
+					},
+				)?;
+				(environment, specular.transition()?, irradiance.transition()?, Some(sky))
+			} else if resources.baked_sky.is_some() {
+				(
+					resources.environment.access(cmd)?,
+					resources.specular.access(cmd)?,
+					resources.irradiance.access(cmd)?,
+					resources.baked_sky,
+				)
+			} else {
+				// never sampled, as IBL is disabled
+				(
+					resources.environment.access_dont_care(cmd)?,
+					resources.specular.access_dont_care(cmd)?,
+					resources.irradiance.access(cmd)?,
+					None,
+				)
+			};
+		Ok(IblAccess {
+			environment,
+			specular,
+			brdf_lut,
+			irradiance,
+			baked_sky,
+		})
+	}
+}
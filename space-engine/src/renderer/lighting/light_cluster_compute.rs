@@ -0,0 +1,58 @@
+use crate::renderer::frame_context::FrameContext;
+use glam::UVec2;
+use rust_gpu_bindless::descriptor::{
+	Bindless, BindlessAllocationScheme, BindlessBufferCreateInfo, BindlessBufferUsage, MutBuffer, MutDesc, RCDescExt,
+};
+use rust_gpu_bindless::pipeline::{
+	BindlessComputePipeline, MutBufferAccess, Recording, RecordingError, ShaderReadWrite,
+};
+use space_asset_rt::meshlet::scene::InstancedMeshletSceneCpu;
+use space_engine_shader::renderer::lighting::light_cluster::{
+	CLUSTER_STRIDE, CLUSTER_Z_SLICES, ClusterGrid, LIGHT_CLUSTER_WG_SIZE, Param,
+};
+
+/// Allocates the buffer holding the lights of each cluster of a viewport of `size`, see
+/// [`space_engine_shader::renderer::lighting::light_cluster`]
+pub fn alloc_light_clusters(bindless: &Bindless, size: UVec2) -> anyhow::Result<MutDesc<MutBuffer<[u32]>>> {
+	let tiles = ClusterGrid::tiles(size);
+	Ok(bindless.buffer().alloc_slice(
+		&BindlessBufferCreateInfo {
+			usage: BindlessBufferUsage::STORAGE_BUFFER,
+			name: "light_clusters",
+			allocation_scheme: BindlessAllocationScheme::AllocatorManaged,
+		},
+		(tiles.x * tiles.y * CLUSTER_Z_SLICES * CLUSTER_STRIDE) as usize,
+	)?)
+}
+
+pub struct LightClusterCompute(BindlessComputePipeline<Param<'static>>);
+
+impl LightClusterCompute {
+	pub fn new(bindless: &Bindless) -> anyhow::Result<Self> {
+		Ok(Self(bindless.create_compute_pipeline(
+			crate::shader::renderer::lighting::light_cluster::light_cluster_cs::new(),
+		)?))
+	}
+
+	/// Assigns the lights of the `scene` to the clusters of the frame's camera
+	pub fn dispatch(
+		&self,
+		cmd: &mut Recording<'_>,
+		frame_context: &FrameContext,
+		scene: &InstancedMeshletSceneCpu,
+		clusters: &MutBufferAccess<[u32], ShaderReadWrite>,
+	) -> Result<(), RecordingError> {
+		profiling::function_scope!();
+		let grid = ClusterGrid::new(&frame_context.frame_data.camera);
+		cmd.dispatch(
+			&self.0,
+			[grid.cluster_count().div_ceil(LIGHT_CLUSTER_WG_SIZE), 1, 1],
+			Param {
+				frame_data: frame_context.frame_data_desc,
+				lights: scene.lights.to_transient(cmd),
+				num_lights: scene.num_lights,
+				clusters: clusters.to_mut_transient()?,
+			},
+		)
+	}
+}
@@ -3,7 +3,11 @@ use rust_gpu_bindless::descriptor::{Bindless, Image2d, MutImage, Transient, Tran
 use rust_gpu_bindless::pipeline::BindlessComputePipeline;
 use rust_gpu_bindless::pipeline::{Recording, RecordingError};
 use space_engine_shader::renderer::g_buffer::GBuffer;
+use space_engine_shader::renderer::lighting::ambient_occlusion::AmbientOcclusion;
+use space_engine_shader::renderer::lighting::ibl::Ibl;
+use space_engine_shader::renderer::lighting::light_cluster::LightClusters;
 use space_engine_shader::renderer::lighting::lighting_compute::{LIGHTING_WG_SIZE, Param};
+use space_engine_shader::renderer::lighting::shadow::ShadowMap;
 
 pub struct LightingCompute(BindlessComputePipeline<Param<'static>>);
 
@@ -14,11 +18,16 @@ impl LightingCompute {
 		)?))
 	}
 
+	#[allow(clippy::too_many_arguments)]
 	pub fn dispatch(
 		&self,
 		cmd: &mut Recording<'_>,
 		frame_context: &FrameContext,
 		g_buffer: GBuffer<Transient>,
+		shadow_map: ShadowMap,
+		light_clusters: LightClusters,
+		ibl: Ibl,
+		ambient_occlusion: AmbientOcclusion,
 		output_image: TransientDesc<MutImage<Image2d>>,
 	) -> Result<(), RecordingError> {
 		profiling::function_scope!();
@@ -34,6 +43,10 @@ impl LightingCompute {
 			Param {
 				frame_data: frame_context.frame_data_desc,
 				g_buffer,
+				shadow_map,
+				light_clusters,
+				ibl,
+				ambient_occlusion,
 				output_image,
 			},
 		)
@@ -1,2 +1,6 @@
+pub mod ambient_occlusion_compute;
+pub mod ibl_compute;
+pub mod light_cluster_compute;
 pub mod lighting_compute;
+pub mod shadow_map;
 pub mod sky_shader_compute;
@@ -0,0 +1,89 @@
+use glam::UVec2;
+use rust_gpu_bindless::descriptor::{
+	Bindless, BindlessAllocationScheme, BindlessBufferCreateInfo, BindlessBufferUsage, BindlessImageCreateInfo,
+	BindlessImageUsage, Buffer, Format, Image2d, MutDesc, MutImage, RCDescExt, TransientDesc,
+};
+use rust_gpu_bindless::pipeline::{AccessError, HasResourceContext, MutImageAccess, Recording, SampledRead};
+use space_engine_shader::renderer::lighting::shadow::{ShadowCascade, ShadowCascades, ShadowMap};
+
+/// The depth atlas of the sun's [`ShadowMap`]. The meshlets of each cascade are selected into the meshlet buffers of
+/// the view, one cascade after another, before the view itself uses them.
+pub struct ShadowMapResources {
+	pub atlas: MutDesc<MutImage<Image2d>>,
+	pub atlas_size: UVec2,
+}
+
+impl ShadowMapResources {
+	pub fn new(bindless: &Bindless, format: Format, atlas_size: UVec2) -> anyhow::Result<Self> {
+		Ok(Self {
+			atlas: alloc_atlas(bindless, format, atlas_size)?,
+			atlas_size,
+		})
+	}
+
+	/// Reallocates the atlas if the cascade count or resolution changed
+	pub fn resize(self, bindless: &Bindless, format: Format, atlas_size: UVec2) -> anyhow::Result<Self> {
+		if self.atlas_size == atlas_size {
+			Ok(self)
+		} else {
+			Ok(Self {
+				atlas: alloc_atlas(bindless, format, atlas_size)?,
+				atlas_size,
+			})
+		}
+	}
+}
+
+fn alloc_atlas(bindless: &Bindless, format: Format, size: UVec2) -> anyhow::Result<MutDesc<MutImage<Image2d>>> {
+	Ok(bindless.image().alloc(&BindlessImageCreateInfo {
+		format,
+		extent: size.into(),
+		usage: BindlessImageUsage::DEPTH_STENCIL_ATTACHMENT | BindlessImageUsage::SAMPLED,
+		name: "shadow_atlas",
+		..Default::default()
+	})?)
+}
+
+/// The drawn shadow map, ready to be sampled by the lighting
+pub struct ShadowMapAccess<'a> {
+	pub atlas: MutImageAccess<'a, Image2d, SampledRead>,
+	pub atlas_size: UVec2,
+	pub cascades: TransientDesc<'a, Buffer<[ShadowCascade]>>,
+	pub cascades_cpu: ShadowCascades,
+}
+
+impl<'a> ShadowMapAccess<'a> {
+	/// Uploads the `cascades` for shaders to read
+	pub fn upload_cascades(
+		cmd: &Recording<'a>,
+		cascades: &ShadowCascades,
+	) -> anyhow::Result<TransientDesc<'a, Buffer<[ShadowCascade]>>> {
+		Ok(cmd
+			.bindless()
+			.buffer()
+			.alloc_shared_from_iter(
+				&BindlessBufferCreateInfo {
+					usage: BindlessBufferUsage::MAP_WRITE | BindlessBufferUsage::STORAGE_BUFFER,
+					name: "ShadowCascades",
+					allocation_scheme: BindlessAllocationScheme::AllocatorManaged,
+				},
+				cascades.cascades,
+			)?
+			.to_transient(cmd))
+	}
+
+	pub fn to_shadow_map(&self) -> Result<ShadowMap<'_>, AccessError> {
+		Ok(ShadowMap {
+			atlas: self.atlas.to_transient_sampled()?,
+			cascades: self.cascades,
+			cascade_far: self.cascades_cpu.cascade_far,
+		})
+	}
+
+	pub fn into_resources(self) -> ShadowMapResources {
+		ShadowMapResources {
+			atlas: self.atlas.into_desc(),
+			atlas_size: self.atlas_size,
+		}
+	}
+}
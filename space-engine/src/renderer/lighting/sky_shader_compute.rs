@@ -1,7 +1,6 @@
 use crate::renderer::frame_context::FrameContext;
-use rust_gpu_bindless::descriptor::{Bindless, Image2d, MutImage, Transient, TransientDesc};
+use rust_gpu_bindless::descriptor::{Bindless, Image, Image2d, MutImage, TransientDesc};
 use rust_gpu_bindless::pipeline::{BindlessComputePipeline, Recording, RecordingError};
-use space_engine_shader::renderer::g_buffer::GBuffer;
 use space_engine_shader::renderer::lighting::sky_shader::{Param, SKY_SHADER_WG_SIZE};
 
 pub struct SkyShaderCompute(BindlessComputePipeline<Param<'static>>);
@@ -17,7 +16,7 @@ impl SkyShaderCompute {
 		&self,
 		cmd: &mut Recording<'_>,
 		frame_context: &FrameContext,
-		g_buffer: GBuffer<Transient>,
+		depth_image: TransientDesc<Image<Image2d>>,
 		output_image: TransientDesc<MutImage<Image2d>>,
 	) -> Result<(), RecordingError> {
 		profiling::function_scope!();
@@ -32,7 +31,7 @@ impl SkyShaderCompute {
 			groups,
 			Param {
 				frame_data: frame_context.frame_data_desc,
-				g_buffer,
+				depth_image,
 				output_image,
 			},
 		)
@@ -14,15 +14,27 @@ use rust_gpu_bindless_shaders::shader_type::TaskShader;
 use space_asset_rt::meshlet::scene::InstancedMeshletSceneCpu;
 use space_engine_shader::renderer::meshlet::intermediate::MeshletInstance;
 use space_engine_shader::renderer::meshlet::mesh_shader::Param;
+use space_engine_shader::renderer::meshlet::meshlet_select::MeshletSelectPass;
 
 pub struct MeshletDraw {
-	pipeline: BindlessMeshGraphicsPipeline<Param<'static>>,
+	g_buffer_pipeline: BindlessMeshGraphicsPipeline<Param<'static>>,
+	visibility_pipeline: BindlessMeshGraphicsPipeline<Param<'static>>,
+	shadow_pipeline: BindlessMeshGraphicsPipeline<Param<'static>>,
 	sampler: RCDesc<Sampler>,
 }
 
 impl MeshletDraw {
-	pub fn new(bindless: &Bindless, g_buffer_format: RenderPassFormat) -> anyhow::Result<Self> {
-		let pipeline = bindless.create_mesh_graphics_pipeline::<Param<'static>>(
+	pub fn new(
+		bindless: &Bindless,
+		g_buffer_format: RenderPassFormat,
+		visibility_format: RenderPassFormat,
+		shadow_format: RenderPassFormat,
+	) -> anyhow::Result<Self> {
+		let depth_stencil_state = PipelineDepthStencilStateCreateInfo::default()
+			.depth_test_enable(true)
+			.depth_write_enable(true)
+			.depth_compare_op(CompareOp::LESS);
+		let g_buffer_pipeline = bindless.create_mesh_graphics_pipeline::<Param<'static>>(
 			&g_buffer_format,
 			&MeshGraphicsPipelineCreateInfo {
 				rasterization_state: PipelineRasterizationStateCreateInfo::default().line_width(1.),
@@ -30,16 +42,38 @@ impl MeshletDraw {
 					PipelineColorBlendAttachmentState::default().color_write_mask(ColorComponentFlags::RGBA),
 					PipelineColorBlendAttachmentState::default().color_write_mask(ColorComponentFlags::RGBA),
 					PipelineColorBlendAttachmentState::default().color_write_mask(ColorComponentFlags::RGBA),
+					PipelineColorBlendAttachmentState::default().color_write_mask(ColorComponentFlags::RGBA),
 				]),
-				depth_stencil_state: PipelineDepthStencilStateCreateInfo::default()
-					.depth_test_enable(true)
-					.depth_write_enable(true)
-					.depth_compare_op(CompareOp::LESS),
+				depth_stencil_state,
 			},
 			Option::<&FakeTaskShader>::None,
 			crate::shader::renderer::meshlet::mesh_shader::meshlet_mesh::new(),
 			crate::shader::renderer::meshlet::mesh_shader::meshlet_fragment_g_buffer::new(),
 		)?;
+		let visibility_pipeline = bindless.create_mesh_graphics_pipeline::<Param<'static>>(
+			&visibility_format,
+			&MeshGraphicsPipelineCreateInfo {
+				rasterization_state: PipelineRasterizationStateCreateInfo::default().line_width(1.),
+				color_blend_state: PipelineColorBlendStateCreateInfo::default().attachments(&[
+					PipelineColorBlendAttachmentState::default().color_write_mask(ColorComponentFlags::R),
+				]),
+				depth_stencil_state,
+			},
+			Option::<&FakeTaskShader>::None,
+			crate::shader::renderer::meshlet::mesh_shader::meshlet_mesh::new(),
+			crate::shader::renderer::meshlet::mesh_shader::meshlet_fragment_visibility::new(),
+		)?;
+		let shadow_pipeline = bindless.create_mesh_graphics_pipeline::<Param<'static>>(
+			&shadow_format,
+			&MeshGraphicsPipelineCreateInfo {
+				rasterization_state: PipelineRasterizationStateCreateInfo::default().line_width(1.),
+				color_blend_state: PipelineColorBlendStateCreateInfo::default(),
+				depth_stencil_state,
+			},
+			Option::<&FakeTaskShader>::None,
+			crate::shader::renderer::meshlet::mesh_shader::meshlet_mesh::new(),
+			crate::shader::renderer::meshlet::mesh_shader::meshlet_fragment_shadow::new(),
+		)?;
 
 		let sampler = bindless.sampler().alloc(&BindlessSamplerCreateInfo {
 			min_filter: Filter::Linear,
@@ -51,24 +85,75 @@ impl MeshletDraw {
 			..BindlessSamplerCreateInfo::default()
 		})?;
 
-		Ok(Self { pipeline, sampler })
+		Ok(Self {
+			g_buffer_pipeline,
+			visibility_pipeline,
+			shadow_pipeline,
+			sampler,
+		})
+	}
+
+	/// The sampler all material textures are sampled with
+	pub fn sampler(&self) -> &RCDesc<Sampler> {
+		&self.sampler
 	}
 
+	/// Draws the meshlets selected in `pass` into the g-buffer
 	pub fn draw(
 		&self,
 		cmd: &mut Rendering,
 		frame_context: &FrameContext,
 		scene: &InstancedMeshletSceneCpu,
 		alloc_buffer: &CompactingAllocBufferReading<MeshletInstance>,
+		pass: MeshletSelectPass,
 	) -> Result<(), RecordingError> {
 		profiling::function_scope!();
+		self.draw_with(&self.g_buffer_pipeline, cmd, frame_context, scene, alloc_buffer, pass)
+	}
+
+	/// Draws the meshlets selected in `pass` into the visibility buffer
+	pub fn draw_visibility(
+		&self,
+		cmd: &mut Rendering,
+		frame_context: &FrameContext,
+		scene: &InstancedMeshletSceneCpu,
+		alloc_buffer: &CompactingAllocBufferReading<MeshletInstance>,
+		pass: MeshletSelectPass,
+	) -> Result<(), RecordingError> {
+		profiling::function_scope!();
+		self.draw_with(&self.visibility_pipeline, cmd, frame_context, scene, alloc_buffer, pass)
+	}
+
+	/// Draws the depth of the meshlets selected in `pass` into a shadow map cascade
+	pub fn draw_shadow(
+		&self,
+		cmd: &mut Rendering,
+		frame_context: &FrameContext,
+		scene: &InstancedMeshletSceneCpu,
+		alloc_buffer: &CompactingAllocBufferReading<MeshletInstance>,
+		pass: MeshletSelectPass,
+	) -> Result<(), RecordingError> {
+		profiling::function_scope!();
+		self.draw_with(&self.shadow_pipeline, cmd, frame_context, scene, alloc_buffer, pass)
+	}
+
+	fn draw_with(
+		&self,
+		pipeline: &BindlessMeshGraphicsPipeline<Param<'static>>,
+		cmd: &mut Rendering,
+		frame_context: &FrameContext,
+		scene: &InstancedMeshletSceneCpu,
+		alloc_buffer: &CompactingAllocBufferReading<MeshletInstance>,
+		pass: MeshletSelectPass,
+	) -> Result<(), RecordingError> {
 		let param = Param {
 			frame_data: frame_context.frame_data_desc,
 			scene: scene.scene.to_transient(cmd),
 			sampler: self.sampler.to_transient(cmd),
 			compacting_alloc_buffer: alloc_buffer.to_reader()?,
+			pass: pass.into(),
 		};
-		cmd.draw_mesh_tasks_indirect(&self.pipeline, alloc_buffer.indirect_args(), param)
+		cmd.draw_mesh_tasks_indirect(pipeline, alloc_buffer.indirect_args(), param)
 	}
 }
 
@@ -3,8 +3,9 @@ use crate::renderer::frame_context::FrameContext;
 use rust_gpu_bindless::descriptor::{Bindless, RCDescExt};
 use rust_gpu_bindless::pipeline::{BindlessComputePipeline, Recording, RecordingError};
 use space_asset_rt::meshlet::scene::InstancedMeshletSceneCpu;
+use space_engine_shader::renderer::hzb::Hzb;
 use space_engine_shader::renderer::meshlet::intermediate::{MeshletGroupInstance, MeshletInstance};
-use space_engine_shader::renderer::meshlet::meshlet_select::Param;
+use space_engine_shader::renderer::meshlet::meshlet_select::{MeshletSelectPass, Param};
 
 pub struct MeshletSelectCompute(BindlessComputePipeline<Param<'static>>);
 
@@ -15,6 +16,9 @@ impl MeshletSelectCompute {
 		)?))
 	}
 
+	/// `hzb_current` is only read in the [`MeshletSelectPass::Second`]. Meshlets with tiny triangles are written to
+	/// `compacting_software_instances_out` instead, if software rasterization is enabled.
+	#[allow(clippy::too_many_arguments)]
 	pub fn dispatch(
 		&self,
 		cmd: &mut Recording<'_>,
@@ -22,6 +26,10 @@ impl MeshletSelectCompute {
 		scene: &InstancedMeshletSceneCpu,
 		compacting_groups_in: &CompactingAllocBufferReading<MeshletGroupInstance>,
 		compacting_instances_out: &CompactingAllocBufferWriting<MeshletInstance>,
+		compacting_software_instances_out: &CompactingAllocBufferWriting<MeshletInstance>,
+		pass: MeshletSelectPass,
+		hzb_last_frame: Hzb,
+		hzb_current: Hzb,
 	) -> Result<(), RecordingError> {
 		profiling::function_scope!();
 		cmd.dispatch_indirect(
@@ -32,6 +40,10 @@ impl MeshletSelectCompute {
 				scene: scene.scene.to_transient(cmd),
 				compacting_groups_in: compacting_groups_in.to_reader()?,
 				compacting_instances_out: compacting_instances_out.to_writer()?,
+				compacting_software_instances_out: compacting_software_instances_out.to_writer()?,
+				hzb_last_frame,
+				hzb_current,
+				pass: pass.into(),
 			},
 		)
 	}
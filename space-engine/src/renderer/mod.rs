@@ -1,5 +1,10 @@
+pub mod bloom_compute;
 pub mod compacting_alloc_buffer;
 pub mod frame_context;
+pub mod hzb_compute;
 pub mod lighting;
 pub mod meshlet;
 pub mod renderers;
+pub mod taa_compute;
+pub mod tonemap_compute;
+pub mod visibility_buffer;
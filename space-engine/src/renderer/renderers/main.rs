@@ -1,22 +1,45 @@
-use crate::renderer::compacting_alloc_buffer::CompactingAllocBuffer;
+use crate::renderer::bloom_compute::{BloomCompute, BloomImages};
+use crate::renderer::compacting_alloc_buffer::{
+	CompactingAllocArgs, CompactingAllocBuffer, CompactingAllocBufferReading, CompactingAllocPartition,
+};
 use crate::renderer::frame_context::FrameContext;
+use crate::renderer::hzb_compute::{HzbCompute, HzbImages};
+use crate::renderer::lighting::ambient_occlusion_compute::{AmbientOcclusionCompute, AoImages};
+use crate::renderer::lighting::ibl_compute::{IblCompute, IblResources};
+use crate::renderer::lighting::light_cluster_compute::{LightClusterCompute, alloc_light_clusters};
 use crate::renderer::lighting::lighting_compute::LightingCompute;
+use crate::renderer::lighting::shadow_map::{ShadowMapAccess, ShadowMapResources};
 use crate::renderer::lighting::sky_shader_compute::SkyShaderCompute;
 use crate::renderer::meshlet::instance_cull_compute::InstanceCullCompute;
 use crate::renderer::meshlet::meshlet_draw::MeshletDraw;
 use crate::renderer::meshlet::meshlet_select_compute::MeshletSelectCompute;
+use crate::renderer::taa_compute::{TaaCompute, TaaImages};
+use crate::renderer::tonemap_compute::{TonemapCompute, TonemapResources};
+use crate::renderer::visibility_buffer::material_pass_compute::{MaterialPassCompute, meshlet_instance_buffers};
+use crate::renderer::visibility_buffer::software_raster_compute::{SoftwareRasterBuffers, SoftwareRasterCompute};
 use anyhow::anyhow;
+use glam::UVec2;
 use rust_gpu_bindless::descriptor::{
-	Bindless, BindlessImageCreateInfo, BindlessImageUsage, Extent, Format, Image2d, ImageDescExt, MutDesc, MutImage,
+	Bindless, BindlessImageCreateInfo, BindlessImageUsage, Extent, Format, Image, Image2d, ImageDescExt, MutBuffer,
+	MutDesc, MutImage, RCDescExt, Transient, TransientDesc,
 };
 use rust_gpu_bindless::pipeline::{
-	ClearValue, ColorAttachment, DepthStencilAttachment, ImageAccessType, LoadOp, MutImageAccess, MutImageAccessExt,
-	Recording, RenderPassFormat, RenderingAttachment, SampledRead, StorageReadWrite, StoreOp,
+	AccessError, ClearValue, ColorAttachment, DepthStencilAttachment, ImageAccessType, LoadOp, MutBufferAccessExt,
+	MutImageAccess, MutImageAccessExt, Recording, RenderPassFormat, RenderingAttachment, SampledRead, ShaderRead,
+	ShaderReadWrite, StorageReadWrite, StoreOp,
 };
+use rust_gpu_bindless_shaders::utils::rect::IRect2;
+use rust_gpu_bindless_shaders::utils::viewport::Viewport;
 use space_asset_rt::meshlet::scene::InstancedMeshletSceneCpu;
-use space_engine_shader::renderer::frame_data::FrameData;
+use space_engine_shader::renderer::camera::Camera;
+use space_engine_shader::renderer::frame_data::{FrameData, NaniteSettings};
 use space_engine_shader::renderer::g_buffer::GBuffer;
+use space_engine_shader::renderer::hzb::Hzb;
+use space_engine_shader::renderer::lighting::light_cluster::LightClusters;
+use space_engine_shader::renderer::lighting::shadow::ShadowCascades;
 use space_engine_shader::renderer::meshlet::intermediate::{MeshletGroupInstance, MeshletInstance};
+use space_engine_shader::renderer::meshlet::meshlet_select::MeshletSelectPass;
+use space_engine_shader::renderer::visibility_buffer::VisibilityId;
 use std::sync::Arc;
 
 #[derive(Copy, Clone, Debug)]
@@ -25,28 +48,59 @@ pub struct RenderPipelineMainFormat {
 	pub g_albedo_format: Format,
 	pub g_normal_format: Format,
 	pub g_rm_format: Format,
+	pub g_emissive_format: Format,
+	pub g_velocity_format: Format,
 	pub depth_format: Format,
+	pub visibility_format: Format,
+	pub shadow_format: Format,
 }
 
 impl RenderPipelineMainFormat {
 	pub fn to_g_buffer_rp(&self) -> RenderPassFormat {
 		RenderPassFormat::new(
-			&[self.g_albedo_format, self.g_normal_format, self.g_rm_format],
+			&[
+				self.g_albedo_format,
+				self.g_normal_format,
+				self.g_rm_format,
+				self.g_emissive_format,
+				self.g_velocity_format,
+			],
 			Some(self.depth_format),
 		)
 	}
+
+	pub fn to_visibility_rp(&self) -> RenderPassFormat {
+		RenderPassFormat::new(&[self.visibility_format], Some(self.depth_format))
+	}
+
+	pub fn to_shadow_rp(&self) -> RenderPassFormat {
+		RenderPassFormat::new(&[], Some(self.shadow_format))
+	}
 }
 
 pub struct RenderPipelineMain {
 	pub bindless: Bindless,
 	pub format: RenderPipelineMainFormat,
+	/// capacity of the meshlet group buffer shared by all views, partitioned evenly across the views rendered in a
+	/// frame
 	pub meshlet_group_capacity: usize,
+	/// capacity of the meshlet instance buffers shared by all views, partitioned evenly across the views rendered in a
+	/// frame
 	pub meshlet_instance_capacity: usize,
 	pub instance_cull: InstanceCullCompute,
 	pub meshlet_select: MeshletSelectCompute,
 	pub meshlet_draw: MeshletDraw,
+	pub hzb: HzbCompute,
 	pub lighting: LightingCompute,
+	pub light_cluster: LightClusterCompute,
+	pub ibl: IblCompute,
+	pub ambient_occlusion: AmbientOcclusionCompute,
 	pub sky_shader: SkyShaderCompute,
+	pub material_pass: MaterialPassCompute,
+	pub software_raster: SoftwareRasterCompute,
+	pub taa: TaaCompute,
+	pub bloom: BloomCompute,
+	pub tonemap: TonemapCompute,
 }
 
 impl RenderPipelineMain {
@@ -63,7 +117,18 @@ impl RenderPipelineMain {
 			g_albedo_format: Format::R8G8B8A8_SRGB,
 			g_normal_format: Format::R16G16B16A16_SFLOAT,
 			g_rm_format: Format::R16G16_SFLOAT,
+			g_emissive_format: Format::R16G16B16A16_SFLOAT,
+			g_velocity_format: Format::R16G16_SFLOAT,
+			visibility_format: Format::R32_UINT,
+			shadow_format: Format::D32_SFLOAT,
 		};
+		if meshlet_instance_capacity > VisibilityId::MAX_MESHLET_INSTANCES as usize {
+			return Err(anyhow!(
+				"meshlet_instance_capacity {} exceeds the {} meshlet instances a visibility buffer can address",
+				meshlet_instance_capacity,
+				VisibilityId::MAX_MESHLET_INSTANCES
+			));
+		}
 
 		Ok(Arc::new(Self {
 			bindless: bindless.clone(),
@@ -72,9 +137,23 @@ impl RenderPipelineMain {
 			meshlet_instance_capacity,
 			instance_cull: InstanceCullCompute::new(bindless)?,
 			meshlet_select: MeshletSelectCompute::new(bindless)?,
-			meshlet_draw: MeshletDraw::new(bindless, format.to_g_buffer_rp())?,
+			meshlet_draw: MeshletDraw::new(
+				bindless,
+				format.to_g_buffer_rp(),
+				format.to_visibility_rp(),
+				format.to_shadow_rp(),
+			)?,
+			hzb: HzbCompute::new(bindless)?,
 			lighting: LightingCompute::new(bindless)?,
+			light_cluster: LightClusterCompute::new(bindless)?,
+			ibl: IblCompute::new(bindless)?,
+			ambient_occlusion: AmbientOcclusionCompute::new(bindless)?,
 			sky_shader: SkyShaderCompute::new(bindless)?,
+			material_pass: MaterialPassCompute::new(bindless)?,
+			software_raster: SoftwareRasterCompute::new(bindless)?,
+			taa: TaaCompute::new(bindless)?,
+			bloom: BloomCompute::new(bindless)?,
+			tonemap: TonemapCompute::new(bindless)?,
 		}))
 	}
 
@@ -85,40 +164,314 @@ impl RenderPipelineMain {
 
 pub struct RendererMain {
 	pub pipeline: Arc<RenderPipelineMain>,
-	resources: Option<RendererMainResources>,
+	/// the resources of each view, see [`RendererMain::new_frame_views`]
+	views: Vec<RendererMainResources>,
+	/// taken while rendering and recreated if rendering failed midway
+	shared: Option<RendererMainShared>,
+}
+
+/// The resources shared by all views, used by one view after another
+struct RendererMainShared {
+	meshlet_buffers: MeshletBuffers,
+	/// the IBL baked from the sky
+	ibl: IblResources,
 }
 
+impl RendererMainShared {
+	fn new(pipeline: &Arc<RenderPipelineMain>) -> anyhow::Result<Self> {
+		Ok(Self {
+			meshlet_buffers: MeshletBuffers::new(pipeline)?,
+			ibl: IblResources::new(&pipeline.bindless)?,
+		})
+	}
+}
+
+/// One of the views rendered by [`RendererMain::new_frame_views`]
+pub struct RenderView<'a, 'b> {
+	/// the frame data of this view, its camera's `viewport_size` being the size of the area rendered
+	pub frame_data: FrameData,
+	pub output_image: &'a MutImageAccess<'b, Image2d, StorageReadWrite>,
+	/// the top left corner of the area rendered into the `output_image`
+	pub output_offset: UVec2,
+}
+
+/// The resources of a single view
 struct RendererMainResources {
 	extent: Extent,
+	g_buffer: GBufferImages,
+	visibility_buffer: MutDesc<MutImage<Image2d>>,
+	depth_image: MutDesc<MutImage<Image2d>>,
+	/// the indirect args of this view's partition of the shared [`MeshletBuffers`]
+	meshlet_args: MeshletArgs,
+	software_raster: SoftwareRasterBuffers,
+	/// HZB of all meshlets drawn last frame, only valid if `hzb_last_frame_camera` is set
+	hzb_last_frame: HzbImages,
+	hzb_last_frame_camera: Option<Camera>,
+	hzb_current: HzbImages,
+	shadow_map: ShadowMapResources,
+	/// the lights of each cluster, see [`space_engine_shader::renderer::lighting::light_cluster`]
+	light_clusters: MutDesc<MutBuffer<[u32]>>,
+	ambient_occlusion: AoImages,
+	taa: TaaImages,
+	bloom: BloomImages,
+	tonemap: TonemapResources,
+}
+
+/// The compacting buffers meshlets are selected into, shared by all views
+struct MeshletBuffers {
+	groups: MutDesc<MutBuffer<[MeshletGroupInstance]>>,
+	/// meshlet instances selected in [`MeshletSelectPass::First`]
+	instances: MutDesc<MutBuffer<[MeshletInstance]>>,
+	/// meshlet instances selected in [`MeshletSelectPass::Second`], kept separately so the visibility buffer can refer
+	/// to the meshlet instances of both passes
+	instances_second: MutDesc<MutBuffer<[MeshletInstance]>>,
+	/// meshlet instances selected in [`MeshletSelectPass::First`] to be rasterized in software
+	software_instances: MutDesc<MutBuffer<[MeshletInstance]>>,
+	/// meshlet instances selected in [`MeshletSelectPass::Second`] to be rasterized in software
+	software_instances_second: MutDesc<MutBuffer<[MeshletInstance]>>,
+}
+
+impl MeshletBuffers {
+	fn new(pipeline: &Arc<RenderPipelineMain>) -> anyhow::Result<Self> {
+		let bindless = &pipeline.bindless;
+		let instances = |name| CompactingAllocBuffer::alloc_buffer(bindless, pipeline.meshlet_instance_capacity, name);
+		Ok(Self {
+			groups: CompactingAllocBuffer::alloc_buffer(
+				bindless,
+				pipeline.meshlet_group_capacity,
+				"compacting_meshlet_groups",
+			)?,
+			instances: instances("compacting_meshlet_instances")?,
+			instances_second: instances("compacting_meshlet_instances_second")?,
+			software_instances: instances("compacting_software_meshlet_instances")?,
+			software_instances_second: instances("compacting_software_meshlet_instances_second")?,
+		})
+	}
+
+	/// The `partition` of these buffers of a single view, counting its allocations in its own `args`
+	fn partition(self, partition: ViewPartition, args: MeshletArgs) -> ViewMeshletBuffers {
+		ViewMeshletBuffers {
+			groups: CompactingAllocBuffer::from_partition(self.groups, partition.groups, args.groups),
+			instances: CompactingAllocBuffer::from_partition(self.instances, partition.instances, args.instances),
+			instances_second: CompactingAllocBuffer::from_partition(
+				self.instances_second,
+				partition.instances,
+				args.instances_second,
+			),
+			software_instances: CompactingAllocBuffer::from_partition(
+				self.software_instances,
+				partition.instances,
+				args.software_instances,
+			),
+			software_instances_second: CompactingAllocBuffer::from_partition(
+				self.software_instances_second,
+				partition.instances,
+				args.software_instances_second,
+			),
+		}
+	}
+}
+
+/// The indirect args of a view's partition of the [`MeshletBuffers`]
+struct MeshletArgs {
+	groups: CompactingAllocArgs,
+	instances: CompactingAllocArgs,
+	instances_second: CompactingAllocArgs,
+	software_instances: CompactingAllocArgs,
+	software_instances_second: CompactingAllocArgs,
+}
+
+impl MeshletArgs {
+	fn new(bindless: &Bindless) -> anyhow::Result<Self> {
+		let args = |name| CompactingAllocArgs::new(bindless, [0, 1, 1], name);
+		Ok(Self {
+			groups: args("compacting_meshlet_groups")?,
+			instances: args("compacting_meshlet_instances")?,
+			instances_second: args("compacting_meshlet_instances_second")?,
+			software_instances: args("compacting_software_meshlet_instances")?,
+			software_instances_second: args("compacting_software_meshlet_instances_second")?,
+		})
+	}
+}
+
+/// A view's partition of the [`MeshletBuffers`], see [`MeshletBuffers::partition`]
+struct ViewMeshletBuffers {
+	groups: CompactingAllocBuffer<MeshletGroupInstance>,
+	instances: CompactingAllocBuffer<MeshletInstance>,
+	instances_second: CompactingAllocBuffer<MeshletInstance>,
+	software_instances: CompactingAllocBuffer<MeshletInstance>,
+	software_instances_second: CompactingAllocBuffer<MeshletInstance>,
+}
+
+impl ViewMeshletBuffers {
+	/// Returns the shared buffers for the next view and the args of this view for the next frame
+	fn into_parts(self) -> (MeshletBuffers, MeshletArgs) {
+		let (groups, groups_args) = self.groups.into_parts();
+		let (instances, instances_args) = self.instances.into_parts();
+		let (instances_second, instances_second_args) = self.instances_second.into_parts();
+		let (software_instances, software_instances_args) = self.software_instances.into_parts();
+		let (software_instances_second, software_instances_second_args) = self.software_instances_second.into_parts();
+		(
+			MeshletBuffers {
+				groups,
+				instances,
+				instances_second,
+				software_instances,
+				software_instances_second,
+			},
+			MeshletArgs {
+				groups: groups_args,
+				instances: instances_args,
+				instances_second: instances_second_args,
+				software_instances: software_instances_args,
+				software_instances_second: software_instances_second_args,
+			},
+		)
+	}
+}
+
+/// The partitions of the [`MeshletBuffers`] a single view allocates from
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct ViewPartition {
+	groups: CompactingAllocPartition,
+	instances: CompactingAllocPartition,
+}
+
+impl ViewPartition {
+	/// The partition of the `index`th of `count` views, splitting the pipeline's capacities evenly
+	fn new(meshlet_group_capacity: usize, meshlet_instance_capacity: usize, index: usize, count: usize) -> Self {
+		Self {
+			groups: CompactingAllocPartition::split(meshlet_group_capacity, index, count),
+			instances: CompactingAllocPartition::split(meshlet_instance_capacity, index, count),
+		}
+	}
+}
+
+/// The camera the HZB of last frame was drawn from and whether to occlusion cull against it. A view without any last
+/// frame, like a newly added one, or with occlusion culling disabled falls back to its own `camera` and doesn't cull.
+fn last_frame_hzb(hzb_last_frame_camera: Option<Camera>, camera: Camera, occlusion_culling: bool) -> (Camera, bool) {
+	(
+		hzb_last_frame_camera.unwrap_or(camera),
+		occlusion_culling && hzb_last_frame_camera.is_some(),
+	)
+}
+
+/// The color images of the g-buffer
+struct GBufferImages {
 	g_albedo: MutDesc<MutImage<Image2d>>,
 	g_normal: MutDesc<MutImage<Image2d>>,
 	g_roughness_metallic: MutDesc<MutImage<Image2d>>,
-	depth_image: MutDesc<MutImage<Image2d>>,
-	compacting_meshlet_groups: CompactingAllocBuffer<MeshletGroupInstance>,
-	compacting_meshlet_instances: CompactingAllocBuffer<MeshletInstance>,
+	g_emissive: MutDesc<MutImage<Image2d>>,
+	/// also written as a storage image by the visibility buffer's material pass
+	g_velocity: MutDesc<MutImage<Image2d>>,
+}
+
+impl GBufferImages {
+	fn new(pipeline: &Arc<RenderPipelineMain>, extent: Extent) -> anyhow::Result<Self> {
+		let alloc = |format: Format, name: &str| {
+			pipeline.bindless.image().alloc(&BindlessImageCreateInfo {
+				format,
+				extent,
+				usage: BindlessImageUsage::COLOR_ATTACHMENT | BindlessImageUsage::SAMPLED,
+				name,
+				..Default::default()
+			})
+		};
+		Ok(Self {
+			g_albedo: alloc(pipeline.format.g_albedo_format, "g_albedo")?,
+			g_normal: alloc(pipeline.format.g_normal_format, "g_normal")?,
+			g_roughness_metallic: alloc(pipeline.format.g_rm_format, "g_roughness_metallic")?,
+			g_emissive: alloc(pipeline.format.g_emissive_format, "g_emissive")?,
+			g_velocity: pipeline.bindless.image().alloc(&BindlessImageCreateInfo {
+				format: pipeline.format.g_velocity_format,
+				extent,
+				usage: BindlessImageUsage::COLOR_ATTACHMENT | BindlessImageUsage::STORAGE | BindlessImageUsage::SAMPLED,
+				name: "g_velocity",
+				..Default::default()
+			})?,
+		})
+	}
+
+	fn access_dont_care<'a, A: ImageAccessType>(
+		self,
+		cmd: &Recording<'a>,
+	) -> Result<GBufferAccess<'a, A>, AccessError> {
+		Ok(GBufferAccess {
+			g_albedo: self.g_albedo.access_dont_care(cmd)?,
+			g_normal: self.g_normal.access_dont_care(cmd)?,
+			g_roughness_metallic: self.g_roughness_metallic.access_dont_care(cmd)?,
+			g_emissive: self.g_emissive.access_dont_care(cmd)?,
+			g_velocity: self.g_velocity.access_dont_care(cmd)?,
+		})
+	}
+}
+
+struct GBufferAccess<'a, A: ImageAccessType> {
+	g_albedo: MutImageAccess<'a, Image2d, A>,
+	g_normal: MutImageAccess<'a, Image2d, A>,
+	g_roughness_metallic: MutImageAccess<'a, Image2d, A>,
+	g_emissive: MutImageAccess<'a, Image2d, A>,
+	g_velocity: MutImageAccess<'a, Image2d, A>,
+}
+
+impl<'a, A: ImageAccessType> GBufferAccess<'a, A> {
+	fn transition<B: ImageAccessType>(self) -> Result<GBufferAccess<'a, B>, AccessError> {
+		Ok(GBufferAccess {
+			g_albedo: self.g_albedo.transition()?,
+			g_normal: self.g_normal.transition()?,
+			g_roughness_metallic: self.g_roughness_metallic.transition()?,
+			g_emissive: self.g_emissive.transition()?,
+			g_velocity: self.g_velocity.transition()?,
+		})
+	}
+
+	fn into_images(self) -> GBufferImages {
+		GBufferImages {
+			g_albedo: self.g_albedo.into_desc(),
+			g_normal: self.g_normal.into_desc(),
+			g_roughness_metallic: self.g_roughness_metallic.into_desc(),
+			g_emissive: self.g_emissive.into_desc(),
+			g_velocity: self.g_velocity.into_desc(),
+		}
+	}
+}
+
+impl GBufferAccess<'_, SampledRead> {
+	fn to_g_buffer<'b>(
+		&'b self,
+		depth_image: TransientDesc<'b, Image<Image2d>>,
+	) -> Result<GBuffer<Transient<'b>>, AccessError> {
+		Ok(GBuffer {
+			g_albedo: self.g_albedo.to_transient_sampled()?,
+			g_normal: self.g_normal.to_transient_sampled()?,
+			g_roughness_metallic: self.g_roughness_metallic.to_transient_sampled()?,
+			g_emissive: self.g_emissive.to_transient_sampled()?,
+			g_velocity: self.g_velocity.to_transient_sampled()?,
+			depth_image,
+		})
+	}
+}
+
+/// The color images meshlets are drawn into, depending on `NaniteSettings::visibility_buffer`. The images of the
+/// other mode are carried along unused.
+enum MeshletTargets<'a> {
+	GBuffer {
+		g_buffer: GBufferAccess<'a, ColorAttachment>,
+		visibility_buffer: MutDesc<MutImage<Image2d>>,
+	},
+	VisibilityBuffer {
+		visibility_buffer: MutImageAccess<'a, Image2d, ColorAttachment>,
+		g_buffer: GBufferImages,
+	},
 }
 
 impl RendererMainResources {
-	pub fn new(pipeline: &Arc<RenderPipelineMain>, extent: Extent) -> anyhow::Result<Self> {
-		let g_albedo = pipeline.bindless.image().alloc(&BindlessImageCreateInfo {
-			format: pipeline.format.g_albedo_format,
+	pub fn new(pipeline: &Arc<RenderPipelineMain>, extent: Extent, shadow_atlas_size: UVec2) -> anyhow::Result<Self> {
+		let visibility_buffer = pipeline.bindless.image().alloc(&BindlessImageCreateInfo {
+			format: pipeline.format.visibility_format,
 			extent,
 			usage: BindlessImageUsage::COLOR_ATTACHMENT | BindlessImageUsage::SAMPLED,
-			name: "g_albedo",
-			..Default::default()
-		})?;
-		let g_normal = pipeline.bindless.image().alloc(&BindlessImageCreateInfo {
-			format: pipeline.format.g_normal_format,
-			extent,
-			usage: BindlessImageUsage::COLOR_ATTACHMENT | BindlessImageUsage::SAMPLED,
-			name: "g_normal",
-			..Default::default()
-		})?;
-		let g_roughness_metallic = pipeline.bindless.image().alloc(&BindlessImageCreateInfo {
-			format: pipeline.format.g_rm_format,
-			extent,
-			usage: BindlessImageUsage::COLOR_ATTACHMENT | BindlessImageUsage::SAMPLED,
-			name: "g_roughness_metallic",
+			name: "visibility_buffer",
 			..Default::default()
 		})?;
 		let depth_image = pipeline.bindless.image().alloc(&BindlessImageCreateInfo {
@@ -128,26 +481,23 @@ impl RendererMainResources {
 			name: "g_depth",
 			..Default::default()
 		})?;
-		let compacting_meshlet_groups = CompactingAllocBuffer::new(
-			&pipeline.bindless,
-			pipeline.meshlet_group_capacity,
-			[0, 1, 1],
-			"compacting_meshlet_groups",
-		)?;
-		let compacting_meshlet_instances = CompactingAllocBuffer::new(
-			&pipeline.bindless,
-			pipeline.meshlet_instance_capacity,
-			[0, 1, 1],
-			"compacting_meshlet_instances",
-		)?;
+		let hzb_size = UVec2::new(extent.width, extent.height);
 		Ok(RendererMainResources {
 			extent,
+			g_buffer: GBufferImages::new(pipeline, extent)?,
+			visibility_buffer,
 			depth_image,
-			g_albedo,
-			g_normal,
-			g_roughness_metallic,
-			compacting_meshlet_groups,
-			compacting_meshlet_instances,
+			meshlet_args: MeshletArgs::new(&pipeline.bindless)?,
+			software_raster: SoftwareRasterBuffers::new(&pipeline.bindless, hzb_size)?,
+			hzb_last_frame: HzbImages::new(&pipeline.bindless, hzb_size)?,
+			hzb_last_frame_camera: None,
+			hzb_current: HzbImages::new(&pipeline.bindless, hzb_size)?,
+			shadow_map: ShadowMapResources::new(&pipeline.bindless, pipeline.format.shadow_format, shadow_atlas_size)?,
+			light_clusters: alloc_light_clusters(&pipeline.bindless, hzb_size)?,
+			ambient_occlusion: AoImages::new(&pipeline.bindless, hzb_size)?,
+			taa: TaaImages::new(&pipeline.bindless, hzb_size)?,
+			bloom: BloomImages::new(&pipeline.bindless, hzb_size)?,
+			tonemap: TonemapResources::new(&pipeline.bindless, hzb_size)?,
 		})
 	}
 }
@@ -155,11 +505,13 @@ impl RendererMainResources {
 impl RendererMain {
 	fn new(pipeline: Arc<RenderPipelineMain>) -> anyhow::Result<Self> {
 		Ok(Self {
+			shared: Some(RendererMainShared::new(&pipeline)?),
 			pipeline,
-			resources: None,
+			views: Vec::new(),
 		})
 	}
 
+	/// Renders a single view covering the entire `output_image`
 	pub fn new_frame(
 		&mut self,
 		cmd: &mut Recording<'_>,
@@ -167,102 +519,628 @@ impl RendererMain {
 		scene: &InstancedMeshletSceneCpu,
 		output_image: &MutImageAccess<'_, Image2d, StorageReadWrite>,
 	) -> anyhow::Result<()> {
-		self.image_supported(output_image)?;
-		let resources = {
-			let extent = output_image.extent();
-			let resources = if let Some(resources) = self.resources.take() {
-				if resources.extent == extent {
-					Some(resources)
-				} else {
-					drop(resources);
-					None
-				}
-			} else {
-				None
-			};
-			if let Some(resources) = resources {
-				resources
-			} else {
-				RendererMainResources::new(&self.pipeline, extent)?
-			}
+		self.new_frame_views(
+			cmd,
+			scene,
+			&[RenderView {
+				frame_data,
+				output_image,
+				output_offset: UVec2::ZERO,
+			}],
+		)
+	}
+
+	/// Renders all `views` of the `scene`, each culling and selecting the LOD of meshlets from its own camera. Each
+	/// view keeps its own resources across frames, like its HZB and TAA history, so the views should be passed in the
+	/// same order every frame. All views share the pipeline's meshlet buffers, each allocating from its own equally sized
+	/// partition, so changing the number of views doesn't reallocate them.
+	///
+	/// Views may share an output image, e.g. for split-screen, as long as their areas don't overlap.
+	pub fn new_frame_views(
+		&mut self,
+		cmd: &mut Recording<'_>,
+		scene: &InstancedMeshletSceneCpu,
+		views: &[RenderView],
+	) -> anyhow::Result<()> {
+		if views.is_empty() {
+			return Ok(());
+		}
+
+		let mut shared = match self.shared.take() {
+			Some(shared) => shared,
+			None => RendererMainShared::new(&self.pipeline)?,
 		};
+		let mut prev_views = std::mem::take(&mut self.views).into_iter();
+		let mut resources_views = Vec::with_capacity(views.len());
+		for (index, view) in views.iter().enumerate() {
+			self.image_supported(view.output_image)?;
+			let viewport_size = view.frame_data.camera.viewport_size;
+			let output_extent = view.output_image.extent();
+			let output_size = UVec2::new(output_extent.width, output_extent.height);
+			if !(view.output_offset + viewport_size).cmple(output_size).all() {
+				return Err(anyhow!(
+					"View of size {} at {} exceeds the output image of size {}",
+					viewport_size,
+					view.output_offset,
+					output_size
+				));
+			}
+
+			let extent = Extent::from(viewport_size);
+			let resources = match prev_views.next() {
+				Some(resources) if resources.extent == extent => resources,
+				_ => RendererMainResources::new(&self.pipeline, extent, view.frame_data.shadow.atlas_size())?,
+			};
+			let partition = ViewPartition::new(
+				self.pipeline.meshlet_group_capacity,
+				self.pipeline.meshlet_instance_capacity,
+				index,
+				views.len(),
+			);
+			let (resources, next_shared) = self.render_view(cmd, view, scene, partition, resources, shared)?;
+			resources_views.push(resources);
+			shared = next_shared;
+		}
+		self.views = resources_views;
+		self.shared = Some(shared);
+		Ok(())
+	}
+
+	/// Renders a single `view` with its `resources` into its `partition` of the `shared` meshlet buffers, returning
+	/// both for the next frame and view
+	fn render_view(
+		&self,
+		cmd: &mut Recording<'_>,
+		view: &RenderView,
+		scene: &InstancedMeshletSceneCpu,
+		partition: ViewPartition,
+		resources: RendererMainResources,
+		shared: RendererMainShared,
+	) -> anyhow::Result<(RendererMainResources, RendererMainShared)> {
+		let frame_data = view.frame_data;
+		let output_image = view.output_image;
 		let frame_context = FrameContext::new(cmd, frame_data)?;
+		let occlusion_culling = frame_data.nanite.occlusion_culling();
+		let software_rasterization = frame_data.nanite.software_rasterization();
+		let hzb_last_frame = resources.hzb_last_frame.access(cmd)?;
+		let (hzb_last_frame_camera, hzb_last_frame_enabled) =
+			last_frame_hzb(resources.hzb_last_frame_camera, frame_data.camera, occlusion_culling);
+		let hzb_last_frame_param = hzb_last_frame.to_hzb(hzb_last_frame_camera, hzb_last_frame_enabled)?;
+		let hzb_disabled = Hzb {
+			enabled: 0,
+			..hzb_last_frame_param
+		};
+		let meshlet_buffers = shared.meshlet_buffers.partition(partition, resources.meshlet_args);
+		let (shadow_map, meshlet_buffers) = self.draw_shadow_map(
+			cmd,
+			frame_data,
+			scene,
+			resources.shadow_map,
+			meshlet_buffers,
+			hzb_disabled,
+		)?;
 
-		let meshlet_instances = resources.compacting_meshlet_instances.transition_writing(cmd)?;
-		let meshlet_groups = resources.compacting_meshlet_groups.transition_writing(cmd)?;
+		let meshlet_instances = meshlet_buffers.instances.transition_writing(cmd)?;
+		let software_meshlet_instances = meshlet_buffers.software_instances.transition_writing(cmd)?;
+		let meshlet_groups = meshlet_buffers.groups.transition_writing(cmd)?;
 		self.pipeline
 			.instance_cull
 			.dispatch(cmd, &frame_context, scene, &meshlet_groups)?;
 		let meshlet_groups = meshlet_groups.transition_reading()?;
-		self.pipeline
-			.meshlet_select
-			.dispatch(cmd, &frame_context, scene, &meshlet_groups, &meshlet_instances)?;
+		self.pipeline.meshlet_select.dispatch(
+			cmd,
+			&frame_context,
+			scene,
+			&meshlet_groups,
+			&meshlet_instances,
+			&software_meshlet_instances,
+			MeshletSelectPass::First,
+			hzb_last_frame_param,
+			hzb_disabled,
+		)?;
 
 		let meshlet_instances = meshlet_instances.transition_reading()?;
-		let mut g_albedo = resources.g_albedo.access_dont_care::<ColorAttachment>(cmd)?;
-		let mut g_normal = resources.g_normal.access_dont_care::<ColorAttachment>(cmd)?;
-		let mut g_roughness_metallic = resources
-			.g_roughness_metallic
-			.access_dont_care::<ColorAttachment>(cmd)?;
+		let software_meshlet_instances = software_meshlet_instances.transition_reading()?;
+		let mut targets = if frame_data.nanite.visibility_buffer() {
+			MeshletTargets::VisibilityBuffer {
+				visibility_buffer: resources.visibility_buffer.access_dont_care(cmd)?,
+				g_buffer: resources.g_buffer,
+			}
+		} else {
+			MeshletTargets::GBuffer {
+				g_buffer: resources.g_buffer.access_dont_care(cmd)?,
+				visibility_buffer: resources.visibility_buffer,
+			}
+		};
 		let mut depth_image = resources.depth_image.access_dont_care::<DepthStencilAttachment>(cmd)?;
-		cmd.begin_rendering(
-			self.pipeline.format.to_g_buffer_rp(),
-			&[
-				RenderingAttachment {
-					image: &mut g_albedo,
-					load_op: LoadOp::Clear(ClearValue::ColorF([0., 0., 0., 0.])),
-					store_op: StoreOp::Store,
-				},
-				RenderingAttachment {
-					image: &mut g_normal,
-					load_op: LoadOp::DontCare,
-					store_op: StoreOp::Store,
-				},
-				RenderingAttachment {
-					image: &mut g_roughness_metallic,
-					load_op: LoadOp::DontCare,
-					store_op: StoreOp::Store,
-				},
-			],
-			Some(RenderingAttachment {
-				image: &mut depth_image,
-				load_op: LoadOp::Clear(ClearValue::DepthStencil { depth: 1., stencil: 0 }),
-				store_op: StoreOp::Store,
-			}),
-			|rendering| {
-				self.pipeline
-					.meshlet_draw
-					.draw(rendering, &frame_context, scene, &meshlet_instances)?;
-				Ok(())
-			},
+		self.draw_meshlets(
+			cmd,
+			&frame_context,
+			scene,
+			&meshlet_instances,
+			&mut targets,
+			&mut depth_image,
+			MeshletSelectPass::First,
 		)?;
+		let software_raster = resources.software_raster.access(cmd, software_rasterization)?;
+		if software_rasterization {
+			self.pipeline.software_raster.rasterize_depth(
+				cmd,
+				&frame_context,
+				scene,
+				&software_meshlet_instances,
+				&software_raster.depth,
+			)?;
+		}
+		let mut software_raster = software_raster.transition::<ShaderRead, ShaderReadWrite>()?;
+
+		let meshlet_instances_second = meshlet_buffers.instances_second.transition_writing(cmd)?;
+		let software_meshlet_instances_second = meshlet_buffers.software_instances_second.transition_writing(cmd)?;
+		let (mut depth_image, hzb_current) = if occlusion_culling {
+			// HZB of the meshlets visible last frame, to test all other meshlets against
+			let depth_sampled = depth_image.transition::<SampledRead>()?;
+			let hzb_current = self.pipeline.hzb.build(
+				cmd,
+				&depth_sampled,
+				&software_raster.depth,
+				software_rasterization,
+				resources.hzb_current,
+			)?;
+			let depth_image = depth_sampled.transition::<DepthStencilAttachment>()?;
+
+			self.pipeline.meshlet_select.dispatch(
+				cmd,
+				&frame_context,
+				scene,
+				&meshlet_groups,
+				&meshlet_instances_second,
+				&software_meshlet_instances_second,
+				MeshletSelectPass::Second,
+				hzb_last_frame_param,
+				hzb_current.to_hzb(frame_data.camera, true)?,
+			)?;
+			(depth_image, hzb_current)
+		} else {
+			(depth_image, resources.hzb_current.access(cmd)?)
+		};
+		// without occlusion culling, the second pass stays empty
+		let meshlet_instances_second = meshlet_instances_second.transition_reading()?;
+		let software_meshlet_instances_second = software_meshlet_instances_second.transition_reading()?;
+		if occlusion_culling {
+			self.draw_meshlets(
+				cmd,
+				&frame_context,
+				scene,
+				&meshlet_instances_second,
+				&mut targets,
+				&mut depth_image,
+				MeshletSelectPass::Second,
+			)?;
+			if software_rasterization {
+				let writing = software_raster.transition::<ShaderReadWrite, ShaderReadWrite>()?;
+				self.pipeline.software_raster.rasterize_depth(
+					cmd,
+					&frame_context,
+					scene,
+					&software_meshlet_instances_second,
+					&writing.depth,
+				)?;
+				software_raster = writing.transition()?;
+			}
+		}
+		if software_rasterization {
+			// both passes only write pixels where their own triangles are nearest, so they need no barrier in between
+			self.pipeline.software_raster.rasterize_visibility(
+				cmd,
+				&frame_context,
+				scene,
+				&software_meshlet_instances,
+				MeshletSelectPass::First,
+				&software_raster,
+			)?;
+			if occlusion_culling {
+				self.pipeline.software_raster.rasterize_visibility(
+					cmd,
+					&frame_context,
+					scene,
+					&software_meshlet_instances_second,
+					MeshletSelectPass::Second,
+					&software_raster,
+				)?;
+			}
+		}
+		let software_raster = software_raster.transition::<ShaderRead, ShaderRead>()?;
 
-		let g_albedo = g_albedo.transition::<SampledRead>()?;
-		let g_normal = g_normal.transition::<SampledRead>()?;
-		let g_roughness_metallic = g_roughness_metallic.transition::<SampledRead>()?;
 		let depth_image = depth_image.transition::<SampledRead>()?;
-		let g_buffer = GBuffer {
-			g_albedo: g_albedo.to_transient_sampled()?,
-			g_normal: g_normal.to_transient_sampled()?,
-			g_roughness_metallic: g_roughness_metallic.to_transient_sampled()?,
-			depth_image: depth_image.to_transient_sampled()?,
+		// HZB of all meshlets drawn this frame, to cull against next frame
+		let hzb_current = if occlusion_culling {
+			self.pipeline.hzb.build(
+				cmd,
+				&depth_image,
+				&software_raster.depth,
+				software_rasterization,
+				hzb_current.into_images(),
+			)?
+		} else {
+			hzb_current
 		};
+		let light_clusters = resources.light_clusters.access::<ShaderReadWrite>(cmd)?;
 		self.pipeline
-			.sky_shader
-			.dispatch(cmd, &frame_context, g_buffer, output_image.to_mut_transient())?;
+			.light_cluster
+			.dispatch(cmd, &frame_context, scene, &light_clusters)?;
+		let light_clusters = light_clusters.transition::<ShaderRead>()?;
+		let light_clusters_param = LightClusters {
+			lights: scene.lights.to_transient(cmd),
+			clusters: light_clusters.to_transient()?,
+		};
+		let ibl = self.pipeline.ibl.bake(cmd, &frame_context, shared.ibl)?;
+		let ibl_param = ibl.to_ibl(self.pipeline.ibl.sampler().to_transient(cmd))?;
+
+		// the frame is lit in HDR to be tonemapped into the output image, with TAA into an intermediate image to be
+		// resolved into the HDR image
+		let hdr_target = resources.tonemap.access_hdr(cmd)?;
+		let taa_target = resources.taa.access_color(cmd)?;
+		let lit_image = if frame_data.taa.enabled() {
+			taa_target.to_mut_transient()
+		} else {
+			hdr_target.to_mut_transient()
+		};
+
+		let depth_transient = depth_image.to_transient_sampled()?;
 		self.pipeline
-			.lighting
-			.dispatch(cmd, &frame_context, g_buffer, output_image.to_mut_transient())?;
+			.sky_shader
+			.dispatch(cmd, &frame_context, depth_transient, lit_image)?;
+		let (g_buffer, visibility_buffer, ambient_occlusion, taa) = match targets {
+			MeshletTargets::GBuffer {
+				g_buffer,
+				visibility_buffer,
+			} => {
+				let g_buffer = g_buffer.transition::<SampledRead>()?;
+				let ambient_occlusion = self.pipeline.ambient_occlusion.dispatch(
+					cmd,
+					&frame_context,
+					depth_transient,
+					Some(g_buffer.g_normal.to_transient_sampled()?),
+					resources.ambient_occlusion,
+				)?;
+				self.pipeline.lighting.dispatch(
+					cmd,
+					&frame_context,
+					g_buffer.to_g_buffer(depth_transient)?,
+					shadow_map.to_shadow_map()?,
+					light_clusters_param,
+					ibl_param,
+					ambient_occlusion.to_ambient_occlusion()?,
+					lit_image,
+				)?;
+				let taa = self.pipeline.taa.dispatch(
+					cmd,
+					&frame_context,
+					taa_target,
+					depth_transient,
+					g_buffer.g_velocity.to_transient_sampled()?,
+					hdr_target.to_mut_transient(),
+				)?;
+				(
+					g_buffer.into_images(),
+					visibility_buffer,
+					ambient_occlusion.into_images(),
+					taa,
+				)
+			}
+			MeshletTargets::VisibilityBuffer {
+				visibility_buffer,
+				g_buffer,
+			} => {
+				let visibility_buffer = visibility_buffer.transition::<SampledRead>()?;
+				// the g-buffer isn't written, so normals are reconstructed from depth
+				let ambient_occlusion = self.pipeline.ambient_occlusion.dispatch(
+					cmd,
+					&frame_context,
+					depth_transient,
+					None,
+					resources.ambient_occlusion,
+				)?;
+				let g_velocity = g_buffer.g_velocity.access_dont_care::<StorageReadWrite>(cmd)?;
+				self.pipeline.material_pass.dispatch(
+					cmd,
+					&frame_context,
+					scene,
+					self.pipeline.meshlet_draw.sampler(),
+					meshlet_instance_buffers(&meshlet_instances, &meshlet_instances_second)?,
+					&visibility_buffer,
+					depth_transient,
+					meshlet_instance_buffers(&software_meshlet_instances, &software_meshlet_instances_second)?,
+					&software_raster,
+					shadow_map.to_shadow_map()?,
+					light_clusters_param,
+					ibl_param,
+					ambient_occlusion.to_ambient_occlusion()?,
+					lit_image,
+					g_velocity.to_mut_transient(),
+				)?;
+				let g_velocity = g_velocity.transition::<SampledRead>()?;
+				let taa = self.pipeline.taa.dispatch(
+					cmd,
+					&frame_context,
+					taa_target,
+					depth_transient,
+					g_velocity.to_transient_sampled()?,
+					hdr_target.to_mut_transient(),
+				)?;
+				(
+					GBufferImages {
+						g_velocity: g_velocity.into_desc(),
+						..g_buffer
+					},
+					visibility_buffer.into_desc(),
+					ambient_occlusion.into_images(),
+					taa,
+				)
+			}
+		};
+		let hdr_target = hdr_target.transition::<SampledRead>()?;
+		let bloom =
+			self.pipeline
+				.bloom
+				.dispatch(cmd, &frame_context, hdr_target.to_transient_sampled()?, resources.bloom)?;
+		let bloom_param = bloom.to_bloom(self.pipeline.bloom.sampler().to_transient(cmd))?;
+		let tonemap = self.pipeline.tonemap.dispatch(
+			cmd,
+			&frame_context,
+			hdr_target,
+			bloom_param,
+			output_image.to_mut_transient(),
+			view.output_offset,
+		)?;
 
-		self.resources = Some(RendererMainResources {
+		let (meshlet_buffers, meshlet_args) = ViewMeshletBuffers {
+			groups: meshlet_groups.transition_reset(),
+			instances: meshlet_instances.transition_reset(),
+			instances_second: meshlet_instances_second.transition_reset(),
+			software_instances: software_meshlet_instances.transition_reset(),
+			software_instances_second: software_meshlet_instances_second.transition_reset(),
+		}
+		.into_parts();
+		let resources = RendererMainResources {
 			extent: resources.extent,
-			g_albedo: g_albedo.into_desc(),
-			g_normal: g_normal.into_desc(),
-			g_roughness_metallic: g_roughness_metallic.into_desc(),
+			g_buffer,
+			visibility_buffer,
 			depth_image: depth_image.into_desc(),
-			compacting_meshlet_groups: meshlet_groups.transition_reset(),
-			compacting_meshlet_instances: meshlet_instances.transition_reset(),
-		});
+			meshlet_args,
+			software_raster: software_raster.into_buffers(),
+			hzb_last_frame: hzb_current.into_images(),
+			hzb_last_frame_camera: occlusion_culling.then_some(frame_data.camera),
+			hzb_current: hzb_last_frame.into_images(),
+			shadow_map: shadow_map.into_resources(),
+			light_clusters: light_clusters.into_desc(),
+			ambient_occlusion,
+			taa,
+			bloom: bloom.into_images(),
+			tonemap,
+		};
+		let shared = RendererMainShared {
+			meshlet_buffers,
+			ibl: ibl.into_resources(),
+		};
+		Ok((resources, shared))
+	}
+
+	/// Draws the depth of all cascades of the sun's shadow map. The meshlets of each cascade are culled and selected
+	/// from its light camera, without occlusion culling, into the view's `meshlet_buffers` before returning them to
+	/// the view.
+	fn draw_shadow_map<'a>(
+		&self,
+		cmd: &mut Recording<'a>,
+		frame_data: FrameData,
+		scene: &InstancedMeshletSceneCpu,
+		resources: ShadowMapResources,
+		meshlet_buffers: ViewMeshletBuffers,
+		hzb_disabled: Hzb,
+	) -> anyhow::Result<(ShadowMapAccess<'a>, ViewMeshletBuffers)> {
+		profiling::function_scope!();
+		let settings = frame_data.shadow;
+		let resources = resources.resize(
+			&self.pipeline.bindless,
+			self.pipeline.format.shadow_format,
+			settings.atlas_size(),
+		)?;
+		let cascades_cpu = ShadowCascades::new(&frame_data);
+		let cascades = ShadowMapAccess::upload_cascades(cmd, &cascades_cpu)?;
+		let mut meshlet_groups = meshlet_buffers.groups;
+		let mut meshlet_instances = meshlet_buffers.instances;
+		let atlas = if settings.enabled() {
+			let mut atlas = resources.atlas.access_dont_care::<DepthStencilAttachment>(cmd)?;
+			let resolution = settings.resolution();
+			let cascade_count = settings.cascade_count() as usize;
+			for (i, cascade) in cascades_cpu.cascades.iter().take(cascade_count).enumerate() {
+				let frame_context = FrameContext::new(
+					cmd,
+					FrameData {
+						camera: cascade.camera,
+						nanite: NaniteSettings {
+							occlusion_culling: 0,
+							backface_culling: 0,
+							software_rasterization: 0,
+							..frame_data.nanite
+						},
+						..frame_data
+					},
+				)?;
+				let groups = meshlet_groups.transition_writing(cmd)?;
+				self.pipeline
+					.instance_cull
+					.dispatch(cmd, &frame_context, scene, &groups)?;
+				let groups = groups.transition_reading()?;
+				let instances = meshlet_instances.transition_writing(cmd)?;
+				// software rasterization is disabled, so nothing is written into the second output
+				self.pipeline.meshlet_select.dispatch(
+					cmd,
+					&frame_context,
+					scene,
+					&groups,
+					&instances,
+					&instances,
+					MeshletSelectPass::First,
+					hzb_disabled,
+					hzb_disabled,
+				)?;
+				let instances = instances.transition_reading()?;
+
+				// the first cascade clears the entire atlas
+				let load_op = if i == 0 {
+					LoadOp::Clear(ClearValue::DepthStencil { depth: 1., stencil: 0 })
+				} else {
+					LoadOp::Load
+				};
+				cmd.begin_rendering(
+					self.pipeline.format.to_shadow_rp(),
+					&[],
+					Some(RenderingAttachment {
+						image: &mut atlas,
+						load_op,
+						store_op: StoreOp::Store,
+					}),
+					|rendering| {
+						let origin = UVec2::new(i as u32 * resolution, 0);
+						rendering.set_viewport(Viewport {
+							x: origin.x as f32,
+							y: origin.y as f32,
+							width: resolution as f32,
+							height: resolution as f32,
+							min_depth: 0.,
+							max_depth: 1.,
+						});
+						rendering.set_scissor(IRect2 {
+							origin: origin.as_ivec2(),
+							extent: UVec2::splat(resolution),
+						});
+						self.pipeline.meshlet_draw.draw_shadow(
+							rendering,
+							&frame_context,
+							scene,
+							&instances,
+							MeshletSelectPass::First,
+						)?;
+						Ok(())
+					},
+				)?;
+				meshlet_groups = groups.transition_reset();
+				meshlet_instances = instances.transition_reset();
+			}
+			atlas.transition::<SampledRead>()?
+		} else {
+			// never sampled, as the sun is never occluded
+			resources.atlas.access_dont_care::<SampledRead>(cmd)?
+		};
+		let shadow_map = ShadowMapAccess {
+			atlas,
+			atlas_size: resources.atlas_size,
+			cascades,
+			cascades_cpu,
+		};
+		let meshlet_buffers = ViewMeshletBuffers {
+			groups: meshlet_groups,
+			instances: meshlet_instances,
+			..meshlet_buffers
+		};
+		Ok((shadow_map, meshlet_buffers))
+	}
+
+	/// Draws the selected meshlets into the `targets`. The first pass clears them and the second pass draws on top.
+	#[allow(clippy::too_many_arguments)]
+	fn draw_meshlets<'a>(
+		&self,
+		cmd: &mut Recording<'a>,
+		frame_context: &FrameContext,
+		scene: &InstancedMeshletSceneCpu,
+		meshlet_instances: &CompactingAllocBufferReading<MeshletInstance>,
+		targets: &mut MeshletTargets<'a>,
+		depth_image: &mut MutImageAccess<'a, Image2d, DepthStencilAttachment>,
+		pass: MeshletSelectPass,
+	) -> anyhow::Result<()> {
+		let depth_load_op = match pass {
+			MeshletSelectPass::First => LoadOp::Clear(ClearValue::DepthStencil { depth: 1., stencil: 0 }),
+			MeshletSelectPass::Second => LoadOp::Load,
+		};
+		match targets {
+			MeshletTargets::GBuffer { g_buffer, .. } => {
+				let (albedo_load_op, load_op) = match pass {
+					MeshletSelectPass::First => (LoadOp::Clear(ClearValue::ColorF([0., 0., 0., 0.])), LoadOp::DontCare),
+					MeshletSelectPass::Second => (LoadOp::Load, LoadOp::Load),
+				};
+				cmd.begin_rendering(
+					self.pipeline.format.to_g_buffer_rp(),
+					&[
+						RenderingAttachment {
+							image: &mut g_buffer.g_albedo,
+							load_op: albedo_load_op,
+							store_op: StoreOp::Store,
+						},
+						RenderingAttachment {
+							image: &mut g_buffer.g_normal,
+							load_op,
+							store_op: StoreOp::Store,
+						},
+						RenderingAttachment {
+							image: &mut g_buffer.g_roughness_metallic,
+							load_op,
+							store_op: StoreOp::Store,
+						},
+						RenderingAttachment {
+							image: &mut g_buffer.g_emissive,
+							load_op,
+							store_op: StoreOp::Store,
+						},
+						RenderingAttachment {
+							image: &mut g_buffer.g_velocity,
+							load_op,
+							store_op: StoreOp::Store,
+						},
+					],
+					Some(RenderingAttachment {
+						image: depth_image,
+						load_op: depth_load_op,
+						store_op: StoreOp::Store,
+					}),
+					|rendering| {
+						self.pipeline
+							.meshlet_draw
+							.draw(rendering, frame_context, scene, meshlet_instances, pass)?;
+						Ok(())
+					},
+				)?;
+			}
+			MeshletTargets::VisibilityBuffer { visibility_buffer, .. } => {
+				let load_op = match pass {
+					MeshletSelectPass::First => LoadOp::Clear(ClearValue::ColorU([VisibilityId::EMPTY.0; 4])),
+					MeshletSelectPass::Second => LoadOp::Load,
+				};
+				cmd.begin_rendering(
+					self.pipeline.format.to_visibility_rp(),
+					&[RenderingAttachment {
+						image: visibility_buffer,
+						load_op,
+						store_op: StoreOp::Store,
+					}],
+					Some(RenderingAttachment {
+						image: depth_image,
+						load_op: depth_load_op,
+						store_op: StoreOp::Store,
+					}),
+					|rendering| {
+						self.pipeline.meshlet_draw.draw_visibility(
+							rendering,
+							frame_context,
+							scene,
+							meshlet_instances,
+							pass,
+						)?;
+						Ok(())
+					},
+				)?;
+			}
+		}
 		Ok(())
 	}
 
@@ -281,3 +1159,56 @@ impl RendererMain {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use glam::Vec2;
+
+	#[test]
+	fn test_view_partition_single() {
+		let partition = ViewPartition::new(100, 1000, 0, 1);
+		assert_eq!(partition.groups, CompactingAllocPartition::full(100));
+		assert_eq!(partition.instances, CompactingAllocPartition::full(1000));
+	}
+
+	#[test]
+	fn test_view_partition_disjoint() {
+		let (group_capacity, instance_capacity, count) = (100, 1000, 3);
+		let partitions = (0..count)
+			.map(|index| ViewPartition::new(group_capacity, instance_capacity, index, count))
+			.collect::<Vec<_>>();
+		for (i, partition) in partitions.iter().enumerate() {
+			assert_eq!(partition.groups.capacity, 33);
+			assert_eq!(partition.instances.capacity, 333);
+			assert!(partition.groups.offset + partition.groups.capacity <= group_capacity);
+			assert!(partition.instances.offset + partition.instances.capacity <= instance_capacity);
+			if let Some(next) = partitions.get(i + 1) {
+				assert_eq!(partition.groups.offset + partition.groups.capacity, next.groups.offset);
+				assert_eq!(
+					partition.instances.offset + partition.instances.capacity,
+					next.instances.offset
+				);
+			}
+		}
+	}
+
+	#[test]
+	fn test_last_frame_hzb() {
+		let camera = |size| Camera::new_perspective_rh_y_flip(size, 1., 0.1, 100., Default::default(), Vec2::ZERO);
+		let current = camera(UVec2::new(1920, 1080));
+		let last = camera(UVec2::new(1280, 720));
+
+		// without a last frame, the view falls back to its own camera
+		let (hzb_camera, enabled) = last_frame_hzb(None, current, true);
+		assert_eq!(hzb_camera.viewport_size, current.viewport_size);
+		assert!(!enabled);
+
+		let (hzb_camera, enabled) = last_frame_hzb(Some(last), current, true);
+		assert_eq!(hzb_camera.viewport_size, last.viewport_size);
+		assert!(enabled);
+
+		let (_, enabled) = last_frame_hzb(Some(last), current, false);
+		assert!(!enabled);
+	}
+}
@@ -0,0 +1,145 @@
+use crate::renderer::frame_context::FrameContext;
+use glam::UVec2;
+use rust_gpu_bindless::descriptor::{
+	AddressMode, Bindless, BindlessImageCreateInfo, BindlessImageUsage, BindlessSamplerCreateInfo, Filter, Format,
+	Image, Image2d, MutDesc, MutImage, RCDesc, RCDescExt, Sampler, TransientDesc,
+};
+use rust_gpu_bindless::pipeline::{
+	AccessError, BindlessComputePipeline, MutImageAccess, MutImageAccessExt, Recording, RecordingError, SampledRead,
+	StorageReadWrite,
+};
+use space_engine_shader::renderer::taa::{Param, TAA_RESOLVE_WG_SIZE};
+
+pub const TAA_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+/// The image the frame is lit into and the history of the resolve, see [`space_engine_shader::renderer::taa`]
+pub struct TaaImages {
+	color: MutDesc<MutImage<Image2d>>,
+	history: MutDesc<MutImage<Image2d>>,
+	next_history: MutDesc<MutImage<Image2d>>,
+	/// whether `history` contains the resolved previous frame
+	history_valid: bool,
+}
+
+impl TaaImages {
+	/// Allocates the images for an output image of `size`
+	pub fn new(bindless: &Bindless, size: UVec2) -> anyhow::Result<Self> {
+		let alloc = |name: &str| {
+			bindless.image().alloc(&BindlessImageCreateInfo {
+				format: TAA_FORMAT,
+				extent: size.into(),
+				usage: BindlessImageUsage::STORAGE | BindlessImageUsage::SAMPLED,
+				name,
+				..Default::default()
+			})
+		};
+		Ok(Self {
+			color: alloc("taa_color")?,
+			history: alloc("taa_history")?,
+			next_history: alloc("taa_next_history")?,
+			history_valid: false,
+		})
+	}
+
+	pub fn access_color<'a>(self, cmd: &Recording<'a>) -> Result<TaaTarget<'a>, AccessError> {
+		Ok(TaaTarget {
+			color: self.color.access_dont_care(cmd)?,
+			history: self.history,
+			next_history: self.next_history,
+			history_valid: self.history_valid,
+		})
+	}
+}
+
+/// The image the frame is lit into if TAA is enabled, to be resolved by [`TaaCompute::dispatch`]
+pub struct TaaTarget<'a> {
+	color: MutImageAccess<'a, Image2d, StorageReadWrite>,
+	history: MutDesc<MutImage<Image2d>>,
+	next_history: MutDesc<MutImage<Image2d>>,
+	history_valid: bool,
+}
+
+impl TaaTarget<'_> {
+	pub fn to_mut_transient(&self) -> TransientDesc<'_, MutImage<Image2d>> {
+		self.color.to_mut_transient()
+	}
+}
+
+pub struct TaaCompute {
+	resolve: BindlessComputePipeline<Param<'static>>,
+	sampler: RCDesc<Sampler>,
+}
+
+impl TaaCompute {
+	pub fn new(bindless: &Bindless) -> anyhow::Result<Self> {
+		Ok(Self {
+			resolve: bindless.create_compute_pipeline(crate::shader::renderer::taa::taa_resolve_cs::new())?,
+			sampler: bindless.sampler().alloc(&BindlessSamplerCreateInfo {
+				min_filter: Filter::Linear,
+				mag_filter: Filter::Linear,
+				address_mode_u: AddressMode::ClampToEdge,
+				address_mode_v: AddressMode::ClampToEdge,
+				address_mode_w: AddressMode::ClampToEdge,
+				..BindlessSamplerCreateInfo::default()
+			})?,
+		})
+	}
+
+	/// Resolves the lit `target` with the history into the `output_image`, if TAA is enabled. The result is kept as the
+	/// history of the next frame. If TAA is disabled, the frame must have been lit into the `output_image` directly,
+	/// and the history is discarded.
+	pub fn dispatch<'a>(
+		&self,
+		cmd: &mut Recording<'a>,
+		frame_context: &FrameContext,
+		target: TaaTarget<'a>,
+		depth_image: TransientDesc<Image<Image2d>>,
+		velocity: TransientDesc<Image<Image2d>>,
+		output_image: TransientDesc<MutImage<Image2d>>,
+	) -> Result<TaaImages, RecordingError> {
+		profiling::function_scope!();
+		if !frame_context.frame_data.taa.enabled() {
+			return Ok(TaaImages {
+				color: target.color.into_desc(),
+				history: target.history,
+				next_history: target.next_history,
+				history_valid: false,
+			});
+		}
+
+		let color = target.color.transition::<SampledRead>()?;
+		let history = if target.history_valid {
+			target.history.access::<SampledRead>(cmd)?
+		} else {
+			// never sampled, as the history is discarded
+			target.history.access_dont_care::<SampledRead>(cmd)?
+		};
+		let history_out = target.next_history.access_dont_care::<StorageReadWrite>(cmd)?;
+		let image_size = frame_context.frame_data.camera.viewport_size;
+		cmd.dispatch(
+			&self.resolve,
+			[
+				image_size.x.div_ceil(TAA_RESOLVE_WG_SIZE.x),
+				image_size.y.div_ceil(TAA_RESOLVE_WG_SIZE.y),
+				1,
+			],
+			Param {
+				frame_data: frame_context.frame_data_desc,
+				color: color.to_transient_sampled()?,
+				depth_image,
+				velocity,
+				history: history.to_transient_sampled()?,
+				sampler: self.sampler.to_transient(cmd),
+				history_valid: target.history_valid as u32,
+				history_out: history_out.to_mut_transient(),
+				output_image,
+			},
+		)?;
+		Ok(TaaImages {
+			color: color.into_desc(),
+			history: history_out.into_desc(),
+			next_history: history.into_desc(),
+			history_valid: true,
+		})
+	}
+}
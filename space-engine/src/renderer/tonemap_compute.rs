@@ -0,0 +1,180 @@
+use crate::renderer::frame_context::FrameContext;
+use glam::UVec2;
+use rust_gpu_bindless::descriptor::{
+	Bindless, BindlessAllocationScheme, BindlessBufferCreateInfo, BindlessBufferUsage, BindlessImageCreateInfo,
+	BindlessImageUsage, Format, Image, Image2d, MutBuffer, MutDesc, MutImage, TransientDesc,
+};
+use rust_gpu_bindless::pipeline::{
+	AccessError, BindlessComputePipeline, ImageAccessType, MutBufferAccessExt, MutImageAccess, MutImageAccessExt,
+	Recording, RecordingError, SampledRead, ShaderRead, ShaderReadWrite, StorageReadWrite,
+};
+use space_engine_shader::renderer::bloom::Bloom;
+use space_engine_shader::renderer::tonemap::{ExposureParam, HISTOGRAM_BINS, HistogramParam, Param, TONEMAP_WG_SIZE};
+
+pub const HDR_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+/// The HDR image the frame is lit into and the state of the auto exposure, see
+/// [`space_engine_shader::renderer::tonemap`]
+pub struct TonemapResources {
+	hdr: MutDesc<MutImage<Image2d>>,
+	histogram: MutDesc<MutBuffer<[u32]>>,
+	exposure: MutDesc<MutBuffer<f32>>,
+	/// whether `exposure` contains the auto exposure of the previous frame
+	exposure_valid: bool,
+}
+
+impl TonemapResources {
+	/// Allocates the resources for an output image of `size`
+	pub fn new(bindless: &Bindless, size: UVec2) -> anyhow::Result<Self> {
+		Ok(Self {
+			hdr: bindless.image().alloc(&BindlessImageCreateInfo {
+				format: HDR_FORMAT,
+				extent: size.into(),
+				usage: BindlessImageUsage::STORAGE | BindlessImageUsage::SAMPLED,
+				name: "hdr",
+				..Default::default()
+			})?,
+			// the exposure pass clears the histogram after reading it, so it only needs to be cleared once
+			histogram: bindless.buffer().alloc_from_iter(
+				&BindlessBufferCreateInfo {
+					usage: BindlessBufferUsage::STORAGE_BUFFER | BindlessBufferUsage::MAP_WRITE,
+					name: "luminance_histogram",
+					allocation_scheme: BindlessAllocationScheme::AllocatorManaged,
+				},
+				std::iter::repeat_n(0, HISTOGRAM_BINS as usize),
+			)?,
+			exposure: bindless.buffer().alloc_sized(&BindlessBufferCreateInfo {
+				usage: BindlessBufferUsage::STORAGE_BUFFER,
+				name: "exposure",
+				allocation_scheme: BindlessAllocationScheme::AllocatorManaged,
+			})?,
+			exposure_valid: false,
+		})
+	}
+
+	pub fn access_hdr<'a>(self, cmd: &Recording<'a>) -> Result<TonemapTarget<'a, StorageReadWrite>, AccessError> {
+		Ok(TonemapTarget {
+			hdr: self.hdr.access_dont_care(cmd)?,
+			histogram: self.histogram,
+			exposure: self.exposure,
+			exposure_valid: self.exposure_valid,
+		})
+	}
+}
+
+/// The HDR image the frame is lit into, to be tonemapped by [`TonemapCompute::dispatch`]
+pub struct TonemapTarget<'a, A: ImageAccessType> {
+	hdr: MutImageAccess<'a, Image2d, A>,
+	histogram: MutDesc<MutBuffer<[u32]>>,
+	exposure: MutDesc<MutBuffer<f32>>,
+	exposure_valid: bool,
+}
+
+impl<'a, A: ImageAccessType> TonemapTarget<'a, A> {
+	pub fn transition<B: ImageAccessType>(self) -> Result<TonemapTarget<'a, B>, AccessError> {
+		Ok(TonemapTarget {
+			hdr: self.hdr.transition()?,
+			histogram: self.histogram,
+			exposure: self.exposure,
+			exposure_valid: self.exposure_valid,
+		})
+	}
+}
+
+impl TonemapTarget<'_, StorageReadWrite> {
+	pub fn to_mut_transient(&self) -> TransientDesc<'_, MutImage<Image2d>> {
+		self.hdr.to_mut_transient()
+	}
+}
+
+impl TonemapTarget<'_, SampledRead> {
+	pub fn to_transient_sampled(&self) -> Result<TransientDesc<'_, Image<Image2d>>, AccessError> {
+		self.hdr.to_transient_sampled()
+	}
+}
+
+pub struct TonemapCompute {
+	histogram: BindlessComputePipeline<HistogramParam<'static>>,
+	exposure: BindlessComputePipeline<ExposureParam<'static>>,
+	tonemap: BindlessComputePipeline<Param<'static>>,
+}
+
+impl TonemapCompute {
+	pub fn new(bindless: &Bindless) -> anyhow::Result<Self> {
+		Ok(Self {
+			histogram: bindless
+				.create_compute_pipeline(crate::shader::renderer::tonemap::tonemap_histogram_cs::new())?,
+			exposure: bindless.create_compute_pipeline(crate::shader::renderer::tonemap::tonemap_exposure_cs::new())?,
+			tonemap: bindless.create_compute_pipeline(crate::shader::renderer::tonemap::tonemap_cs::new())?,
+		})
+	}
+
+	/// Exposes and tonemaps the HDR `target` with the `bloom` mixed in into the `output_image` at `output_offset`. With
+	/// auto exposure, the exposure is first adapted to the luminance histogram of the `target`.
+	pub fn dispatch<'a>(
+		&self,
+		cmd: &mut Recording<'a>,
+		frame_context: &FrameContext,
+		target: TonemapTarget<'a, SampledRead>,
+		bloom: Bloom,
+		output_image: TransientDesc<MutImage<Image2d>>,
+		output_offset: UVec2,
+	) -> Result<TonemapResources, RecordingError> {
+		profiling::function_scope!();
+		let hdr = target.hdr;
+		let image_size = frame_context.frame_data.camera.viewport_size;
+		let groups = [
+			image_size.x.div_ceil(TONEMAP_WG_SIZE.x),
+			image_size.y.div_ceil(TONEMAP_WG_SIZE.y),
+			1,
+		];
+		let auto_exposure = frame_context.frame_data.tonemap.auto_exposure();
+
+		let mut histogram = target.histogram.access::<ShaderReadWrite>(cmd)?;
+		let exposure = target.exposure.access::<ShaderReadWrite>(cmd)?;
+		if auto_exposure {
+			cmd.dispatch(
+				&self.histogram,
+				groups,
+				HistogramParam {
+					frame_data: frame_context.frame_data_desc,
+					hdr_image: hdr.to_transient_sampled()?,
+					histogram: histogram.to_mut_transient()?,
+				},
+			)?;
+			// a transition to the same access type is a no-op, so it takes two to wait for the finished histogram
+			histogram = histogram.transition::<ShaderRead>()?.transition::<ShaderReadWrite>()?;
+			cmd.dispatch(
+				&self.exposure,
+				[1, 1, 1],
+				ExposureParam {
+					frame_data: frame_context.frame_data_desc,
+					histogram: histogram.to_mut_transient()?,
+					exposure: exposure.to_mut_transient()?,
+					adapt: target.exposure_valid as u32,
+				},
+			)?;
+		}
+
+		// the exposure is only read with auto exposure
+		let exposure = exposure.transition::<ShaderRead>()?;
+		cmd.dispatch(
+			&self.tonemap,
+			groups,
+			Param {
+				frame_data: frame_context.frame_data_desc,
+				hdr_image: hdr.to_transient_sampled()?,
+				exposure: exposure.to_transient()?,
+				bloom,
+				output_image,
+				output_offset,
+			},
+		)?;
+		Ok(TonemapResources {
+			hdr: hdr.into_desc(),
+			histogram: histogram.into_desc(),
+			exposure: exposure.into_desc(),
+			exposure_valid: auto_exposure,
+		})
+	}
+}
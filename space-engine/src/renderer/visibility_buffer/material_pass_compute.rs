@@ -0,0 +1,89 @@
+use crate::renderer::compacting_alloc_buffer::CompactingAllocBufferReading;
+use crate::renderer::frame_context::FrameContext;
+use crate::renderer::visibility_buffer::software_raster_compute::SoftwareRasterAccess;
+use rust_gpu_bindless::descriptor::{
+	Bindless, Image, Image2d, Image2dU, MutImage, RCDesc, RCDescExt, Sampler, TransientDesc,
+};
+use rust_gpu_bindless::pipeline::{
+	AccessError, BindlessComputePipeline, MutImageAccess, Recording, RecordingError, SampledRead, ShaderRead,
+};
+use space_asset_rt::meshlet::scene::InstancedMeshletSceneCpu;
+use space_engine_shader::renderer::lighting::ambient_occlusion::AmbientOcclusion;
+use space_engine_shader::renderer::lighting::ibl::Ibl;
+use space_engine_shader::renderer::lighting::light_cluster::LightClusters;
+use space_engine_shader::renderer::lighting::shadow::ShadowMap;
+use space_engine_shader::renderer::meshlet::intermediate::MeshletInstance;
+use space_engine_shader::renderer::visibility_buffer::MeshletInstanceBuffers;
+use space_engine_shader::renderer::visibility_buffer::material_pass::{MATERIAL_PASS_WG_SIZE, Param};
+
+/// The meshlet instances selected in the first and second pass
+pub fn meshlet_instance_buffers<'a>(
+	first: &'a CompactingAllocBufferReading<MeshletInstance>,
+	second: &'a CompactingAllocBufferReading<MeshletInstance>,
+) -> Result<MeshletInstanceBuffers<'a>, AccessError> {
+	Ok(MeshletInstanceBuffers {
+		first: first.to_reader()?,
+		second: second.to_reader()?,
+	})
+}
+
+pub struct MaterialPassCompute(BindlessComputePipeline<Param<'static>>);
+
+impl MaterialPassCompute {
+	pub fn new(bindless: &Bindless) -> anyhow::Result<Self> {
+		Ok(Self(bindless.create_compute_pipeline(
+			crate::shader::renderer::visibility_buffer::material_pass::visibility_material_cs::new(),
+		)?))
+	}
+
+	/// Shades all pixels covered by a triangle in the `visibility_buffer`, which must have the format `R32_UINT`, or in
+	/// the `software_raster` buffers, if software rasterization is enabled.
+	#[allow(clippy::too_many_arguments)]
+	pub fn dispatch<'a>(
+		&self,
+		cmd: &mut Recording<'a>,
+		frame_context: &FrameContext,
+		scene: &InstancedMeshletSceneCpu,
+		sampler: &RCDesc<Sampler>,
+		meshlet_instances: MeshletInstanceBuffers,
+		visibility_buffer: &MutImageAccess<'a, Image2d, SampledRead>,
+		depth_image: TransientDesc<Image<Image2d>>,
+		software_meshlet_instances: MeshletInstanceBuffers,
+		software_raster: &SoftwareRasterAccess<'a, ShaderRead, ShaderRead>,
+		shadow_map: ShadowMap,
+		light_clusters: LightClusters,
+		ibl: Ibl,
+		ambient_occlusion: AmbientOcclusion,
+		output_image: TransientDesc<MutImage<Image2d>>,
+		g_velocity: TransientDesc<MutImage<Image2d>>,
+	) -> Result<(), RecordingError> {
+		profiling::function_scope!();
+		// Safety: attachments are always typed as float images, but the visibility buffer contains unsigned integers
+		let visibility_buffer: TransientDesc<Image<Image2dU>> =
+			unsafe { TransientDesc::new(visibility_buffer.to_transient_sampled()?.id(), cmd) };
+		let image_size = frame_context.frame_data.camera.viewport_size;
+		let groups = [
+			image_size.x.div_ceil(MATERIAL_PASS_WG_SIZE.x),
+			image_size.y.div_ceil(MATERIAL_PASS_WG_SIZE.y),
+			1,
+		];
+		let param = Param {
+			frame_data: frame_context.frame_data_desc,
+			scene: scene.scene.to_transient(cmd),
+			sampler: sampler.to_transient(cmd),
+			meshlet_instances,
+			visibility_buffer,
+			depth_image,
+			software_meshlet_instances,
+			software_depth: software_raster.depth.to_transient()?,
+			software_visibility: software_raster.visibility.to_transient()?,
+			shadow_map,
+			light_clusters,
+			ibl,
+			ambient_occlusion,
+			output_image,
+			g_velocity,
+		};
+		cmd.dispatch(&self.0, groups, param)
+	}
+}
@@ -0,0 +1,2 @@
+pub mod material_pass_compute;
+pub mod software_raster_compute;
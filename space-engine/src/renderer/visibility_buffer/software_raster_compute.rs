@@ -0,0 +1,189 @@
+use crate::renderer::compacting_alloc_buffer::CompactingAllocBufferReading;
+use crate::renderer::frame_context::FrameContext;
+use glam::UVec2;
+use rust_gpu_bindless::descriptor::{
+	Bindless, BindlessAllocationScheme, BindlessBufferCreateInfo, BindlessBufferUsage, Buffer, MutBuffer, MutDesc,
+	RCDesc, RCDescExt,
+};
+use rust_gpu_bindless::pipeline::{
+	AccessError, BindlessComputePipeline, BufferAccessType, MutBufferAccess, MutBufferAccessExt, Recording,
+	RecordingError, ShaderRead, ShaderReadWrite, TransferWrite,
+};
+use space_asset_rt::meshlet::scene::InstancedMeshletSceneCpu;
+use space_engine_shader::renderer::meshlet::intermediate::MeshletInstance;
+use space_engine_shader::renderer::meshlet::meshlet_select::MeshletSelectPass;
+use space_engine_shader::renderer::visibility_buffer::VisibilityId;
+use space_engine_shader::renderer::visibility_buffer::software_raster::{
+	DepthParam, SOFTWARE_DEPTH_CLEAR, VisibilityParam, software_depth_bits,
+};
+
+/// The per-pixel depth and [`VisibilityId`] buffers written by the software rasterizer, see
+/// [`space_engine_shader::renderer::visibility_buffer::software_raster`]
+pub struct SoftwareRasterBuffers {
+	depth: MutDesc<MutBuffer<[u32]>>,
+	visibility: MutDesc<MutBuffer<[u32]>>,
+	cleared: SoftwareRasterCleared,
+}
+
+/// Buffers containing the cleared values, copied over the buffers at the start of a frame
+struct SoftwareRasterCleared {
+	depth: RCDesc<Buffer<[u32]>>,
+	visibility: RCDesc<Buffer<[u32]>>,
+}
+
+impl SoftwareRasterBuffers {
+	/// Allocates the buffers for a viewport of `size`
+	pub fn new(bindless: &Bindless, size: UVec2) -> anyhow::Result<Self> {
+		let len = (size.x * size.y) as usize;
+		let alloc = |name: &str| {
+			bindless.buffer().alloc_slice(
+				&BindlessBufferCreateInfo {
+					usage: BindlessBufferUsage::STORAGE_BUFFER | BindlessBufferUsage::TRANSFER_DST,
+					name,
+					allocation_scheme: BindlessAllocationScheme::AllocatorManaged,
+				},
+				len,
+			)
+		};
+		let alloc_cleared = |value: u32, name: &str| {
+			bindless.buffer().alloc_shared_from_iter(
+				&BindlessBufferCreateInfo {
+					usage: BindlessBufferUsage::STORAGE_BUFFER
+						| BindlessBufferUsage::TRANSFER_SRC
+						| BindlessBufferUsage::MAP_WRITE,
+					name,
+					allocation_scheme: BindlessAllocationScheme::AllocatorManaged,
+				},
+				std::iter::repeat_n(value, len),
+			)
+		};
+		Ok(Self {
+			depth: alloc("software_raster_depth")?,
+			visibility: alloc("software_raster_visibility")?,
+			cleared: SoftwareRasterCleared {
+				depth: alloc_cleared(
+					software_depth_bits(SOFTWARE_DEPTH_CLEAR),
+					"software_raster_depth cleared",
+				)?,
+				visibility: alloc_cleared(VisibilityId::EMPTY.0, "software_raster_visibility cleared")?,
+			},
+		})
+	}
+
+	/// Accesses both buffers for rasterization. Clearing them is only necessary if they are rasterized into, otherwise
+	/// their contents must not be read.
+	pub fn access<'a>(
+		self,
+		cmd: &mut Recording<'a>,
+		clear: bool,
+	) -> Result<SoftwareRasterAccess<'a, ShaderReadWrite, ShaderReadWrite>, RecordingError> {
+		let (depth, visibility) = if clear {
+			let depth = self.depth.access::<TransferWrite>(cmd)?;
+			let visibility = self.visibility.access::<TransferWrite>(cmd)?;
+			cmd.copy_buffer_to_buffer_slice(&self.cleared.depth, &depth)?;
+			cmd.copy_buffer_to_buffer_slice(&self.cleared.visibility, &visibility)?;
+			(depth.transition()?, visibility.transition()?)
+		} else {
+			(self.depth.access(cmd)?, self.visibility.access(cmd)?)
+		};
+		Ok(SoftwareRasterAccess {
+			depth,
+			visibility,
+			cleared: self.cleared,
+		})
+	}
+}
+
+pub struct SoftwareRasterAccess<'a, D: BufferAccessType, V: BufferAccessType> {
+	/// `f32` depth bits per pixel
+	pub depth: MutBufferAccess<'a, [u32], D>,
+	/// [`VisibilityId`] per pixel
+	pub visibility: MutBufferAccess<'a, [u32], V>,
+	cleared: SoftwareRasterCleared,
+}
+
+impl<'a, D: BufferAccessType, V: BufferAccessType> SoftwareRasterAccess<'a, D, V> {
+	pub fn transition<D2: BufferAccessType, V2: BufferAccessType>(
+		self,
+	) -> Result<SoftwareRasterAccess<'a, D2, V2>, AccessError> {
+		Ok(SoftwareRasterAccess {
+			depth: self.depth.transition()?,
+			visibility: self.visibility.transition()?,
+			cleared: self.cleared,
+		})
+	}
+
+	pub fn into_buffers(self) -> SoftwareRasterBuffers {
+		SoftwareRasterBuffers {
+			depth: self.depth.into_desc(),
+			visibility: self.visibility.into_desc(),
+			cleared: self.cleared,
+		}
+	}
+}
+
+pub struct SoftwareRasterCompute {
+	depth_pipeline: BindlessComputePipeline<DepthParam<'static>>,
+	visibility_pipeline: BindlessComputePipeline<VisibilityParam<'static>>,
+}
+
+impl SoftwareRasterCompute {
+	pub fn new(bindless: &Bindless) -> anyhow::Result<Self> {
+		Ok(Self {
+			depth_pipeline: bindless.create_compute_pipeline(
+				crate::shader::renderer::visibility_buffer::software_raster::software_raster_depth_cs::new(),
+			)?,
+			visibility_pipeline: bindless.create_compute_pipeline(
+				crate::shader::renderer::visibility_buffer::software_raster::software_raster_visibility_cs::new(),
+			)?,
+		})
+	}
+
+	/// Rasterizes the depth of all `meshlet_instances`, keeping the nearest depth per pixel
+	pub fn rasterize_depth(
+		&self,
+		cmd: &mut Recording<'_>,
+		frame_context: &FrameContext,
+		scene: &InstancedMeshletSceneCpu,
+		meshlet_instances: &CompactingAllocBufferReading<MeshletInstance>,
+		depth: &MutBufferAccess<[u32], ShaderReadWrite>,
+	) -> Result<(), RecordingError> {
+		profiling::function_scope!();
+		cmd.dispatch_indirect(
+			&self.depth_pipeline,
+			meshlet_instances.indirect_args(),
+			DepthParam {
+				frame_data: frame_context.frame_data_desc,
+				scene: scene.scene.to_transient(cmd),
+				meshlet_instances: meshlet_instances.to_reader()?,
+				depth: depth.to_mut_transient()?,
+			},
+		)
+	}
+
+	/// Rasterizes all `meshlet_instances` selected in `pass` again, after the depth of all passes has been rasterized,
+	/// writing the [`VisibilityId`] of the triangles matching the nearest depth
+	pub fn rasterize_visibility(
+		&self,
+		cmd: &mut Recording<'_>,
+		frame_context: &FrameContext,
+		scene: &InstancedMeshletSceneCpu,
+		meshlet_instances: &CompactingAllocBufferReading<MeshletInstance>,
+		pass: MeshletSelectPass,
+		software_raster: &SoftwareRasterAccess<ShaderRead, ShaderReadWrite>,
+	) -> Result<(), RecordingError> {
+		profiling::function_scope!();
+		cmd.dispatch_indirect(
+			&self.visibility_pipeline,
+			meshlet_instances.indirect_args(),
+			VisibilityParam {
+				frame_data: frame_context.frame_data_desc,
+				scene: scene.scene.to_transient(cmd),
+				meshlet_instances: meshlet_instances.to_reader()?,
+				pass: pass.into(),
+				depth: software_raster.depth.to_transient()?,
+				visibility: software_raster.visibility.to_mut_transient()?,
+			},
+		)
+	}
+}